@@ -0,0 +1,252 @@
+//! requests of the rest apis of forges, which repositories may be read through rather
+//! than cloned. pages are followed by their `Link` headers, requests are held back until
+//! rate limits reset and other transient failures are retried with backoff
+use crate::{error::LinecatError, TIMESTAMP_FORMAT};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{error::Error, io, thread, time::Duration};
+use ureq::{Agent, AgentBuilder, Response};
+
+/// requests made for a page before giving up on it
+const ATTEMPTS: u32 = 6;
+
+/// the delay before the first retry, doubling with each attempt after
+const BACKOFF: Duration = Duration::from_millis(500);
+
+/// the url of the `rel="next"` page of a `Link` header, if there is one
+pub fn next(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let (url, rel) = part.split_once(';')?;
+        rel.contains(r#"rel="next""#).then(|| {
+            url.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+    })
+}
+
+/// formats an iso 8601 date the way `%ai` does
+pub fn timestamp(date: &str) -> Result<String, Box<dyn Error>> {
+    Ok(DateTime::parse_from_rfc3339(date)
+        .map_err(|e| format!("invalid date {}: {}", date, e))?
+        .format(TIMESTAMP_FORMAT)
+        .to_string())
+}
+
+/// the seconds to wait before retrying a request refused for exceeding a rate limit, as
+/// its `Retry-After` header or the reset of its `RateLimit` headers, which github prefixes
+/// with `X-`, tell. none for other refusals
+fn limited(response: &Response) -> Option<u64> {
+    if let Some(after) = response.header("retry-after") {
+        return after.trim().parse().ok();
+    }
+    let header = |name: &str| {
+        response
+            .header(&format!("x-{}", name))
+            .or_else(|| response.header(name))
+    };
+    if header("ratelimit-remaining") != Some("0") {
+        return None;
+    }
+    let reset = header("ratelimit-reset")?.trim().parse::<i64>().ok()?;
+    Some((reset - Utc::now().timestamp()).max(0) as u64 + 1)
+}
+
+/// whether a failed request is worth retrying, as when the api is overloaded
+fn transient(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Status(status, _) => *status == 429 || *status >= 500,
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+/// a client of a forge's api, reading a single repository
+pub struct Client {
+    agent: Agent,
+    /// what's read, as in `github repo meetup/api`, which failures are described by
+    origin: String,
+    /// headers sent with every request, as of the api's version and auth token
+    headers: Vec<(&'static str, String)>,
+    pub(crate) backoff: Duration,
+}
+
+impl Client {
+    pub fn new(
+        origin: String,
+        headers: Vec<(&'static str, String)>,
+    ) -> Self {
+        Client {
+            agent: AgentBuilder::new()
+                .timeout(Duration::from_secs(60))
+                .user_agent(concat!("git-linecat/", env!("CARGO_PKG_VERSION")))
+                .build(),
+            origin,
+            headers,
+            backoff: BACKOFF,
+        }
+    }
+
+    /// a failure to read the repository, which unavailable inputs exit with
+    pub fn unavailable(
+        &self,
+        error: impl ToString,
+    ) -> LinecatError {
+        LinecatError::Io {
+            origin: self.origin.clone(),
+            source: io::Error::other(error.to_string()),
+        }
+    }
+
+    /// gets a url, waiting out rate limits and retrying transient failures,
+    /// describing the last once out of attempts
+    pub fn get(
+        &self,
+        url: &str,
+    ) -> Result<Response, LinecatError> {
+        let mut delay = self.backoff;
+        let mut attempt = 1;
+        loop {
+            let mut request = self.agent.get(url);
+            for (name, value) in &self.headers {
+                request = request.set(name, value);
+            }
+            match request.call() {
+                Ok(response) => return Ok(response),
+                Err(ureq::Error::Status(403 | 429, response)) if limited(&response).is_some() => {
+                    let seconds = limited(&response).unwrap_or_default();
+                    tracing::warn!(
+                        seconds,
+                        origin = %self.origin,
+                        "rate limited, waiting for the limit to reset"
+                    );
+                    thread::sleep(Duration::from_secs(seconds));
+                }
+                Err(e) if transient(&e) && attempt < ATTEMPTS => {
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(self.unavailable(e)),
+            }
+        }
+    }
+
+    /// gets the json of a url
+    pub fn json<T>(
+        &self,
+        url: &str,
+    ) -> Result<T, LinecatError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        serde_json::from_reader(self.get(url)?.into_reader()).map_err(|e| self.unavailable(e))
+    }
+
+    /// gets every page of a listing, following `Link` headers from `url`,
+    /// handing each page's json to `page` as it's read
+    pub fn pages<T, F>(
+        &self,
+        url: String,
+        mut page: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        T: for<'de> Deserialize<'de>,
+        F: FnMut(T) -> Result<(), Box<dyn Error>>,
+    {
+        let mut url = Some(url);
+        while let Some(current) = url.take() {
+            let response = self.get(&current)?;
+            url = response.header("link").and_then(next);
+            page(
+                serde_json::from_reader(response.into_reader()).map_err(|e| self.unavailable(e))?,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    /// answers requests with statuses, headers and bodies in order, in which `{url}`
+    /// stands for the server's own, sending back the request line of each
+    pub fn serve(
+        responses: Vec<(u16, &'static str, &'static str)>
+    ) -> Result<(String, mpsc::Receiver<String>), Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let (sender, receiver) = mpsc::channel();
+        let base = url.clone();
+        thread::spawn(move || {
+            for ((status, headers, body), stream) in responses.into_iter().zip(listener.incoming())
+            {
+                let mut stream = BufReader::new(stream.expect("connection"));
+                let mut request = String::new();
+                stream.read_line(&mut request).expect("request line");
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).expect("header line");
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                drop(sender.send(request.trim().to_string()));
+                let body = body.replace("{url}", &base);
+                write!(
+                    stream.get_mut(),
+                    "HTTP/1.1 {} OK\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    headers.replace("{url}", &base),
+                    body.len(),
+                    body
+                )
+                .expect("response");
+            }
+        });
+        Ok((url, receiver))
+    }
+
+    #[test]
+    fn next_pages_are_linked() {
+        assert_eq!(
+            next(
+                r#"<https://api.github.com/repositories/1/commits?page=2>; rel="next", <https://api.github.com/repositories/1/commits?page=9>; rel="last""#
+            ),
+            Some("https://api.github.com/repositories/1/commits?page=2".into())
+        );
+        assert_eq!(
+            next(r#"<https://api.github.com/repositories/1/commits?page=1>; rel="first""#),
+            None
+        );
+    }
+
+    #[test]
+    fn waits_out_rate_limits() -> Result<(), Box<dyn Error>> {
+        let (url, requests) = serve(vec![
+            (429, "Retry-After: 0\r\n", ""),
+            (403, "RateLimit-Remaining: 0\r\nRateLimit-Reset: 0\r\n", ""),
+            (503, "", ""),
+            (200, "", "[1]"),
+            (403, "", ""),
+        ])?;
+        let mut client = Client::new("test repo".into(), Vec::new());
+        client.backoff = Duration::from_millis(1);
+        let mut pages = Vec::new();
+        client.pages(url.clone(), |page: Vec<u8>| {
+            pages.push(page);
+            Ok(())
+        })?;
+        assert_eq!(pages, vec![vec![1]]);
+        assert_eq!(requests.try_iter().count(), 4);
+        let refused = client.get(&url).err().ok_or("expected a refusal")?;
+        assert_eq!(refused.exit_code(), crate::error::EX_IOERR);
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! categorizes paths as a repository's
+//! [.gitattributes](https://github.com/github-linguist/linguist/blob/main/docs/overrides.md)
+//! does for github's language stats, marking them `linguist-generated` or
+//! `linguist-vendored`, or unmarking paths the built-in heuristics would mark
+use crate::{codeowners::glob, Change, Emitter};
+use globset::GlobSet;
+use std::{collections::HashMap, error::Error, fs, path::Path};
+
+const GENERATED: &str = "linguist-generated";
+const VENDORED: &str = "linguist-vendored";
+
+struct Rule {
+    pattern: GlobSet,
+    /// attributes the rule sets, to true or false, or unspecifies, with `!`
+    attributes: Vec<(String, Option<bool>)>,
+}
+
+pub struct Attributes {
+    rules: Vec<Rule>,
+}
+
+/// the globs of a gitattributes pattern, which unlike a gitignore pattern never
+/// matches the paths within a directory it names
+fn globs(pattern: &str) -> Result<GlobSet, Box<dyn Error>> {
+    // patterns are relative to the root when they contain a separator, and match at any depth otherwise
+    let base = match pattern.trim_start_matches('/') {
+        trimmed if pattern.contains('/') || trimmed.starts_with("**") => trimmed.to_string(),
+        trimmed => format!("**/{}", trimmed),
+    };
+    Ok(GlobSet::builder().add(glob(&base)?).build()?)
+}
+
+/// an attribute and its state, as in `linguist-generated`, `-linguist-generated`,
+/// `linguist-generated=false` or `!linguist-generated`
+fn attribute(word: &str) -> (String, Option<bool>) {
+    if let Some(name) = word.strip_prefix('-') {
+        return (name.into(), Some(false));
+    }
+    if let Some(name) = word.strip_prefix('!') {
+        return (name.into(), None);
+    }
+    match word.split_once('=') {
+        Some((name, value)) => (name.into(), Some(!matches!(value, "false" | "0" | "no"))),
+        _ => (word.into(), Some(true)),
+    }
+}
+
+impl Attributes {
+    /// the attributes of the `.gitattributes` at the root of a work tree, if it has one
+    pub fn discover(path: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        let path = path.join(".gitattributes");
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read gitattributes {}: {}", path.display(), e))?;
+        Self::parse(&content)
+            .map(Some)
+            .map_err(|e| format!("invalid gitattributes {}: {}", path.display(), e).into())
+    }
+
+    fn parse(content: &str) -> Result<Self, Box<dyn Error>> {
+        let mut rules = Vec::new();
+        for line in content.lines() {
+            let mut words = line.split_whitespace();
+            let pattern = match words.next() {
+                Some(pattern) if !pattern.starts_with('#') && !pattern.starts_with("[attr]") => {
+                    pattern
+                }
+                _ => continue,
+            };
+            let attributes = words
+                .map(attribute)
+                .filter(|(name, _)| name == GENERATED || name == VENDORED)
+                .collect::<Vec<_>>();
+            if attributes.is_empty() {
+                continue;
+            }
+            rules.push(Rule {
+                pattern: globs(pattern)
+                    .map_err(|e| format!("invalid pattern {}: {}", pattern, e))?,
+                attributes,
+            });
+        }
+        Ok(Attributes { rules })
+    }
+
+    /// the state of an attribute of a path, given by the last rule matching it which mentions it
+    fn get(
+        &self,
+        path: &str,
+        name: &str,
+    ) -> Option<bool> {
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| rule.pattern.is_match(path))
+            .find_map(|rule| rule.attributes.iter().rev().find(|(n, _)| n == name))
+            .and_then(|(_, state)| *state)
+    }
+
+    /// whether a path is vendored and whether it's generated, where its attributes say so
+    pub fn linguist(
+        &self,
+        path: &str,
+    ) -> (Option<bool>, Option<bool>) {
+        (self.get(path, VENDORED), self.get(path, GENERATED))
+    }
+}
+
+/// recategorizes the changes of repos whose `.gitattributes` mark their paths
+/// vendored or generated, or not, before handing them to another emitter
+pub struct Linguist<E> {
+    /// the attributes of each repo's work tree
+    pub repos: HashMap<String, Attributes>,
+    pub emitter: E,
+}
+
+impl<E> Emitter for Linguist<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(attributes) = self.repos.get(&line.repo) {
+            let (vendored, generated) = attributes.linguist(&line.path);
+            if vendored.is_some() || generated.is_some() {
+                line.categories = Change::categories_with(&line.path, vendored, generated);
+                line.category = line.categories[0].clone();
+            }
+        }
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Category;
+
+    #[test]
+    fn last_mention_of_an_attribute_wins() -> Result<(), Box<dyn Error>> {
+        let attributes = Attributes::parse(
+            "# github language stats\n\
+             *.rs text eol=lf\n\
+             *.pb.go -diff\n\
+             api/*.json linguist-generated\n\
+             api/schema.json -linguist-generated\n\
+             third_party/** linguist-vendored=true\n\
+             vendor/** linguist-vendored=false\n\
+             web/dist/** linguist-generated linguist-vendored\n\
+             web/dist/keep.js !linguist-generated\n",
+        )?;
+        assert_eq!(attributes.linguist("src/main.rs"), (None, None));
+        assert_eq!(attributes.linguist("api/users.json"), (None, Some(true)));
+        assert_eq!(attributes.linguist("api/schema.json"), (None, Some(false)));
+        assert_eq!(attributes.linguist("api/v1/users.json"), (None, None));
+        assert_eq!(
+            attributes.linguist("third_party/zlib/zlib.h"),
+            (Some(true), None)
+        );
+        assert_eq!(attributes.linguist("web/dist/keep.js"), (Some(true), None));
+        Ok(())
+    }
+
+    #[test]
+    fn attributes_override_built_in_categories() -> Result<(), Box<dyn Error>> {
+        let attributes = Attributes::parse(
+            "gen/** linguist-generated\n\
+             vendor/** linguist-vendored=false\n\
+             *.lock -linguist-generated\n",
+        )?;
+        let categorize = |path: &str| {
+            let (vendored, generated) = attributes.linguist(path);
+            Change::categorize_with(path, vendored, generated)
+        };
+        assert_eq!(categorize("gen/client.go"), Category::Generated);
+        assert_eq!(categorize("vendor/lib/lib_test.go"), Category::Test);
+        assert_eq!(categorize("Cargo.lock"), Category::Default);
+        Ok(())
+    }
+}
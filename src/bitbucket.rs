@@ -0,0 +1,465 @@
+//! reads the commits of a repository and the paths each changes through the rest apis
+//! of [bitbucket cloud](https://developer.atlassian.com/cloud/bitbucket/rest/api-group-commits/)
+//! and of bitbucket server, for repositories which aren't cloned locally. unlike those of
+//! other forges, their pages link to the next in their bodies rather than `Link` headers
+use crate::{
+    api::{timestamp, Client},
+    manifest::{Forge, Remote},
+    progress, Change, Emitter, Header, Parse, Path, TIMESTAMP_FORMAT,
+};
+use chrono::DateTime;
+use serde::Deserialize;
+use std::error::Error;
+
+/// commits listed per page, the most bitbucket cloud allows
+const PER_PAGE: usize = 100;
+
+/// a page of bitbucket cloud's listings, linking to the next
+#[derive(Deserialize)]
+struct CloudPage<T> {
+    values: Vec<T>,
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CloudCommit {
+    hash: String,
+    date: String,
+    message: String,
+    author: CloudAuthor,
+    parents: Vec<CloudParent>,
+}
+
+#[derive(Deserialize)]
+struct CloudAuthor {
+    /// the author as git recorded them, as `Luna <luna@moon.com>`
+    raw: String,
+}
+
+#[derive(Deserialize)]
+struct CloudParent {
+    hash: String,
+}
+
+/// the lines a commit changes of a single path
+#[derive(Deserialize)]
+struct Diffstat {
+    lines_added: usize,
+    lines_removed: usize,
+    old: Option<CloudFile>,
+    new: Option<CloudFile>,
+}
+
+#[derive(Deserialize)]
+struct CloudFile {
+    path: String,
+}
+
+/// a page of bitbucket server's listings, which starts where the last ended
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerPage<T> {
+    values: Vec<T>,
+    is_last_page: bool,
+    next_page_start: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerCommit {
+    id: String,
+    author: ServerPerson,
+    /// milliseconds since the epoch
+    author_timestamp: i64,
+    committer: Option<ServerPerson>,
+    committer_timestamp: Option<i64>,
+    message: String,
+    parents: Vec<ServerParent>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerPerson {
+    email_address: Option<String>,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ServerParent {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct ServerDiffs {
+    diffs: Vec<ServerDiff>,
+}
+
+/// the diff of a single path, in hunks of segments of added, removed or context lines
+#[derive(Deserialize)]
+struct ServerDiff {
+    source: Option<ServerPath>,
+    destination: Option<ServerPath>,
+    #[serde(default)]
+    binary: bool,
+    #[serde(default)]
+    hunks: Vec<Hunk>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerPath {
+    to_string: String,
+}
+
+#[derive(Deserialize)]
+struct Hunk {
+    segments: Vec<Segment>,
+}
+
+#[derive(Deserialize)]
+struct Segment {
+    #[serde(rename = "type")]
+    kind: String,
+    lines: Vec<serde_json::Value>,
+}
+
+/// the email of a `Name <email>` signature, or the whole of it without one
+fn email(raw: &str) -> String {
+    match raw.rsplit_once('<') {
+        Some((_, rest)) => rest.trim_end_matches('>').to_string(),
+        _ => raw.trim().to_string(),
+    }
+}
+
+/// formats milliseconds since the epoch the way `%ai` does, in utc
+fn millis(timestamp: i64) -> Result<String, Box<dyn Error>> {
+    Ok(DateTime::from_timestamp_millis(timestamp)
+        .ok_or_else(|| format!("invalid timestamp {}", timestamp))?
+        .format(TIMESTAMP_FORMAT)
+        .to_string())
+}
+
+/// a message's subject and body, when `with_message`
+fn message(
+    message: &str,
+    with_message: bool,
+) -> (Option<String>, Option<String>) {
+    let (subject, body) = message.split_once('\n').unwrap_or((message, ""));
+    (
+        Some(subject.trim_end().to_string()).filter(|_| with_message),
+        Some(body.trim().to_string()).filter(|b| with_message && !b.is_empty()),
+    )
+}
+
+/// a path changed from `old` to `new`, renames written as git writes them
+fn renamed(
+    old: Option<String>,
+    new: Option<String>,
+) -> Option<String> {
+    match (old, new) {
+        (Some(old), Some(new)) if old != new => Some(format!("{} => {}", old, new)),
+        (_, Some(path)) | (Some(path), None) => Some(path),
+        _ => None,
+    }
+}
+
+/// emits the changes of a commit, or the record of a merge
+fn emit<E>(
+    repository: &str,
+    header: Header,
+    paths: Vec<Path>,
+    emitter: &mut E,
+) -> Result<(), Box<dyn Error>>
+where
+    E: Emitter,
+{
+    let mut changes = paths
+        .into_iter()
+        .map(|path| (repository.to_string(), header.clone(), path).into());
+    if header.parents.as_ref().is_some_and(|p| p.len() > 1) {
+        let changes = changes.collect::<Vec<Change>>();
+        return emitter.emit(Change::merge(repository.into(), header, &changes));
+    }
+    changes.try_for_each(|change| emitter.emit(change))
+}
+
+/// reads bitbucket cloud's commits, then the diffstat of each
+fn cloud<E>(
+    repository: &str,
+    client: &Client,
+    base_url: &str,
+    repo: &str,
+    &Parse {
+        with_message,
+        merges,
+        ..
+    }: &Parse,
+    emitter: &mut E,
+) -> Result<(), Box<dyn Error>>
+where
+    E: Emitter,
+{
+    let base = format!("{}/2.0/repositories/{}", base_url, repo);
+    let mut url = Some(format!("{}/commits?pagelen={}", base, PER_PAGE));
+    while let Some(current) = url.take() {
+        let page: CloudPage<CloudCommit> = client.json(&current)?;
+        url = page.next;
+        for commit in page.values {
+            if commit.parents.len() > 1 && !merges {
+                continue;
+            }
+            progress::commit();
+            let mut paths = Vec::new();
+            let mut diffstat = Some(format!("{}/diffstat/{}", base, commit.hash));
+            while let Some(current) = diffstat.take() {
+                let page: CloudPage<Diffstat> = client.json(&current)?;
+                diffstat = page.next;
+                paths.extend(page.values.into_iter().filter_map(|stat| {
+                    Some(Path {
+                        additions: Some(stat.lines_added),
+                        deletions: Some(stat.lines_removed),
+                        path: renamed(stat.old.map(|f| f.path), stat.new.map(|f| f.path))?,
+                    })
+                }));
+            }
+            let (subject, body) = message(&commit.message, with_message);
+            let header = Header {
+                sha: commit.hash,
+                author: email(&commit.author.raw),
+                timestamp: timestamp(&commit.date)?,
+                committer: None,
+                commit_timestamp: None,
+                parents: Some(commit.parents.into_iter().map(|p| p.hash).collect()),
+                tags: None,
+                branches: None,
+                subject,
+                body,
+            };
+            emit(repository, header, paths, emitter)?;
+        }
+    }
+    Ok(())
+}
+
+/// reads bitbucket server's commits, then the diff of each, counting the lines of its hunks
+fn server<E>(
+    repository: &str,
+    client: &Client,
+    base_url: &str,
+    repo: &str,
+    &Parse {
+        with_message,
+        include_binary,
+        merges,
+        ..
+    }: &Parse,
+    emitter: &mut E,
+) -> Result<(), Box<dyn Error>>
+where
+    E: Emitter,
+{
+    let (project, slug) = repo
+        .split_once('/')
+        .ok_or_else(|| client.unavailable("expected a repository as PROJECT/name"))?;
+    let base = format!(
+        "{}/rest/api/1.0/projects/{}/repos/{}/commits",
+        base_url, project, slug
+    );
+    let mut start = Some(0);
+    while let Some(at) = start.take() {
+        let page: ServerPage<ServerCommit> =
+            client.json(&format!("{}?limit={}&start={}", base, PER_PAGE, at))?;
+        start = page.next_page_start.filter(|_| !page.is_last_page);
+        for commit in page.values {
+            if commit.parents.len() > 1 && !merges {
+                continue;
+            }
+            progress::commit();
+            let diffs: ServerDiffs =
+                client.json(&format!("{}/{}/diff?contextLines=0", base, commit.id))?;
+            let paths = diffs
+                .diffs
+                .into_iter()
+                .filter(|diff| include_binary || !diff.binary)
+                .filter_map(|diff| {
+                    let count = |kind: &str| {
+                        diff.hunks
+                            .iter()
+                            .flat_map(|hunk| &hunk.segments)
+                            .filter(|segment| segment.kind == kind)
+                            .map(|segment| segment.lines.len())
+                            .sum()
+                    };
+                    let (additions, deletions) = match diff.binary {
+                        true => (None, None),
+                        _ => (Some(count("ADDED")), Some(count("REMOVED"))),
+                    };
+                    Some(Path {
+                        additions,
+                        deletions,
+                        path: renamed(
+                            diff.source.map(|p| p.to_string),
+                            diff.destination.map(|p| p.to_string),
+                        )?,
+                    })
+                })
+                .collect();
+            let person = |person: &ServerPerson| {
+                person
+                    .email_address
+                    .clone()
+                    .unwrap_or_else(|| person.name.clone())
+            };
+            let (subject, body) = message(&commit.message, with_message);
+            let header = Header {
+                author: person(&commit.author),
+                timestamp: millis(commit.author_timestamp)?,
+                committer: commit.committer.as_ref().map(person),
+                commit_timestamp: commit.committer_timestamp.map(millis).transpose()?,
+                sha: commit.id,
+                parents: Some(commit.parents.into_iter().map(|p| p.id).collect()),
+                tags: None,
+                branches: None,
+                subject,
+                body,
+            };
+            emit(repository, header, paths, emitter)?;
+        }
+    }
+    Ok(())
+}
+
+/// lists the commits of a repository of bitbucket cloud, as `workspace/name`, or of a
+/// bitbucket server, as `PROJECT/name`, newest first as `git log` does, emitting a change
+/// per path of each and fetching the paths of each commit by requests of their own.
+/// merges are skipped unless `merges`. neither api lists commits since a date, so
+/// `--since` only filters changes once read. bitbucket cloud doesn't tell binary files
+/// apart, so their changes are emitted with no lines changed
+pub fn read<E>(
+    repository: String,
+    remote: &Remote,
+    parse: &Parse,
+    emitter: &mut E,
+) -> Result<(), Box<dyn Error>>
+where
+    E: Emitter,
+{
+    let headers = remote
+        .token
+        .iter()
+        .map(|token| ("Authorization", format!("Bearer {}", token)))
+        .collect();
+    let client = Client::new(format!("bitbucket repo {}", remote.repo), headers);
+    let base_url = remote.base_url.trim_end_matches('/');
+    match remote.forge {
+        Forge::BitbucketServer => {
+            server(&repository, &client, base_url, &remote.repo, parse, emitter)
+        }
+        _ => cloud(&repository, &client, base_url, &remote.repo, parse, emitter),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::tests::serve, Collect};
+
+    #[test]
+    fn signatures_are_emails() {
+        assert_eq!(email("Luna <luna@moon.com>"), "luna@moon.com");
+        assert_eq!(email("luna"), "luna");
+    }
+
+    #[test]
+    fn reads_cloud_pages() -> Result<(), Box<dyn Error>> {
+        let (url, requests) = serve(vec![
+            (
+                200,
+                "",
+                r#"{"values":[{"hash":"a","date":"2019-08-09T19:36:50+00:00","message":"fix things\n","author":{"raw":"Luna <luna@moon.com>"},"parents":[{"hash":"p"}]},{"hash":"m","date":"2019-08-09T19:36:50+00:00","message":"merge","author":{"raw":"Luna <luna@moon.com>"},"parents":[{"hash":"a"},{"hash":"b"}]}],"next":"{url}/2.0/repositories/meetup/api/commits?page=2"}"#,
+            ),
+            (
+                200,
+                "",
+                r#"{"values":[{"lines_added":2,"lines_removed":1,"old":{"path":"src/old.rs"},"new":{"path":"src/new.rs"}},{"lines_added":0,"lines_removed":4,"old":{"path":"gone.rs"},"new":null}]}"#,
+            ),
+            (200, "", r#"{"values":[]}"#),
+        ])?;
+        let mut collect = Collect::default();
+        read(
+            "meetup/api".into(),
+            &Remote {
+                forge: Forge::Bitbucket,
+                repo: "meetup/api".into(),
+                base_url: url,
+                token: None,
+            },
+            &Parse::default(),
+            &mut collect,
+        )?;
+        let requests = requests.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            vec![
+                "GET /2.0/repositories/meetup/api/commits?pagelen=100 HTTP/1.1",
+                "GET /2.0/repositories/meetup/api/diffstat/a HTTP/1.1",
+                "GET /2.0/repositories/meetup/api/commits?page=2 HTTP/1.1",
+            ]
+        );
+        let changes = collect.0;
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].path, "src/new.rs");
+        assert_eq!(changes[0].old_path.as_deref(), Some("src/old.rs"));
+        assert_eq!(changes[0].author, "luna@moon.com");
+        assert_eq!(changes[0].timestamp, "2019-08-09 19:36:50 +0000");
+        assert_eq!(changes[1].path, "gone.rs");
+        assert_eq!(changes[1].deletions, Some(4));
+        Ok(())
+    }
+
+    #[test]
+    fn reads_server_pages() -> Result<(), Box<dyn Error>> {
+        let (url, requests) = serve(vec![
+            (
+                200,
+                "",
+                r#"{"values":[{"id":"a","author":{"name":"luna","emailAddress":"luna@moon.com"},"authorTimestamp":1565379410000,"committer":{"name":"sol"},"committerTimestamp":1565379410000,"message":"init","parents":[]}],"isLastPage":false,"nextPageStart":1}"#,
+            ),
+            (
+                200,
+                "",
+                r#"{"diffs":[{"source":null,"destination":{"toString":"src/lib.rs"},"hunks":[{"segments":[{"type":"REMOVED","lines":[{}]},{"type":"ADDED","lines":[{},{}]}]}]},{"source":null,"destination":{"toString":"logo.png"},"binary":true}]}"#,
+            ),
+            (200, "", r#"{"values":[],"isLastPage":true}"#),
+        ])?;
+        let mut collect = Collect::default();
+        read(
+            "api".into(),
+            &Remote {
+                forge: Forge::BitbucketServer,
+                repo: "MEET/api".into(),
+                base_url: url,
+                token: Some("secret".into()),
+            },
+            &Parse::default(),
+            &mut collect,
+        )?;
+        let requests = requests.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            requests[0],
+            "GET /rest/api/1.0/projects/MEET/repos/api/commits?limit=100&start=0 HTTP/1.1"
+        );
+        assert_eq!(
+            requests[2],
+            "GET /rest/api/1.0/projects/MEET/repos/api/commits?limit=100&start=1 HTTP/1.1"
+        );
+        let changes = collect.0;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "src/lib.rs");
+        assert_eq!(changes[0].additions, Some(2));
+        assert_eq!(changes[0].deletions, Some(1));
+        assert_eq!(changes[0].committer.as_deref(), Some("sol"));
+        assert_eq!(changes[0].timestamp, "2019-08-09 19:36:50 +0000");
+        Ok(())
+    }
+}
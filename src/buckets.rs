@@ -0,0 +1,69 @@
+//! spans of time derived from when changes were authored, in utc, so
+//! downstream queries bucket changes the same way `summarize` does
+use crate::{parse_timestamp, summarize::Bucket, Change, Emitter};
+use chrono::{Datelike, Timelike, Utc};
+use std::error::Error;
+
+/// sets the `year`, `year_month`, `iso_week`, `weekday` and `hour_utc` of changes
+/// before handing them to another emitter. changes with timestamps which fail to
+/// parse are handed along without them
+pub struct TimeBuckets<E> {
+    pub emitter: E,
+}
+
+impl<E> Emitter for TimeBuckets<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Ok(timestamp) = parse_timestamp(&line.timestamp) {
+            let utc = timestamp.with_timezone(&Utc);
+            line.year = Some(utc.year());
+            line.year_month = Some(utc.format(Bucket::Month.format()).to_string());
+            line.iso_week = Some(utc.format(Bucket::Week.format()).to_string());
+            line.weekday = Some(utc.format("%A").to_string());
+            line.hour_utc = Some(utc.hour());
+        }
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collect;
+
+    #[test]
+    fn buckets_in_utc() -> Result<(), Box<dyn Error>> {
+        let mut buckets = TimeBuckets {
+            emitter: Collect::default(),
+        };
+        buckets.emit(Change {
+            timestamp: "2019-12-29 21:36:50 -0400".into(),
+            ..Change::default()
+        })?;
+        buckets.emit(Change {
+            timestamp: "garbled".into(),
+            ..Change::default()
+        })?;
+        let (bucketed, garbled) = (&buckets.emitter.0[0], &buckets.emitter.0[1]);
+        assert_eq!(bucketed.year, Some(2019));
+        assert_eq!(bucketed.year_month.as_deref(), Some("2019-12"));
+        assert_eq!(bucketed.iso_week.as_deref(), Some("2020-W01"));
+        assert_eq!(bucketed.weekday.as_deref(), Some("Monday"));
+        assert_eq!(bucketed.hour_utc, Some(1));
+        assert_eq!(garbled.year, None);
+        Ok(())
+    }
+}
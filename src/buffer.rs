@@ -0,0 +1,215 @@
+//! hands changes to an emitter on a thread of its own through a bounded channel,
+//! so the latency of a network sink doesn't stall reading. once `capacity` changes
+//! are waiting to be written, reading waits for the sink to catch up
+use crate::{error::LinecatError, Change, Emitter};
+use std::{
+    error::Error,
+    sync::mpsc::{sync_channel, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+enum Message {
+    Change(Box<Change>),
+    Flush,
+}
+
+type Outcome = Result<(), Box<dyn Error + Send + Sync>>;
+
+/// an error of the worker's emitter, which may cross back to the reading thread.
+/// errors of a kind keep it, so runs exit as they would writing changes directly
+fn sendable(e: Box<dyn Error>) -> Box<dyn Error + Send + Sync> {
+    match e.downcast::<LinecatError>() {
+        Ok(e) => e,
+        Err(e) => e.to_string().into(),
+    }
+}
+
+pub struct Buffered {
+    sender: Option<SyncSender<Message>>,
+    worker: Option<JoinHandle<Outcome>>,
+}
+
+impl Buffered {
+    /// starts a thread writing changes to the emitter `open` returns, with up
+    /// to `capacity` changes waiting. fails if the emitter can't be opened
+    pub fn spawn<F>(
+        capacity: usize,
+        open: F,
+    ) -> Result<Self, Box<dyn Error>>
+    where
+        F: FnOnce() -> Result<Box<dyn Emitter>, Box<dyn Error>> + Send + 'static,
+    {
+        let (sender, receiver) = sync_channel(capacity);
+        let (opened, open_result) = sync_channel(1);
+        let worker = thread::spawn(move || {
+            let mut emitter = match open() {
+                Ok(emitter) => {
+                    drop(opened.send(Ok(())));
+                    emitter
+                }
+                Err(e) => {
+                    drop(opened.send(Err(sendable(e))));
+                    return Ok(());
+                }
+            };
+            for message in receiver {
+                match message {
+                    Message::Change(change) => emitter.emit(*change),
+                    Message::Flush => emitter.flush(),
+                }
+                .map_err(sendable)?;
+            }
+            emitter.finish().map_err(sendable)
+        });
+        open_result
+            .recv()
+            .map_err(|_| "buffered emitter failed to open")?
+            .map_err(|e| e as Box<dyn Error>)?;
+        Ok(Buffered {
+            sender: Some(sender),
+            worker: Some(worker),
+        })
+    }
+
+    /// waits for the worker to finish, returning the error that stopped it, if any
+    fn join(&mut self) -> Result<(), Box<dyn Error>> {
+        self.sender.take();
+        match self.worker.take() {
+            Some(worker) => worker
+                .join()
+                .map_err(|_| "buffered emitter panicked")?
+                .map_err(|e| e as Box<dyn Error>),
+            _ => Ok(()),
+        }
+    }
+
+    fn send(
+        &mut self,
+        message: Message,
+    ) -> Result<(), Box<dyn Error>> {
+        let sent = match &self.sender {
+            Some(sender) => sender.send(message).is_ok(),
+            _ => false,
+        };
+        if sent {
+            return Ok(());
+        }
+        // the worker only stops receiving once it fails
+        self.join()?;
+        Err("buffered emitter stopped".into())
+    }
+}
+
+impl Emitter for Buffered {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.send(Message::Change(Box::new(line)))
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.send(Message::Flush)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// collects changes and flushes, failing after emitting `limit` changes
+    struct Collect {
+        events: Arc<Mutex<Vec<String>>>,
+        limit: usize,
+    }
+
+    impl Emitter for Collect {
+        fn emit(
+            &mut self,
+            line: Change,
+        ) -> Result<(), Box<dyn Error>> {
+            let mut events = self.events.lock().map_err(|_| "poisoned")?;
+            if events.len() >= self.limit {
+                return Err("unavailable".into());
+            }
+            events.push(line.path);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+            self.events
+                .lock()
+                .map_err(|_| "poisoned")?
+                .push("flush".into());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+            self.events
+                .lock()
+                .map_err(|_| "poisoned")?
+                .push("finish".into());
+            Ok(())
+        }
+    }
+
+    fn buffered(
+        events: &Arc<Mutex<Vec<String>>>,
+        limit: usize,
+    ) -> Result<Buffered, Box<dyn Error>> {
+        let events = events.clone();
+        Buffered::spawn(1, move || {
+            Ok(Box::new(Collect { events, limit }) as Box<dyn Emitter>)
+        })
+    }
+
+    fn change(path: &str) -> Change {
+        Change {
+            path: path.into(),
+            ..Change::default()
+        }
+    }
+
+    #[test]
+    fn writes_changes_in_order() -> Result<(), Box<dyn Error>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut emitter = buffered(&events, usize::MAX)?;
+        for path in &["a.rs", "b.rs", "c.rs"] {
+            emitter.emit(change(path))?;
+        }
+        emitter.flush()?;
+        emitter.emit(change("d.rs"))?;
+        emitter.finish()?;
+        assert_eq!(
+            *events.lock().map_err(|_| "poisoned")?,
+            vec!["a.rs", "b.rs", "c.rs", "flush", "d.rs", "finish"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn surfaces_sink_errors() -> Result<(), Box<dyn Error>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut emitter = buffered(&events, 2)?;
+        let err = (0..10)
+            .map(|i| emitter.emit(change(&i.to_string())))
+            .find_map(Result::err)
+            .map_or_else(|| emitter.finish(), Err)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unavailable");
+        Ok(())
+    }
+
+    #[test]
+    fn surfaces_open_errors() {
+        let err = Buffered::spawn(1, || Err("connection refused".into()))
+            .err()
+            .map(|e| e.to_string());
+        assert_eq!(err.as_deref(), Some("connection refused"));
+    }
+}
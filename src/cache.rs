@@ -0,0 +1,301 @@
+//! keeps bare clones of remote repositories in a cache directory, cloning those
+//! it doesn't hold yet and fetching the branches and tags of those it does, so
+//! repositories which aren't cloned locally may be read as though they were
+use crate::{
+    git,
+    manifest::{Input, Source},
+};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+    thread,
+};
+
+/// the variable a token is handed to git's credential helper by, keeping it out of
+/// process listings and the config of clones
+const TOKEN: &str = "GIT_LINECAT_CLONE_TOKEN";
+
+/// answers git's requests for credentials with the token of [`TOKEN`], as the
+/// password of the `x-access-token` user github expects
+const CREDENTIAL_HELPER: &str =
+    "!f() { echo username=x-access-token; echo \"password=$GIT_LINECAT_CLONE_TOKEN\"; }; f";
+
+/// a repository to keep a clone of
+pub struct Remote {
+    /// the name its changes are tagged with and its clone kept under, as `meetup/api`
+    pub name: String,
+    pub url: String,
+}
+
+/// how much of each repository's history and contents to transfer, keeping the clones of
+/// huge repositories manageable. all of either is transferred by default
+#[derive(Default)]
+pub struct Shallow {
+    /// the commits of each branch to keep, from its tip
+    pub depth: Option<u32>,
+    /// the date, as git understands it, before which commits aren't kept
+    pub since: Option<String>,
+    /// a partial clone filter, as `blob:none`, leaving out contents which git fetches
+    /// once they're read
+    pub filter: Option<String>,
+}
+
+impl Shallow {
+    /// the arguments of `git clone` and `git fetch` bounding what they transfer
+    fn args(&self) -> Vec<String> {
+        self.depth
+            .map(|depth| format!("--depth={}", depth))
+            .into_iter()
+            .chain(
+                self.since
+                    .iter()
+                    .map(|since| format!("--shallow-since={}", since)),
+            )
+            .chain(
+                self.filter
+                    .iter()
+                    .map(|filter| format!("--filter={}", filter)),
+            )
+            .collect()
+    }
+}
+
+/// the directory under `workdir` a repository's clone is kept in
+pub fn dir(
+    workdir: &Path,
+    name: &str,
+) -> PathBuf {
+    workdir.join(format!("{}.git", name))
+}
+
+/// runs git with the token, if any, authenticating its requests
+fn run(
+    args: &[&str],
+    token: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let mut command = Command::new("git");
+    if let Some(token) = token {
+        command
+            .args(["-c", "credential.helper="])
+            .args(["-c", &format!("credential.helper={}", CREDENTIAL_HELPER)])
+            .env(TOKEN, token);
+    }
+    let output = command
+        .args(args)
+        // failing rather than prompting for credentials which were never given
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed ({}): {}",
+            args[0],
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// clones a repository into `dir`, or fetches its branches and tags when it's already cloned,
+/// transferring no more than `shallow` allows
+pub fn sync(
+    remote: &Remote,
+    dir: &Path,
+    token: Option<&str>,
+    shallow: &Shallow,
+) -> Result<(), Box<dyn Error>> {
+    let path = dir.to_string_lossy();
+    let bounds = shallow.args();
+    let bounds = bounds.iter().map(String::as_str);
+    if dir.exists() {
+        let mut args = vec!["-C", &path, "fetch", "--quiet", "--prune", "--tags"];
+        args.extend(bounds);
+        args.extend([remote.url.as_str(), "+refs/heads/*:refs/heads/*"].iter());
+        return run(&args, token);
+    }
+    let mut args = vec!["clone", "--quiet", "--bare"];
+    args.extend(bounds);
+    args.extend([remote.url.as_str(), &path].iter());
+    run(&args, token)
+}
+
+/// clones or fetches each repository into `workdir` with up to `jobs` threads, returning
+/// a source for each. repositories which fail to sync, or which have no commits yet, are
+/// skipped with a warning rather than failing every other
+pub fn sync_all(
+    remotes: Vec<Remote>,
+    workdir: &Path,
+    token: Option<&str>,
+    shallow: &Shallow,
+    jobs: usize,
+) -> Vec<Source> {
+    let queue = Mutex::new(remotes.into_iter().enumerate());
+    let synced = Mutex::new(Vec::new());
+    // the queue is locked only long enough to take the next repository off it
+    let next = || queue.lock().ok().and_then(|mut queue| queue.next());
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            scope.spawn(|| {
+                while let Some((i, remote)) = next() {
+                    let dir = dir(workdir, &remote.name);
+                    match sync(&remote, &dir, token, shallow).and_then(|_| git::head(&dir)) {
+                        Ok(Some(_)) => {
+                            if let Ok(mut synced) = synced.lock() {
+                                synced.push((i, remote.name, dir));
+                            }
+                        }
+                        Ok(None) => {
+                            tracing::warn!(repo = %remote.name, "skipping repository without commits")
+                        }
+                        Err(e) => tracing::warn!(repo = %remote.name, "skipping repository: {}", e),
+                    }
+                }
+            });
+        }
+    });
+    let mut synced = synced.into_inner().unwrap_or_default();
+    // sources are read in the order they were listed, however they were synced
+    synced.sort_by_key(|(i, _, _)| *i);
+    synced
+        .into_iter()
+        .map(|(_, name, dir)| Source {
+            name,
+            input: Input::Repo(dir),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Backend, Collect, Parse};
+    use std::{env, fs};
+
+    #[test]
+    fn clones_then_fetches() -> Result<(), Box<dyn Error>> {
+        let origin = git::tests::repo("cache-origin")?;
+        let workdir = env::temp_dir().join(format!("git-linecat-cache-{}", std::process::id()));
+        drop(fs::remove_dir_all(&workdir));
+        let remotes = || {
+            vec![
+                Remote {
+                    name: "meetup/api".into(),
+                    url: origin.to_string_lossy().into(),
+                },
+                Remote {
+                    name: "meetup/gone".into(),
+                    url: workdir.join("nowhere").to_string_lossy().into(),
+                },
+            ]
+        };
+        let sources = sync_all(remotes(), &workdir, None, &Shallow::default(), 2);
+        assert_eq!(
+            sources,
+            vec![Source {
+                name: "meetup/api".into(),
+                input: Input::Repo(workdir.join("meetup/api.git")),
+            }]
+        );
+        let cloned = git::head(&workdir.join("meetup/api.git"))?;
+        assert_eq!(cloned, git::head(&origin)?);
+        fs::write(origin.join("README.md"), "hi\n")?;
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&origin)
+            .args(["-c", "user.name=luna", "-c", "user.email=luna@moon.com"])
+            .args(["commit", "-q", "--allow-empty", "-m", "more"])
+            .status()?;
+        assert!(status.success());
+        assert_eq!(
+            sync_all(remotes(), &workdir, None, &Shallow::default(), 1).len(),
+            1
+        );
+        assert_eq!(
+            git::head(&workdir.join("meetup/api.git"))?,
+            git::head(&origin)?
+        );
+        fs::remove_dir_all(&workdir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn shallow_clones_skip_where_theyre_cut_off() -> Result<(), Box<dyn Error>> {
+        let origin = git::tests::repo("cache-shallow")?;
+        for file in ["README.md", "src/main.rs"] {
+            fs::write(origin.join(file), "hi\n")?;
+            for args in [
+                &["add", "."][..],
+                &[
+                    "-c",
+                    "user.name=luna",
+                    "-c",
+                    "user.email=luna@moon.com",
+                    "commit",
+                    "-q",
+                    "-m",
+                    file,
+                ][..],
+            ] {
+                let status = Command::new("git")
+                    .arg("-C")
+                    .arg(&origin)
+                    .args(args)
+                    .status()?;
+                assert!(status.success(), "git {:?} failed", args);
+            }
+        }
+        let workdir = env::temp_dir().join(format!("git-linecat-shallow-{}", std::process::id()));
+        drop(fs::remove_dir_all(&workdir));
+        // git ignores depths of clones by path rather than url
+        let remote = Remote {
+            name: "meetup/api".into(),
+            url: format!("file://{}", origin.display()),
+        };
+        let shallow = Shallow {
+            depth: Some(2),
+            ..Shallow::default()
+        };
+        let sources = sync_all(vec![remote], &workdir, None, &shallow, 1);
+        let dir = workdir.join("meetup/api.git");
+        assert_eq!(git::shallow(&dir)?.len(), 1);
+        let mut collect = Collect::default();
+        for source in sources {
+            crate::read(
+                source,
+                &Backend::Git,
+                &Parse::default(),
+                true,
+                &[],
+                &mut collect,
+            )?;
+        }
+        // the readme's commit is where the clone's cut off, so the whole tree isn't counted as added
+        let paths = collect
+            .0
+            .into_iter()
+            .map(|change| change.path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["src/main.rs"]);
+        // ranges, as watch reads, are left to include what they do
+        let head = git::head(&dir)?.ok_or("expected a commit")?;
+        let mut collect = Collect::default();
+        crate::read(
+            Source {
+                name: "meetup/api".into(),
+                input: Input::Repo(dir.clone()),
+            },
+            &Backend::Git,
+            &Parse::default(),
+            true,
+            &[format!("{}..{}", head, head)],
+            &mut collect,
+        )?;
+        assert!(collect.0.is_empty());
+        fs::remove_dir_all(&workdir)?;
+        Ok(())
+    }
+}
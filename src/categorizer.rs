@@ -0,0 +1,179 @@
+//! categorizes paths with an external program, for classification logic
+//! which can't be expressed as rules.
+//!
+//! the program is spawned once and reads paths from stdin, one per line, writing
+//! a category label to stdout for each, in order. blank labels leave the category
+//! already assigned in place. paths are written in batches, so labels may be
+//! buffered until stdin is closed or a batch is complete
+use crate::{Category, Change, Emitter};
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{BufRead, BufReader, BufWriter, Lines, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+/// changes buffered before their paths are written to the program. kept small
+/// enough that the labels read back fit within a pipe buffer
+const BATCH_SIZE: usize = 256;
+
+/// recategorizes changes with the labels an external program assigns their paths
+/// before handing them to another emitter. paths are only asked about once
+pub struct Categorizer<E> {
+    exe: PathBuf,
+    child: Child,
+    stdin: Option<BufWriter<ChildStdin>>,
+    labels: Lines<BufReader<ChildStdout>>,
+    categories: HashMap<String, Option<Category>>,
+    pending: Vec<Change>,
+    pub emitter: E,
+}
+
+impl<E> Categorizer<E>
+where
+    E: Emitter,
+{
+    /// spawns `exe`, which inherits stderr so its own failures are visible
+    pub fn spawn(
+        exe: &Path,
+        emitter: E,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut child = Command::new(exe)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to run categorizer {}: {}", exe.display(), e))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Ok(Categorizer {
+            exe: exe.into(),
+            child,
+            stdin: Some(BufWriter::new(stdin)),
+            labels: BufReader::new(stdout).lines(),
+            categories: HashMap::new(),
+            pending: Vec::new(),
+            emitter,
+        })
+    }
+
+    /// asks the program about the unseen paths of pending changes,
+    /// then emits them with their categories
+    fn categorize(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut unseen = Vec::new();
+        for line in &self.pending {
+            if !self.categories.contains_key(&line.path) && !unseen.contains(&&line.path) {
+                unseen.push(&line.path);
+            }
+        }
+        if !unseen.is_empty() {
+            let stdin = self.stdin.as_mut().ok_or("categorizer is finished")?;
+            for path in &unseen {
+                writeln!(stdin, "{}", path)?;
+            }
+            stdin.flush()?;
+            for path in unseen {
+                let label = self.labels.next().ok_or_else(|| {
+                    format!(
+                        "categorizer {} exited without labeling {}",
+                        self.exe.display(),
+                        path
+                    )
+                })??;
+                let category = match label.trim() {
+                    "" => None,
+                    label => Some(serde_json::from_value(label.into())?),
+                };
+                self.categories.insert(path.clone(), category);
+            }
+        }
+        for mut line in self.pending.drain(..) {
+            if let Some(Some(category)) = self.categories.get(&line.path) {
+                line.categorize_as(category.clone());
+            }
+            self.emitter.emit(line)?;
+        }
+        Ok(())
+    }
+}
+
+impl<E> Emitter for Categorizer<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.pending.push(line);
+        if self.pending.len() >= BATCH_SIZE {
+            self.categorize()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.categorize()?;
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.categorize()?;
+        // closing stdin lets the program exit
+        drop(self.stdin.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(format!("categorizer {} failed ({})", self.exe.display(), status).into());
+        }
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collect;
+
+    fn change(
+        path: &str,
+        category: Category,
+    ) -> Change {
+        Change {
+            path: path.into(),
+            category,
+            ..Change::default()
+        }
+    }
+
+    #[test]
+    fn categorizes_with_labels() -> Result<(), Box<dyn Error>> {
+        let mut categorizer =
+            Categorizer::spawn(Path::new("tests/data/categorizer.sh"), Collect::default())?;
+        categorizer.emit(change("api/users.proto", Category::Default))?;
+        categorizer.emit(change("README.md", Category::Docs))?;
+        categorizer.emit(change("docs/api.proto", Category::Docs))?;
+        categorizer.emit(change("api/users.proto", Category::Default))?;
+        categorizer.finish()?;
+        assert_eq!(
+            (categorizer.emitter.0)
+                .into_iter()
+                .map(|change| (change.path, change.category))
+                .collect::<Vec<_>>(),
+            vec![
+                ("api/users.proto".into(), Category::Custom("protos".into())),
+                ("README.md".into(), Category::Docs),
+                ("docs/api.proto".into(), Category::Custom("protos".into())),
+                ("api/users.proto".into(), Category::Custom("protos".into())),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_when_labels_are_missing() -> Result<(), Box<dyn Error>> {
+        let mut categorizer = Categorizer::spawn(Path::new("true"), Collect::default())?;
+        categorizer.emit(change("src/main.rs", Category::Default))?;
+        assert!(categorizer.finish().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+//! measures rework, how often a path is modified again shortly
+//! after a previous change to it
+use crate::{
+    format::{write_records, Format},
+    hotspots::Window,
+    Change, Emitter,
+};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, error::Error, io::Write};
+
+const COLUMNS: &[&str] = &[
+    "path",
+    "commits",
+    "reworked",
+    "rework_ratio",
+    "lines",
+    "reworked_lines",
+];
+
+/// a commit's change to a path
+struct Touch {
+    epoch_seconds: i64,
+    sha: String,
+    lines: usize,
+}
+
+/// buffers changes per path, writing the rework of each path once all changes are seen.
+/// a commit reworks a path when it modifies it within the window of the commit before it
+pub struct Churn<W>
+where
+    W: Write,
+{
+    within: Window,
+    format: Format,
+    write: Option<W>,
+    paths: HashMap<String, Vec<Touch>>,
+}
+
+impl<W> Churn<W>
+where
+    W: Write,
+{
+    pub fn new(
+        within: Window,
+        format: Format,
+        write: W,
+    ) -> Self {
+        Churn {
+            within,
+            format,
+            write: Some(write),
+            paths: HashMap::new(),
+        }
+    }
+}
+
+/// the reworked commits of a path and the lines they touched
+fn rework(
+    Window(span): Window,
+    touches: &mut Vec<Touch>,
+) -> (usize, usize) {
+    touches.sort_by_key(|touch| touch.epoch_seconds);
+    // changes emitted once per co-author share a commit
+    touches.dedup_by(|a, b| a.sha == b.sha);
+    touches
+        .windows(2)
+        .filter(|pair| pair[1].epoch_seconds - pair[0].epoch_seconds <= span)
+        .fold((0, 0), |(reworked, lines), pair| {
+            (reworked + 1, lines + pair[1].lines)
+        })
+}
+
+impl<W> Emitter for Churn<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        // records of merges have no path to attribute
+        if line.merge {
+            return Ok(());
+        }
+        // changes without a parseable timestamp can't be placed in time
+        if let Some(epoch_seconds) = line.epoch_seconds {
+            self.paths
+                .entry(line.canonical_path.unwrap_or(line.path))
+                .or_default()
+                .push(Touch {
+                    epoch_seconds,
+                    sha: line.sha,
+                    lines: line.additions.unwrap_or_default() + line.deletions.unwrap_or_default(),
+                });
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let write = match self.write.take() {
+            Some(write) => write,
+            _ => return Ok(()),
+        };
+        let mut paths = std::mem::take(&mut self.paths)
+            .into_iter()
+            .map(|(path, mut touches)| {
+                let (reworked, reworked_lines) = rework(self.within, &mut touches);
+                let lines = touches.iter().map(|touch| touch.lines).sum::<usize>();
+                (reworked, path, touches.len(), lines, reworked_lines)
+            })
+            .collect::<Vec<_>>();
+        paths.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        let records = paths
+            .into_iter()
+            .map(|(reworked, path, commits, lines, reworked_lines)| {
+                let values = vec![
+                    Value::String(path),
+                    commits.into(),
+                    reworked.into(),
+                    (reworked as f64 / commits as f64).into(),
+                    lines.into(),
+                    reworked_lines.into(),
+                ];
+                COLUMNS
+                    .iter()
+                    .map(|column| column.to_string())
+                    .zip(values)
+                    .collect::<Map<_, _>>()
+            })
+            .collect();
+        write_records(&self.format, write, COLUMNS, records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(
+        path: &str,
+        sha: &str,
+        day: i64,
+        additions: usize,
+    ) -> Change {
+        Change {
+            path: path.into(),
+            sha: sha.into(),
+            epoch_seconds: Some(day * 24 * 60 * 60),
+            additions: Some(additions),
+            ..Change::default()
+        }
+    }
+
+    #[test]
+    fn measures_rework_within_window() -> Result<(), Box<dyn Error>> {
+        let mut buf = Vec::new();
+        {
+            let mut churn = Churn::new("7d".parse()?, Format::Csv, &mut buf);
+            for line in [
+                change("src/lib.rs", "sha4", 30, 1),
+                change("src/lib.rs", "sha3", 12, 2),
+                change("src/lib.rs", "sha2", 10, 3),
+                change("src/lib.rs", "sha2", 10, 3),
+                change("README.md", "sha2", 10, 5),
+                change("src/lib.rs", "sha1", 0, 4),
+            ] {
+                churn.emit(line)?;
+            }
+            churn.finish()?;
+        }
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "path,commits,reworked,rework_ratio,lines,reworked_lines\n\
+             src/lib.rs,4,1,0.25,10,2\n\
+             README.md,1,0,0.0,5,0\n"
+        );
+        Ok(())
+    }
+}
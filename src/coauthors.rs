@@ -0,0 +1,126 @@
+//! parses `Co-authored-by:` trailers of commit messages, crediting everyone who
+//! paired on a commit rather than only the author who committed it
+use crate::{format::id, Change, Emitter};
+use regex::Regex;
+use std::{error::Error, sync::OnceLock};
+
+fn trailer() -> &'static Regex {
+    static TRAILER: OnceLock<Regex> = OnceLock::new();
+    TRAILER.get_or_init(|| {
+        Regex::new(r"(?im)^co-authored-by:[ \t]*(?:[^<\n]*<(?P<email>[^>\n]+)>|(?P<bare>\S+))")
+            .expect("co-author trailer regex is valid")
+    })
+}
+
+/// the distinct emails of co-authors named by a commit message body, in order.
+/// trailers without an `<email>` contribute their first word
+pub fn parse(body: &str) -> Vec<String> {
+    let mut co_authors: Vec<String> = Vec::new();
+    for captures in trailer().captures_iter(body) {
+        let email = match captures.name("email").or_else(|| captures.name("bare")) {
+            Some(email) => email.as_str().trim(),
+            _ => continue,
+        };
+        if !co_authors.iter().any(|seen| seen == email) {
+            co_authors.push(email.into());
+        }
+    }
+    co_authors
+}
+
+/// emits a change once for its author and once more for each of its co-authors,
+/// each copy's `author_role` telling which it is
+pub struct Explode<E> {
+    pub emitter: E,
+}
+
+impl<E> Emitter for Explode<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        line.author_role = Some("author".into());
+        let copies = line
+            .co_authors
+            .iter()
+            .flatten()
+            .map(|co_author| {
+                let mut copy = Change {
+                    author_role: Some("co_author".into()),
+                    // which describe the commit's own author
+                    author_raw: None,
+                    author_canonical: None,
+                    ..line.clone()
+                };
+                copy.set_author(co_author.clone());
+                copy.record_id = Some(id(&copy));
+                copy
+            })
+            .collect::<Vec<_>>();
+        self.emitter.emit(line)?;
+        for copy in copies {
+            self.emitter.emit(copy)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collect;
+
+    #[test]
+    fn parses_trailers() {
+        assert_eq!(
+            parse(
+                "pairing on the parser\n\n\
+                 Co-authored-by: Sol Sun <sol@sun.com>\n\
+                 co-authored-by: Terra <terra@earth.com>\n\
+                 Co-Authored-By: Sol Sun <sol@sun.com>\n\
+                 Co-authored-by: luna"
+            ),
+            vec!["sol@sun.com", "terra@earth.com", "luna"]
+        );
+        assert!(parse("mentions co-authored-by: in passing").is_empty());
+    }
+
+    #[test]
+    fn explodes_changes_per_co_author() -> Result<(), Box<dyn Error>> {
+        let mut explode = Explode {
+            emitter: Collect::default(),
+        };
+        explode.emit(Change {
+            author: "luna@moon.com".into(),
+            co_authors: Some(vec!["sol@sun.com".into()]),
+            ..Change::default()
+        })?;
+        explode.emit(Change {
+            author: "terra@earth.com".into(),
+            ..Change::default()
+        })?;
+        assert_eq!(
+            (explode.emitter.0)
+                .into_iter()
+                .map(|change| (change.author, change.author_role))
+                .collect::<Vec<_>>(),
+            vec![
+                ("luna@moon.com".into(), Some("author".into())),
+                ("sol@sun.com".into(), Some("co_author".into())),
+                ("terra@earth.com".into(), Some("author".into())),
+            ]
+        );
+        Ok(())
+    }
+}
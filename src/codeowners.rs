@@ -0,0 +1,154 @@
+//! attributes paths to the owners a
+//! [CODEOWNERS](https://docs.github.com/en/repositories/managing-your-repositorys-settings-and-features/customizing-your-repository/about-code-owners)
+//! file assigns them. patterns follow gitignore semantics and the last matching
+//! rule wins, so a later rule without owners leaves its paths unowned
+use crate::{Change, Emitter};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use std::{error::Error, fs, path::Path};
+
+struct Rule {
+    patterns: GlobSet,
+    owners: Vec<String>,
+}
+
+pub struct Codeowners {
+    rules: Vec<Rule>,
+}
+
+/// a glob matching `*` within a single path component, as gitignore does
+pub(crate) fn glob(pattern: &str) -> Result<Glob, Box<dyn Error>> {
+    Ok(GlobBuilder::new(pattern).literal_separator(true).build()?)
+}
+
+/// the globs equivalent to a gitignore style pattern
+fn globs(pattern: &str) -> Result<GlobSet, Box<dyn Error>> {
+    let directory = pattern.ends_with('/');
+    let trimmed = pattern.trim_end_matches('/');
+    // patterns are relative to the root when they contain a separator
+    // other than a trailing one, and match at any depth otherwise
+    let anchored = trimmed.contains('/');
+    let trimmed = trimmed.trim_start_matches('/');
+    let base = match (anchored, trimmed.starts_with("**")) {
+        (false, false) => format!("**/{}", trimmed),
+        _ => trimmed.to_string(),
+    };
+    let mut builder = GlobSetBuilder::new();
+    // patterns naming a directory own everything beneath it, though
+    // `docs/*` owns only the files immediately within docs
+    if !base.ends_with("/*") {
+        builder.add(glob(&format!("{}/**", base))?);
+    }
+    if !directory {
+        builder.add(glob(&base)?);
+    }
+    Ok(builder.build()?)
+}
+
+impl Codeowners {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read codeowners {}: {}", path.display(), e))?;
+        Self::parse(&content)
+    }
+
+    fn parse(content: &str) -> Result<Self, Box<dyn Error>> {
+        let mut rules = Vec::new();
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            // gitlab style [section] headers carry no pattern
+            let pattern = match words.next() {
+                Some(pattern) if !pattern.starts_with('[') => pattern,
+                _ => continue,
+            };
+            rules.push(Rule {
+                patterns: globs(pattern)
+                    .map_err(|e| format!("invalid codeowners pattern {}: {}", pattern, e))?,
+                owners: words.map(Into::into).collect(),
+            });
+        }
+        Ok(Codeowners { rules })
+    }
+
+    /// the owners of a path, which may be none
+    pub fn owners(
+        &self,
+        path: &str,
+    ) -> &[String] {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.patterns.is_match(path))
+            .map(|rule| rule.owners.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+/// sets the `owners` of changes before handing them to another emitter
+pub struct Own<E> {
+    pub codeowners: Codeowners,
+    pub emitter: E,
+}
+
+impl<E> Emitter for Own<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        line.owners =
+            Some(self.codeowners.owners(&line.path).to_vec()).filter(|owners| !owners.is_empty());
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_matching_rule_wins() -> Result<(), Box<dyn Error>> {
+        let codeowners = Codeowners::parse(
+            "# everything else\n\
+             *       @org/core\n\
+             *.js    @org/web # scripts\n\
+             /docs/  docs@example.com\n\
+             apps/   @org/apps\n\
+             /build/logs/\n\
+             [Database]\n\
+             db/**/migrations @org/dba @luna\n",
+        )?;
+        assert_eq!(codeowners.owners("src/main.rs"), ["@org/core"]);
+        assert_eq!(codeowners.owners("src/app.js"), ["@org/web"]);
+        assert_eq!(
+            codeowners.owners("docs/guide/intro.js"),
+            ["docs@example.com"]
+        );
+        assert_eq!(codeowners.owners("src/docs/intro.md"), ["@org/core"]);
+        assert_eq!(codeowners.owners("nested/apps/main.rs"), ["@org/apps"]);
+        assert!(codeowners.owners("build/logs/out.log").is_empty());
+        assert_eq!(
+            codeowners.owners("db/users/migrations/001.sql"),
+            ["@org/dba", "@luna"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn stars_match_within_components() -> Result<(), Box<dyn Error>> {
+        let codeowners = Codeowners::parse("docs/*  @org/docs\n")?;
+        assert_eq!(codeowners.owners("docs/intro.md"), ["@org/docs"]);
+        assert!(codeowners.owners("docs/guide/intro.md").is_empty());
+        Ok(())
+    }
+}
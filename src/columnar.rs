@@ -0,0 +1,535 @@
+//! columnar encodings of changes, buffered into typed arrow record batches
+use crate::{parse_timestamp, Category, Change, Emitter, Fields, Kind};
+use arrow_array::{
+    builder::{
+        BooleanBuilder, Float64Builder, Int64Builder, ListBuilder, StringBuilder,
+        TimestampMillisecondBuilder,
+    },
+    ArrayRef, RecordBatch,
+};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+#[cfg(feature = "parquet")]
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use std::{error::Error, io::Write, sync::Arc};
+
+/// rows buffered before being written out as a single record batch
+const BATCH_SIZE: usize = 8192;
+
+fn data_type(kind: Kind) -> DataType {
+    match kind {
+        Kind::String => DataType::Utf8,
+        Kind::Integer => DataType::Int64,
+        Kind::Boolean => DataType::Boolean,
+        Kind::Float => DataType::Float64,
+        Kind::Timestamp => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        Kind::List => DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+    }
+}
+
+/// the arrow schema of changes. every column is nullable
+/// as optional fields may be omitted
+pub fn schema(fields: &Fields) -> SchemaRef {
+    Arc::new(Schema::new(
+        fields
+            .iter()
+            .map(|(name, kind)| Field::new(*name, data_type(*kind), true))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+enum Column {
+    String(StringBuilder),
+    Integer(Int64Builder),
+    Boolean(BooleanBuilder),
+    Float(Float64Builder),
+    Timestamp(TimestampMillisecondBuilder),
+    List(ListBuilder<StringBuilder>),
+}
+
+impl Column {
+    fn new(kind: Kind) -> Self {
+        match kind {
+            Kind::String => Column::String(StringBuilder::new()),
+            Kind::Integer => Column::Integer(Int64Builder::new()),
+            Kind::Boolean => Column::Boolean(BooleanBuilder::new()),
+            Kind::Float => Column::Float(Float64Builder::new()),
+            Kind::Timestamp => {
+                Column::Timestamp(TimestampMillisecondBuilder::new().with_timezone("UTC"))
+            }
+            Kind::List => Column::List(ListBuilder::new(StringBuilder::new())),
+        }
+    }
+
+    fn append(
+        &mut self,
+        value: Option<Datum>,
+    ) -> Result<(), Box<dyn Error>> {
+        match (self, value) {
+            (Column::String(builder), Some(Datum::Str(s))) => builder.append_value(s),
+            (Column::String(builder), None) => builder.append_null(),
+            (Column::Integer(builder), Some(Datum::Int(n))) => builder.append_value(n),
+            (Column::Integer(builder), None) => builder.append_null(),
+            (Column::Boolean(builder), Some(Datum::Bool(b))) => builder.append_value(b),
+            (Column::Boolean(builder), None) => builder.append_null(),
+            (Column::Float(builder), Some(Datum::Float(n))) => builder.append_value(n),
+            (Column::Float(builder), None) => builder.append_null(),
+            (Column::Timestamp(builder), Some(Datum::Str(s))) => {
+                builder.append_value(parse_timestamp(s)?.timestamp_millis())
+            }
+            (Column::Timestamp(builder), None) => builder.append_null(),
+            (Column::List(builder), Some(Datum::List(items))) => {
+                for item in items {
+                    builder.values().append_value(item);
+                }
+                builder.append(true)
+            }
+            (Column::List(builder), Some(Datum::Categories(categories))) => {
+                for category in categories {
+                    builder.values().append_value(category.name());
+                }
+                builder.append(true)
+            }
+            (Column::List(builder), None) => builder.append(false),
+            (_, Some(value)) => return Err(format!("unexpected value {:?}", value).into()),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Column::String(builder) => Arc::new(builder.finish()),
+            Column::Integer(builder) => Arc::new(builder.finish()),
+            Column::Boolean(builder) => Arc::new(builder.finish()),
+            Column::Float(builder) => Arc::new(builder.finish()),
+            Column::Timestamp(builder) => Arc::new(builder.finish()),
+            Column::List(builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+/// a field of a change, borrowed rather than serialized
+#[derive(Debug, PartialEq)]
+enum Datum<'a> {
+    Str(&'a str),
+    Int(i64),
+    Bool(bool),
+    Float(f64),
+    List(&'a [String]),
+    Categories(&'a [Category]),
+}
+
+/// the value of a named field of a change, or `None` where its serialized
+/// form would omit it, without the cost of serializing each change
+fn datum<'a>(
+    line: &'a Change,
+    name: &str,
+) -> Option<Datum<'a>> {
+    let flag = |set: bool| Some(Datum::Bool(true)).filter(|_| set);
+    let text = |value: &'a Option<String>| value.as_deref().map(Datum::Str);
+    match name {
+        "repo" => Some(Datum::Str(&line.repo)),
+        "sha" => Some(Datum::Str(&line.sha)),
+        "author" => Some(Datum::Str(&line.author)),
+        "timestamp" => Some(Datum::Str(&line.timestamp)),
+        "timestamp_utc" => text(&line.timestamp_utc),
+        "epoch_seconds" => line.epoch_seconds.map(Datum::Int),
+        "path" => Some(Datum::Str(&line.path)),
+        "old_path" => text(&line.old_path),
+        "renamed" => flag(line.renamed),
+        "ext" => text(&line.ext),
+        "category" => Some(Datum::Str(line.category.name())),
+        "additions" => line.additions.map(|n| Datum::Int(n as i64)),
+        "deletions" => line.deletions.map(|n| Datum::Int(n as i64)),
+        "binary" => flag(line.binary),
+        "dir" => text(&line.dir),
+        "top_dir" => text(&line.top_dir),
+        "path_prefix" => text(&line.path_prefix),
+        "subject" => text(&line.subject),
+        "body" => text(&line.body),
+        "author_raw" => text(&line.author_raw),
+        "author_canonical" => text(&line.author_canonical),
+        "language" => text(&line.language),
+        "commit_type" => text(&line.commit_type),
+        "scope" => text(&line.scope),
+        "breaking" => flag(line.breaking),
+        "references" => line.references.as_deref().map(Datum::List),
+        "committer" => text(&line.committer),
+        "commit_timestamp" => text(&line.commit_timestamp),
+        "co_authors" => line.co_authors.as_deref().map(Datum::List),
+        "author_role" => text(&line.author_role),
+        "record_id" => text(&line.record_id),
+        "owners" => line.owners.as_deref().map(Datum::List),
+        "author_user" => text(&line.author_user),
+        "author_domain" => text(&line.author_domain),
+        "year" => line.year.map(|n| Datum::Int(n.into())),
+        "year_month" => text(&line.year_month),
+        "iso_week" => text(&line.iso_week),
+        "weekday" => text(&line.weekday),
+        "hour_utc" => line.hour_utc.map(|n| Datum::Int(n.into())),
+        "local_hour" => line.local_hour.map(|n| Datum::Int(n.into())),
+        "local_weekday" => text(&line.local_weekday),
+        "total_lines" => line.total_lines.map(|n| Datum::Int(n as i64)),
+        "pct_changed" => line.pct_changed.map(Datum::Float),
+        "canonical_path" => text(&line.canonical_path),
+        "merge" => flag(line.merge),
+        "parents" => line.parents.as_deref().map(Datum::List),
+        "tags" => line.tags.as_deref().map(Datum::List),
+        "branches" => line.branches.as_deref().map(Datum::List),
+        "submodule" => text(&line.submodule),
+        "categories" if !line.categories.is_empty() => Some(Datum::Categories(&line.categories)),
+        _ => None,
+    }
+}
+
+/// buffers changes into columns
+pub struct Batches {
+    fields: Fields,
+    schema: SchemaRef,
+    columns: Vec<Column>,
+    rows: usize,
+}
+
+impl Batches {
+    pub fn new(fields: Fields) -> Self {
+        Batches {
+            schema: schema(&fields),
+            columns: fields.iter().map(|(_, kind)| Column::new(*kind)).collect(),
+            fields,
+            rows: 0,
+        }
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    pub fn push(
+        &mut self,
+        line: &Change,
+    ) -> Result<(), Box<dyn Error>> {
+        for ((name, _), column) in self.fields.iter().zip(self.columns.iter_mut()) {
+            column.append(datum(line, name))?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.rows >= BATCH_SIZE
+    }
+
+    /// the buffered rows as a record batch, if any, leaving the buffer empty
+    pub fn take(&mut self) -> Result<Option<RecordBatch>, Box<dyn Error>> {
+        if self.rows == 0 {
+            return Ok(None);
+        }
+        self.rows = 0;
+        Ok(Some(RecordBatch::try_new(
+            self.schema.clone(),
+            self.columns.iter_mut().map(Column::finish).collect(),
+        )?))
+    }
+}
+
+/// writes changes as an arrow ipc stream of record batches, which readers such as
+/// polars' `read_ipc_stream` load without parsing
+pub struct Arrow<W>
+where
+    W: Write,
+{
+    batches: Batches,
+    writer: Option<StreamWriter<W>>,
+}
+
+impl<W> Arrow<W>
+where
+    W: Write,
+{
+    pub fn new(
+        write: W,
+        fields: Fields,
+    ) -> Result<Self, Box<dyn Error>> {
+        let batches = Batches::new(fields);
+        let writer = StreamWriter::try_new(write, &batches.schema())?;
+        Ok(Arrow {
+            batches,
+            writer: Some(writer),
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if let (Some(batch), Some(writer)) = (self.batches.take()?, self.writer.as_mut()) {
+            writer.write(&batch)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W> Emitter for Arrow<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.batches.push(&line)?;
+        if self.batches.is_full() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+        if let Some(mut writer) = self.writer.take() {
+            writer.finish()?;
+            writer.into_inner()?.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// writes changes as a snappy compressed parquet file
+#[cfg(feature = "parquet")]
+pub struct Parquet<W>
+where
+    W: Write + Send,
+{
+    batches: Batches,
+    writer: Option<ArrowWriter<W>>,
+}
+
+#[cfg(feature = "parquet")]
+impl<W> Parquet<W>
+where
+    W: Write + Send,
+{
+    pub fn new(
+        write: W,
+        fields: Fields,
+    ) -> Result<Self, Box<dyn Error>> {
+        let batches = Batches::new(fields);
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = ArrowWriter::try_new(write, batches.schema(), Some(props))?;
+        Ok(Parquet {
+            batches,
+            writer: Some(writer),
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if let (Some(batch), Some(writer)) = (self.batches.take()?, self.writer.as_mut()) {
+            writer.write(&batch)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl<W> Emitter for Parquet<W>
+where
+    W: Write + Send,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.batches.push(&line)?;
+        if self.batches.is_full() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FIELDS;
+    use arrow_array::{cast::AsArray, types::TimestampMillisecondType};
+    use arrow_ipc::reader::StreamReader;
+    #[cfg(feature = "parquet")]
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    #[cfg(feature = "parquet")]
+    use std::{env, fs::File};
+
+    #[test]
+    fn data_matches_serialized_change() -> Result<(), Box<dyn Error>> {
+        let change = Change {
+            repo: "test".into(),
+            timestamp: "2019-08-09 15:36:50 -0400".into(),
+            timestamp_utc: Some("2019-08-09T19:36:50Z".into()),
+            epoch_seconds: Some(1_565_379_410),
+            old_path: Some("old.rs".into()),
+            renamed: true,
+            ext: Some("rs".into()),
+            category: crate::Category::Custom("fixtures".into()),
+            additions: Some(2),
+            deletions: Some(0),
+            binary: true,
+            dir: Some("src/bin".into()),
+            top_dir: Some("src".into()),
+            path_prefix: Some("src".into()),
+            subject: Some("subject".into()),
+            body: Some("body".into()),
+            author_raw: Some("luna@old.moon.com".into()),
+            author_canonical: Some("luna@moon.com".into()),
+            language: Some("Rust".into()),
+            commit_type: Some("feat".into()),
+            scope: Some("parser".into()),
+            breaking: true,
+            references: Some(vec!["#1".into()]),
+            committer: Some("sol@sun.com".into()),
+            commit_timestamp: Some("2019-08-10 09:00:00 -0400".into()),
+            co_authors: Some(vec!["terra@earth.com".into()]),
+            author_role: Some("author".into()),
+            owners: Some(vec!["@org/core".into()]),
+            author_user: Some("luna".into()),
+            author_domain: Some("moon.com".into()),
+            year: Some(2019),
+            year_month: Some("2019-08".into()),
+            iso_week: Some("2019-W32".into()),
+            weekday: Some("Friday".into()),
+            hour_utc: Some(19),
+            local_hour: Some(15),
+            local_weekday: Some("Friday".into()),
+            record_id: Some("4bd1a5a5".into()),
+            total_lines: Some(60),
+            pct_changed: Some(3.36),
+            canonical_path: Some("src/bin/new.rs".into()),
+            merge: true,
+            parents: Some(vec!["f00d".into()]),
+            tags: Some(vec!["v1.0".into()]),
+            branches: Some(vec!["main".into()]),
+            submodule: Some("vendor/lib".into()),
+            categories: vec![
+                crate::Category::Custom("fixtures".into()),
+                crate::Category::Test,
+            ],
+            ..Change::default()
+        };
+        let value = serde_json::to_value(&change)?;
+        for (name, _) in FIELDS {
+            let datum = datum(&change, name).ok_or_else(|| format!("missing {}", name))?;
+            let expected = &value[*name];
+            match datum {
+                Datum::Str(s) => assert_eq!(expected, s, "{}", name),
+                Datum::Int(n) => assert_eq!(expected, n, "{}", name),
+                Datum::Bool(b) => assert_eq!(expected, b, "{}", name),
+                Datum::Float(n) => assert_eq!(expected, n, "{}", name),
+                Datum::List(items) => assert_eq!(expected, &serde_json::json!(items), "{}", name),
+                Datum::Categories(categories) => {
+                    assert_eq!(expected, &serde_json::json!(categories), "{}", name)
+                }
+            }
+        }
+        let omitted = serde_json::to_value(Change::default())?;
+        for (name, _) in FIELDS {
+            assert_eq!(
+                datum(&Change::default(), name).is_none(),
+                omitted.get(name).is_none_or(|value| value.is_null()),
+                "{}",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn arrow_streams_record_batches() -> Result<(), Box<dyn Error>> {
+        let mut buf = Vec::new();
+        {
+            let mut arrow = Arrow::new(&mut buf, FIELDS.to_vec())?;
+            for path in &["a.rs", "b.rs"] {
+                arrow.emit(Change {
+                    timestamp: "2019-08-09 15:36:50 -0400".into(),
+                    path: path.to_string(),
+                    ..Change::default()
+                })?;
+            }
+            arrow.finish()?;
+        }
+        let batches =
+            StreamReader::try_new(buf.as_slice(), None)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(batches.len(), 1);
+        let paths = batches[0]
+            .column_by_name("path")
+            .ok_or("missing path")?
+            .as_string::<i32>();
+        assert_eq!(paths.value(1), "b.rs");
+        assert_eq!(
+            batches[0]
+                .column_by_name("timestamp")
+                .ok_or("missing timestamp")?
+                .as_primitive::<TimestampMillisecondType>()
+                .value(1),
+            1_565_379_410_000
+        );
+        assert!(batches[0]
+            .column_by_name("renamed")
+            .ok_or("missing renamed")?
+            .is_null(0));
+        Ok(())
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_round_trips_typed_columns() -> Result<(), Box<dyn Error>> {
+        let path = env::temp_dir().join(format!("git-linecat-{}.parquet", std::process::id()));
+        let mut parquet = Parquet::new(File::create(&path)?, FIELDS.to_vec())?;
+        parquet.emit(Change {
+            timestamp: "2019-08-09 15:36:50 -0400".into(),
+            path: "Makefile".into(),
+            additions: Some(3),
+            references: Some(vec!["#1".into(), "GH-2".into()]),
+            ..Change::default()
+        })?;
+        parquet.finish()?;
+
+        let batches = ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?
+            .build()?
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 1);
+        assert_eq!(
+            batch
+                .column_by_name("timestamp")
+                .ok_or("missing timestamp")?
+                .as_primitive::<TimestampMillisecondType>()
+                .value(0),
+            1_565_379_410_000
+        );
+        assert!(batch.column_by_name("ext").ok_or("missing ext")?.is_null(0));
+        let references = batch
+            .column_by_name("references")
+            .ok_or("missing references")?
+            .as_list::<i32>()
+            .value(0);
+        assert_eq!(references.as_string::<i32>().value(1), "GH-2");
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_timestamps_are_rejected() {
+        let mut batches = Batches::new(FIELDS.to_vec());
+        assert!(batches
+            .push(&Change {
+                timestamp: "yesterday".into(),
+                ..Change::default()
+            })
+            .is_err());
+    }
+}
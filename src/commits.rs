@@ -0,0 +1,384 @@
+//! folds the changes of each commit into a single record of totals,
+//! for commit level rather than per path analysis, or nests them in
+//! a single document per commit
+use crate::{
+    format::{write_records, Format, Projection},
+    Change, Emitter, Fields,
+};
+use serde_json::{json, Map, Value};
+use std::{collections::BTreeMap, error::Error, io::Write, str::FromStr};
+
+/// what each emitted record describes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Granularity {
+    /// a path changed by a commit
+    File,
+    Commit,
+}
+
+impl FromStr for Granularity {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(Granularity::File),
+            "commit" => Ok(Granularity::Commit),
+            other => Err(format!("unsupported granularity {}", other)),
+        }
+    }
+}
+
+/// fields describing a single path, which commit records total or nest instead
+const PATH_FIELDS: &[&str] = &[
+    "path",
+    "old_path",
+    "renamed",
+    "ext",
+    "category",
+    "categories",
+    "additions",
+    "deletions",
+    "binary",
+    "dir",
+    "top_dir",
+    "path_prefix",
+    "language",
+    "owners",
+    "record_id",
+    "total_lines",
+    "pct_changed",
+    "canonical_path",
+];
+
+/// columns following a commit's own fields
+const TOTALS: &[&str] = &["files", "additions", "deletions", "categories"];
+
+#[derive(Default)]
+struct Totals {
+    files: usize,
+    additions: usize,
+    deletions: usize,
+}
+
+impl Totals {
+    fn add(
+        &mut self,
+        change: &Change,
+    ) {
+        self.files += 1;
+        self.additions += change.additions.unwrap_or_default();
+        self.deletions += change.deletions.unwrap_or_default();
+    }
+}
+
+/// the commit whose changes are being folded
+struct Commit {
+    /// the commit's own fields, taken from its first change
+    fields: Map<String, Value>,
+    totals: Totals,
+    categories: BTreeMap<String, Totals>,
+}
+
+/// emits a record per commit with its total files, additions and deletions, and
+/// those totals per category. changes of a commit are expected to arrive together
+pub struct Commits<W>
+where
+    W: Write,
+{
+    columns: Vec<&'static str>,
+    format: Format,
+    write: Option<W>,
+    commit: Option<Commit>,
+    records: Vec<Map<String, Value>>,
+}
+
+impl<W> Commits<W>
+where
+    W: Write,
+{
+    pub fn new(
+        fields: Fields,
+        format: Format,
+        write: W,
+    ) -> Self {
+        Commits {
+            columns: fields
+                .into_iter()
+                .map(|(name, _)| name)
+                .filter(|name| !PATH_FIELDS.contains(name))
+                .chain(TOTALS.iter().copied())
+                .collect(),
+            format,
+            write: Some(write),
+            commit: None,
+            records: Vec::new(),
+        }
+    }
+
+    fn fold(&mut self) {
+        let Commit {
+            mut fields,
+            totals,
+            categories,
+        } = match self.commit.take() {
+            Some(commit) => commit,
+            _ => return,
+        };
+        let categories = categories
+            .into_iter()
+            .map(|(category, totals)| {
+                (
+                    category,
+                    json!({
+                        "files": totals.files,
+                        "additions": totals.additions,
+                        "deletions": totals.deletions,
+                    }),
+                )
+            })
+            .collect::<Map<_, _>>();
+        fields.insert("files".into(), totals.files.into());
+        fields.insert("additions".into(), totals.additions.into());
+        fields.insert("deletions".into(), totals.deletions.into());
+        fields.insert("categories".into(), categories.into());
+        let record = self
+            .columns
+            .iter()
+            .map(|column| {
+                let value = fields.remove(*column).unwrap_or(Value::Null);
+                (column.to_string(), value)
+            })
+            .collect();
+        self.records.push(record);
+    }
+}
+
+impl<W> Emitter for Commits<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let same = self.commit.as_ref().is_some_and(|commit| {
+            commit.fields.get("repo") == Some(&Value::from(&line.repo[..]))
+                && commit.fields.get("sha") == Some(&Value::from(&line.sha[..]))
+        });
+        if !same {
+            self.fold();
+            let fields = match serde_json::to_value(&line)? {
+                Value::Object(fields) => fields,
+                _ => return Err("expected changes to serialize as objects".into()),
+            };
+            self.commit = Some(Commit {
+                fields,
+                totals: Totals::default(),
+                categories: BTreeMap::new(),
+            });
+        }
+        if let Some(commit) = self.commit.as_mut() {
+            commit.totals.add(&line);
+            commit
+                .categories
+                .entry(line.category.to_string())
+                .or_default()
+                .add(&line);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let write = match self.write.take() {
+            Some(write) => write,
+            _ => return Ok(()),
+        };
+        self.fold();
+        let records = std::mem::take(&mut self.records);
+        write_records(&self.format, write, &self.columns, records)
+    }
+}
+
+/// the commit whose changes are being nested
+struct Nested {
+    repo: String,
+    sha: String,
+    /// the commit's own fields, taken from its first change
+    fields: Map<String, Value>,
+    files: Vec<Value>,
+}
+
+/// emits newline delimited json of a document per commit, its own fields alongside a
+/// `files` array of those of each path it changed, for document stores which prefer
+/// them nested. changes of a commit are expected to arrive together
+pub struct Nest<W>
+where
+    W: Write,
+{
+    write: W,
+    commit: Projection,
+    file: Projection,
+    nested: Option<Nested>,
+}
+
+impl<W> Nest<W>
+where
+    W: Write,
+{
+    pub fn new(
+        write: W,
+        projection: Projection,
+    ) -> Self {
+        let (file, commit) = projection.partition(PATH_FIELDS);
+        Nest {
+            write,
+            commit,
+            file,
+            nested: None,
+        }
+    }
+
+    fn write_nested(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(Nested {
+            mut fields, files, ..
+        }) = self.nested.take()
+        {
+            fields.insert("files".into(), files.into());
+            serde_json::to_writer(&mut self.write, &fields)?;
+            self.write.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl<W> Emitter for Nest<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let file = self.file.project(&line)?.into();
+        match &mut self.nested {
+            Some(nested) if nested.repo == line.repo && nested.sha == line.sha => {
+                nested.files.push(file)
+            }
+            _ => {
+                self.write_nested()?;
+                let fields = self.commit.project(&line)?;
+                self.nested = Some(Nested {
+                    repo: line.repo,
+                    sha: line.sha,
+                    fields,
+                    files: vec![file],
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        // the commit arriving may have more changes to come, so is left pending
+        self.write.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.write_nested()?;
+        Emitter::flush(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Category, FIELDS};
+
+    fn change(
+        sha: &str,
+        category: Category,
+        additions: usize,
+    ) -> Change {
+        Change {
+            repo: "test".into(),
+            sha: sha.into(),
+            author: "luna@moon.com".into(),
+            category,
+            additions: Some(additions),
+            deletions: Some(1),
+            ..Change::default()
+        }
+    }
+
+    fn commits(format: Format) -> Result<String, Box<dyn Error>> {
+        let mut buf = Vec::new();
+        {
+            let mut commits = Commits::new(FIELDS[..13].to_vec(), format, &mut buf);
+            for line in [
+                change("a", Category::Test, 2),
+                change("a", Category::Docs, 3),
+                change("a", Category::Test, 4),
+                change("b", Category::Default, 5),
+            ] {
+                commits.emit(line)?;
+            }
+            commits.finish()?;
+        }
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn folds_changes_into_commits() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            commits(Format::Json)?,
+            "{\"repo\":\"test\",\"sha\":\"a\",\"author\":\"luna@moon.com\",\"timestamp\":\"\",\"files\":3,\"additions\":9,\"deletions\":3,\
+             \"categories\":{\"docs\":{\"files\":1,\"additions\":3,\"deletions\":1},\"test\":{\"files\":2,\"additions\":6,\"deletions\":2}}}\n\
+             {\"repo\":\"test\",\"sha\":\"b\",\"author\":\"luna@moon.com\",\"timestamp\":\"\",\"files\":1,\"additions\":5,\"deletions\":1,\
+             \"categories\":{\"default\":{\"files\":1,\"additions\":5,\"deletions\":1}}}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn nests_files_in_commits() -> Result<(), Box<dyn Error>> {
+        let mut nest = Nest::new(
+            Vec::new(),
+            Projection::new(
+                &[FIELDS[0], FIELDS[1], FIELDS[6], FIELDS[11]].to_vec(),
+                &["path=file".parse()?],
+            ),
+        );
+        for (sha, path, additions) in [
+            ("a", "src/lib.rs", 2),
+            ("a", "README.md", 3),
+            ("b", "src/lib.rs", 4),
+        ] {
+            nest.emit(Change {
+                path: path.into(),
+                ..change(sha, Category::Default, additions)
+            })?;
+        }
+        nest.finish()?;
+        assert_eq!(
+            String::from_utf8(nest.write)?,
+            "{\"repo\":\"test\",\"sha\":\"a\",\"files\":[{\"file\":\"src/lib.rs\",\"additions\":2},{\"file\":\"README.md\",\"additions\":3}]}\n\
+             {\"repo\":\"test\",\"sha\":\"b\",\"files\":[{\"file\":\"src/lib.rs\",\"additions\":4}]}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn csv_commits_encode_categories_as_json() -> Result<(), Box<dyn Error>> {
+        let csv = commits(Format::Csv)?;
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("repo,sha,author,timestamp,timestamp_utc,epoch_seconds,files,additions,deletions,categories")
+        );
+        assert_eq!(
+            lines.nth(1),
+            Some("test,b,luna@moon.com,,,,1,5,1,\"{\"\"default\"\":{\"\"files\"\":1,\"\"additions\"\":5,\"\"deletions\"\":1}}\"")
+        );
+        Ok(())
+    }
+}
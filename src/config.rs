@@ -0,0 +1,228 @@
+//! default flags shared through a toml config file, by default
+//! `~/.config/git-linecat/config.toml`. top level keys are flags given to every
+//! invocation and each `[profiles.NAME]` table adds to or overrides them when
+//! selected with `--profile NAME`
+//!
+//! ```toml
+//! output-format = "csv"
+//! rules = "/etc/git-linecat/rules.yml"
+//! exclude-bots = true
+//! exclude-author = ["release@example.com", "deploy@example.com"]
+//!
+//! [profiles.warehouse]
+//! output-format = "json"
+//! output = "postgres://linecat@db.example.com/changes"
+//! ```
+//!
+//! flags naming outputs and their secrets may instead be given by `GIT_LINECAT_*`
+//! variables, which flags of the command line take precedence over and which take
+//! precedence over the config file. only those of [`VARIABLES`] have one: the users
+//! and passwords of postgres and clickhouse outputs travel in their uri, so in
+//! `GIT_LINECAT_OUTPUT`, while s3 and gcs outputs read their keys, region and endpoint
+//! from the standard `AWS_*` variables alone
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use toml::{Table, Value};
+
+pub struct Config {
+    defaults: Table,
+    profiles: Table,
+}
+
+/// where the config file is looked for, following the xdg base directory spec
+pub fn path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("git-linecat").join("config.toml"))
+}
+
+/// flags which may be given by a variable instead, keeping secrets out of process listings
+pub const VARIABLES: &[&str] = &["output", "webhook-header", "metrics"];
+
+/// the variable a flag may be given by, as `GIT_LINECAT_OUTPUT` for `--output`
+pub fn variable(flag: &str) -> String {
+    format!("GIT_LINECAT_{}", flag.to_uppercase().replace('-', "_"))
+}
+
+/// the values of the variable of a flag, one per line, if it's set
+pub fn vars<T>(flag: &str) -> Result<Vec<T>, Box<dyn Error>>
+where
+    T: FromStr<Err = String>,
+{
+    let name = variable(flag);
+    match env::var(&name) {
+        Ok(value) => value
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.parse()
+                    .map_err(|e| format!("invalid {}: {}", name, e).into())
+            })
+            .collect(),
+        Err(env::VarError::NotPresent) => Ok(Vec::new()),
+        Err(e) => Err(format!("invalid {}: {}", name, e).into()),
+    }
+}
+
+/// the value of the variable of a flag, if it's set
+pub fn var<T>(flag: &str) -> Result<Option<T>, Box<dyn Error>>
+where
+    T: FromStr<Err = String>,
+{
+    Ok(vars(flag)?.pop())
+}
+
+/// whether a flag is given by its variable, leaving out that of the config file
+fn overridden(flag: &str) -> bool {
+    VARIABLES.contains(&flag) && env::var_os(variable(flag)).is_some_and(|v| !v.is_empty())
+}
+
+/// the profile named by a `--profile NAME` or `--profile=NAME` among command line args,
+/// which must be known before they're parsed
+pub fn profile(args: &[String]) -> Option<&str> {
+    args.iter()
+        .take_while(|arg| *arg != "--")
+        .enumerate()
+        .find_map(|(i, arg)| match arg.strip_prefix("--profile") {
+            Some("") => args.get(i + 1).map(String::as_str),
+            Some(rest) => rest.strip_prefix('='),
+            _ => None,
+        })
+}
+
+/// the args for a flag's value. lists repeat the flag and `false` leaves it out
+fn flag(
+    name: &str,
+    value: &Value,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    Ok(match value {
+        Value::Boolean(true) => vec![format!("--{}", name)],
+        Value::Boolean(false) => Vec::new(),
+        Value::String(s) => vec![format!("--{}={}", name, s)],
+        Value::Integer(i) => vec![format!("--{}={}", name, i)],
+        Value::Float(f) => vec![format!("--{}={}", name, f)],
+        Value::Array(values) => {
+            let mut args = Vec::new();
+            for value in values {
+                match value {
+                    Value::Array(_) | Value::Table(_) | Value::Boolean(_) => {
+                        return Err(format!("invalid value of {}: {}", name, value).into())
+                    }
+                    value => args.extend(flag(name, value)?),
+                }
+            }
+            args
+        }
+        other => return Err(format!("invalid value of {}: {}", name, other).into()),
+    })
+}
+
+impl Config {
+    /// loads the config file at a path, if there is one
+    pub fn discover(path: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read config {}: {}", path.display(), e))?;
+        Self::parse(&content)
+            .map(Some)
+            .map_err(|e| format!("invalid config {}: {}", path.display(), e).into())
+    }
+
+    fn parse(content: &str) -> Result<Self, Box<dyn Error>> {
+        let mut defaults: Table = content.parse()?;
+        let profiles = match defaults.remove("profiles") {
+            Some(Value::Table(profiles)) => profiles,
+            Some(_) => return Err("profiles must be a table of profiles".into()),
+            _ => Table::new(),
+        };
+        Ok(Config { defaults, profiles })
+    }
+
+    /// the flags the config gives, followed by those of a profile, which as later
+    /// args override them, as the command line's args override both. flags given
+    /// by their variables are left out
+    pub fn args(
+        &self,
+        profile: Option<&str>,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut args = Vec::new();
+        for (name, value) in self.defaults.iter().filter(|(name, _)| !overridden(name)) {
+            args.extend(flag(name, value)?);
+        }
+        if let Some(name) = profile {
+            match self.profiles.get(name) {
+                Some(Value::Table(flags)) => {
+                    for (name, value) in flags.iter().filter(|(name, _)| !overridden(name)) {
+                        args.extend(flag(name, value)?);
+                    }
+                }
+                Some(_) => return Err(format!("profile {} must be a table of flags", name).into()),
+                _ => return Err(format!("no profile named {}", name).into()),
+            }
+        }
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_follow_defaults() -> Result<(), Box<dyn Error>> {
+        let config = Config::parse(
+            "output-format = \"csv\"\n\
+             with-message = true\n\
+             progress = false\n\
+             exclude-author = [\"release@example.com\", \"deploy@example.com\"]\n\
+             [profiles.warehouse]\n\
+             output-format = \"json\"\n\
+             webhook-batch = 500\n",
+        )?;
+        assert_eq!(
+            config.args(None)?,
+            vec![
+                "--exclude-author=release@example.com",
+                "--exclude-author=deploy@example.com",
+                "--output-format=csv",
+                "--with-message"
+            ]
+        );
+        assert_eq!(
+            config.args(Some("warehouse"))?[4..],
+            ["--output-format=json", "--webhook-batch=500"]
+        );
+        assert_eq!(
+            config.args(Some("staging")).unwrap_err().to_string(),
+            "no profile named staging"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn profiles_are_named_before_parsing() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            profile(&args(&["git-linecat", "--profile", "ci", "-r", "api"])),
+            Some("ci")
+        );
+        assert_eq!(profile(&args(&["git-linecat", "--profile=ci"])), Some("ci"));
+        assert_eq!(profile(&args(&["git-linecat", "--", "--profile=ci"])), None);
+        assert_eq!(profile(&args(&["git-linecat", "--profiles"])), None);
+    }
+
+    #[test]
+    fn flags_are_named_as_variables() {
+        assert_eq!(variable("output"), "GIT_LINECAT_OUTPUT");
+        assert_eq!(variable("webhook-header"), "GIT_LINECAT_WEBHOOK_HEADER");
+    }
+}
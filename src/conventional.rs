@@ -0,0 +1,91 @@
+//! parses [conventional commit](https://www.conventionalcommits.org) subjects,
+//! as in `feat(parser): support renames` or `chore!: drop old toolchains`
+use regex::Regex;
+use std::sync::OnceLock;
+
+#[derive(Debug, PartialEq)]
+pub struct Conventional {
+    /// the lowercased type, as in `feat` or `fix`
+    pub commit_type: String,
+    pub scope: Option<String>,
+    /// marked with a `!` or declared by a `BREAKING CHANGE` footer
+    pub breaking: bool,
+}
+
+fn pattern() -> &'static Regex {
+    static SUBJECT: OnceLock<Regex> = OnceLock::new();
+    SUBJECT.get_or_init(|| {
+        Regex::new(r"^(?P<type>[A-Za-z]+)(?:\((?P<scope>[^()]+)\))?(?P<breaking>!)?: ")
+            .expect("conventional subject regex is valid")
+    })
+}
+
+/// parses a commit subject, and the body whose footers may declare breaking changes.
+/// subjects which aren't conventional are `None`
+pub fn parse(
+    subject: &str,
+    body: Option<&str>,
+) -> Option<Conventional> {
+    let captures = pattern().captures(subject)?;
+    let footer = body.is_some_and(|body| {
+        body.lines().any(|line| {
+            line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:")
+        })
+    });
+    Some(Conventional {
+        commit_type: captures["type"].to_lowercase(),
+        scope: captures.name("scope").map(|scope| scope.as_str().into()),
+        breaking: captures.name("breaking").is_some() || footer,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_conventional_subjects() {
+        assert_eq!(
+            parse("feat(parser): support renames", None),
+            Some(Conventional {
+                commit_type: "feat".into(),
+                scope: Some("parser".into()),
+                breaking: false,
+            })
+        );
+        assert_eq!(
+            parse("Fix: off by one", None),
+            Some(Conventional {
+                commit_type: "fix".into(),
+                scope: None,
+                breaking: false,
+            })
+        );
+        assert_eq!(
+            parse("chore!: drop old toolchains", None).map(|c| c.breaking),
+            Some(true)
+        );
+        assert_eq!(parse("update readme", None), None);
+        assert_eq!(parse("feat:missing space", None), None);
+    }
+
+    #[test]
+    fn footers_declare_breaking_changes() {
+        assert_eq!(
+            parse(
+                "refactor(api): rename fields",
+                Some("details\n\nBREAKING CHANGE: `user` is now `author`")
+            )
+            .map(|c| c.breaking),
+            Some(true)
+        );
+        assert_eq!(
+            parse(
+                "refactor(api): rename fields",
+                Some("not a BREAKING CHANGE: footer")
+            )
+            .map(|c| c.breaking),
+            Some(false)
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! finds paths which change together, the temporal coupling of paths modified in the
+//! same commits. commits touching many paths, such as reformats, are left out as they
+//! couple everything they touch
+use crate::{
+    format::{write_records, Format},
+    Change, Emitter,
+};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, error::Error, io::Write};
+
+const COLUMNS: &[&str] = &[
+    "path",
+    "coupled_path",
+    "co_changes",
+    "commits",
+    "coupled_commits",
+    "confidence",
+];
+
+/// buffers the paths of each commit, writing coupled pairs once all changes are seen
+pub struct Coupling<W>
+where
+    W: Write,
+{
+    min_support: usize,
+    min_confidence: f64,
+    max_files: usize,
+    format: Format,
+    write: Option<W>,
+    /// the repo, sha and paths of the commit whose changes are arriving.
+    /// changes of a commit arrive together
+    commit: Option<(String, String, Vec<String>)>,
+    commits: HashMap<String, usize>,
+    pairs: HashMap<(String, String), usize>,
+}
+
+impl<W> Coupling<W>
+where
+    W: Write,
+{
+    pub fn new(
+        min_support: usize,
+        min_confidence: f64,
+        max_files: usize,
+        format: Format,
+        write: W,
+    ) -> Self {
+        Coupling {
+            min_support,
+            min_confidence,
+            max_files,
+            format,
+            write: Some(write),
+            commit: None,
+            commits: HashMap::new(),
+            pairs: HashMap::new(),
+        }
+    }
+
+    fn fold(&mut self) {
+        let mut paths = match self.commit.take() {
+            Some((_, _, paths)) if paths.len() <= self.max_files => paths,
+            _ => return,
+        };
+        paths.sort();
+        // changes emitted once per co-author repeat their paths
+        paths.dedup();
+        for (i, path) in paths.iter().enumerate() {
+            *self.commits.entry(path.clone()).or_default() += 1;
+            for coupled in &paths[i + 1..] {
+                *self
+                    .pairs
+                    .entry((path.clone(), coupled.clone()))
+                    .or_default() += 1;
+            }
+        }
+    }
+}
+
+impl<W> Emitter for Coupling<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        // records of merges have no path to attribute
+        if line.merge {
+            return Ok(());
+        }
+        let path = line.canonical_path.unwrap_or(line.path);
+        match &mut self.commit {
+            Some((repo, sha, paths)) if *repo == line.repo && *sha == line.sha => paths.push(path),
+            _ => {
+                self.fold();
+                self.commit = Some((line.repo, line.sha, vec![path]));
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.fold();
+        let write = match self.write.take() {
+            Some(write) => write,
+            _ => return Ok(()),
+        };
+        let commits = std::mem::take(&mut self.commits);
+        let mut pairs = std::mem::take(&mut self.pairs)
+            .into_iter()
+            .filter(|(_, co_changes)| *co_changes >= self.min_support)
+            .map(|((path, coupled_path), co_changes)| {
+                let (path_commits, coupled_commits) = (commits[&path], commits[&coupled_path]);
+                // the share of commits to the less frequently changed path
+                // which also changed the other
+                let confidence = co_changes as f64 / path_commits.min(coupled_commits) as f64;
+                (
+                    path,
+                    coupled_path,
+                    co_changes,
+                    path_commits,
+                    coupled_commits,
+                    confidence,
+                )
+            })
+            .filter(|pair| pair.5 >= self.min_confidence)
+            .collect::<Vec<_>>();
+        pairs.sort_by(|a, b| {
+            b.2.cmp(&a.2)
+                .then_with(|| a.0.cmp(&b.0))
+                .then_with(|| a.1.cmp(&b.1))
+        });
+        let records = pairs
+            .into_iter()
+            .map(
+                |(path, coupled_path, co_changes, path_commits, coupled_commits, confidence)| {
+                    let values = vec![
+                        Value::String(path),
+                        Value::String(coupled_path),
+                        co_changes.into(),
+                        path_commits.into(),
+                        coupled_commits.into(),
+                        confidence.into(),
+                    ];
+                    COLUMNS
+                        .iter()
+                        .map(|column| column.to_string())
+                        .zip(values)
+                        .collect::<Map<_, _>>()
+                },
+            )
+            .collect();
+        write_records(&self.format, write, COLUMNS, records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(
+        sha: &str,
+        path: &str,
+    ) -> Change {
+        Change {
+            repo: "test".into(),
+            sha: sha.into(),
+            path: path.into(),
+            ..Change::default()
+        }
+    }
+
+    #[test]
+    fn finds_coupled_pairs() -> Result<(), Box<dyn Error>> {
+        let mut buf = Vec::new();
+        {
+            let mut coupling = Coupling::new(2, 0.5, 3, Format::Csv, &mut buf);
+            for line in [
+                change("sha1", "src/lib.rs"),
+                change("sha1", "src/main.rs"),
+                change("sha2", "src/lib.rs"),
+                change("sha2", "src/main.rs"),
+                change("sha2", "README.md"),
+                change("sha3", "src/lib.rs"),
+                change("sha3", "README.md"),
+                change("sha4", "src/lib.rs"),
+                change("sha5", "src/lib.rs"),
+                change("sha5", "src/main.rs"),
+                change("sha5", "README.md"),
+                change("sha5", "Makefile"),
+            ] {
+                coupling.emit(line)?;
+            }
+            coupling.finish()?;
+        }
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "path,coupled_path,co_changes,commits,coupled_commits,confidence\n\
+             README.md,src/lib.rs,2,2,4,1.0\n\
+             src/lib.rs,src/main.rs,2,4,2,1.0\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+//! drops changes already emitted within a run, as when overlapping
+//! windows of history are read together
+use crate::{format::id, Change, Emitter};
+use std::{collections::HashSet, error::Error};
+
+/// passes on only the first change of each `record_id`, reporting how many
+/// duplicates were dropped once finished
+pub struct Dedupe<E> {
+    pub emitter: E,
+    seen: HashSet<String>,
+    dropped: usize,
+}
+
+impl<E> Dedupe<E> {
+    pub fn new(emitter: E) -> Self {
+        Dedupe {
+            emitter,
+            seen: HashSet::new(),
+            dropped: 0,
+        }
+    }
+}
+
+impl<E> Emitter for Dedupe<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let record_id = line.record_id.clone().unwrap_or_else(|| id(&line));
+        if !self.seen.insert(record_id) {
+            self.dropped += 1;
+            return Ok(());
+        }
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.dropped > 0 {
+            tracing::warn!(dropped = self.dropped, "dropped duplicate changes");
+        }
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collect;
+
+    fn change(
+        sha: &str,
+        path: &str,
+    ) -> Change {
+        let mut change = Change {
+            repo: "test".into(),
+            sha: sha.into(),
+            path: path.into(),
+            ..Change::default()
+        };
+        change.record_id = Some(id(&change));
+        change
+    }
+
+    #[test]
+    fn drops_duplicate_changes() -> Result<(), Box<dyn Error>> {
+        let mut dedupe = Dedupe::new(Collect::default());
+        for line in [
+            change("a", "src/lib.rs"),
+            change("a", "src/main.rs"),
+            change("b", "src/lib.rs"),
+            change("a", "src/lib.rs"),
+        ] {
+            dedupe.emit(line)?;
+        }
+        dedupe.finish()?;
+        assert_eq!(dedupe.dropped, 1);
+        assert_eq!(
+            dedupe
+                .emitter
+                .0
+                .iter()
+                .map(|line| (&line.sha[..], &line.path[..]))
+                .collect::<Vec<_>>(),
+            vec![
+                ("a", "src/lib.rs"),
+                ("a", "src/main.rs"),
+                ("b", "src/lib.rs")
+            ]
+        );
+        Ok(())
+    }
+}
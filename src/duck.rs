@@ -0,0 +1,160 @@
+//! writes changes into a `changes` table of a duckdb database, through its appender
+use crate::{parse_timestamp, Change, Emitter, Fields, Kind};
+use duckdb::{
+    appender_params_from_iter,
+    types::{TimeUnit, Value as Sql},
+    Connection,
+};
+use serde_json::Value;
+use std::{error::Error, mem, path::Path};
+
+/// rows appended at once
+const BATCH_SIZE: usize = 10_000;
+
+fn column_type(kind: Kind) -> &'static str {
+    match kind {
+        // lists are stored as json arrays, as the appender can't append lists
+        Kind::String | Kind::List => "VARCHAR",
+        Kind::Timestamp => "TIMESTAMP",
+        Kind::Integer => "BIGINT",
+        Kind::Boolean => "BOOLEAN",
+        Kind::Float => "DOUBLE",
+    }
+}
+
+/// converts a field value to its sql representation. timestamps are stored in utc
+fn sql(
+    kind: Kind,
+    value: Option<&Value>,
+) -> Result<Sql, Box<dyn Error>> {
+    Ok(match (kind, value) {
+        (_, None) | (_, Some(Value::Null)) => Sql::Null,
+        (Kind::Timestamp, Some(Value::String(s))) => Sql::Timestamp(
+            TimeUnit::Microsecond,
+            parse_timestamp(s)?.timestamp_micros(),
+        ),
+        (_, Some(Value::String(s))) => Sql::Text(s.clone()),
+        (_, Some(Value::Bool(b))) => Sql::Boolean(*b),
+        (_, Some(Value::Number(n))) => n
+            .as_i64()
+            .map(Sql::BigInt)
+            .or_else(|| n.as_f64().map(Sql::Double))
+            .unwrap_or(Sql::Null),
+        (_, Some(other)) => Sql::Text(other.to_string()),
+    })
+}
+
+pub struct DuckDb {
+    conn: Connection,
+    fields: Fields,
+    /// rows yet to be appended
+    pending: Vec<Vec<Sql>>,
+}
+
+impl DuckDb {
+    pub fn open(
+        path: &Path,
+        fields: Fields,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new(Connection::open(path)?, fields)
+    }
+
+    /// creates the `changes` table, if it doesn't already exist. column names
+    /// are quoted as some, like `references`, are sql keywords
+    pub fn new(
+        conn: Connection,
+        fields: Fields,
+    ) -> Result<Self, Box<dyn Error>> {
+        let columns = fields
+            .iter()
+            .map(|(name, kind)| format!("\"{}\" {}", name, column_type(*kind)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS changes ({});",
+            columns
+        ))?;
+        Ok(DuckDb {
+            conn,
+            fields,
+            pending: Vec::new(),
+        })
+    }
+
+    pub(crate) fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    fn append(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let columns: Vec<&str> = self.fields.iter().map(|(name, _)| *name).collect();
+        let mut appender = self.conn.appender_with_columns("changes", &columns)?;
+        appender.append_rows(
+            mem::take(&mut self.pending)
+                .into_iter()
+                .map(appender_params_from_iter),
+        )?;
+        appender.flush()?;
+        Ok(())
+    }
+}
+
+impl Emitter for DuckDb {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let value = serde_json::to_value(&line)?;
+        let row = self
+            .fields
+            .iter()
+            .map(|(name, kind)| sql(*kind, value.get(name)))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.pending.push(row);
+        if self.pending.len() >= BATCH_SIZE {
+            self.append()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.append()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.append()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FIELDS;
+
+    #[test]
+    fn duckdb_appends_changes() -> Result<(), Box<dyn Error>> {
+        let mut duckdb = DuckDb::new(Connection::open_in_memory()?, FIELDS.to_vec())?;
+        duckdb.emit(Change {
+            repo: "test".into(),
+            timestamp: "2019-08-09 15:36:50 -0400".into(),
+            path: "Makefile".into(),
+            additions: Some(3),
+            references: Some(vec!["#1".into()]),
+            ..Change::default()
+        })?;
+        duckdb.finish()?;
+        let (timestamp, ext, additions, references): (String, Option<String>, i64, String) =
+            duckdb.conn.query_row(
+                "SELECT CAST(timestamp AS VARCHAR), ext, additions, \"references\" FROM changes WHERE repo = 'test'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )?;
+        assert_eq!(timestamp, "2019-08-09 19:36:50");
+        assert_eq!(ext, None);
+        assert_eq!(additions, 3);
+        assert_eq!(references, r##"["#1"]"##);
+        Ok(())
+    }
+}
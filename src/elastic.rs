@@ -0,0 +1,246 @@
+//! writes changes straight to an elasticsearch or opensearch index through its
+//! `_bulk` api, retrying with backoff when the cluster pushes back
+use crate::{format::id, Change, Emitter};
+use serde_json::{json, Value};
+use std::{error::Error, mem, ops::Range, thread, time::Duration};
+use ureq::{Agent, AgentBuilder};
+
+/// documents sent per `_bulk` request
+const BATCH_SIZE: usize = 500;
+
+/// requests made for a batch before giving up on documents that keep being rejected
+const ATTEMPTS: u32 = 6;
+
+/// the delay before the first retry, doubling with each attempt after
+const BACKOFF: Duration = Duration::from_millis(500);
+
+/// whether a response status is worth retrying, as when the cluster is overloaded
+fn retryable(status: u16) -> bool {
+    status == 429 || status >= 500
+}
+
+/// indexes changes into an index by their [`id`], so re-runs replace documents
+pub struct Elasticsearch {
+    agent: Agent,
+    /// the `_bulk` endpoint of the cluster
+    endpoint: String,
+    index: String,
+    /// action and document line pairs yet to be sent, serialized in place
+    /// into a buffer reused between batches
+    buf: Vec<u8>,
+    /// the range of `buf` each pending pair spans
+    pending: Vec<Range<usize>>,
+    /// the body of each request, reused between requests
+    body: Vec<u8>,
+    backoff: Duration,
+}
+
+impl Elasticsearch {
+    /// a cluster at a base `url`, as in `http://localhost:9200`
+    pub fn new(
+        url: &str,
+        index: String,
+    ) -> Self {
+        Elasticsearch {
+            agent: AgentBuilder::new().timeout(Duration::from_secs(60)).build(),
+            endpoint: format!("{}/_bulk", url.trim_end_matches('/')),
+            index,
+            buf: Vec::new(),
+            pending: Vec::new(),
+            body: Vec::new(),
+            backoff: BACKOFF,
+        }
+    }
+
+    /// sends pending documents, keeping their buffer for the next batch
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        let pending = mem::take(&mut self.pending);
+        let sent = self.send(pending);
+        self.buf.clear();
+        sent
+    }
+
+    /// sends documents, resubmitting only those which failed transiently
+    fn send(
+        &mut self,
+        mut pending: Vec<Range<usize>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut delay = self.backoff;
+        for attempt in 1..=ATTEMPTS {
+            if pending.is_empty() {
+                return Ok(());
+            }
+            self.body.clear();
+            for document in &pending {
+                self.body.extend_from_slice(&self.buf[document.clone()]);
+            }
+            let response = self
+                .agent
+                .post(&self.endpoint)
+                .set("Content-Type", "application/x-ndjson")
+                .send_bytes(&self.body);
+            pending = match response {
+                Ok(response) => {
+                    let reply: Value = serde_json::from_str(&response.into_string()?)?;
+                    if reply["errors"].as_bool() != Some(true) {
+                        return Ok(());
+                    }
+                    let items = reply["items"].as_array().cloned().unwrap_or_default();
+                    let mut retry = Vec::new();
+                    for (document, item) in pending.into_iter().zip(items) {
+                        let result = &item["index"];
+                        match result["status"].as_u64().unwrap_or_default() as u16 {
+                            status if retryable(status) => retry.push(document),
+                            status if status >= 300 => {
+                                return Err(format!(
+                                    "failed to index {}: {}",
+                                    result["_id"], result["error"]
+                                )
+                                .into())
+                            }
+                            _ => (),
+                        }
+                    }
+                    retry
+                }
+                Err(ureq::Error::Status(status, _)) if retryable(status) => pending,
+                // connections refused or reset while the cluster restarts
+                Err(ureq::Error::Transport(_)) if attempt < ATTEMPTS => pending,
+                Err(e) => return Err(format!("failed to write to elasticsearch: {}", e).into()),
+            };
+            if !pending.is_empty() && attempt < ATTEMPTS {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+        Err(format!(
+            "gave up indexing {} documents into {} after {} attempts",
+            pending.len(),
+            self.index,
+            ATTEMPTS
+        )
+        .into())
+    }
+}
+
+impl Emitter for Elasticsearch {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let start = self.buf.len();
+        let action = json!({ "index": { "_index": self.index, "_id": id(&line) } });
+        serde_json::to_writer(&mut self.buf, &action)?;
+        self.buf.push(b'\n');
+        serde_json::to_writer(&mut self.buf, &line)?;
+        self.buf.push(b'\n');
+        self.pending.push(start..self.buf.len());
+        if self.pending.len() >= BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    /// serves canned responses to requests in order, sending back each request's body
+    fn serve(
+        responses: Vec<(u16, &'static str)>
+    ) -> Result<(String, mpsc::Receiver<String>), Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for ((status, body), stream) in responses.into_iter().zip(listener.incoming()) {
+                let mut stream = BufReader::new(stream.expect("connection"));
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).expect("request line");
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().expect("content length");
+                        }
+                    }
+                }
+                let mut request = vec![0; length];
+                stream.read_exact(&mut request).expect("request body");
+                drop(sender.send(String::from_utf8_lossy(&request).into_owned()));
+                write!(
+                    stream.get_mut(),
+                    "HTTP/1.1 {} OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .expect("response");
+            }
+        });
+        Ok((url, receiver))
+    }
+
+    fn change(path: &str) -> Change {
+        Change {
+            repo: "test".into(),
+            sha: "sha".into(),
+            path: path.into(),
+            ..Change::default()
+        }
+    }
+
+    #[test]
+    fn retries_rejected_documents() -> Result<(), Box<dyn Error>> {
+        let (url, requests) = serve(vec![
+            (429, r#"{"error":"too many requests"}"#),
+            (
+                200,
+                r#"{"errors":true,"items":[{"index":{"status":201}},{"index":{"status":429}}]}"#,
+            ),
+            (
+                200,
+                r#"{"errors":false,"items":[{"index":{"status":201}}]}"#,
+            ),
+        ])?;
+        let mut elasticsearch = Elasticsearch::new(&url, "churn".into());
+        elasticsearch.backoff = Duration::from_millis(1);
+        elasticsearch.emit(change("a.rs"))?;
+        elasticsearch.emit(change("b.rs"))?;
+        elasticsearch.finish()?;
+        let bodies = requests.try_iter().collect::<Vec<_>>();
+        assert_eq!(bodies.len(), 3);
+        assert_eq!(bodies[0].lines().count(), 4);
+        assert_eq!(bodies[0], bodies[1]);
+        assert_eq!(bodies[2].lines().count(), 2);
+        assert!(bodies[2].contains("b.rs"));
+        assert!(bodies[2].contains(r#""_index":"churn""#));
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_rejected_documents() -> Result<(), Box<dyn Error>> {
+        let (url, _) = serve(vec![(
+            200,
+            r#"{"errors":true,"items":[{"index":{"_id":"a","status":400,"error":"mapper_parsing_exception"}}]}"#,
+        )])?;
+        let mut elasticsearch = Elasticsearch::new(&url, "churn".into());
+        elasticsearch.emit(change("a.rs"))?;
+        let err = elasticsearch.finish().unwrap_err();
+        assert!(err.to_string().contains("mapper_parsing_exception"));
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! errors which tell bad input apart from unavailable sinks, so a pipeline's retry
+//! policy may act on the exit code of a failed run. they're carried as the
+//! `Box<dyn Error>`s everything else is, and recovered by [`exit_code`]
+use crate::{Change, Emitter};
+use std::{error::Error, fmt, io};
+
+/// exit codes, following sysexits.h
+pub const EX_DATAERR: i32 = 65;
+pub const EX_UNAVAILABLE: i32 = 69;
+pub const EX_IOERR: i32 = 74;
+
+#[derive(Debug)]
+pub enum LinecatError {
+    /// a source couldn't be read
+    Io { origin: String, source: io::Error },
+    /// a line where a commit header was expected, when parsing strictly
+    ParseHeader {
+        origin: String,
+        line: usize,
+        found: String,
+    },
+    /// a line where a path or commit header was expected, when parsing strictly
+    ParsePath {
+        origin: String,
+        line: usize,
+        found: String,
+    },
+    /// changes couldn't be written to a sink
+    Emit { sink: String, reason: String },
+}
+
+impl LinecatError {
+    /// an error writing to `sink`
+    pub fn emit(
+        sink: impl fmt::Display,
+        error: impl fmt::Display,
+    ) -> Self {
+        LinecatError::Emit {
+            sink: sink.to_string(),
+            reason: error.to_string(),
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            LinecatError::Io { .. } => EX_IOERR,
+            LinecatError::ParseHeader { .. } | LinecatError::ParsePath { .. } => EX_DATAERR,
+            LinecatError::Emit { .. } => EX_UNAVAILABLE,
+        }
+    }
+}
+
+impl fmt::Display for LinecatError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            LinecatError::Io { origin, source } => {
+                write!(f, "failed to read {}: {}", origin, source)
+            }
+            LinecatError::ParseHeader {
+                origin,
+                line,
+                found,
+            } => write!(
+                f,
+                "{}:{}: expected a commit header but found {:?}",
+                origin, line, found
+            ),
+            LinecatError::ParsePath {
+                origin,
+                line,
+                found,
+            } => write!(
+                f,
+                "{}:{}: expected a path or commit header but found {:?}",
+                origin, line, found
+            ),
+            LinecatError::Emit { sink, reason } => {
+                write!(f, "failed to write to {}: {}", sink, reason)
+            }
+        }
+    }
+}
+
+impl Error for LinecatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LinecatError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// the code a run failing with `error` exits with, 1 for errors of no particular kind
+pub fn exit_code(error: &(dyn Error + 'static)) -> i32 {
+    match error.downcast_ref::<LinecatError>() {
+        Some(error) => error.exit_code(),
+        _ if error.is::<io::Error>() => EX_IOERR,
+        _ => 1,
+    }
+}
+
+/// blames the errors of the emitter writing to a sink on the sink, as
+/// [`LinecatError::Emit`]s
+pub struct Blame<E> {
+    pub sink: String,
+    pub emitter: E,
+}
+
+impl<E> Blame<E> {
+    fn blame(
+        &self,
+        result: Result<(), Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        result.map_err(|e| match e.downcast::<LinecatError>() {
+            Ok(e) => e as Box<dyn Error>,
+            Err(e) => LinecatError::emit(&self.sink, e).into(),
+        })
+    }
+}
+
+impl<E> Emitter for Blame<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let result = self.emitter.emit(line);
+        self.blame(result)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        let result = self.emitter.flush();
+        self.blame(result)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let result = self.emitter.finish();
+        self.blame(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Unavailable;
+
+    impl Emitter for Unavailable {
+        fn emit(
+            &mut self,
+            _: Change,
+        ) -> Result<(), Box<dyn Error>> {
+            Err("connection refused".into())
+        }
+    }
+
+    #[test]
+    fn blames_sinks_for_their_errors() {
+        let mut blame = Blame {
+            sink: "kafka topic changes".into(),
+            emitter: Unavailable,
+        };
+        let err = blame.emit(Change::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to write to kafka topic changes: connection refused"
+        );
+        assert_eq!(exit_code(&*err), EX_UNAVAILABLE);
+        assert_eq!(blame.finish().ok(), Some(()));
+    }
+
+    #[test]
+    fn errors_of_no_kind_exit_with_one() {
+        let err: Box<dyn Error> = "--nest may only be written as json".into();
+        assert_eq!(exit_code(&*err), 1);
+        let err: Box<dyn Error> = io::Error::from(io::ErrorKind::BrokenPipe).into();
+        assert_eq!(exit_code(&*err), EX_IOERR);
+    }
+}
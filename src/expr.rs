@@ -0,0 +1,889 @@
+//! a small jq-like expression language evaluated against the json of each change,
+//! as in `.additions + .deletions > 100`, selecting changes with `--select` and
+//! assigning their fields with `--map`, as in `.ext = (.ext // "none")`
+use crate::{Change, Emitter};
+use regex::Regex;
+use serde_json::{Map as Object, Number, Value};
+use std::{borrow::Cow, cmp::Ordering, convert::TryFrom, error::Error, str::FromStr};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Clone, Debug)]
+pub enum Function {
+    Length,
+    Not,
+    AsciiDowncase,
+    AsciiUpcase,
+    ToString,
+    ToNumber,
+    /// a regex, given as a literal so it's compiled once
+    Test(Regex),
+    StartsWith(Box<Expr>),
+    EndsWith(Box<Expr>),
+    Contains(Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Literal(Value),
+    /// `[a, b]`
+    Array(Vec<Expr>),
+    /// `.`, the value piped in
+    Identity,
+    /// `.name` of a value
+    Field(Box<Expr>, String),
+    /// `[index]` of an array or `["name"]` of an object
+    Index(Box<Expr>, Box<Expr>),
+    /// `a | b`, evaluating `b` against the value of `a`
+    Pipe(Box<Expr>, Box<Expr>),
+    /// `a // b`, `b` when `a` is false, null or fails
+    Alternative(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Negate(Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    Call(Function),
+    /// `.name = value`, evaluating the value against the whole record
+    Assign(String, Box<Expr>),
+    /// `.name |= value`, evaluating the value against the field
+    Update(String, Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// `.name`
+    Field(String),
+    Dot,
+    Ident(String),
+    Literal(Value),
+    Symbol(&'static str),
+}
+
+/// longer symbols first, so `|=` isn't read as `|`
+const SYMBOLS: &[&str] = &[
+    "|=", "//", "==", "!=", "<=", ">=", "|", "<", ">", "+", "-", "*", "/", "%", "(", ")", "[", "]",
+    "=", ",",
+];
+
+fn describe(token: Option<&Token>) -> String {
+    match token {
+        Some(Token::Field(name)) => format!("`.{}`", name),
+        Some(Token::Dot) => "`.`".into(),
+        Some(Token::Ident(name)) => format!("`{}`", name),
+        Some(Token::Literal(value)) => format!("`{}`", value),
+        Some(Token::Symbol(symbol)) => format!("`{}`", symbol),
+        None => "end of expression".into(),
+    }
+}
+
+fn ident(s: &str) -> usize {
+    s.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(s.len())
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c == '.' {
+            let len = ident(&rest[1..]);
+            if len > 0 && !rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                tokens.push(Token::Field(rest[1..=len].into()));
+                len + 1
+            } else {
+                tokens.push(Token::Dot);
+                1
+            }
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = rest[..len]
+                .parse::<i64>()
+                .map(Value::from)
+                .or_else(|_| rest[..len].parse::<f64>().map(number))
+                .map_err(|_| format!("invalid number {}", &rest[..len]))?;
+            tokens.push(Token::Literal(number));
+            len
+        } else if c == '"' {
+            let mut escaped = false;
+            let len = rest[1..]
+                .find(|c: char| {
+                    let end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    end
+                })
+                .ok_or("unterminated string")?
+                + 2;
+            let string: String = serde_json::from_str(&rest[..len])
+                .map_err(|_| format!("invalid string {}", &rest[..len]))?;
+            tokens.push(Token::Literal(string.into()));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = ident(rest);
+            tokens.push(Token::Ident(rest[..len].into()));
+            len
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .ok_or_else(|| format!("unexpected `{}`", c))?;
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// a recursive descent parser, with operators binding as tightly as they do in jq
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    fn eat(
+        &mut self,
+        symbol: &str,
+    ) -> bool {
+        let found = match self.peek() {
+            Some(Token::Symbol(s)) => *s == symbol,
+            Some(Token::Ident(s)) => s == symbol,
+            _ => false,
+        };
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn expect(
+        &mut self,
+        symbol: &str,
+    ) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(format!(
+                "expected `{}` but found {}",
+                symbol,
+                describe(self.peek())
+            ))
+        }
+    }
+
+    fn pipe(&mut self) -> Result<Expr, String> {
+        let mut expr = self.alternative()?;
+        while self.eat("|") {
+            expr = Expr::Pipe(Box::new(expr), Box::new(self.alternative()?));
+        }
+        Ok(expr)
+    }
+
+    fn alternative(&mut self) -> Result<Expr, String> {
+        let expr = self.assignment()?;
+        if self.eat("//") {
+            return Ok(Expr::Alternative(
+                Box::new(expr),
+                Box::new(self.alternative()?),
+            ));
+        }
+        Ok(expr)
+    }
+
+    fn assignment(&mut self) -> Result<Expr, String> {
+        let expr = self.or()?;
+        let assign = if self.eat("=") {
+            Expr::Assign
+        } else if self.eat("|=") {
+            Expr::Update
+        } else {
+            return Ok(expr);
+        };
+        match expr {
+            Expr::Field(of, name) if matches!(*of, Expr::Identity) => {
+                Ok(assign(name, Box::new(self.or()?)))
+            }
+            _ => Err("only fields of a change may be assigned, as in `.category = \"big\"`".into()),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.comparison()?;
+        while self.eat("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.comparison()?));
+        }
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let expr = self.additive()?;
+        let op = match self.peek() {
+            Some(Token::Symbol("==")) => Op::Equal,
+            Some(Token::Symbol("!=")) => Op::NotEqual,
+            Some(Token::Symbol("<")) => Op::Less,
+            Some(Token::Symbol("<=")) => Op::LessOrEqual,
+            Some(Token::Symbol(">")) => Op::Greater,
+            Some(Token::Symbol(">=")) => Op::GreaterOrEqual,
+            _ => return Ok(expr),
+        };
+        self.at += 1;
+        Ok(Expr::Binary(op, Box::new(expr), Box::new(self.additive()?)))
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        let mut expr = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("+")) => Op::Add,
+                Some(Token::Symbol("-")) => Op::Subtract,
+                _ => return Ok(expr),
+            };
+            self.at += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.multiplicative()?));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("*")) => Op::Multiply,
+                Some(Token::Symbol("/")) => Op::Divide,
+                Some(Token::Symbol("%")) => Op::Remainder,
+                _ => return Ok(expr),
+            };
+            self.at += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        loop {
+            expr = match self.peek() {
+                Some(Token::Field(name)) => {
+                    let name = name.clone();
+                    self.at += 1;
+                    Expr::Field(Box::new(expr), name)
+                }
+                Some(Token::Symbol("[")) => {
+                    self.at += 1;
+                    let index = self.pipe()?;
+                    self.expect("]")?;
+                    Expr::Index(Box::new(expr), Box::new(index))
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    /// the argument of a function, as in `startswith("src/")`
+    fn argument(&mut self) -> Result<Box<Expr>, String> {
+        self.expect("(")?;
+        let expr = self.pipe()?;
+        self.expect(")")?;
+        Ok(Box::new(expr))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.next();
+        Ok(match token {
+            Some(Token::Literal(value)) => Expr::Literal(value),
+            Some(Token::Field(name)) => Expr::Field(Box::new(Expr::Identity), name),
+            Some(Token::Dot) => Expr::Identity,
+            Some(Token::Symbol("(")) => {
+                let expr = self.pipe()?;
+                self.expect(")")?;
+                expr
+            }
+            Some(Token::Symbol("[")) => {
+                let mut values = Vec::new();
+                if !self.eat("]") {
+                    loop {
+                        values.push(self.alternative()?);
+                        if self.eat("]") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Expr::Array(values)
+            }
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                "if" => self.conditional()?,
+                "length" => Expr::Call(Function::Length),
+                "not" => Expr::Call(Function::Not),
+                "ascii_downcase" => Expr::Call(Function::AsciiDowncase),
+                "ascii_upcase" => Expr::Call(Function::AsciiUpcase),
+                "tostring" => Expr::Call(Function::ToString),
+                "tonumber" => Expr::Call(Function::ToNumber),
+                "startswith" => Expr::Call(Function::StartsWith(self.argument()?)),
+                "endswith" => Expr::Call(Function::EndsWith(self.argument()?)),
+                "contains" => Expr::Call(Function::Contains(self.argument()?)),
+                "test" => match *self.argument()? {
+                    Expr::Literal(Value::String(pattern)) => Expr::Call(Function::Test(
+                        Regex::new(&pattern).map_err(|e| e.to_string())?,
+                    )),
+                    _ => return Err("test takes a regex given as a string".into()),
+                },
+                _ => return Err(format!("unknown function `{}`", ident)),
+            },
+            token => return Err(format!("unexpected {}", describe(token.as_ref()))),
+        })
+    }
+
+    /// the rest of an `if`, whose `else` defaults to the value piped in
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let condition = self.pipe()?;
+        self.expect("then")?;
+        let then = self.pipe()?;
+        let otherwise = if self.eat("elif") {
+            self.conditional()?
+        } else if self.eat("else") {
+            let otherwise = self.pipe()?;
+            self.expect("end")?;
+            otherwise
+        } else {
+            self.expect("end")?;
+            Expr::Identity
+        };
+        Ok(Expr::If(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |e: String| format!("invalid expression {}: {}", s, e);
+        let mut parser = Parser {
+            tokens: tokenize(s).map_err(invalid)?,
+            at: 0,
+        };
+        let expr = parser.pipe().map_err(invalid)?;
+        match parser.peek() {
+            None => Ok(expr),
+            token => Err(invalid(format!("unexpected {}", describe(token)))),
+        }
+    }
+}
+
+/// whether a value counts as true, as everything but false and null does
+pub fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// a number, as an integer when it's whole so sums of integer fields stay integers
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < 9e15 {
+        Value::from(n as i64)
+    } else {
+        Number::from_f64(n).map_or(Value::Null, Value::Number)
+    }
+}
+
+/// orders values of different kinds as jq does, nulls first and objects last
+fn compare(
+    a: &Value,
+    b: &Value,
+) -> Ordering {
+    let rank = |value: &Value| match value {
+        Value::Null => 0,
+        Value::Bool(false) => 1,
+        Value::Bool(true) => 2,
+        Value::Number(_) => 3,
+        Value::String(_) => 4,
+        Value::Array(_) => 5,
+        Value::Object(_) => 6,
+    };
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare(a, b))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Value::Object(_), Value::Object(_)) => a.to_string().cmp(&b.to_string()),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn arithmetic(
+    op: Op,
+    a: Value,
+    b: Value,
+) -> Result<Value, String> {
+    let fail = |a: &Value, b: &Value| {
+        Err(format!(
+            "cannot apply {:?} to {} and {}",
+            op,
+            kind(a),
+            kind(b)
+        ))
+    };
+    Ok(match (op, a, b) {
+        (Op::Add, Value::Null, b) => b,
+        (Op::Add, a, Value::Null) => a,
+        (Op::Add, Value::String(a), Value::String(b)) => Value::String(a + &b),
+        (Op::Add, Value::Array(mut a), Value::Array(b)) => {
+            a.extend(b);
+            Value::Array(a)
+        }
+        (Op::Add, Value::Object(mut a), Value::Object(b)) => {
+            a.extend(b);
+            Value::Object(a)
+        }
+        (Op::Subtract, Value::Array(a), Value::Array(b)) => {
+            Value::Array(a.into_iter().filter(|value| !b.contains(value)).collect())
+        }
+        (op, Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (a.as_f64().unwrap_or(0.0), b.as_f64().unwrap_or(0.0));
+            match op {
+                Op::Add => number(a + b),
+                Op::Subtract => number(a - b),
+                Op::Multiply => number(a * b),
+                Op::Divide if b == 0.0 => return Err(format!("cannot divide {} by zero", a)),
+                Op::Divide => number(a / b),
+                // remainders are of integers, as in jq, so divisors like 0.5 are zero too
+                _ => match (a as i64).checked_rem(b as i64) {
+                    Some(remainder) => Value::from(remainder),
+                    None if b as i64 == 0 => return Err(format!("cannot divide {} by zero", a)),
+                    None => return Err(format!("{} % {} overflows", a, b)),
+                },
+            }
+        }
+        (_, a, b) => return fail(&a, &b),
+    })
+}
+
+fn field(
+    value: &Value,
+    name: &str,
+) -> Result<Value, String> {
+    match value {
+        Value::Object(object) => Ok(object.get(name).cloned().unwrap_or(Value::Null)),
+        Value::Null => Ok(Value::Null),
+        other => Err(format!("cannot index {} with \"{}\"", kind(other), name)),
+    }
+}
+
+fn assign(
+    value: &Value,
+    name: &str,
+    to: Value,
+) -> Result<Value, String> {
+    let mut object = match value {
+        Value::Object(object) => object.clone(),
+        Value::Null => Object::new(),
+        other => return Err(format!("cannot assign .{} of {}", name, kind(other))),
+    };
+    object.insert(name.into(), to);
+    Ok(Value::Object(object))
+}
+
+/// whether `b` is within `a`, as a substring, or each element of an array within an element
+fn contains(
+    a: &Value,
+    b: &Value,
+) -> Result<bool, String> {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => Ok(a.contains(b.as_str())),
+        (Value::Array(a), Value::Array(b)) => {
+            for b in b {
+                let mut found = false;
+                for a in a {
+                    if contains(a, b)? {
+                        found = true;
+                        break;
+                    }
+                }
+                if !found {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        (a, b) if kind(a) == kind(b) => Ok(a == b),
+        (a, b) => Err(format!(
+            "cannot check whether {} contains {}",
+            kind(a),
+            kind(b)
+        )),
+    }
+}
+
+impl Function {
+    fn call(
+        &self,
+        input: &Value,
+    ) -> Result<Value, String> {
+        let strings = |arg: &Expr| match (input, arg.eval(input)?) {
+            (Value::String(a), Value::String(b)) => Ok((a.clone(), b)),
+            (a, b) => Err(format!(
+                "expected strings but found {} and {}",
+                kind(a),
+                kind(&b)
+            )),
+        };
+        Ok(match (self, input) {
+            (Function::Length, Value::Null) => Value::from(0),
+            (Function::Length, Value::String(s)) => Value::from(s.chars().count()),
+            (Function::Length, Value::Array(values)) => Value::from(values.len()),
+            (Function::Length, Value::Object(object)) => Value::from(object.len()),
+            (Function::Length, Value::Number(n)) => number(n.as_f64().unwrap_or(0.0).abs()),
+            (Function::Not, value) => Value::Bool(!truthy(value)),
+            (Function::AsciiDowncase, Value::String(s)) => Value::String(s.to_ascii_lowercase()),
+            (Function::AsciiUpcase, Value::String(s)) => Value::String(s.to_ascii_uppercase()),
+            (Function::ToString, Value::String(s)) => Value::String(s.clone()),
+            (Function::ToString, value) => Value::String(value.to_string()),
+            (Function::ToNumber, Value::Number(n)) => Value::Number(n.clone()),
+            (Function::ToNumber, Value::String(s)) => s
+                .parse::<i64>()
+                .map(Value::from)
+                .or_else(|_| s.parse::<f64>().map(number))
+                .map_err(|_| format!("cannot parse {} as a number", s))?,
+            (Function::Test(regex), Value::String(s)) => Value::Bool(regex.is_match(s)),
+            (Function::StartsWith(arg), _) => {
+                let (a, b) = strings(arg)?;
+                Value::Bool(a.starts_with(&b))
+            }
+            (Function::EndsWith(arg), _) => {
+                let (a, b) = strings(arg)?;
+                Value::Bool(a.ends_with(&b))
+            }
+            (Function::Contains(arg), a) => Value::Bool(contains(a, &arg.eval(input)?)?),
+            (function, value) => {
+                return Err(format!(
+                    "{:?} may not be applied to {}",
+                    function,
+                    kind(value)
+                ))
+            }
+        })
+    }
+}
+
+impl Expr {
+    pub fn eval(
+        &self,
+        input: &Value,
+    ) -> Result<Value, String> {
+        Ok(match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Array(values) => Value::Array(
+                values
+                    .iter()
+                    .map(|value| value.eval(input))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expr::Identity => input.clone(),
+            Expr::Field(of, name) => {
+                // fields of the record itself are read without copying it
+                let of = match **of {
+                    Expr::Identity => Cow::Borrowed(input),
+                    _ => Cow::Owned(of.eval(input)?),
+                };
+                field(&of, name)?
+            }
+            Expr::Index(of, index) => match (of.eval(input)?, index.eval(input)?) {
+                (Value::Null, _) => Value::Null,
+                (Value::Array(values), Value::Number(n)) => {
+                    let n = n.as_i64().unwrap_or(0);
+                    let n = if n < 0 { values.len() as i64 + n } else { n };
+                    usize::try_from(n)
+                        .ok()
+                        .and_then(|n| values.get(n).cloned())
+                        .unwrap_or(Value::Null)
+                }
+                (value @ Value::Object(_), Value::String(name)) => field(&value, &name)?,
+                (value, index) => {
+                    return Err(format!(
+                        "cannot index {} with {}",
+                        kind(&value),
+                        kind(&index)
+                    ))
+                }
+            },
+            Expr::Pipe(a, b) => b.eval(&a.eval(input)?)?,
+            Expr::Alternative(a, b) => match a.eval(input) {
+                Ok(value) if truthy(&value) => value,
+                _ => b.eval(input)?,
+            },
+            Expr::And(a, b) => Value::Bool(truthy(&a.eval(input)?) && truthy(&b.eval(input)?)),
+            Expr::Or(a, b) => Value::Bool(truthy(&a.eval(input)?) || truthy(&b.eval(input)?)),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(input)?, b.eval(input)?);
+                let ordering = compare(&a, &b);
+                match op {
+                    Op::Equal => Value::Bool(ordering == Ordering::Equal),
+                    Op::NotEqual => Value::Bool(ordering != Ordering::Equal),
+                    Op::Less => Value::Bool(ordering == Ordering::Less),
+                    Op::LessOrEqual => Value::Bool(ordering != Ordering::Greater),
+                    Op::Greater => Value::Bool(ordering == Ordering::Greater),
+                    Op::GreaterOrEqual => Value::Bool(ordering != Ordering::Less),
+                    op => arithmetic(*op, a, b)?,
+                }
+            }
+            Expr::Negate(expr) => match expr.eval(input)? {
+                Value::Number(n) => number(-n.as_f64().unwrap_or(0.0)),
+                other => return Err(format!("cannot negate {}", kind(&other))),
+            },
+            Expr::If(condition, then, otherwise) => {
+                if truthy(&condition.eval(input)?) {
+                    then.eval(input)?
+                } else {
+                    otherwise.eval(input)?
+                }
+            }
+            Expr::Call(function) => function.call(input)?,
+            Expr::Assign(name, value) => assign(input, name, value.eval(input)?)?,
+            Expr::Update(name, value) => assign(input, name, value.eval(&field(input, name)?)?)?,
+        })
+    }
+}
+
+/// forwards changes for which an expression is neither false nor null
+pub struct Select<E> {
+    pub expr: Expr,
+    pub emitter: E,
+}
+
+impl<E> Emitter for Select<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let value = serde_json::to_value(&line)?;
+        let selected = self
+            .expr
+            .eval(&value)
+            .map_err(|e| format!("failed to select {}: {}", line.path, e))?;
+        if truthy(&selected) {
+            self.emitter.emit(line)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+/// forwards the changes an expression maps each change to, which must remain changes,
+/// their fields holding values of the kinds they're emitted as
+pub struct Map<E> {
+    pub expr: Expr,
+    pub emitter: E,
+}
+
+impl<E> Emitter for Map<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let value = serde_json::to_value(&line)?;
+        let mapped = self
+            .expr
+            .eval(&value)
+            .and_then(|value| serde_json::from_value(value).map_err(|e| e.to_string()))
+            .map_err(|e| format!("failed to map {}: {}", line.path, e))?;
+        self.emitter.emit(mapped)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Category, Collect};
+    use serde_json::json;
+
+    fn eval(
+        expr: &str,
+        input: Value,
+    ) -> Result<Value, String> {
+        expr.parse::<Expr>()?.eval(&input)
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let change =
+            json!({"path": "src/lib.rs", "additions": 90, "deletions": 20, "references": ["#1"]});
+        assert_eq!(
+            eval(".additions + .deletions > 100", change.clone()),
+            Ok(json!(true))
+        );
+        assert_eq!(
+            eval(".additions - .deletions * 2", change.clone()),
+            Ok(json!(50))
+        );
+        assert_eq!(eval(".additions / 4", change.clone()), Ok(json!(22.5)));
+        assert_eq!(eval(".ext // \"none\"", change.clone()), Ok(json!("none")));
+        assert_eq!(
+            eval(
+                "(.path | startswith(\"src/\")) and (.path | test(\"\\\\.rs$\"))",
+                change.clone()
+            ),
+            Ok(json!(true))
+        );
+        assert_eq!(eval(".references | length", change.clone()), Ok(json!(1)));
+        assert_eq!(eval(".references[-1]", change.clone()), Ok(json!("#1")));
+        assert_eq!(
+            eval(".references | contains([\"#1\"])", change.clone()),
+            Ok(json!(true))
+        );
+        assert_eq!(eval(".missing.nested", change.clone()), Ok(Value::Null));
+        assert_eq!(
+            eval(
+                "if .additions > 100 then \"big\" elif .additions > 50 then \"medium\" else \"small\" end",
+                change.clone()
+            ),
+            Ok(json!("medium"))
+        );
+        assert_eq!(eval(".binary | not", change.clone()), Ok(json!(true)));
+        assert!(eval(".path - 1", change).is_err());
+    }
+
+    #[test]
+    fn remainders_of_integer_zero_are_errors() {
+        let change = json!({"additions": 90});
+        assert_eq!(eval(".additions % 7", change.clone()), Ok(json!(6)));
+        assert!(eval(".additions % 0", change.clone()).is_err());
+        assert!(eval(".additions % 0.5 == 0", change.clone()).is_err());
+        assert!(eval("-9223372036854775808 % -1", change).is_err());
+    }
+
+    #[test]
+    fn reports_invalid_expressions() {
+        for expr in &[
+            ".additions >",
+            "(.path",
+            ".path | frobnicate",
+            "\"open",
+            ".a.b = 1",
+            "1 ) 2",
+        ] {
+            assert!(expr.parse::<Expr>().is_err(), "{} parsed", expr);
+        }
+    }
+
+    #[test]
+    fn assigns_fields() {
+        let change = json!({"path": "Makefile", "category": "default"});
+        assert_eq!(
+            eval(
+                ".ext = (.ext // \"none\") | .path |= ascii_downcase",
+                change
+            ),
+            Ok(json!({"path": "makefile", "category": "default", "ext": "none"}))
+        );
+    }
+
+    #[test]
+    fn maps_and_selects_changes() -> Result<(), Box<dyn Error>> {
+        let mut emitter = Map {
+            expr: "if .additions > 100 then .category = \"big\" end".parse()?,
+            emitter: Select {
+                expr: ".category != \"default\"".parse()?,
+                emitter: Collect::default(),
+            },
+        };
+        for additions in &[500, 5] {
+            emitter.emit(Change {
+                path: "src/lib.rs".into(),
+                additions: Some(*additions),
+                ..Change::default()
+            })?;
+        }
+        let changes = &emitter.emitter.emitter.0;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].category, Category::Custom("big".into()));
+        // changes may only be mapped to changes
+        let mut emitter = Map {
+            expr: ".additions = \"many\"".parse()?,
+            emitter: Collect::default(),
+        };
+        assert!(emitter.emit(Change::default()).is_err());
+        Ok(())
+    }
+}
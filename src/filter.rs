@@ -0,0 +1,623 @@
+//! drops changes before they reach an emitter, so aggregates
+//! only ever see the changes asked for
+use crate::{hotspots::Window, parse_timestamp, progress, Change, Emitter};
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::{error::Error, str::FromStr};
+
+/// emails of well known bots, such as `49699333+dependabot[bot]@users.noreply.github.com`
+pub const BOTS: &str = r"(?i)\[bot\]@|^(dependabot|renovate|greenkeeper|snyk-bot|mergify|github-actions)|@renovateapp\.com$";
+
+/// a point in time, given as an iso date, a `%ai` or rfc3339 timestamp,
+/// or a span of days or weeks before now, as in `90d`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bound(pub DateTime<Utc>);
+
+impl Bound {
+    /// the bound in a form `git log --since` understands
+    pub fn to_git(self) -> String {
+        self.0.to_rfc3339_opts(SecondsFormat::Secs, true)
+    }
+}
+
+impl FromStr for Bound {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(Window(span)) = s.parse() {
+            return Ok(Bound(Utc::now() - Duration::seconds(span)));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(Bound(
+                date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc(),
+            ));
+        }
+        parse_timestamp(s)
+            .map(|timestamp| Bound(timestamp.with_timezone(&Utc)))
+            .map_err(|_| {
+                format!(
+                    "expected a date like 2019-08-09, a timestamp, or a span like 90d, got {}",
+                    s
+                )
+            })
+    }
+}
+
+/// forwards changes authored within `since` and `until`, inclusive, by an author
+/// email matching any of `authors`, if given, and none of `excluded_authors`,
+/// adding and deleting between `min_lines` and `max_lines` lines, inclusive,
+/// to another emitter. changes with timestamps which can't be parsed are dropped
+/// as malformed, failing when `strict`
+pub struct Filter<E> {
+    pub since: Option<Bound>,
+    pub until: Option<Bound>,
+    pub authors: Vec<Regex>,
+    pub excluded_authors: Vec<Regex>,
+    pub min_lines: Option<usize>,
+    pub max_lines: Option<usize>,
+    pub strict: bool,
+    pub emitter: E,
+}
+
+impl<E> Filter<E> {
+    fn keep(
+        &self,
+        change: &Change,
+    ) -> Result<bool, Box<dyn Error>> {
+        if !self.authors.is_empty() && !self.authors.iter().any(|a| a.is_match(&change.author)) {
+            return Ok(false);
+        }
+        if self
+            .excluded_authors
+            .iter()
+            .any(|a| a.is_match(&change.author))
+        {
+            return Ok(false);
+        }
+        // binary files change no lines
+        let lines = change.additions.unwrap_or_default() + change.deletions.unwrap_or_default();
+        if self.min_lines.is_some_and(|min| lines < min)
+            || self.max_lines.is_some_and(|max| lines > max)
+        {
+            return Ok(false);
+        }
+        if self.since.is_none() && self.until.is_none() {
+            return Ok(true);
+        }
+        let timestamp = match parse_timestamp(&change.timestamp) {
+            Ok(timestamp) => timestamp.with_timezone(&Utc),
+            Err(e) if self.strict => return Err(e),
+            Err(e) => {
+                progress::malformed();
+                tracing::warn!(repo = %change.repo, sha = %change.sha, "dropping change: {}", e);
+                return Ok(false);
+            }
+        };
+        Ok(self.since.iter().all(|Bound(since)| timestamp >= *since)
+            && self.until.iter().all(|Bound(until)| timestamp <= *until))
+    }
+}
+
+impl<E> Emitter for Filter<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.keep(&line)? {
+            self.emitter.emit(line)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+/// forwards the changes of commits changing at most `max_files` paths, dropping
+/// whole commits which change more, as vendoring and reformatting commits do.
+/// changes of a commit are expected to arrive together, and flushes to come
+/// between commits, as they do between reads of a watched repository
+pub struct HugeCommits<E> {
+    max_files: usize,
+    /// the repo and sha of the commit arriving
+    commit: Option<(String, String)>,
+    /// changes of the commit arriving, until it's found to change too many paths
+    pending: Vec<Change>,
+    huge: bool,
+    pub emitter: E,
+}
+
+impl<E> HugeCommits<E>
+where
+    E: Emitter,
+{
+    pub fn new(
+        max_files: usize,
+        emitter: E,
+    ) -> Self {
+        HugeCommits {
+            max_files,
+            commit: None,
+            pending: Vec::new(),
+            huge: false,
+            emitter,
+        }
+    }
+
+    fn end_commit(&mut self) -> Result<(), Box<dyn Error>> {
+        self.huge = false;
+        for line in self.pending.drain(..) {
+            self.emitter.emit(line)?;
+        }
+        Ok(())
+    }
+}
+
+impl<E> Emitter for HugeCommits<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let arriving = self
+            .commit
+            .as_ref()
+            .is_some_and(|(repo, sha)| *repo == line.repo && *sha == line.sha);
+        if !arriving {
+            self.end_commit()?;
+            self.commit = Some((line.repo.clone(), line.sha.clone()));
+        }
+        if self.huge {
+            return Ok(());
+        }
+        self.pending.push(line);
+        if self.pending.len() > self.max_files {
+            self.pending.clear();
+            self.huge = true;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.end_commit()?;
+        self.commit = None;
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.end_commit()?;
+        self.emitter.finish()
+    }
+}
+
+/// the fraction of commits sampled, greater than 0 and at most 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rate(pub f64);
+
+impl FromStr for Rate {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<f64>() {
+            Ok(rate) if rate > 0.0 && rate <= 1.0 => Ok(Rate(rate)),
+            _ => Err(format!(
+                "expected a rate greater than 0 and at most 1, as in 0.05, but found {}",
+                s
+            )),
+        }
+    }
+}
+
+/// forwards a pseudo random `rate` of commits, each with every one of its changes.
+/// whether a commit is sampled is decided by a digest of its sha and a `seed`, so
+/// the same commits are sampled whatever order they're read in, and re-runs with
+/// another seed sample others
+pub struct Sample<E> {
+    rate: Rate,
+    seed: u64,
+    /// the sha of the last commit arriving and whether it's sampled
+    commit: Option<(String, bool)>,
+    pub emitter: E,
+}
+
+impl<E> Sample<E> {
+    pub fn new(
+        rate: Rate,
+        seed: u64,
+        emitter: E,
+    ) -> Self {
+        Sample {
+            rate,
+            seed,
+            commit: None,
+            emitter,
+        }
+    }
+
+    fn sampled(
+        &self,
+        sha: &str,
+    ) -> bool {
+        let digest = Sha256::new()
+            .chain_update(self.seed.to_be_bytes())
+            .chain_update(sha)
+            .finalize();
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        // the top 53 bits, which a float holds exactly, as a fraction of 1
+        let fraction = (u64::from_be_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64;
+        fraction < self.rate.0
+    }
+}
+
+impl<E> Emitter for Sample<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let sampled = match &self.commit {
+            Some((sha, sampled)) if *sha == line.sha => *sampled,
+            _ => {
+                let sampled = self.sampled(&line.sha);
+                self.commit = Some((line.sha.clone(), sampled));
+                sampled
+            }
+        };
+        if sampled {
+            self.emitter.emit(line)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+/// where changes arrive relative to the commits bounding those forwarded
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Position {
+    /// before the `after` commit
+    Before,
+    /// at the `after` commit
+    After,
+    Between,
+    /// at or past the `before` commit
+    Past,
+}
+
+/// forwards the changes of commits read after the `after` commit and before the
+/// `before` commit, exclusive, in the order they're read. either may be given as
+/// an abbreviated sha. changes of a commit are expected to arrive together
+pub struct Between<E> {
+    after: Option<String>,
+    before: Option<String>,
+    position: Position,
+    pub emitter: E,
+}
+
+impl<E> Between<E> {
+    pub fn new(
+        after: Option<String>,
+        before: Option<String>,
+        emitter: E,
+    ) -> Self {
+        Between {
+            position: if after.is_some() {
+                Position::Before
+            } else {
+                Position::Between
+            },
+            after,
+            before,
+            emitter,
+        }
+    }
+}
+
+impl<E> Emitter for Between<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let is = |sha: &Option<String>| sha.as_ref().is_some_and(|sha| line.sha.starts_with(sha));
+        self.position = match self.position {
+            _ if is(&self.before) => Position::Past,
+            Position::Before if is(&self.after) => Position::After,
+            Position::After if !is(&self.after) => Position::Between,
+            position => position,
+        };
+        if self.position == Position::Between {
+            self.emitter.emit(line)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if let (Position::Before, Some(after)) = (self.position, &self.after) {
+            return Err(format!("commit {} given by --after-sha was never read", after).into());
+        }
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Collect;
+
+    fn change(timestamp: &str) -> Change {
+        Change {
+            timestamp: timestamp.into(),
+            ..Change::default()
+        }
+    }
+
+    #[test]
+    fn parses_bounds() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            "2019-08-09".parse::<Bound>()?.to_git(),
+            "2019-08-09T00:00:00Z"
+        );
+        assert_eq!(
+            "2019-08-09 15:36:50 -0400".parse::<Bound>()?.to_git(),
+            "2019-08-09T19:36:50Z"
+        );
+        let Bound(relative) = "90d".parse()?;
+        assert!(relative < Utc::now() - Duration::days(89));
+        assert!("last tuesday".parse::<Bound>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn filters_by_timestamp() -> Result<(), Box<dyn Error>> {
+        let mut filter = Filter {
+            since: Some("2019-08-01".parse()?),
+            until: Some("2019-08-31".parse()?),
+            authors: Vec::new(),
+            excluded_authors: Vec::new(),
+            min_lines: None,
+            max_lines: None,
+            strict: true,
+            emitter: Collect::default(),
+        };
+        for timestamp in &[
+            "2019-07-31 23:59:59 +0000",
+            "2019-08-01 00:00:00 +0000",
+            "2019-08-09 15:36:50 -0400",
+            "2019-08-31 20:00:00 -0400",
+        ] {
+            filter.emit(change(timestamp))?;
+        }
+        assert_eq!(filter.emitter.0.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_timestamps_are_dropped_unless_strict() -> Result<(), Box<dyn Error>> {
+        let mut filter = Filter {
+            since: Some("2019-08-01".parse()?),
+            until: None,
+            authors: Vec::new(),
+            excluded_authors: Vec::new(),
+            min_lines: None,
+            max_lines: None,
+            strict: false,
+            emitter: Collect::default(),
+        };
+        filter.emit(change("yesterday"))?;
+        filter.emit(change("2019-08-09 15:36:50 -0400"))?;
+        assert_eq!(filter.emitter.0.len(), 1);
+        filter.strict = true;
+        assert!(filter.emit(change("yesterday")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn unbounded_filters_skip_parsing() -> Result<(), Box<dyn Error>> {
+        let mut filter = Filter {
+            since: None,
+            until: None,
+            authors: Vec::new(),
+            excluded_authors: Vec::new(),
+            min_lines: None,
+            max_lines: None,
+            strict: true,
+            emitter: Collect::default(),
+        };
+        filter.emit(change("yesterday"))?;
+        assert_eq!(filter.emitter.0.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn filters_by_author() -> Result<(), Box<dyn Error>> {
+        let mut filter = Filter {
+            since: None,
+            until: None,
+            authors: vec![Regex::new("@moon.com$")?],
+            excluded_authors: vec![Regex::new("^luna@")?, Regex::new(BOTS)?],
+            min_lines: None,
+            max_lines: None,
+            strict: true,
+            emitter: Collect::default(),
+        };
+        for author in &[
+            "luna@moon.com",
+            "io@moon.com",
+            "sol@sun.com",
+            "49699333+dependabot[bot]@moon.com",
+        ] {
+            filter.emit(Change {
+                author: author.to_string(),
+                ..Change::default()
+            })?;
+        }
+        assert_eq!(filter.emitter.0.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn filters_by_lines() -> Result<(), Box<dyn Error>> {
+        let mut filter = Filter {
+            since: None,
+            until: None,
+            authors: Vec::new(),
+            excluded_authors: Vec::new(),
+            min_lines: Some(2),
+            max_lines: Some(10),
+            strict: true,
+            emitter: Collect::default(),
+        };
+        for (additions, deletions) in &[
+            (Some(1), Some(0)),
+            (Some(1), Some(1)),
+            (Some(6), Some(4)),
+            (Some(11), Some(0)),
+            (None, None),
+        ] {
+            filter.emit(Change {
+                additions: *additions,
+                deletions: *deletions,
+                ..Change::default()
+            })?;
+        }
+        assert_eq!(filter.emitter.0.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn drops_huge_commits() -> Result<(), Box<dyn Error>> {
+        let mut huge = HugeCommits::new(2, Collect::default());
+        for sha in &["d4", "c3", "c3", "c3", "b2", "b2"] {
+            huge.emit(Change {
+                sha: sha.to_string(),
+                ..Change::default()
+            })?;
+        }
+        huge.flush()?;
+        assert_eq!(huge.emitter.0.len(), 3);
+        huge.emit(Change {
+            sha: "a1".into(),
+            ..Change::default()
+        })?;
+        huge.finish()?;
+        assert_eq!(huge.emitter.0.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn samples_whole_commits() -> Result<(), Box<dyn Error>> {
+        let sample =
+            |rate: f64, seed: u64, shas: &[String]| -> Result<Vec<String>, Box<dyn Error>> {
+                let mut sample = Sample::new(Rate(rate), seed, Collect::default());
+                for sha in shas {
+                    for path in 0..3 {
+                        sample.emit(Change {
+                            sha: sha.clone(),
+                            path: path.to_string(),
+                            ..Change::default()
+                        })?;
+                    }
+                }
+                Ok(sample
+                    .emitter
+                    .0
+                    .into_iter()
+                    .map(|change| change.sha)
+                    .collect())
+            };
+        let shas = (0..1000).map(|i| format!("{:040x}", i)).collect::<Vec<_>>();
+        let sampled = sample(0.1, 0, &shas)?;
+        assert_eq!(sampled.len() % 3, 0);
+        assert!(sampled
+            .chunks(3)
+            .all(|commit| commit.iter().all(|sha| *sha == commit[0])));
+        assert!(
+            (70..130).contains(&(sampled.len() / 3)),
+            "{}",
+            sampled.len() / 3
+        );
+        // the same commits are sampled in any order, and others with another seed
+        let reversed = shas.iter().rev().cloned().collect::<Vec<_>>();
+        let mut resampled = sample(0.1, 0, &reversed)?;
+        resampled.reverse();
+        assert_eq!(sampled, resampled);
+        assert_ne!(sampled, sample(0.1, 1, &shas)?);
+        assert_eq!(sample(1.0, 0, &shas)?.len(), 3000);
+        Ok(())
+    }
+
+    #[test]
+    fn rates_are_fractions() {
+        assert_eq!("0.05".parse(), Ok(Rate(0.05)));
+        assert_eq!("1".parse(), Ok(Rate(1.0)));
+        assert!("0".parse::<Rate>().is_err());
+        assert!("5%".parse::<Rate>().is_err());
+    }
+
+    #[test]
+    fn forwards_commits_between_shas() -> Result<(), Box<dyn Error>> {
+        let shas = ["d4", "c3", "c3", "b2", "b2", "a1"];
+        let between = |after: Option<&str>, before: Option<&str>| {
+            let mut between = Between::new(
+                after.map(Into::into),
+                before.map(Into::into),
+                Collect::default(),
+            );
+            for sha in &shas {
+                between.emit(Change {
+                    sha: sha.to_string(),
+                    ..Change::default()
+                })?;
+            }
+            between.finish().map(|_| between.emitter.0.len())
+        };
+        assert_eq!(between(Some("c"), None)?, 3);
+        assert_eq!(between(None, Some("b2"))?, 3);
+        assert_eq!(between(Some("d4"), Some("a1"))?, 4);
+        assert!(between(Some("e5"), None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn matches_bots() -> Result<(), Box<dyn Error>> {
+        let bots = Regex::new(BOTS)?;
+        for bot in &[
+            "49699333+dependabot[bot]@users.noreply.github.com",
+            "29139614+renovate[bot]@users.noreply.github.com",
+            "bot@renovateapp.com",
+            "dependabot-preview[bot]@users.noreply.github.com",
+            "41898282+github-actions[bot]@users.noreply.github.com",
+        ] {
+            assert!(bots.is_match(bot), "{} is a bot", bot);
+        }
+        assert!(!bots.is_match("luna@moon.com"));
+        assert!(!bots.is_match("1234+luna@users.noreply.github.com"));
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! reads `git log` output as it's appended, like `tail -f`, for long running
+//! producers writing to a pipe, fifo or file
+use crate::output::Compress;
+use std::{
+    error::Error,
+    fs::File,
+    io::{stdin, BufRead, BufReader},
+    mem,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// how long to wait at the end of a file before reading it again
+pub const INTERVAL: Duration = Duration::from_millis(250);
+
+/// lines of a reader which, rather than ending at its end, waits `interval`
+/// and reads again. fifos are reopened once their writers close, waiting on
+/// the next. lines are only yielded once terminated, so those being written
+/// aren't split. ends only when reading fails
+pub struct Follow {
+    reader: Box<dyn BufRead>,
+    fifo: Option<PathBuf>,
+    interval: Duration,
+    partial: Vec<u8>,
+}
+
+impl Follow {
+    pub fn new(
+        reader: Box<dyn BufRead>,
+        interval: Duration,
+    ) -> Self {
+        Follow {
+            reader,
+            fifo: None,
+            interval,
+            partial: Vec::new(),
+        }
+    }
+}
+
+/// follows `git log` output at a path, or stdin given `-`
+pub fn open(
+    logs: &str,
+    interval: Duration,
+) -> Result<Follow, Box<dyn Error>> {
+    if logs == "-" {
+        return Ok(Follow::new(Box::new(BufReader::new(stdin())), interval));
+    }
+    let path = Path::new(logs);
+    if Compress::infer(path) != Compress::None {
+        return Err(format!("--follow can't read compressed logs {}", logs).into());
+    }
+    let file = File::open(path).map_err(|e| format!("failed to read {}: {}", logs, e))?;
+    let fifo = fifo(&file)?;
+    Ok(Follow {
+        fifo: Some(path.into()).filter(|_| fifo),
+        ..Follow::new(Box::new(BufReader::new(file)), interval)
+    })
+}
+
+#[cfg(unix)]
+fn fifo(file: &File) -> Result<bool, Box<dyn Error>> {
+    use std::os::unix::fs::FileTypeExt;
+    Ok(file.metadata()?.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn fifo(_: &File) -> Result<bool, Box<dyn Error>> {
+    Ok(false)
+}
+
+/// a line with invalid utf-8 replaced
+fn lossy(line: Vec<u8>) -> String {
+    String::from_utf8(line).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+impl Iterator for Follow {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            // bytes rather than strings, so lines which aren't utf-8 don't end the read
+            match self.reader.read_until(b'\n', &mut self.partial) {
+                Ok(0) => match &self.fifo {
+                    // opening blocks until there's a writer to read from
+                    Some(path) => {
+                        self.reader = Box::new(BufReader::new(File::open(path).ok()?));
+                        // the last line of a closed writer is complete, if unterminated
+                        if !self.partial.is_empty() {
+                            return Some(lossy(mem::take(&mut self.partial)));
+                        }
+                    }
+                    _ => thread::sleep(self.interval),
+                },
+                Ok(_) if self.partial.ends_with(b"\n") => {
+                    let mut line = mem::take(&mut self.partial);
+                    line.pop();
+                    if line.ends_with(b"\r") {
+                        line.pop();
+                    }
+                    return Some(lossy(line));
+                }
+                Ok(_) => (),
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, io::Write};
+
+    #[test]
+    fn waits_for_appended_lines() -> Result<(), Box<dyn Error>> {
+        let path = env::temp_dir().join("git-linecat-follow-test.log");
+        fs::write(&path, b"a\r\ncaf\xe9\nb")?;
+        let mut follow = open(path.to_str().unwrap(), Duration::from_millis(1))?;
+        assert_eq!(follow.next(), Some("a".into()));
+        assert_eq!(follow.next(), Some("caf\u{fffd}".into()));
+        let append = {
+            let path = path.clone();
+            thread::spawn(move || -> std::io::Result<()> {
+                thread::sleep(Duration::from_millis(20));
+                let mut file = fs::OpenOptions::new().append(true).open(path)?;
+                file.write_all(b"c\nd\n")
+            })
+        };
+        assert_eq!(
+            follow.take(2).collect::<Vec<_>>(),
+            vec!["bc".to_string(), "d".into()]
+        );
+        append.join().unwrap()?;
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_compressed_logs() {
+        assert!(open("history.log.gz", Duration::from_millis(1)).is_err());
+    }
+}
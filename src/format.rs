@@ -0,0 +1,775 @@
+//! encodings changes may be emitted in
+use crate::{Change, Emitter, Fields};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+#[cfg(feature = "msgpack")]
+use std::convert::TryFrom;
+use std::{error::Error, io::Write, str::FromStr};
+
+/// the elasticsearch index changes are written to unless told otherwise
+pub const DEFAULT_INDEX: &str = "git-linecat";
+
+/// the namespace of prometheus metrics, which subcommands suffix with their name
+pub const NAMESPACE: &str = "git_linecat";
+
+#[derive(Clone, Debug)]
+pub enum Format {
+    Json,
+    Csv,
+    Parquet,
+    /// an arrow ipc stream of record batches
+    Arrow,
+    Sqlite,
+    /// elasticsearch `_bulk` actions indexing changes into the named index
+    EsBulk(String),
+    /// messagepack maps, each prefixed by its length
+    Msgpack,
+    /// prometheus text exposition of aggregates, gauges of the named namespace
+    Prometheus(String),
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "parquet" => Ok(Format::Parquet),
+            "arrow" => Ok(Format::Arrow),
+            "sqlite" => Ok(Format::Sqlite),
+            "es-bulk" => Ok(Format::EsBulk(DEFAULT_INDEX.into())),
+            "msgpack" => Ok(Format::Msgpack),
+            "prometheus" => Ok(Format::Prometheus(NAMESPACE.into())),
+            other => Err(format!("unsupported output format {}", other)),
+        }
+    }
+}
+
+/// a field emitted under another name, as in `old=new`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for Rename {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() => Ok(Rename {
+                from: from.into(),
+                to: to.into(),
+            }),
+            _ => Err(format!("expected a rename as old=new, got {}", s)),
+        }
+    }
+}
+
+/// the name a field is emitted under. the last of its renames wins
+pub fn renamed<'a>(
+    renames: &'a [Rename],
+    field: &'a str,
+) -> &'a str {
+    renames
+        .iter()
+        .rev()
+        .find(|rename| rename.from == field)
+        .map_or(field, |rename| &rename.to)
+}
+
+/// the fields emitted of each change, in order, and the names they're emitted under
+#[derive(Clone, Debug)]
+pub struct Projection(Vec<(&'static str, String)>);
+
+impl Projection {
+    pub fn new(
+        fields: &Fields,
+        renames: &[Rename],
+    ) -> Self {
+        Projection(
+            fields
+                .iter()
+                .map(|(field, _)| (*field, renamed(renames, field).to_string()))
+                .collect(),
+        )
+    }
+
+    /// splits projected fields into those among the given fields and the rest
+    pub fn partition(
+        &self,
+        fields: &[&str],
+    ) -> (Projection, Projection) {
+        let (among, rest) = self
+            .0
+            .iter()
+            .cloned()
+            .partition(|(field, _)| fields.contains(field));
+        (Projection(among), Projection(rest))
+    }
+
+    /// whether any field is emitted under another name
+    pub fn renames(&self) -> bool {
+        self.0.iter().any(|(field, name)| field != name)
+    }
+
+    /// the projected fields of a change, omitting those it omits when serialized
+    pub fn project(
+        &self,
+        line: &Change,
+    ) -> Result<Map<String, Value>, Box<dyn Error>> {
+        let mut value = serde_json::to_value(line)?;
+        Ok(self
+            .0
+            .iter()
+            .filter_map(|(field, name)| value.get_mut(*field).map(|v| (name.clone(), v.take())))
+            .collect())
+    }
+}
+
+/// emits newline delimited json
+pub struct Json<W>(pub W)
+where
+    W: Write;
+
+impl<W> Emitter for Json<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut self.0, &line)?;
+        self.0.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.0.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Emitter::flush(self)
+    }
+}
+
+/// emits newline delimited json of a projection of each change
+pub struct Projected<W>
+where
+    W: Write,
+{
+    write: W,
+    projection: Projection,
+}
+
+impl<W> Projected<W>
+where
+    W: Write,
+{
+    pub fn new(
+        write: W,
+        projection: Projection,
+    ) -> Self {
+        Projected { write, projection }
+    }
+}
+
+impl<W> Emitter for Projected<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut self.write, &self.projection.project(&line)?)?;
+        self.write.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.write.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Emitter::flush(self)
+    }
+}
+
+/// emits messagepack maps of each change, or of a projection of it, keyed by field name.
+/// each is prefixed by its length as a big endian u32 so readers may frame records
+/// without decoding them
+#[cfg(feature = "msgpack")]
+pub struct Msgpack<W>
+where
+    W: Write,
+{
+    write: W,
+    projection: Option<Projection>,
+    /// reused between records
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "msgpack")]
+impl<W> Msgpack<W>
+where
+    W: Write,
+{
+    pub fn new(
+        write: W,
+        projection: Option<Projection>,
+    ) -> Self {
+        Msgpack {
+            write,
+            projection,
+            buf: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<W> Emitter for Msgpack<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.buf.clear();
+        match &self.projection {
+            Some(projection) => {
+                rmp_serde::encode::write_named(&mut self.buf, &projection.project(&line)?)?
+            }
+            _ => rmp_serde::encode::write_named(&mut self.buf, &line)?,
+        }
+        let len = u32::try_from(self.buf.len())
+            .map_err(|_| format!("record of {} bytes is too large to frame", self.buf.len()))?;
+        self.write.write_all(&len.to_be_bytes())?;
+        self.write.write_all(&self.buf)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.write.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Emitter::flush(self)
+    }
+}
+
+/// emits a header row of fields followed by one row per change.
+/// fields a change omits are left empty
+pub struct Csv<W>
+where
+    W: Write,
+{
+    writer: csv::Writer<W>,
+    projection: Projection,
+    header: bool,
+}
+
+impl<W> Csv<W>
+where
+    W: Write,
+{
+    pub fn new(
+        write: W,
+        fields: Fields,
+    ) -> Self {
+        Self::projected(write, Projection::new(&fields, &[]))
+    }
+
+    /// emits the fields of a projection, headed by the names they're emitted under
+    pub fn projected(
+        write: W,
+        projection: Projection,
+    ) -> Self {
+        Csv {
+            writer: csv::Writer::from_writer(write),
+            projection,
+            header: false,
+        }
+    }
+
+    /// emits rows without a header, as to files already headed
+    pub fn headed(mut self) -> Self {
+        self.header = true;
+        self
+    }
+
+    fn header(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.header {
+            self.writer
+                .write_record(self.projection.0.iter().map(|(_, name)| name))?;
+            self.header = true;
+        }
+        Ok(())
+    }
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// a metric or label name, with characters prometheus doesn't allow replaced
+fn metric_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// writes records as prometheus text exposition, for node_exporter's textfile
+/// collector. each numeric column is a gauge, labelled by the other columns
+fn write_prometheus<W>(
+    namespace: &str,
+    mut write: W,
+    columns: &[&str],
+    records: &[Map<String, Value>],
+) -> Result<(), Box<dyn Error>>
+where
+    W: Write,
+{
+    let (metrics, labels): (Vec<&str>, Vec<&str>) = columns.iter().partition(|column| {
+        records
+            .iter()
+            .any(|record| record.get(**column).is_some_and(Value::is_number))
+    });
+    for metric in metrics {
+        let name = format!("{}_{}", namespace, metric_name(metric));
+        writeln!(write, "# TYPE {} gauge", name)?;
+        for record in records {
+            let value = match record.get(metric) {
+                Some(Value::Number(value)) => value,
+                _ => continue,
+            };
+            let labels: Vec<_> = labels
+                .iter()
+                .filter_map(|label| match record.get(*label) {
+                    // nested values, as ownership's shares, make no sense of a label
+                    None | Some(Value::Null | Value::Object(_) | Value::Array(_)) => None,
+                    value => Some(format!(
+                        "{}=\"{}\"",
+                        metric_name(label),
+                        cell(value)
+                            .replace('\\', "\\\\")
+                            .replace('"', "\\\"")
+                            .replace('\n', "\\n")
+                    )),
+                })
+                .collect();
+            match labels.is_empty() {
+                true => writeln!(write, "{} {}", name, value)?,
+                _ => writeln!(write, "{}{{{}}} {}", name, labels.join(","), value)?,
+            }
+        }
+    }
+    write.flush()?;
+    Ok(())
+}
+
+/// writes records sharing a fixed set of columns, such as aggregates, as
+/// newline delimited json, csv or prometheus gauges. json records omit null columns
+pub fn write_records<W>(
+    format: &Format,
+    mut write: W,
+    columns: &[&str],
+    records: Vec<Map<String, Value>>,
+) -> Result<(), Box<dyn Error>>
+where
+    W: Write,
+{
+    match format {
+        Format::Json => {
+            for mut record in records {
+                record.retain(|_, value| !value.is_null());
+                serde_json::to_writer(&mut write, &record)?;
+                write.write_all(b"\n")?;
+            }
+            write.flush()?;
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(write);
+            writer.write_record(columns)?;
+            for record in records {
+                writer.write_record(columns.iter().map(|column| cell(record.get(*column))))?;
+            }
+            writer.flush()?;
+        }
+        Format::Prometheus(namespace) => write_prometheus(namespace, write, columns, &records)?,
+        Format::Parquet | Format::Arrow | Format::Sqlite | Format::EsBulk(_) | Format::Msgpack => {
+            return Err("aggregates may only be written as json, csv or prometheus".into())
+        }
+    }
+    Ok(())
+}
+
+impl<W> Emitter for Csv<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.header()?;
+        let value = serde_json::to_value(&line)?;
+        self.writer.write_record(
+            self.projection
+                .0
+                .iter()
+                .map(|(field, _)| cell(value.get(field))),
+        )?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.header()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// a stable id of a change, the hex sha256 digest of its repo, commit and path,
+/// so documents keyed by it are replaced rather than duplicated when re-written.
+/// copies of a change emitted for its co-authors digest their author too
+pub fn id(change: &Change) -> String {
+    let mut digest = Sha256::new();
+    let co_author = change.author_role.as_deref() == Some("co_author");
+    let parts = [&change.repo, &change.sha, &change.path, &change.author];
+    for part in &parts[..if co_author { 4 } else { 3 }] {
+        digest.update(part.as_bytes());
+        digest.update([0]);
+    }
+    format!("{:x}", digest.finalize())
+}
+
+/// emits newline delimited elasticsearch `_bulk` api requests, an `index`
+/// action followed by its document, keyed by each change's [`id`]
+pub struct EsBulk<W>
+where
+    W: Write,
+{
+    write: W,
+    index: String,
+    /// the fields of documents, when not every field
+    projection: Option<Projection>,
+}
+
+impl<W> EsBulk<W>
+where
+    W: Write,
+{
+    pub fn new(
+        write: W,
+        index: String,
+    ) -> Self {
+        EsBulk {
+            write,
+            index,
+            projection: None,
+        }
+    }
+
+    /// indexes documents of only the fields of a projection
+    pub fn project(
+        mut self,
+        projection: Projection,
+    ) -> Self {
+        self.projection = Some(projection);
+        self
+    }
+}
+
+impl<W> Emitter for EsBulk<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let action = json!({ "index": { "_index": self.index, "_id": id(&line) } });
+        serde_json::to_writer(&mut self.write, &action)?;
+        self.write.write_all(b"\n")?;
+        match &self.projection {
+            Some(projection) => {
+                serde_json::to_writer(&mut self.write, &projection.project(&line)?)?
+            }
+            _ => serde_json::to_writer(&mut self.write, &line)?,
+        }
+        self.write.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.write.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Emitter::flush(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Category, FIELDS};
+
+    #[test]
+    fn json_emits_lines() -> Result<(), Box<dyn Error>> {
+        let mut json = Json(Vec::new());
+        json.emit(Change::default())?;
+        json.emit(Change::default())?;
+        json.finish()?;
+        assert_eq!(json.0.iter().filter(|b| **b == b'\n').count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn csv_emits_header_and_rows() -> Result<(), Box<dyn Error>> {
+        let mut buf = Vec::new();
+        {
+            let mut csv = Csv::new(&mut buf, FIELDS[..13].to_vec());
+            csv.emit(Change {
+                repo: "test".into(),
+                path: "a, b.rs".into(),
+                ext: Some("rs".into()),
+                category: Category::Test,
+                additions: Some(1),
+                ..Change::default()
+            })?;
+            csv.emit(Change {
+                path: "Makefile".into(),
+                ..Change::default()
+            })?;
+            csv.finish()?;
+        }
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "repo,sha,author,timestamp,timestamp_utc,epoch_seconds,path,old_path,renamed,ext,category,additions,deletions\n\
+             test,,,,,,\"a, b.rs\",,,rs,test,1,\n\
+             ,,,,,,Makefile,,,,default,,\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn projections_select_and_rename_fields() -> Result<(), Box<dyn Error>> {
+        let fields = vec![FIELDS[6], FIELDS[1], FIELDS[7], FIELDS[11]];
+        let renames = ["sha=commit".parse()?, "path=file".parse()?];
+        let change = Change {
+            sha: "abc".into(),
+            path: "src/lib.rs".into(),
+            additions: Some(3),
+            ..Change::default()
+        };
+        let mut json = Projected::new(Vec::new(), Projection::new(&fields, &renames));
+        json.emit(change.clone())?;
+        assert_eq!(
+            String::from_utf8(json.write)?,
+            "{\"file\":\"src/lib.rs\",\"commit\":\"abc\",\"additions\":3}\n"
+        );
+        let mut buf = Vec::new();
+        {
+            let mut csv = Csv::projected(&mut buf, Projection::new(&fields, &renames));
+            csv.emit(change)?;
+            csv.finish()?;
+        }
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "file,commit,old_path,additions\nsrc/lib.rs,abc,,3\n"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_emits_length_prefixed_maps() -> Result<(), Box<dyn Error>> {
+        let change = Change {
+            repo: "test".into(),
+            path: "src/lib.rs".into(),
+            additions: Some(3),
+            ..Change::default()
+        };
+        let mut msgpack = Msgpack::new(Vec::new(), None);
+        msgpack.emit(change.clone())?;
+        msgpack.emit(Change {
+            path: "src/main.rs".into(),
+            ..change
+        })?;
+        msgpack.finish()?;
+        let mut buf = &msgpack.write[..];
+        let mut paths = Vec::new();
+        while !buf.is_empty() {
+            let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+            let record: Value = rmp_serde::from_slice(&buf[4..4 + len])?;
+            paths.push(record["path"].clone());
+            buf = &buf[4 + len..];
+        }
+        assert_eq!(paths, vec!["src/lib.rs", "src/main.rs"]);
+        Ok(())
+    }
+
+    #[test]
+    fn renames_are_parsed() {
+        assert_eq!(
+            "sha=commit".parse(),
+            Ok(Rename {
+                from: "sha".into(),
+                to: "commit".into()
+            })
+        );
+        assert!("sha".parse::<Rename>().is_err());
+        assert!("sha=".parse::<Rename>().is_err());
+    }
+
+    #[test]
+    fn csv_emits_header_without_rows() -> Result<(), Box<dyn Error>> {
+        let mut buf = Vec::new();
+        Csv::new(&mut buf, FIELDS.to_vec()).finish()?;
+        assert_eq!(buf.iter().filter(|b| **b == b'\n').count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn es_bulk_emits_actions_and_documents() -> Result<(), Box<dyn Error>> {
+        let change = || Change {
+            repo: "test".into(),
+            sha: "61708727af02089cef4a72c6a532ddf332111b14".into(),
+            path: "src/main.rs".into(),
+            ..Change::default()
+        };
+        let mut bulk = EsBulk::new(Vec::new(), "churn".into());
+        bulk.emit(change())?;
+        bulk.emit(Change {
+            path: "src/lib.rs".into(),
+            ..change()
+        })?;
+        bulk.finish()?;
+        let output = String::from_utf8(bulk.write)?;
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        let action: Value = serde_json::from_str(lines[0])?;
+        assert_eq!(action["index"]["_index"], "churn");
+        assert_eq!(action["index"]["_id"], id(&change()));
+        assert!(lines[1].starts_with("{\"repo\":\"test\""));
+        assert_ne!(lines[0], lines[2]);
+        Ok(())
+    }
+
+    #[test]
+    fn ids_are_stable() {
+        assert_eq!(
+            id(&Change {
+                repo: "test".into(),
+                sha: "sha".into(),
+                path: "a".into(),
+                ..Change::default()
+            }),
+            id(&Change {
+                repo: "test".into(),
+                sha: "sha".into(),
+                path: "a".into(),
+                additions: Some(2),
+                ..Change::default()
+            })
+        );
+        assert_ne!(
+            id(&Change {
+                sha: "sh".into(),
+                path: "aa".into(),
+                ..Change::default()
+            }),
+            id(&Change {
+                sha: "sha".into(),
+                path: "a".into(),
+                ..Change::default()
+            })
+        );
+        let co_author = |author: &str| Change {
+            author: author.into(),
+            author_role: Some("co_author".into()),
+            ..Change::default()
+        };
+        assert_ne!(
+            id(&co_author("luna@moon.com")),
+            id(&co_author("sol@sun.com"))
+        );
+        assert_eq!(
+            id(&Change {
+                author: "luna@moon.com".into(),
+                author_role: Some("author".into()),
+                ..Change::default()
+            }),
+            id(&Change::default())
+        );
+    }
+
+    #[test]
+    fn records_keep_column_order() -> Result<(), Box<dyn Error>> {
+        let record = || {
+            let mut record = Map::new();
+            record.insert("author".into(), "luna@moon.com".into());
+            record.insert("category".into(), Value::Null);
+            record.insert("additions".into(), 2.into());
+            vec![record]
+        };
+        let columns = &["author", "category", "additions"];
+        let mut json = Vec::new();
+        write_records(&Format::Json, &mut json, columns, record())?;
+        assert_eq!(
+            String::from_utf8(json)?,
+            "{\"author\":\"luna@moon.com\",\"additions\":2}\n"
+        );
+        let mut csv = Vec::new();
+        write_records(&Format::Csv, &mut csv, columns, record())?;
+        assert_eq!(
+            String::from_utf8(csv)?,
+            "author,category,additions\nluna@moon.com,,2\n"
+        );
+        assert!(write_records(&Format::Parquet, Vec::new(), columns, record()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn records_are_written_as_prometheus_gauges() -> Result<(), Box<dyn Error>> {
+        let mut record = Map::new();
+        record.insert("repo".into(), "meetup/\"linecat\"".into());
+        record.insert("category".into(), Value::Null);
+        record.insert("additions".into(), 2.into());
+        record.insert("share".into(), 0.5.into());
+        let mut text = Vec::new();
+        write_records(
+            &Format::Prometheus("git_linecat_summarize".into()),
+            &mut text,
+            &["repo", "category", "additions", "share"],
+            vec![record],
+        )?;
+        assert_eq!(
+            String::from_utf8(text)?,
+            "# TYPE git_linecat_summarize_additions gauge\n\
+             git_linecat_summarize_additions{repo=\"meetup/\\\"linecat\\\"\"} 2\n\
+             # TYPE git_linecat_summarize_share gauge\n\
+             git_linecat_summarize_share{repo=\"meetup/\\\"linecat\\\"\"} 0.5\n"
+        );
+        Ok(())
+    }
+}
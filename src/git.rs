@@ -0,0 +1,410 @@
+//! runs `git log` on behalf of the user so the expected
+//! pretty format doesn't need to be remembered
+use crate::lossy_lines;
+use std::{
+    error::Error,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+/// the `--pretty` format the header parser expects
+pub const PRETTY_FORMAT: &str = r#"--pretty=format:"%H","%ae","%ai","%ce","%ci","%P"%d"#;
+
+/// the `--pretty` format the header parser expects when including commit messages.
+/// bodies may span many lines so are terminated with a record separator
+pub const MESSAGE_FORMAT: &str =
+    r#"--pretty=format:"%H","%ae","%ai","%ce","%ci","%P","%s"%d%n%b%x1e"#;
+
+/// spawns `git log` within the repository at `path` with a `--pretty` format,
+/// handing its stdout lines to `consume`. `args` are passed along to `git log`.
+/// git's own failures are surfaced with its stderr output
+pub fn log<F>(
+    path: &Path,
+    pretty: &str,
+    args: &[String],
+    consume: F,
+) -> Result<(), Box<dyn Error>>
+where
+    F: FnOnce(&mut dyn Iterator<Item = String>) -> Result<(), Box<dyn Error>>,
+{
+    let mut child = Command::new("git")
+        // paths which aren't ascii are quoted and escaped, whatever their encoding
+        .args(["-c", "core.quotepath=on"])
+        .arg("-C")
+        .arg(path)
+        .arg("log")
+        .arg(pretty)
+        // messages are reencoded from whatever they were committed in
+        .args(["--encoding=UTF-8", "--numstat", "--no-merges"])
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let consumed = consume(&mut lossy_lines(BufReader::new(stdout)));
+    if consumed.is_err() {
+        // git may be blocked writing to a pipe that is no longer read
+        let _ = child.kill();
+    }
+    let output = child.wait_with_output()?;
+    if consumed.is_ok() && !output.status.success() {
+        return Err(format!(
+            "git log failed in {} ({}): {}",
+            path.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    consumed
+}
+
+/// the commit `HEAD` of the repository at `path` points to,
+/// if any have been committed yet
+pub fn head(path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .arg("-C")
+            .arg(path)
+            .args(args)
+            .output()
+            .map_err(|e| format!("failed to run git: {}", e))
+    };
+    let output = git(&["rev-parse", "--verify", "-q", "HEAD^{commit}"])?;
+    if output.status.success() {
+        return Ok(Some(String::from_utf8_lossy(&output.stdout).trim().into()));
+    }
+    // fails the same way whether history is empty or there's no repository at all
+    let output = git(&["rev-parse", "--git-dir"])?;
+    if output.status.success() {
+        return Ok(None);
+    }
+    Err(format!(
+        "{} is not a git repository: {}",
+        path.display(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    )
+    .into())
+}
+
+/// the commits a shallow clone at `path` has its history cut off at, none for
+/// repositories with all of theirs
+pub fn shallow(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(["rev-parse", "--git-path", "shallow"])
+        .output()
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} is not a git repository: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    // relative to the repository, which git was run within
+    let file = path.join(String::from_utf8_lossy(&output.stdout).trim());
+    match fs::read_to_string(file) {
+        Ok(shallow) => Ok(shallow.lines().map(String::from).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// the paths of the submodules of the repository at `path`, and those of their own
+/// submodules, each with whether it's checked out. bare repositories have none
+pub fn submodules(path: &Path) -> Result<Vec<(String, bool)>, Box<dyn Error>> {
+    let git = |args: &[&str]| -> Result<String, Box<dyn Error>> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(path)
+            .args(args)
+            .output()
+            .map_err(|e| format!("failed to run git: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "git {} failed in {} ({}): {}",
+                args.join(" "),
+                path.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into())
+    };
+    if git(&["rev-parse", "--is-inside-work-tree"])?.trim() != "true" {
+        return Ok(Vec::new());
+    }
+    // lines like ` <sha> <path> (<describe>)`, flagged `-` when not checked out
+    Ok(git(&["submodule", "status", "--recursive"])?
+        .lines()
+        .filter_map(|line| {
+            let (flag, rest) = line.split_at(line.chars().next()?.len_utf8());
+            let (_, path) = rest.split_once(' ')?;
+            let path = match path.ends_with(')') {
+                true => path.rsplit_once(" (").map_or(path, |(path, _)| path),
+                _ => path,
+            };
+            Some((path.to_string(), flag != "-"))
+        })
+        .collect())
+}
+
+/// a long running `git cat-file --batch` within a repository, looking up
+/// the contents of files at commits without a process per file
+pub struct Blobs {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Blobs {
+    pub fn spawn(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(path)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to run git: {}", e))?;
+        Ok(Blobs {
+            stdin: child.stdin.take().expect("stdin is piped"),
+            stdout: BufReader::new(child.stdout.take().expect("stdout is piped")),
+            child,
+        })
+    }
+
+    /// the number of lines of `file` at commit `sha`, or `None` when it
+    /// isn't a file of the commit
+    pub fn lines(
+        &mut self,
+        sha: &str,
+        file: &str,
+    ) -> Result<Option<usize>, Box<dyn Error>> {
+        // requests are newline delimited
+        if file.contains('\n') {
+            return Ok(None);
+        }
+        writeln!(self.stdin, "{}:{}", sha, file)?;
+        self.stdin.flush()?;
+        let mut header = String::new();
+        if self.stdout.read_line(&mut header)? == 0 {
+            return Err("git cat-file exited unexpectedly".into());
+        }
+        // `<oid> <type> <size>`, or `<object> missing` for unknown objects
+        let (kind, size) = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            [_, kind, size] if !header.ends_with(" missing\n") => (kind, size.parse::<u64>()?),
+            _ => return Ok(None),
+        };
+        let mut lines = 0;
+        let mut last = b'\n';
+        let mut content = (&mut self.stdout).take(size);
+        loop {
+            let chunk = content.fill_buf()?;
+            if chunk.is_empty() {
+                break;
+            }
+            lines += chunk.iter().filter(|b| **b == b'\n').count();
+            last = chunk[chunk.len() - 1];
+            let read = chunk.len();
+            content.consume(read);
+        }
+        // contents are followed by a newline of their own
+        self.stdout.read_exact(&mut [0])?;
+        if last != b'\n' {
+            lines += 1;
+        }
+        Ok(Some(lines).filter(|_| kind == "blob"))
+    }
+}
+
+impl Drop for Blobs {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::{env, path::PathBuf};
+
+    /// creates a fresh repository under the system temp dir with a single commit
+    pub(crate) fn repo(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let dir = env::temp_dir().join(format!("git-linecat-{}-{}", name, std::process::id()));
+        drop(fs::remove_dir_all(&dir));
+        fs::create_dir_all(dir.join("src"))?;
+        fs::write(dir.join("src/lib.rs"), "fn main() {\n}\n")?;
+        for args in &[
+            &["init", "-q"][..],
+            &["add", "."][..],
+            &[
+                "-c",
+                "user.name=luna",
+                "-c",
+                "user.email=luna@moon.com",
+                "commit",
+                "-q",
+                "-m",
+                "init",
+            ][..],
+        ] {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(&dir)
+                .args(*args)
+                .status()?;
+            assert!(status.success(), "git {:?} failed", args);
+        }
+        Ok(dir)
+    }
+
+    /// creates a fresh repository with another as its submodule at `vendor/lib`
+    pub(crate) fn superproject(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let lib = repo(&format!("{}-lib", name))?;
+        let dir = repo(name)?;
+        let added = Command::new("git")
+            .arg("-C")
+            .arg(&dir)
+            .args(["-c", "protocol.file.allow=always", "submodule", "add", "-q"])
+            .arg(&lib)
+            .arg("vendor/lib")
+            .status()?;
+        assert!(added.success(), "git submodule add failed");
+        let committed = Command::new("git")
+            .arg("-C")
+            .arg(&dir)
+            .args(["-c", "user.name=luna", "-c", "user.email=luna@moon.com"])
+            .args(["commit", "-q", "-m", "vendor lib"])
+            .status()?;
+        assert!(committed.success(), "git commit failed");
+        Ok(dir)
+    }
+
+    #[test]
+    fn log_streams_lines() -> Result<(), Box<dyn Error>> {
+        let dir = repo("log")?;
+        let mut lines = Vec::new();
+        log(&dir, PRETTY_FORMAT, &[], |l| {
+            lines.extend(l);
+            Ok(())
+        })?;
+        assert!(lines[0].contains("luna@moon.com"));
+        assert!(lines[1].ends_with("src/lib.rs"));
+        Ok(())
+    }
+
+    #[test]
+    fn log_reads_past_latin1() -> Result<(), Box<dyn Error>> {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = repo("log-latin1")?;
+        let message =
+            env::temp_dir().join(format!("git-linecat-latin1-{}.msg", std::process::id()));
+        fs::write(&message, b"caf\xe9\n")?;
+        fs::write(dir.join(OsStr::from_bytes(b"caf\xe9.txt")), "hi\n")?;
+        let committed = Command::new("git")
+            .arg("-C")
+            .arg(&dir)
+            .args(["add", "."])
+            .status()?
+            .success()
+            && Command::new("git")
+                .arg("-C")
+                .arg(&dir)
+                .args(["-c", "user.name=luna", "-c", "user.email=luna@moon.com"])
+                .args(["commit", "-q", "-F"])
+                .arg(&message)
+                .status()?
+                .success();
+        assert!(committed);
+        fs::remove_file(&message)?;
+        let mut lines = Vec::new();
+        log(&dir, MESSAGE_FORMAT, &[], |l| {
+            lines.extend(l);
+            Ok(())
+        })?;
+        // the commit before the one in latin-1 is read too
+        assert!(lines.iter().any(|line| line.ends_with("src/lib.rs")));
+        assert!(lines.iter().any(|line| line.ends_with("\"caf\\351.txt\"")));
+        // git reencodes what it can, and what it can't is replaced
+        assert!(lines.iter().any(|line| line.contains(r#","caf"#)));
+        Ok(())
+    }
+
+    #[test]
+    fn head_resolves_commits() -> Result<(), Box<dyn Error>> {
+        let dir = repo("head")?;
+        assert!(head(&dir)?.is_some_and(|sha| sha.len() == 40));
+        assert!(head(Path::new("/not/a/git/repo")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn shallow_lists_cut_off_commits() -> Result<(), Box<dyn Error>> {
+        let dir = repo("shallow")?;
+        assert!(shallow(&dir)?.is_empty());
+        let sha = head(&dir)?.ok_or("expected a commit")?;
+        fs::write(dir.join(".git/shallow"), format!("{}\n", sha))?;
+        assert_eq!(shallow(&dir)?, vec![sha]);
+        assert!(shallow(Path::new("/not/a/git/repo")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn submodules_are_listed() -> Result<(), Box<dyn Error>> {
+        let dir = superproject("submodules")?;
+        assert_eq!(submodules(&dir)?, vec![("vendor/lib".to_string(), true)]);
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&dir)
+            .args(["submodule", "deinit", "-q", "vendor/lib"])
+            .status()?;
+        assert!(status.success());
+        assert_eq!(submodules(&dir)?, vec![("vendor/lib".to_string(), false)]);
+        assert!(submodules(&repo("submodules-none")?)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn blobs_count_lines() -> Result<(), Box<dyn Error>> {
+        let dir = repo("blobs")?;
+        let sha = head(&dir)?.ok_or("expected a commit")?;
+        let mut blobs = Blobs::spawn(&dir)?;
+        assert_eq!(blobs.lines(&sha, "src/lib.rs")?, Some(2));
+        assert_eq!(blobs.lines(&sha, "src")?, None);
+        assert_eq!(blobs.lines(&sha, "src/main.rs")?, None);
+        assert_eq!(blobs.lines(&sha, "src/lib.rs")?, Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn log_surfaces_git_errors() {
+        let err = log(Path::new("/not/a/git/repo"), PRETTY_FORMAT, &[], |l| {
+            assert_eq!(l.count(), 0);
+            Ok(())
+        })
+        .unwrap_err();
+        assert!(err.to_string().starts_with("git log failed"));
+    }
+
+    #[test]
+    fn log_passes_args() -> Result<(), Box<dyn Error>> {
+        let dir = repo("log-args")?;
+        let mut lines = Vec::new();
+        log(&dir, PRETTY_FORMAT, &["--author=sol@sun.com".into()], |l| {
+            lines.extend(l);
+            Ok(())
+        })?;
+        assert!(lines.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+//! reads the commits of a repository and the paths each changes through the
+//! [github rest api](https://docs.github.com/en/rest/commits/commits), for
+//! repositories which aren't cloned locally
+use crate::{
+    api::{timestamp, Client},
+    manifest::Remote,
+    progress, Change, Emitter, Header, Parse, Path,
+};
+use serde::Deserialize;
+use std::error::Error;
+
+/// commits listed per page, the most github allows
+const PER_PAGE: usize = 100;
+
+#[derive(Deserialize)]
+struct Listed {
+    sha: String,
+    parents: Vec<Parent>,
+}
+
+#[derive(Deserialize)]
+struct Parent {
+    sha: String,
+}
+
+/// a commit as its own endpoint describes it, with the paths it changes
+#[derive(Deserialize)]
+struct Commit {
+    sha: String,
+    commit: Details,
+    parents: Vec<Parent>,
+    #[serde(default)]
+    files: Vec<File>,
+}
+
+#[derive(Deserialize)]
+struct Details {
+    author: Signature,
+    committer: Signature,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct Signature {
+    email: String,
+    date: String,
+}
+
+#[derive(Deserialize)]
+struct File {
+    filename: String,
+    previous_filename: Option<String>,
+    additions: usize,
+    deletions: usize,
+}
+
+/// a repository of an organization, as listed
+#[derive(Deserialize)]
+pub struct Repository {
+    /// the repository's name, as `org/name`
+    pub full_name: String,
+    pub clone_url: String,
+    pub fork: bool,
+    pub archived: bool,
+}
+
+/// a client authenticated by a token, if any
+fn client(
+    origin: String,
+    token: Option<&str>,
+) -> Client {
+    let mut headers = vec![
+        ("Accept", "application/vnd.github+json".to_string()),
+        ("X-GitHub-Api-Version", "2022-11-28".to_string()),
+    ];
+    if let Some(token) = token {
+        headers.push(("Authorization", format!("Bearer {}", token)));
+    }
+    Client::new(origin, headers)
+}
+
+/// lists every repository of an organization the token may see
+pub fn repositories(
+    base_url: &str,
+    org: &str,
+    token: Option<&str>,
+) -> Result<Vec<Repository>, Box<dyn Error>> {
+    let client = client(format!("github org {}", org), token);
+    let mut repositories = Vec::new();
+    client.pages(
+        format!(
+            "{}/orgs/{}/repos?type=all&per_page={}",
+            base_url.trim_end_matches('/'),
+            org,
+            PER_PAGE
+        ),
+        |page: Vec<Repository>| {
+            repositories.extend(page);
+            Ok(())
+        },
+    )?;
+    Ok(repositories)
+}
+
+/// a commit with every path it changes, which github pages through for
+/// commits changing more than 300
+fn commit(
+    client: &Client,
+    url: String,
+) -> Result<Commit, Box<dyn Error>> {
+    let mut commit: Option<Commit> = None;
+    client.pages(url, |page: Commit| {
+        match &mut commit {
+            Some(commit) => commit.files.extend(page.files),
+            _ => commit = Some(page),
+        }
+        Ok(())
+    })?;
+    Ok(commit.ok_or_else(|| client.unavailable("expected a commit"))?)
+}
+
+/// the header of a commit, with its message when `with_message`
+fn header(
+    commit: &Commit,
+    with_message: bool,
+) -> Result<Header, Box<dyn Error>> {
+    let Details {
+        author,
+        committer,
+        message,
+    } = &commit.commit;
+    let (subject, body) = message.split_once('\n').unwrap_or((message, ""));
+    Ok(Header {
+        sha: commit.sha.clone(),
+        author: author.email.clone(),
+        timestamp: timestamp(&author.date)?,
+        committer: Some(committer.email.clone()),
+        commit_timestamp: Some(timestamp(&committer.date)?),
+        parents: Some(commit.parents.iter().map(|p| p.sha.clone()).collect()),
+        tags: None,
+        branches: None,
+        subject: Some(subject.trim_end().to_string()).filter(|_| with_message),
+        body: Some(body.trim().to_string()).filter(|b| with_message && !b.is_empty()),
+    })
+}
+
+/// a changed file as a `--numstat` path, renames written as git writes them
+fn path(file: File) -> Path {
+    Path {
+        additions: Some(file.additions),
+        deletions: Some(file.deletions),
+        path: match file.previous_filename {
+            Some(old) => format!("{} => {}", old, file.filename),
+            _ => file.filename,
+        },
+    }
+}
+
+/// lists the commits of a repository newest first, as `git log` does, emitting a change
+/// per path of each and fetching the paths of each commit by a request of its own.
+/// merges are skipped unless `merges`, and commits before `since` aren't listed.
+/// github doesn't tell binary files apart, so their changes are emitted with no lines changed
+pub fn read<E>(
+    repository: String,
+    remote: &Remote,
+    &Parse {
+        with_message,
+        merges,
+        ..
+    }: &Parse,
+    since: Option<&str>,
+    emitter: &mut E,
+) -> Result<(), Box<dyn Error>>
+where
+    E: Emitter,
+{
+    let client = client(
+        format!("github repo {}", remote.repo),
+        remote.token.as_deref(),
+    );
+    let base_url = remote.base_url.trim_end_matches('/');
+    let mut url = format!(
+        "{}/repos/{}/commits?per_page={}",
+        base_url, remote.repo, PER_PAGE
+    );
+    if let Some(since) = since {
+        url.push_str(&format!("&since={}", since));
+    }
+    client.pages(url, |listed: Vec<Listed>| {
+        for Listed { sha, parents } in listed {
+            let merge = parents.len() > 1;
+            if merge && !merges {
+                continue;
+            }
+            progress::commit();
+            let commit = commit(
+                &client,
+                format!("{}/repos/{}/commits/{}", base_url, remote.repo, sha),
+            )?;
+            let header = header(&commit, with_message)?;
+            let paths = commit.files.into_iter().map(path);
+            if merge {
+                let changes = paths
+                    .map(|path| (repository.clone(), header.clone(), path).into())
+                    .collect::<Vec<Change>>();
+                emitter.emit(Change::merge(repository.clone(), header, &changes))?;
+                continue;
+            }
+            for path in paths {
+                emitter.emit((repository.clone(), header.clone(), path).into())?;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::tests::serve, manifest::Forge, Collect};
+
+    #[test]
+    fn reads_pages_of_commits() -> Result<(), Box<dyn Error>> {
+        let (url, requests) = serve(vec![
+            (
+                200,
+                "Link: <{url}/repos/meetup/api/commits?page=2>; rel=\"next\"\r\n",
+                r#"[{"sha":"a","parents":[{"sha":"p"}]},{"sha":"m","parents":[{"sha":"a"},{"sha":"b"}]}]"#,
+            ),
+            (
+                200,
+                "",
+                r#"{"sha":"a","parents":[{"sha":"p"}],"commit":{"author":{"email":"luna@moon.com","date":"2019-08-09T19:36:50Z"},"committer":{"email":"sol@sun.com","date":"2019-08-10T13:00:00Z"},"message":"fix: things\n\nat last"},"files":[{"filename":"src/new.rs","previous_filename":"src/old.rs","additions":2,"deletions":1}]}"#,
+            ),
+            (429, "Retry-After: 0\r\n", ""),
+            (200, "", r#"[{"sha":"b","parents":[]}]"#),
+            (
+                200,
+                "",
+                r#"{"sha":"b","parents":[],"commit":{"author":{"email":"luna@moon.com","date":"2019-08-08T18:03:38-04:00"},"committer":{"email":"luna@moon.com","date":"2019-08-08T18:03:38-04:00"},"message":"init"},"files":[{"filename":"README.md","additions":10,"deletions":0}]}"#,
+            ),
+        ])?;
+        let mut collect = Collect::default();
+        read(
+            "meetup/api".into(),
+            &Remote {
+                forge: Forge::Github,
+                repo: "meetup/api".into(),
+                base_url: url,
+                token: Some("secret".into()),
+            },
+            &Parse {
+                with_message: true,
+                ..Parse::default()
+            },
+            Some("2019-08-01T00:00:00Z"),
+            &mut collect,
+        )?;
+        let requests = requests.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            requests[0],
+            "GET /repos/meetup/api/commits?per_page=100&since=2019-08-01T00:00:00Z HTTP/1.1"
+        );
+        assert_eq!(requests[1], "GET /repos/meetup/api/commits/a HTTP/1.1");
+        assert_eq!(requests.len(), 5);
+        let changes = collect.0;
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].path, "src/new.rs");
+        assert_eq!(changes[0].old_path.as_deref(), Some("src/old.rs"));
+        assert_eq!(changes[0].timestamp, "2019-08-09 19:36:50 +0000");
+        assert_eq!(changes[0].committer.as_deref(), Some("sol@sun.com"));
+        assert_eq!(changes[0].subject.as_deref(), Some("fix: things"));
+        assert_eq!(changes[0].body.as_deref(), Some("at last"));
+        assert_eq!(changes[1].path, "README.md");
+        assert_eq!(changes[1].timestamp, "2019-08-08 18:03:38 -0400");
+        Ok(())
+    }
+
+    #[test]
+    fn lists_repositories_of_orgs() -> Result<(), Box<dyn Error>> {
+        let (url, requests) = serve(vec![(
+            200,
+            "",
+            r#"[{"full_name":"meetup/api","clone_url":"https://github.com/meetup/api.git","fork":false,"archived":true}]"#,
+        )])?;
+        let repositories = repositories(&url, "meetup", None)?;
+        assert_eq!(
+            requests.try_iter().collect::<Vec<_>>(),
+            vec!["GET /orgs/meetup/repos?type=all&per_page=100 HTTP/1.1"]
+        );
+        assert_eq!(repositories.len(), 1);
+        assert_eq!(repositories[0].full_name, "meetup/api");
+        assert!(repositories[0].archived);
+        Ok(())
+    }
+
+    #[test]
+    fn refusals_fail_as_unreadable_input() -> Result<(), Box<dyn Error>> {
+        let (url, _) = serve(vec![(404, "", r#"{"message":"Not Found"}"#)])?;
+        let read = read(
+            "meetup/gone".into(),
+            &Remote {
+                forge: Forge::Github,
+                repo: "meetup/gone".into(),
+                base_url: url,
+                token: None,
+            },
+            &Parse::default(),
+            None,
+            &mut Collect::default(),
+        );
+        let error = read.err().ok_or("expected a failure")?;
+        assert_eq!(crate::error::exit_code(&*error), crate::error::EX_IOERR);
+        Ok(())
+    }
+}
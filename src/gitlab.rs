@@ -0,0 +1,234 @@
+//! reads the commits of a project and the paths each changes through the
+//! [gitlab rest api](https://docs.gitlab.com/ee/api/commits.html) of gitlab.com
+//! or a self-hosted instance, for projects which aren't cloned locally
+use crate::{
+    api::{timestamp, Client},
+    manifest::Remote,
+    progress, Change, Emitter, Header, Parse, Path,
+};
+use serde::Deserialize;
+use std::error::Error;
+
+/// commits and diffs listed per page, the most gitlab allows
+const PER_PAGE: usize = 100;
+
+/// a commit as gitlab lists it, which unlike github's includes its header
+#[derive(Deserialize)]
+struct Commit {
+    id: String,
+    parent_ids: Vec<String>,
+    author_email: String,
+    authored_date: String,
+    committer_email: String,
+    committed_date: String,
+    message: String,
+}
+
+/// the diff of a single path of a commit
+#[derive(Deserialize)]
+struct Diff {
+    old_path: String,
+    new_path: String,
+    renamed_file: bool,
+    diff: String,
+}
+
+/// the header of a commit, with its message when `with_message`
+fn header(
+    commit: Commit,
+    with_message: bool,
+) -> Result<Header, Box<dyn Error>> {
+    let (subject, body) = commit
+        .message
+        .split_once('\n')
+        .unwrap_or((&commit.message, ""));
+    Ok(Header {
+        timestamp: timestamp(&commit.authored_date)?,
+        commit_timestamp: Some(timestamp(&commit.committed_date)?),
+        subject: Some(subject.trim_end().to_string()).filter(|_| with_message),
+        body: Some(body.trim().to_string()).filter(|b| with_message && !b.is_empty()),
+        sha: commit.id,
+        author: commit.author_email,
+        committer: Some(commit.committer_email),
+        parents: Some(commit.parent_ids),
+        tags: None,
+        branches: None,
+    })
+}
+
+/// a path's diff as a `--numstat` path, counting the lines its hunks add and delete.
+/// binary files, which gitlab describes rather than diffs, have neither
+fn path(diff: Diff) -> Path {
+    let (additions, deletions) = match diff.diff.starts_with("Binary files") {
+        true => (None, None),
+        _ => {
+            let (mut additions, mut deletions) = (0, 0);
+            for line in diff.diff.lines() {
+                match line.as_bytes().first() {
+                    Some(b'+') => additions += 1,
+                    Some(b'-') => deletions += 1,
+                    _ => (),
+                }
+            }
+            (Some(additions), Some(deletions))
+        }
+    };
+    Path {
+        additions,
+        deletions,
+        path: match diff.renamed_file {
+            true => format!("{} => {}", diff.old_path, diff.new_path),
+            _ => diff.new_path,
+        },
+    }
+}
+
+/// lists the commits of a project newest first, as `git log` does, emitting a change
+/// per path of each and fetching the diffs of each commit by requests of their own.
+/// merges are skipped unless `merges`, and commits before `since` aren't listed.
+/// diffs too large for gitlab to return are counted as changing no lines
+pub fn read<E>(
+    repository: String,
+    remote: &Remote,
+    &Parse {
+        with_message,
+        include_binary,
+        merges,
+        ..
+    }: &Parse,
+    since: Option<&str>,
+    emitter: &mut E,
+) -> Result<(), Box<dyn Error>>
+where
+    E: Emitter,
+{
+    let headers = remote
+        .token
+        .iter()
+        .map(|token| ("PRIVATE-TOKEN", token.clone()))
+        .collect();
+    let client = Client::new(format!("gitlab project {}", remote.repo), headers);
+    // projects are addressed by their url encoded path
+    let project = format!(
+        "{}/api/v4/projects/{}/repository/commits",
+        remote.base_url.trim_end_matches('/'),
+        remote.repo.replace('/', "%2F")
+    );
+    let mut url = format!("{}?per_page={}", project, PER_PAGE);
+    if let Some(since) = since {
+        url.push_str(&format!("&since={}", since));
+    }
+    client.pages(url, |listed: Vec<Commit>| {
+        for commit in listed {
+            let merge = commit.parent_ids.len() > 1;
+            if merge && !merges {
+                continue;
+            }
+            progress::commit();
+            let mut paths = Vec::new();
+            client.pages(
+                format!("{}/{}/diff?per_page={}", project, commit.id, PER_PAGE),
+                |diffs: Vec<Diff>| {
+                    paths.extend(
+                        diffs
+                            .into_iter()
+                            .map(path)
+                            .filter(|path| include_binary || path.additions.is_some()),
+                    );
+                    Ok(())
+                },
+            )?;
+            let header = header(commit, with_message)?;
+            if merge {
+                let changes = paths
+                    .into_iter()
+                    .map(|path| (repository.clone(), header.clone(), path).into())
+                    .collect::<Vec<Change>>();
+                emitter.emit(Change::merge(repository.clone(), header, &changes))?;
+                continue;
+            }
+            for path in paths {
+                emitter.emit((repository.clone(), header.clone(), path).into())?;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::tests::serve, manifest::Forge, Collect};
+
+    #[test]
+    fn diffs_are_counted() {
+        let counted = path(Diff {
+            old_path: "src/old.rs".into(),
+            new_path: "src/new.rs".into(),
+            renamed_file: true,
+            diff: "@@ -1,2 +1,3 @@\n-a\n+b\n+c\n d\n\\ No newline at end of file\n".into(),
+        });
+        assert_eq!(counted.additions, Some(2));
+        assert_eq!(counted.deletions, Some(1));
+        assert_eq!(counted.path, "src/old.rs => src/new.rs");
+        let binary = path(Diff {
+            old_path: "logo.png".into(),
+            new_path: "logo.png".into(),
+            renamed_file: false,
+            diff: "Binary files a/logo.png and b/logo.png differ\n".into(),
+        });
+        assert_eq!(binary.additions, None);
+    }
+
+    #[test]
+    fn reads_pages_of_commits() -> Result<(), Box<dyn Error>> {
+        let (url, requests) = serve(vec![
+            (
+                200,
+                "",
+                r#"[{"id":"a","parent_ids":["p"],"author_email":"luna@moon.com","authored_date":"2019-08-09T15:36:50.000-04:00","committer_email":"sol@sun.com","committed_date":"2019-08-10T09:00:00.000-04:00","message":"fix: things\n"}]"#,
+            ),
+            (
+                200,
+                "Link: <{url}/api/v4/projects/meetup%2Fapi/repository/commits/a/diff?page=2>; rel=\"next\"\r\n",
+                r#"[{"old_path":"src/lib.rs","new_path":"src/lib.rs","renamed_file":false,"diff":"@@ -1 +1,2 @@\n-a\n+b\n+c\n"}]"#,
+            ),
+            (
+                200,
+                "",
+                r#"[{"old_path":"logo.png","new_path":"logo.png","renamed_file":false,"diff":"Binary files a/logo.png and b/logo.png differ\n"}]"#,
+            ),
+        ])?;
+        let mut collect = Collect::default();
+        read(
+            "meetup/api".into(),
+            &Remote {
+                forge: Forge::Gitlab,
+                repo: "meetup/api".into(),
+                base_url: format!("{}/", url),
+                token: Some("secret".into()),
+            },
+            &Parse {
+                with_message: true,
+                ..Parse::default()
+            },
+            None,
+            &mut collect,
+        )?;
+        let requests = requests.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            requests[0],
+            "GET /api/v4/projects/meetup%2Fapi/repository/commits?per_page=100 HTTP/1.1"
+        );
+        assert_eq!(requests.len(), 3);
+        let changes = collect.0;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "src/lib.rs");
+        assert_eq!(changes[0].additions, Some(2));
+        assert_eq!(changes[0].deletions, Some(1));
+        assert_eq!(changes[0].timestamp, "2019-08-09 15:36:50 -0400");
+        assert_eq!(changes[0].subject.as_deref(), Some("fix: things"));
+        assert_eq!(changes[0].body, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+//! header lines of quoted fields separated by commas, as git is asked to format them,
+//! and of formats described by `--header-format`, as in `%H,%ae,%ai,%s`
+//! for `git log --pretty=format:'"%H","%ae","%ai","%s"'`
+use crate::{decorated, Header};
+use std::{error::Error, ops::Range, str::FromStr};
+
+/// splits a header line into the ranges of its quoted fields. git quotes nothing
+/// within fields, so a field ends at the first quote followed by another field or
+/// the end of the line, and names and subjects may carry quotes and commas of their own
+pub(crate) fn fields(line: &str) -> Option<Vec<Range<usize>>> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    loop {
+        if !line[start..].starts_with('"') {
+            return None;
+        }
+        start += 1;
+        let end = line[start..].match_indices('"').find_map(|(i, _)| {
+            let after = &line[start + i + 1..];
+            Some(start + i).filter(|_| after.is_empty() || after.starts_with(",\""))
+        })?;
+        fields.push(start..end);
+        match end + 1 == line.len() {
+            true => return Some(fields),
+            _ => start = end + 2,
+        }
+    }
+}
+
+/// splits unquoted `%d` decorations, as in ` (HEAD -> main)`, from the end of a header line
+pub(crate) fn decorations(line: &str) -> (&str, Option<&str>) {
+    match line.rfind("\" (") {
+        Some(i) if line.ends_with(')') => (&line[..=i], Some(&line[i + 1..])),
+        _ => (line, None),
+    }
+}
+
+/// the field each supported placeholder fills
+fn placeholder(placeholder: &str) -> Option<&'static str> {
+    Some(match placeholder {
+        "%H" | "%h" => "sha",
+        "%ae" | "%aE" => "author",
+        "%ai" | "%aI" => "timestamp",
+        "%ce" | "%cE" => "committer",
+        "%ci" | "%cI" => "commit_timestamp",
+        "%P" => "parents",
+        "%s" => "subject",
+        "%d" | "%D" => "decorations",
+        _ => return None,
+    })
+}
+
+/// the placeholders of a header line, each quoted and separated by commas. placeholders
+/// without a field of their own, as `%an`, are read but not emitted
+#[derive(Clone, Debug)]
+pub struct HeaderFormat {
+    placeholders: Vec<String>,
+    /// the field of each placeholder
+    names: Vec<Option<&'static str>>,
+    /// whether lines end with unquoted `%d` decorations, as git's own formats do
+    decorated: bool,
+}
+
+impl FromStr for HeaderFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let placeholders: Vec<String> = s.split(',').map(|p| p.trim().to_string()).collect();
+        let mut names: Vec<Option<&'static str>> = Vec::new();
+        for p in &placeholders {
+            if !p.starts_with('%') || p.len() < 2 {
+                return Err(format!("expected a placeholder but found {:?}", p));
+            }
+            let name = placeholder(p);
+            if name.is_some() && names.contains(&name) {
+                return Err(format!("placeholders of {} may only appear once", s));
+            }
+            names.push(name);
+        }
+        for name in &["sha", "author", "timestamp"] {
+            if !names.contains(&Some(name)) {
+                return Err("--header-format must include %H, %ae and %ai".into());
+            }
+        }
+        Ok(HeaderFormat {
+            placeholders,
+            names,
+            decorated: false,
+        })
+    }
+}
+
+impl HeaderFormat {
+    /// the format git is asked for by default, [`crate::git::PRETTY_FORMAT`] or with
+    /// commit messages [`crate::git::MESSAGE_FORMAT`], whose `%P` parents are known
+    /// by their position rather than told apart from subjects by their shas
+    pub fn git(with_message: bool) -> Self {
+        let placeholders = match with_message {
+            true => "%H,%ae,%ai,%ce,%ci,%P,%s",
+            _ => "%H,%ae,%ai,%ce,%ci,%P",
+        };
+        HeaderFormat {
+            decorated: true,
+            ..placeholders.parse().expect("a valid format")
+        }
+    }
+
+    /// the `--pretty` format git is asked for, with commit messages
+    /// following as [`crate::git::MESSAGE_FORMAT`] has them
+    pub fn pretty(
+        &self,
+        with_message: bool,
+    ) -> String {
+        let quoted: Vec<_> = self
+            .placeholders
+            .iter()
+            .map(|p| format!("\"{}\"", p))
+            .collect();
+        format!(
+            "--pretty=format:{}{}{}",
+            quoted.join(","),
+            if self.decorated { "%d" } else { "" },
+            if with_message { "%n%b%x1e" } else { "" }
+        )
+    }
+
+    pub fn parse(
+        &self,
+        line: &str,
+    ) -> Result<Header, Box<dyn Error>> {
+        let expected = || format!("expected a header of {}", self.placeholders.join(","));
+        let (line, decorations) = match self.decorated {
+            true => decorations(line),
+            _ => (line, None),
+        };
+        let fields = fields(line).ok_or_else(expected)?;
+        // a subject may itself have split into several fields
+        let extra = match fields.len().checked_sub(self.names.len()) {
+            Some(0) => 0,
+            Some(extra) if self.names.contains(&Some("subject")) => extra,
+            _ => return Err(expected().into()),
+        };
+        let position = |name: &str| self.names.iter().position(|n| *n == Some(name));
+        let subject = position("subject");
+        let field = |name: &str| {
+            let i = position(name)?;
+            let range = match subject {
+                Some(s) if s == i => fields[i].start..fields[i + extra].end,
+                Some(s) if s < i => fields[i + extra].clone(),
+                _ => fields[i].clone(),
+            };
+            Some(line[range].to_string())
+        };
+        let (tags, branches) = field("decorations")
+            .as_deref()
+            .or(decorations)
+            .map(decorated)
+            .unwrap_or_default();
+        Ok(Header {
+            sha: field("sha")
+                .filter(|s| !s.is_empty())
+                .ok_or_else(expected)?,
+            author: field("author").unwrap_or_default(),
+            timestamp: field("timestamp").unwrap_or_default(),
+            committer: field("committer"),
+            commit_timestamp: field("commit_timestamp"),
+            parents: field("parents")
+                .map(|parents| parents.split_whitespace().map(Into::into).collect()),
+            tags,
+            branches,
+            subject: field("subject"),
+            body: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_quoted_fields() {
+        let line = r#""a","Jane Doe","say "hi", ok","""","x,"y""#;
+        let values: Vec<_> = fields(line)
+            .unwrap()
+            .into_iter()
+            .map(|range| &line[range])
+            .collect();
+        assert_eq!(
+            values,
+            vec!["a", "Jane Doe", r#"say "hi", ok"#, r#""""#, r#"x,"y"#]
+        );
+        assert_eq!(fields(r#""a","b"#), None);
+        assert_eq!(fields("1\t2\tsrc/lib.rs"), None);
+    }
+
+    #[test]
+    fn parses_headers_of_a_format() -> Result<(), Box<dyn Error>> {
+        let format: HeaderFormat = "%H,%an,%ae,%ai,%D,%s".parse()?;
+        assert_eq!(
+            format.pretty(false),
+            r#"--pretty=format:"%H","%an","%ae","%ai","%D","%s""#
+        );
+        let header = format.parse(
+            r#""61708727af02089cef4a72c6a532ddf332111b14","Luna Moon","luna@moon.com","2019-08-08 18:03:38 -0400","tag: v1.0","fix "quoted", things""#,
+        )?;
+        assert_eq!(header.author, "luna@moon.com");
+        assert_eq!(header.tags, Some(vec!["v1.0".into()]));
+        assert_eq!(header.branches, None);
+        assert_eq!(header.subject.as_deref(), Some(r#"fix "quoted", things"#));
+        assert!(format
+            .parse(r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com""#)
+            .is_err());
+        let format: HeaderFormat = "%H,%s,%an,%ae,%ai".parse()?;
+        let header = format.parse(
+            r#""61708727af02089cef4a72c6a532ddf332111b14","split","here","Moon, Luna","luna@moon.com","2019-08-08 18:03:38 -0400""#,
+        )?;
+        assert_eq!(header.subject.as_deref(), Some(r#"split","here"#));
+        assert_eq!(header.author, "luna@moon.com");
+        Ok(())
+    }
+
+    #[test]
+    fn parses_headers_of_gits_own_format() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            HeaderFormat::git(false).pretty(false),
+            crate::git::PRETTY_FORMAT
+        );
+        assert_eq!(
+            HeaderFormat::git(true).pretty(true),
+            crate::git::MESSAGE_FORMAT
+        );
+        let format = HeaderFormat::git(true);
+        let header = format.parse(
+            r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","sol@sun.com","2019-08-10 09:00:00 -0400","","1862ee78aac96224fd7fbc8781ad12f9d06127a2" (tag: v1.0)"#,
+        )?;
+        assert_eq!(header.parents, Some(vec![]));
+        assert_eq!(
+            header.subject.as_deref(),
+            Some("1862ee78aac96224fd7fbc8781ad12f9d06127a2")
+        );
+        assert_eq!(header.tags, Some(vec!["v1.0".into()]));
+        let header = format.parse(
+            r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","sol@sun.com","2019-08-10 09:00:00 -0400","1862ee78aac96224fd7fbc8781ad12f9d06127a2","""#,
+        )?;
+        assert_eq!(
+            header.parents,
+            Some(vec!["1862ee78aac96224fd7fbc8781ad12f9d06127a2".into()])
+        );
+        assert_eq!(header.subject.as_deref(), Some(""));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_formats_without_required_fields() {
+        assert!("%H,%ae".parse::<HeaderFormat>().is_err());
+        assert!("%H,%ae,%ai,%H".parse::<HeaderFormat>().is_err());
+        assert!("%H,%ae,%ai,%h".parse::<HeaderFormat>().is_err());
+        assert!("%H,ae,%ai".parse::<HeaderFormat>().is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! ranks paths by churn, the number of commits touching a path
+//! multiplied by the lines they touched
+use crate::{
+    format::{write_records, Format},
+    Change, Emitter,
+};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, error::Error, io::Write, str::FromStr};
+
+/// a span of history ending at the latest change seen, as in `90d` or `12w`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Window(pub i64);
+
+impl FromStr for Window {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, unit) = s.split_at(s.len().saturating_sub(1));
+        let days = match unit {
+            "d" => 1,
+            "w" => 7,
+            _ => return Err(format!("expected a window like 90d or 12w, got {}", s)),
+        };
+        count
+            .parse::<i64>()
+            .map(|count| Window(count * days * 24 * 60 * 60))
+            .map_err(|_| format!("expected a window like 90d or 12w, got {}", s))
+    }
+}
+
+const COLUMNS: &[&str] = &["path", "commits", "additions", "deletions", "churn"];
+
+/// a commit's change to a path
+struct Touch {
+    epoch_seconds: Option<i64>,
+    additions: usize,
+    deletions: usize,
+}
+
+/// buffers changes per path, writing the top ranked paths once all changes are seen
+pub struct Hotspots<W>
+where
+    W: Write,
+{
+    top: usize,
+    window: Option<Window>,
+    format: Format,
+    write: Option<W>,
+    paths: HashMap<String, Vec<Touch>>,
+    latest: Option<i64>,
+}
+
+impl<W> Hotspots<W>
+where
+    W: Write,
+{
+    pub fn new(
+        top: usize,
+        window: Option<Window>,
+        format: Format,
+        write: W,
+    ) -> Self {
+        Hotspots {
+            top,
+            window,
+            format,
+            write: Some(write),
+            paths: HashMap::new(),
+            latest: None,
+        }
+    }
+
+    fn in_window(
+        &self,
+        touch: &Touch,
+    ) -> bool {
+        match (self.window, self.latest, touch.epoch_seconds) {
+            (None, _, _) => true,
+            (Some(Window(span)), Some(latest), Some(epoch)) => latest - epoch <= span,
+            // changes without a parseable timestamp can't be placed in a window
+            _ => false,
+        }
+    }
+}
+
+impl<W> Emitter for Hotspots<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        // records of merges have no path to attribute
+        if line.merge {
+            return Ok(());
+        }
+        self.latest = self.latest.max(line.epoch_seconds);
+        self.paths
+            .entry(line.canonical_path.unwrap_or(line.path))
+            .or_default()
+            .push(Touch {
+                epoch_seconds: line.epoch_seconds,
+                additions: line.additions.unwrap_or_default(),
+                deletions: line.deletions.unwrap_or_default(),
+            });
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let write = match self.write.take() {
+            Some(write) => write,
+            _ => return Ok(()),
+        };
+        let mut ranked = std::mem::take(&mut self.paths)
+            .into_iter()
+            .filter_map(|(path, touches)| {
+                let (commits, additions, deletions) = touches
+                    .iter()
+                    .filter(|touch| self.in_window(touch))
+                    .fold((0, 0, 0), |(commits, additions, deletions), touch| {
+                        (
+                            commits + 1,
+                            additions + touch.additions,
+                            deletions + touch.deletions,
+                        )
+                    });
+                if commits == 0 {
+                    return None;
+                }
+                let churn = commits * (additions + deletions);
+                Some((churn, path, commits, additions, deletions))
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        let records = ranked
+            .into_iter()
+            .take(self.top)
+            .map(|(churn, path, commits, additions, deletions)| {
+                let values = vec![
+                    Value::String(path),
+                    commits.into(),
+                    additions.into(),
+                    deletions.into(),
+                    churn.into(),
+                ];
+                COLUMNS
+                    .iter()
+                    .map(|column| column.to_string())
+                    .zip(values)
+                    .collect::<Map<_, _>>()
+            })
+            .collect();
+        write_records(&self.format, write, COLUMNS, records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(
+        path: &str,
+        epoch_seconds: i64,
+        additions: usize,
+    ) -> Change {
+        Change {
+            path: path.into(),
+            epoch_seconds: Some(epoch_seconds),
+            additions: Some(additions),
+            ..Change::default()
+        }
+    }
+
+    fn hotspots(
+        top: usize,
+        window: Option<Window>,
+    ) -> Result<String, Box<dyn Error>> {
+        let day = 24 * 60 * 60;
+        let mut buf = Vec::new();
+        {
+            let mut hotspots = Hotspots::new(top, window, Format::Csv, &mut buf);
+            for line in [
+                change("src/lib.rs", 10 * day, 5),
+                change("src/lib.rs", 9 * day, 5),
+                change("README.md", 10 * day, 15),
+                change("Makefile", 0, 100),
+            ] {
+                hotspots.emit(line)?;
+            }
+            hotspots.finish()?;
+        }
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn ranks_paths_by_churn() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            hotspots(2, None)?,
+            "path,commits,additions,deletions,churn\n\
+             Makefile,1,100,0,100\n\
+             src/lib.rs,2,10,0,20\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ranks_paths_within_window() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            hotspots(10, Some("7d".parse()?))?,
+            "path,commits,additions,deletions,churn\n\
+             src/lib.rs,2,10,0,20\n\
+             README.md,1,15,0,15\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parses_windows() {
+        assert_eq!("90d".parse(), Ok(Window(90 * 24 * 60 * 60)));
+        assert_eq!("2w".parse(), Ok(Window(14 * 24 * 60 * 60)));
+        assert!("90".parse::<Window>().is_err());
+        assert!("d".parse::<Window>().is_err());
+    }
+}
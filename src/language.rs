@@ -0,0 +1,97 @@
+//! resolves the language of a path from its file name or extension
+//! using a bundled subset of github linguist's languages
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::OnceLock,
+};
+
+const LANGUAGES: &str = include_str!("languages.yml");
+
+#[derive(Deserialize)]
+struct LanguageDef {
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    filenames: Vec<String>,
+}
+
+struct Languages {
+    /// languages keyed by lowercased extension, without a leading `.`
+    extensions: HashMap<String, String>,
+    /// languages keyed by exact file name, which take precedence over extensions
+    filenames: HashMap<String, String>,
+}
+
+fn compile(defs: BTreeMap<String, LanguageDef>) -> Languages {
+    let mut languages = Languages {
+        extensions: HashMap::new(),
+        filenames: HashMap::new(),
+    };
+    for (name, def) in defs {
+        for ext in def.extensions {
+            let ext = ext.trim_start_matches('.').to_lowercase();
+            languages.extensions.insert(ext, name.clone());
+        }
+        for filename in def.filenames {
+            languages.filenames.insert(filename, name.clone());
+        }
+    }
+    languages
+}
+
+fn languages() -> &'static Languages {
+    static COMPILED: OnceLock<Languages> = OnceLock::new();
+    COMPILED.get_or_init(|| {
+        compile(serde_yaml::from_str(LANGUAGES).expect("bundled languages are valid yaml"))
+    })
+}
+
+/// the language of a path, when known
+pub fn of(path: &str) -> Option<String> {
+    let languages = languages();
+    let name = path.rsplit('/').next().unwrap_or_default();
+    languages
+        .filenames
+        .get(name)
+        .or_else(|| {
+            let (_, ext) = name.rsplit_once('.')?;
+            languages.extensions.get(&ext.to_lowercase())
+        })
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_languages() {
+        assert_eq!(of("src/main.rs").as_deref(), Some("Rust"));
+        assert_eq!(of("web/App.TSX").as_deref(), Some("TSX"));
+        assert_eq!(of("Makefile").as_deref(), Some("Makefile"));
+        assert_eq!(of("docker/Dockerfile").as_deref(), Some("Dockerfile"));
+        assert_eq!(of("Cargo.lock").as_deref(), Some("TOML"));
+        assert_eq!(of("bin/run"), None);
+        assert_eq!(of("archive.unknown"), None);
+    }
+
+    #[test]
+    fn bundled_extensions_and_filenames_are_unique() -> Result<(), Box<dyn std::error::Error>> {
+        let defs: BTreeMap<String, LanguageDef> = serde_yaml::from_str(LANGUAGES)?;
+        let mut seen = std::collections::HashSet::new();
+        for def in defs.values() {
+            for ext in &def.extensions {
+                assert!(seen.insert(ext.to_lowercase()), "{} is claimed twice", ext);
+            }
+            for filename in &def.filenames {
+                assert!(
+                    seen.insert(filename.clone()),
+                    "{} is claimed twice",
+                    filename
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,2362 @@
+//! transforms and categorizes git log output. expects input in the format of the output of
+//! `git log --pretty=format:'"%H","%ae","%ai"' --numstat --no-merges`,
+//! or with commit messages,
+//! `git log --pretty=format:'"%H","%ae","%ai","%s"%n%b%x1e' --numstat --no-merges`.
+//! either may include the committer's `"%ce","%ci"` following the author's `"%ai"`,
+//! then the parents' `"%P"`, and may end with unquoted `%d` decorations.
+//! headers may instead be json objects, as in `{"sha":"%H","author":"%ae","timestamp":"%ai"}`.
+//! changes are parsed by [`run`] and handed to an [`Emitter`]
+
+use chrono::{DateTime, FixedOffset, SecondsFormat, Timelike, Utc};
+use flate2::read::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fmt,
+    fs::File,
+    io::{stdin, stdout, BufRead, BufReader, BufWriter, StdoutLock},
+    path::Path as StdPath,
+    str::FromStr,
+};
+
+#[cfg(any(feature = "github", feature = "gitlab", feature = "bitbucket"))]
+pub mod api;
+pub mod attributes;
+#[cfg(feature = "bitbucket")]
+pub mod bitbucket;
+pub mod buckets;
+pub mod buffer;
+pub mod cache;
+pub mod categorizer;
+pub mod churn;
+#[cfg(feature = "clickhouse")]
+pub mod clickhouse;
+pub mod coauthors;
+pub mod codeowners;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod commits;
+pub mod config;
+pub mod conventional;
+pub mod coupling;
+pub mod dedupe;
+#[cfg(feature = "duckdb")]
+pub mod duck;
+#[cfg(feature = "elasticsearch")]
+pub mod elastic;
+pub mod error;
+pub mod expr;
+pub mod filter;
+pub mod follow;
+pub mod format;
+pub mod git;
+#[cfg(feature = "github")]
+pub mod github;
+#[cfg(feature = "gitlab")]
+pub mod gitlab;
+pub mod header;
+pub mod hotspots;
+pub mod language;
+#[cfg(feature = "libgit2")]
+pub mod libgit;
+pub mod limit;
+pub mod logging;
+pub mod mailmap;
+pub mod manifest;
+pub mod metrics;
+#[cfg(feature = "s3")]
+pub mod object;
+pub mod output;
+pub mod ownership;
+pub mod parallel;
+pub mod partition;
+#[cfg(feature = "postgres")]
+pub mod pg;
+pub mod prefix;
+pub mod progress;
+#[cfg(feature = "duckdb")]
+pub mod query;
+pub mod references;
+pub mod renames;
+pub mod rotate;
+pub mod rules;
+pub mod schema;
+pub mod size;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod state;
+pub mod summarize;
+pub mod summary;
+#[cfg(feature = "kafka")]
+pub mod topic;
+pub mod trend;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod watch;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+use error::LinecatError;
+use header::HeaderFormat;
+use logging::Traced;
+use manifest::{Forge, Input, Source};
+use output::Compress;
+
+/// a commit header line, as formatted by [`git::PRETTY_FORMAT`] or [`git::MESSAGE_FORMAT`],
+/// or a json object of its fields
+#[derive(Deserialize)]
+struct HeaderLine {
+    sha: String,
+    author: String,
+    timestamp: String,
+    committer: Option<String>,
+    commit_timestamp: Option<String>,
+    parents: Option<String>,
+    subject: Option<String>,
+    decorations: Option<String>,
+}
+
+impl HeaderLine {
+    /// reads the fields of a header line by position. the committer's pair is
+    /// told apart by its timestamp, parents by their shas, and the subject is
+    /// whatever remains, commas and quotes included
+    fn split(line: &str) -> Option<Self> {
+        let (line, decorations) = header::decorations(line);
+        let fields = header::fields(line)?;
+        let field = |i: usize| fields.get(i).map(|range| line[range.clone()].to_string());
+        let timestamp = |value: &str| {
+            let bytes = value.as_bytes();
+            bytes.len() >= 10
+                && bytes[..10].iter().enumerate().all(|(i, b)| match i {
+                    4 | 7 => *b == b'-',
+                    _ => b.is_ascii_digit(),
+                })
+        };
+        let sha = field(0).filter(|sha| !sha.is_empty() && !sha.contains(char::is_whitespace))?;
+        let author = field(1).filter(|author| !author.is_empty())?;
+        // names in place of emails, as of `%an`, push the timestamp out of place
+        let author_timestamp = field(2).filter(|at| timestamp(at))?;
+        let mut next = 3;
+        let (committer, commit_timestamp) = match (field(next), field(next + 1)) {
+            (Some(committer), Some(at)) if !committer.is_empty() && timestamp(&at) => {
+                next += 2;
+                (Some(committer), Some(at))
+            }
+            _ => (None, None),
+        };
+        let parents = field(next).filter(|parents| {
+            parents
+                .split(' ')
+                .filter(|_| !parents.is_empty())
+                .all(|sha| {
+                    (40..=64).contains(&sha.len())
+                        && sha.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+                })
+        });
+        if parents.is_some() {
+            next += 1;
+        }
+        let subject = fields
+            .get(next)
+            .map(|first| line[first.start..fields[fields.len() - 1].end].to_string());
+        Some(HeaderLine {
+            sha,
+            author,
+            timestamp: author_timestamp,
+            committer,
+            commit_timestamp,
+            parents,
+            subject,
+            decorations: decorations.map(Into::into),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Header {
+    pub sha: String,
+    pub author: String,
+    pub timestamp: String,
+    /// the committer's email, when the pretty format includes `%ce`
+    pub committer: Option<String>,
+    /// when the commit was committed, when the pretty format includes `%ci`
+    pub commit_timestamp: Option<String>,
+    /// shas of the commit's parents, when the pretty format includes `%P`
+    pub parents: Option<Vec<String>>,
+    /// tags and branches pointing at the commit, when the pretty format ends with `%d`
+    pub tags: Option<Vec<String>>,
+    pub branches: Option<Vec<String>>,
+    pub subject: Option<String>,
+    /// the commit message body, which follows rather than being part of the header line
+    pub body: Option<String>,
+}
+
+impl FromStr for Header {
+    type Err = Box<dyn Error>;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let HeaderLine {
+            sha,
+            author,
+            timestamp,
+            committer,
+            commit_timestamp,
+            parents,
+            subject,
+            decorations,
+        } = match line.starts_with('{') {
+            // json objects of the same fields, which survive quotes and commas in values
+            true => {
+                serde_json::from_str(line).map_err(|e| format!("invalid json header: {}", e))?
+            }
+            _ => HeaderLine::split(line).ok_or("expected a commit header")?,
+        };
+        let (tags, branches) = decorations.as_deref().map(decorated).unwrap_or_default();
+        Ok(Header {
+            sha,
+            author,
+            timestamp,
+            committer,
+            commit_timestamp,
+            parents: parents.map(|parents| parents.split_whitespace().map(Into::into).collect()),
+            tags,
+            branches,
+            subject,
+            body: None,
+        })
+    }
+}
+
+/// splits `%d` decorations, as in ` (HEAD -> main, tag: v1.0, origin/main)`, into
+/// the tags and branches they name. `HEAD`s and shallow clones' `grafted` are left out
+pub(crate) fn decorated(decorations: &str) -> (Option<Vec<String>>, Option<Vec<String>>) {
+    let (mut tags, mut branches) = (Vec::new(), Vec::new());
+    let names = decorations
+        .trim()
+        .trim_start_matches('(')
+        .trim_end_matches(')');
+    for name in names.split(", ").filter(|name| !name.is_empty()) {
+        let name = name.strip_prefix("HEAD -> ").unwrap_or(name);
+        match name.strip_prefix("tag: ") {
+            Some(tag) => tags.push(tag.trim_start_matches("refs/tags/").to_string()),
+            _ if name == "HEAD" || name.ends_with("/HEAD") || name == "grafted" => (),
+            _ => branches.push(
+                name.trim_start_matches("refs/heads/")
+                    .trim_start_matches("refs/remotes/")
+                    .to_string(),
+            ),
+        }
+    }
+    let some = |names: Vec<String>| Some(names).filter(|names| !names.is_empty());
+    (some(tags), some(branches))
+}
+
+/// path changes of a commit. binary files, which git reports
+/// with `-` in place of line counts, have neither
+pub struct Path {
+    pub additions: Option<usize>,
+    pub deletions: Option<usize>,
+    /// the path as git printed it, possibly quoted
+    pub path: String,
+}
+
+impl FromStr for Path {
+    type Err = Box<dyn Error>;
+
+    /// splits off the two leading numeric columns, treating the
+    /// remainder of the line as the path, spaces and all
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut rest = line;
+        let mut column = || -> Result<Option<usize>, Box<dyn Error>> {
+            let trimmed = rest.trim_start();
+            let end = trimmed
+                .find(char::is_whitespace)
+                .ok_or("expected additions, deletions, and a path")?;
+            rest = &trimmed[end..];
+            match &trimmed[..end] {
+                "-" => Ok(None),
+                count => Ok(Some(count.parse()?)),
+            }
+        };
+        let additions = column()?;
+        let deletions = column()?;
+        let path = rest.strip_prefix('\t').unwrap_or_else(|| rest.trim_start());
+        if path.is_empty() {
+            return Err("expected a path".into());
+        }
+        Ok(Path {
+            additions,
+            deletions,
+            path: path.into(),
+        })
+    }
+}
+
+/// unquotes a path git has quoted for containing special characters,
+/// as in `"a\303\251.txt"`. unquoted paths are returned as is
+fn unquote(path: &str) -> String {
+    let quoted = match path.strip_prefix('"').and_then(|p| p.strip_suffix('"')) {
+        Some(quoted) => quoted,
+        _ => return path.into(),
+    };
+    let mut bytes = Vec::with_capacity(quoted.len());
+    let mut chars = quoted.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('a') => bytes.push(0x07),
+            Some('b') => bytes.push(0x08),
+            Some('f') => bytes.push(0x0c),
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('v') => bytes.push(0x0b),
+            Some(d @ '0'..='7') => {
+                let mut octal = d.to_digit(8).unwrap_or_default();
+                for _ in 0..2 {
+                    if let Some(d) = chars.peek().and_then(|c| c.to_digit(8)) {
+                        octal = octal * 8 + d;
+                        chars.next();
+                    }
+                }
+                bytes.push(octal as u8);
+            }
+            Some(other) => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(other.encode_utf8(&mut buf).as_bytes());
+            }
+            None => bytes.push(b'\\'),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Test,
+    Docs,
+    Build,
+    Ci,
+    Config,
+    Vendored,
+    Generated,
+    Assets,
+    #[default]
+    Default,
+    /// a category named by user defined rules
+    #[serde(untagged)]
+    Custom(String),
+}
+
+impl Category {
+    /// the category's serialized name
+    pub fn name(&self) -> &str {
+        match self {
+            Category::Test => "test",
+            Category::Docs => "docs",
+            Category::Build => "build",
+            Category::Ci => "ci",
+            Category::Config => "config",
+            Category::Vendored => "vendored",
+            Category::Generated => "generated",
+            Category::Assets => "assets",
+            Category::Default => "default",
+            Category::Custom(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// the type of a serialized field, for outputs with typed schemas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    String,
+    Integer,
+    Boolean,
+    Float,
+    /// a `%ai` or rfc3339 formatted date
+    Timestamp,
+    /// a list of strings
+    List,
+}
+
+/// the `chrono` format of `%ai` timestamps
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+/// parses a `%ai` or strict iso 8601 `%aI` formatted timestamp
+pub fn parse_timestamp(timestamp: &str) -> Result<DateTime<FixedOffset>, Box<dyn Error>> {
+    DateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+        .or_else(|_| DateTime::parse_from_rfc3339(timestamp))
+        .map_err(|e| format!("invalid timestamp {}: {}", timestamp, e).into())
+}
+
+/// a selection of serialized field names and types, for outputs with fixed columns
+pub type Fields = Vec<(&'static str, Kind)>;
+
+/// serialized field names and types, in order, including optional fields
+/// which may be omitted from individual records
+pub const FIELDS: &[(&str, Kind)] = &[
+    ("repo", Kind::String),
+    ("sha", Kind::String),
+    ("author", Kind::String),
+    ("timestamp", Kind::Timestamp),
+    ("timestamp_utc", Kind::Timestamp),
+    ("epoch_seconds", Kind::Integer),
+    ("path", Kind::String),
+    ("old_path", Kind::String),
+    ("renamed", Kind::Boolean),
+    ("ext", Kind::String),
+    ("category", Kind::String),
+    ("additions", Kind::Integer),
+    ("deletions", Kind::Integer),
+    ("binary", Kind::Boolean),
+    ("dir", Kind::String),
+    ("top_dir", Kind::String),
+    ("path_prefix", Kind::String),
+    ("subject", Kind::String),
+    ("body", Kind::String),
+    ("author_raw", Kind::String),
+    ("author_canonical", Kind::String),
+    ("language", Kind::String),
+    ("commit_type", Kind::String),
+    ("scope", Kind::String),
+    ("breaking", Kind::Boolean),
+    ("references", Kind::List),
+    ("committer", Kind::String),
+    ("commit_timestamp", Kind::Timestamp),
+    ("co_authors", Kind::List),
+    ("author_role", Kind::String),
+    ("owners", Kind::List),
+    ("author_user", Kind::String),
+    ("author_domain", Kind::String),
+    ("year", Kind::Integer),
+    ("year_month", Kind::String),
+    ("iso_week", Kind::String),
+    ("weekday", Kind::String),
+    ("hour_utc", Kind::Integer),
+    ("local_hour", Kind::Integer),
+    ("local_weekday", Kind::String),
+    ("record_id", Kind::String),
+    ("total_lines", Kind::Integer),
+    ("pct_changed", Kind::Float),
+    ("canonical_path", Kind::String),
+    ("merge", Kind::Boolean),
+    ("parents", Kind::List),
+    ("tags", Kind::List),
+    ("branches", Kind::List),
+    ("submodule", Kind::String),
+    ("categories", Kind::List),
+];
+
+/// a changed path of a commit. changes are deserialized from what `--map` makes of
+/// them, so fields they don't have are rejected
+#[derive(Clone, Default, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Change {
+    pub repo: String,
+    pub sha: String,
+    pub author: String,
+    pub timestamp: String,
+    /// `timestamp` normalized to rfc3339 utc, when it could be parsed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_utc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epoch_seconds: Option<i64>,
+    pub path: String,
+    /// the path before a rename
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub renamed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ext: Option<String>,
+    pub category: Category,
+    /// every category of the path, the first of which is its `category`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<Category>,
+    /// lines added, unknown for binary files
+    pub additions: Option<usize>,
+    pub deletions: Option<usize>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub binary: bool,
+    /// the directory of the path, omitted for paths at the root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// the first directory of the path, omitted for paths at the root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_dir: Option<String>,
+    /// the directory of the path truncated to a fixed depth
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// the author as git reported it, when canonicalized by a mailmap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_raw: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_canonical: Option<String>,
+    /// the language of the path, resolved from its file name or extension
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// the type of a conventional commit subject, as in `feat` or `fix`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub breaking: bool,
+    /// issues and tickets referenced by the commit message, as in `#1234` or `JIRA-456`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub references: Option<Vec<String>>,
+    /// the committer's email, which differs from the author's for rebased or applied commits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub committer: Option<String>,
+    /// when the commit was committed, as git reported it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_timestamp: Option<String>,
+    /// emails of those credited by `Co-authored-by:` trailers of the commit message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub co_authors: Option<Vec<String>>,
+    /// whether `author` is the commit's `author` or one of its `co_author`s,
+    /// when changes are emitted once per co-author
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_role: Option<String>,
+    /// owners of the path assigned by a CODEOWNERS file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owners: Option<Vec<String>>,
+    /// the author's email before its `@`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_user: Option<String>,
+    /// the author's email after its `@`, lowercased
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_domain: Option<String>,
+    /// the utc year the change was authored in, given `--time-buckets`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    /// as in `2019-08`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year_month: Option<String>,
+    /// as in `2019-W32`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iso_week: Option<String>,
+    /// as in `Friday`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weekday: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hour_utc: Option<u32>,
+    /// the hour of the day in the author's own timezone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_hour: Option<u32>,
+    /// the day of the week in the author's own timezone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_weekday: Option<String>,
+    /// a stable id of the change, as [`format::id`] derives it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_id: Option<String>,
+    /// lines of the path at the commit, given `--line-counts`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_lines: Option<usize>,
+    /// the percent of the path's lines before and after the commit it changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pct_changed: Option<f64>,
+    /// the newest name of the path's file, given `--follow-renames`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canonical_path: Option<String>,
+    /// whether this is the record of a merge commit rather than of one of its paths,
+    /// given `--include-merges`. records of merges have an empty `path`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub merge: bool,
+    /// shas of the commit's parents, when the pretty format includes `%P`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parents: Option<Vec<String>>,
+    /// tags pointing at the commit, when the pretty format ends with `%d`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// local and remote branches pointing at the commit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branches: Option<Vec<String>>,
+    /// the path of the submodule the change was committed to, relative to the repository
+    /// it's a submodule of, given `--recurse-submodules`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub submodule: Option<String>,
+}
+
+const VENDORED_DIRS: &[&str] = &[
+    "vendor/",
+    "third_party/",
+    "node_modules/",
+    "bower_components/",
+];
+const GENERATED_DIRS: &[&str] = &["__generated__/"];
+const GENERATED_NAMES: &[&str] = &[
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "pnpm-lock.yaml",
+    "go.sum",
+];
+const GENERATED_EXTS: &[&str] = &["lock"];
+/// endings of names given to generated, compiled and minified files
+const GENERATED_SUFFIXES: &[&str] = &[
+    ".pb.go",
+    ".pb.gw.go",
+    ".pb.cc",
+    ".pb.h",
+    "_pb2.py",
+    "_pb2_grpc.py",
+    "_pb.js",
+    "_pb.d.ts",
+    "_generated.rs",
+    "_generated.go",
+    ".generated.ts",
+    ".generated.cs",
+    ".g.dart",
+    ".freezed.dart",
+    ".designer.cs",
+    ".min.js",
+    ".min.css",
+    ".js.map",
+    ".css.map",
+];
+/// beginnings of names given to generated files, as in kubernetes' `zz_generated.deepcopy.go`
+const GENERATED_PREFIXES: &[&str] = &["zz_generated."];
+/// directories of tests, their fixtures and mocks
+const TEST_DIRS: &[&str] = &[
+    "test",
+    "tests",
+    "testing",
+    "testdata",
+    "__tests__",
+    "__mocks__",
+    "spec",
+    "specs",
+    "fixtures",
+    "e2e",
+];
+/// endings of the lowercased names of test files, before their extension, which
+/// may be named after the files they test, as in `parse_test.go` or `app.spec.ts`
+const TEST_SUFFIXES: &[&str] = &["_test", "_tests", "_spec", "_unittest", ".test", ".spec"];
+/// endings of the names of test classes, as in `ParserTest.java` or `ParserSpec.scala`
+const TEST_CLASS_SUFFIXES: &[&str] = &["Test", "Tests", "Spec", "IT"];
+/// extensions of languages whose files are named after the classes they define
+const CLASS_EXTS: &[&str] = &[
+    "java", "kt", "kts", "scala", "groovy", "cs", "fs", "vb", "swift", "php", "m", "mm",
+];
+const CI_DIRS: &[&str] = &[".github/workflows/", ".circleci/", ".buildkite/"];
+const CI_NAMES: &[&str] = &[
+    ".travis.yml",
+    ".gitlab-ci.yml",
+    "jenkinsfile",
+    "azure-pipelines.yml",
+];
+const BUILD_NAMES: &[&str] = &[
+    "dockerfile",
+    "makefile",
+    "cargo.toml",
+    "build.rs",
+    "package.json",
+    "pom.xml",
+    "build.gradle",
+    "build.sbt",
+    "cmakelists.txt",
+    "setup.py",
+    "gemfile",
+    "go.mod",
+];
+const BUILD_EXTS: &[&str] = &["mk", "gradle", "bzl", "cmake"];
+const DOCS_DIRS: &[&str] = &["docs/", "doc/"];
+const DOCS_NAMES: &[&str] = &["license", "changelog", "authors", "codeowners"];
+const DOCS_EXTS: &[&str] = &["md", "markdown", "rst", "adoc", "asciidoc"];
+const CONFIG_EXTS: &[&str] = &[
+    "yml",
+    "yaml",
+    "toml",
+    "ini",
+    "cfg",
+    "conf",
+    "properties",
+    "env",
+];
+const ASSETS_EXTS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "svg", "ico", "webp", "bmp", "woff", "woff2", "ttf", "otf", "eot",
+    "mp3", "mp4", "wav", "ogg", "webm", "pdf",
+];
+
+/// splits numstat's rename syntax, `old => new` or `prefix/{old => new}/suffix`,
+/// into old and new paths
+fn rename(path: &str) -> Option<(String, String)> {
+    let (prefix, rest, suffix) = match (path.find('{'), path.rfind('}')) {
+        (Some(open), Some(close)) if open < close => {
+            (&path[..open], &path[open + 1..close], &path[close + 1..])
+        }
+        _ => ("", path, ""),
+    };
+    let (old, new) = rest.split_once(" => ")?;
+    // either side of a braced rename may be empty, as in `src/{ => sub}/lib.rs`
+    let join = |part: &str| format!("{}{}{}", prefix, part, suffix).replace("//", "/");
+    Some((join(old), join(new)))
+}
+
+/// whether a path is of a test, its fixtures or mocks by the conventions of
+/// test directories and of test file names, as in `tests/`, `test_parse.py` and
+/// `ParserTest.java`, but not `contest.rs` or `latest_config.yml`
+fn is_test(path: &str) -> bool {
+    let mut segments = path.split('/').rev();
+    let name = segments.next().unwrap_or_default();
+    if segments.any(|dir| TEST_DIRS.contains(&dir.to_lowercase().as_str())) {
+        return true;
+    }
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, ext.to_lowercase()),
+        _ => (name, String::new()),
+    };
+    let lower = stem.to_lowercase();
+    // class names are matched by their case, as `Contest` isn't a test
+    if CLASS_EXTS.contains(&ext.as_str()) {
+        return TEST_CLASS_SUFFIXES
+            .iter()
+            .any(|suffix| stem.len() > suffix.len() && stem.ends_with(suffix))
+            || (stem.starts_with("Test") && stem[4..].starts_with(char::is_uppercase));
+    }
+    match ext.as_str() {
+        "py" if name == "conftest.py" || lower.starts_with("test_") => true,
+        "rb" | "c" | "cc" | "cpp" | "sh" if lower.starts_with("test_") => true,
+        _ => TEST_SUFFIXES.iter().any(|suffix| lower.ends_with(suffix)),
+    }
+}
+
+impl Change {
+    /// replaces the author along with its user and domain, returning the author replaced
+    pub fn set_author(
+        &mut self,
+        author: String,
+    ) -> String {
+        let (user, domain) = match author.rsplit_once('@') {
+            Some((user, domain)) => (Some(user.into()), Some(domain.to_lowercase())),
+            _ => (None, None),
+        };
+        self.author_user = user;
+        self.author_domain = domain;
+        std::mem::replace(&mut self.author, author)
+    }
+
+    /// the record of a merge, totaling the `changes` of its diff against
+    /// its first parent, when they're known
+    pub fn merge(
+        repository: String,
+        header: Header,
+        changes: &[Change],
+    ) -> Change {
+        let total = |count: fn(&Change) -> Option<usize>| {
+            Some(changes.iter().filter_map(count).sum()).filter(|_| !changes.is_empty())
+        };
+        let mut change: Change = (
+            repository,
+            header,
+            Path {
+                additions: total(|change| change.additions),
+                deletions: total(|change| change.deletions),
+                path: String::new(),
+            },
+        )
+            .into();
+        change.binary = false;
+        change.merge = true;
+        change
+    }
+
+    /// the built-in category of a path, the first of its categories
+    pub fn categorize(path: &str) -> Category {
+        Change::categorize_with(path, None, None)
+    }
+
+    /// the first of the built-in categories of a path, as [`Change::categories_with`] finds them
+    pub fn categorize_with(
+        path: &str,
+        vendored: Option<bool>,
+        generated: Option<bool>,
+    ) -> Category {
+        Change::categories_with(path, vendored, generated).remove(0)
+    }
+
+    /// every built-in category of a path, in order, or `default` for paths of none.
+    /// paths may be known to be vendored or generated, or not, when given, as the
+    /// `.gitattributes` of a repository may say
+    pub fn categories_with(
+        path: &str,
+        vendored: Option<bool>,
+        generated: Option<bool>,
+    ) -> Vec<Category> {
+        let lower = path.to_lowercase();
+        let in_dir = |dirs: &[&str]| {
+            dirs.iter()
+                .any(|dir| lower.starts_with(dir) || lower.contains(&format!("/{}", dir)))
+        };
+        let name = lower.rsplit('/').next().unwrap_or_default();
+        let ext = StdPath::new(name)
+            .extension()
+            .and_then(OsStr::to_str)
+            .unwrap_or_default();
+        let generated = generated.unwrap_or_else(|| {
+            in_dir(GENERATED_DIRS)
+                || GENERATED_NAMES.contains(&name)
+                || GENERATED_EXTS.contains(&ext)
+                || GENERATED_SUFFIXES
+                    .iter()
+                    .any(|suffix| name.ends_with(suffix))
+                || GENERATED_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+        });
+        let categories: Vec<_> = vec![
+            (
+                vendored.unwrap_or_else(|| in_dir(VENDORED_DIRS)),
+                Category::Vendored,
+            ),
+            (generated, Category::Generated),
+            (is_test(path), Category::Test),
+            (in_dir(CI_DIRS) || CI_NAMES.contains(&name), Category::Ci),
+            (
+                BUILD_NAMES.contains(&name) || BUILD_EXTS.contains(&ext),
+                Category::Build,
+            ),
+            (
+                in_dir(DOCS_DIRS) || DOCS_NAMES.contains(&name) || DOCS_EXTS.contains(&ext),
+                Category::Docs,
+            ),
+            (CONFIG_EXTS.contains(&ext), Category::Config),
+            (ASSETS_EXTS.contains(&ext), Category::Assets),
+        ]
+        .into_iter()
+        .filter_map(|(matched, category)| matched.then_some(category))
+        .collect();
+        if categories.is_empty() {
+            vec![Category::Default]
+        } else {
+            categories
+        }
+    }
+
+    /// makes a category, as rules or categorizers label a path, the change's
+    /// primary `category`, ahead of the other `categories` it has
+    pub fn categorize_as(
+        &mut self,
+        category: Category,
+    ) {
+        self.categories
+            .retain(|other| *other != category && *other != Category::Default);
+        self.categories.insert(0, category.clone());
+        self.category = category;
+    }
+}
+
+impl From<(String, Header, Path)> for Change {
+    fn from(line: (String, Header, Path)) -> Change {
+        let (
+            repo,
+            Header {
+                sha,
+                author,
+                timestamp,
+                committer,
+                commit_timestamp,
+                parents,
+                tags,
+                branches,
+                subject,
+                body,
+            },
+            Path {
+                additions,
+                deletions,
+                path,
+            },
+        ) = line;
+        let (old_path, path) = match rename(&path) {
+            Some((old, new)) => (Some(unquote(&old)), unquote(&new)),
+            _ => (None, unquote(&path)),
+        };
+        let renamed = old_path.is_some();
+        let categories = Change::categories_with(&path, None, None);
+        let ext = StdPath::new(&path)
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(|s| s.into());
+        let language = language::of(&path);
+        let dir = path.rsplit_once('/').map(|(dir, _)| dir.to_string());
+        let top_dir = path.split_once('/').map(|(top, _)| top.to_string());
+        let conventional = subject
+            .as_deref()
+            .and_then(|subject| conventional::parse(subject, body.as_deref()));
+        let co_authors = body
+            .as_deref()
+            .map(coauthors::parse)
+            .filter(|co_authors| !co_authors.is_empty());
+        let parsed = parse_timestamp(&timestamp).ok();
+        let mut change = Change {
+            repo,
+            sha,
+            author: String::new(),
+            timestamp_utc: parsed.map(|t| {
+                t.with_timezone(&Utc)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true)
+            }),
+            epoch_seconds: parsed.map(|t| t.timestamp()),
+            timestamp,
+            path,
+            old_path,
+            renamed,
+            category: categories[0].clone(),
+            categories,
+            ext,
+            binary: additions.is_none() && deletions.is_none(),
+            additions,
+            deletions,
+            dir,
+            top_dir,
+            path_prefix: None,
+            subject,
+            body,
+            author_raw: None,
+            author_canonical: None,
+            language,
+            breaking: conventional.as_ref().is_some_and(|c| c.breaking),
+            scope: conventional.as_ref().and_then(|c| c.scope.clone()),
+            commit_type: conventional.map(|c| c.commit_type),
+            references: None,
+            committer,
+            commit_timestamp,
+            co_authors,
+            author_role: None,
+            owners: None,
+            author_user: None,
+            author_domain: None,
+            year: None,
+            year_month: None,
+            iso_week: None,
+            weekday: None,
+            hour_utc: None,
+            local_hour: parsed.map(|t| t.hour()),
+            local_weekday: parsed.map(|t| t.format("%A").to_string()),
+            record_id: None,
+            total_lines: None,
+            pct_changed: None,
+            canonical_path: None,
+            merge: false,
+            parents,
+            tags,
+            branches,
+            submodule: None,
+        };
+        change.set_author(author);
+        change.record_id = Some(format::id(&change));
+        change
+    }
+}
+
+enum State {
+    Reset,
+    /// collecting commit message body lines, up to a record separator
+    Body(Header, Vec<String>),
+    /// expecting paths of a commit
+    Next(Header),
+}
+
+/// a destination for changes
+pub trait Emitter {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// writes out changes buffered so far, where the emitter buffers them
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// called once after the last change has been emitted
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+impl<E> Emitter for Box<E>
+where
+    E: Emitter + ?Sized,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        (**self).emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        (**self).flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        (**self).finish()
+    }
+}
+
+/// collects the changes emitted into it, for tests of other emitters
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct Collect(pub Vec<Change>);
+
+#[cfg(test)]
+impl Emitter for Collect {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.0.push(line);
+        Ok(())
+    }
+}
+
+/// emits newline delimited json to stdout, locked and buffered for as long as
+/// the emitter lives
+pub struct Stdout(format::Json<BufWriter<StdoutLock<'static>>>);
+
+impl Stdout {
+    pub fn new() -> Self {
+        Stdout(format::Json(BufWriter::new(stdout().lock())))
+    }
+}
+
+impl Default for Stdout {
+    fn default() -> Self {
+        Stdout::new()
+    }
+}
+
+impl Emitter for Stdout {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.0.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.0.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.0.finish()
+    }
+}
+
+/// how history is read given a `--repo-path`
+#[derive(Debug)]
+pub enum Backend {
+    Git,
+    Libgit2,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "git" => Ok(Backend::Git),
+            "libgit2" => Ok(Backend::Libgit2),
+            other => Err(format!("unsupported backend {}", other)),
+        }
+    }
+}
+
+/// how `git log` output is parsed into changes
+#[derive(Clone, Debug, Default)]
+pub struct Parse {
+    /// whether headers are followed by commit messages, as with [`git::MESSAGE_FORMAT`]
+    pub with_message: bool,
+    /// whether changes of binary files are emitted
+    pub include_binary: bool,
+    /// whether merges are emitted, as a record of the commit rather than of its paths
+    pub merges: bool,
+    /// the format of header lines, when not the one [`Header`]s are parsed from by default
+    pub header_format: Option<HeaderFormat>,
+    /// whether the submodules of repositories read with the git backend are read too,
+    /// recursively, their changes tagged with the submodule's path
+    pub submodules: bool,
+}
+
+impl Parse {
+    fn header(
+        &self,
+        line: &str,
+    ) -> Result<Header, Box<dyn Error>> {
+        match &self.header_format {
+            Some(format) => format.parse(line),
+            _ => line.parse(),
+        }
+    }
+}
+
+/// a commit whose changes are held back until it's known whether it's a merge
+struct Pending {
+    header: Header,
+    changes: Vec<Change>,
+    merge: bool,
+}
+
+/// holds back the changes of each commit until it's known whether it's a merge, as those
+/// with more than one `%P` parent, or whose header `-m` repeats for each parent, are.
+/// merges listed as headers without paths can't be told apart from empty commits, so are dropped
+#[derive(Default)]
+struct Merges(Option<Pending>);
+
+impl Merges {
+    fn header<E>(
+        &mut self,
+        repository: &str,
+        header: &Header,
+        emitter: &mut E,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        E: Emitter,
+    {
+        if let Some(pending) = self.0.as_mut().filter(|p| p.header.sha == header.sha) {
+            pending.merge = true;
+            return Ok(());
+        }
+        self.settle(repository, emitter)?;
+        self.0 = Some(Pending {
+            header: header.clone(),
+            changes: Vec::new(),
+            merge: header
+                .parents
+                .as_ref()
+                .is_some_and(|parents| parents.len() > 1),
+        });
+        Ok(())
+    }
+
+    /// holds a change of the pending commit. those against any but
+    /// the first parent of a merge are dropped
+    fn change(
+        &mut self,
+        change: Change,
+    ) {
+        if let Some(pending) = self.0.as_mut().filter(|p| !p.merge) {
+            pending.changes.push(change);
+        }
+    }
+
+    /// emits the changes of the pending commit, or the record of a merge
+    fn settle<E>(
+        &mut self,
+        repository: &str,
+        emitter: &mut E,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        E: Emitter,
+    {
+        match self.0.take() {
+            Some(Pending {
+                header,
+                changes,
+                merge,
+            }) if merge => emitter.emit(Change::merge(repository.into(), header, &changes)),
+            Some(Pending { changes, .. }) => changes
+                .into_iter()
+                .try_for_each(|change| emitter.emit(change)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// the lines of a reader as [`BufRead::lines`] reads them, but with invalid utf-8 replaced
+/// rather than ending the read at the first line with any, as of latin-1 paths or messages
+pub fn lossy_lines<R>(reader: R) -> impl Iterator<Item = String>
+where
+    R: BufRead,
+{
+    reader.split(b'\n').map_while(Result::ok).map(|mut line| {
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        String::from_utf8(line)
+            .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+    })
+}
+
+/// opens a log file, decompressing `.gz` and `.zst` files as they're read
+fn open(logs: &str) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    let file = File::open(logs).map_err(|source| LinecatError::Io {
+        origin: logs.into(),
+        source,
+    })?;
+    Ok(match Compress::infer(StdPath::new(logs)) {
+        Compress::None => Box::new(BufReader::new(file)),
+        Compress::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        #[cfg(feature = "zstd")]
+        Compress::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::new(file)?)),
+        #[cfg(not(feature = "zstd"))]
+        Compress::Zstd => return Err("git-linecat was built without the zstd feature".into()),
+    })
+}
+
+/// maps an uncompressed log file into memory, so its lines may be read as slices
+/// of the map rather than copied into a string each
+#[cfg(feature = "mmap")]
+fn map(logs: &str) -> Result<Option<memmap2::Mmap>, Box<dyn Error>> {
+    if Compress::infer(StdPath::new(logs)) != Compress::None {
+        return Ok(None);
+    }
+    let io = |source| LinecatError::Io {
+        origin: logs.into(),
+        source,
+    };
+    let file = File::open(logs).map_err(io)?;
+    if !file.metadata().map_err(io)?.is_file() {
+        return Ok(None);
+    }
+    // safety: the map is only read, and logs are expected not to be truncated as they're read
+    let map = unsafe { memmap2::Mmap::map(&file) }.map_err(io)?;
+    Ok(Some(map))
+}
+
+/// malformed lines warned of individually before only being counted
+const REPORTED_LINES: usize = 10;
+
+/// the number of well formed lines of each kind read from a source
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Lines {
+    /// commit header lines
+    pub headers: usize,
+    /// commit messages, each terminated by a record separator
+    pub bodies: usize,
+    /// `--numstat` lines of text files
+    pub paths: usize,
+    /// `--numstat` lines of binary files, which are not emitted
+    pub binary: usize,
+    pub blank: usize,
+}
+
+/// handles lines which aren't part of a commit, failing on the first when `strict`
+/// and otherwise skipping them with a warning on stderr
+#[derive(Debug)]
+pub struct Malformed {
+    /// where lines were read from, as in a file name
+    pub origin: String,
+    pub strict: bool,
+    /// the number of lines skipped
+    pub skipped: usize,
+    /// the lines which were well formed
+    pub lines: Lines,
+}
+
+impl Malformed {
+    pub fn new(
+        origin: impl Into<String>,
+        strict: bool,
+    ) -> Self {
+        Malformed {
+            origin: origin.into(),
+            strict,
+            skipped: 0,
+            lines: Lines::default(),
+        }
+    }
+
+    /// a line found where a commit header was expected
+    fn header(
+        &mut self,
+        number: usize,
+        line: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let origin = self.origin.clone();
+        self.line(LinecatError::ParseHeader {
+            origin,
+            line: number,
+            found: line.into(),
+        })
+    }
+
+    /// a line found where a path or commit header was expected
+    fn path(
+        &mut self,
+        number: usize,
+        line: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let origin = self.origin.clone();
+        self.line(LinecatError::ParsePath {
+            origin,
+            line: number,
+            found: line.into(),
+        })
+    }
+
+    fn line(
+        &mut self,
+        error: LinecatError,
+    ) -> Result<(), Box<dyn Error>> {
+        progress::malformed();
+        if self.strict {
+            return Err(error.into());
+        }
+        self.skipped += 1;
+        if self.skipped <= REPORTED_LINES {
+            tracing::warn!("skipping {}", error);
+        }
+        Ok(())
+    }
+
+    fn finish(&self) {
+        if self.skipped > 0 {
+            tracing::warn!(
+                skipped = self.skipped,
+                origin = %self.origin,
+                "skipped malformed lines"
+            );
+        }
+    }
+}
+
+/// runs `git log` within the repository at `path`, parsing its output into changes
+fn log<E>(
+    name: String,
+    path: &StdPath,
+    parse: &Parse,
+    args: &[String],
+    emitter: &mut E,
+    malformed: &mut Malformed,
+) -> Result<(), Box<dyn Error>>
+where
+    E: Emitter,
+{
+    let mut args = args.to_vec();
+    if parse.merges {
+        // undoes the `--no-merges` git log is run with
+        args.push("--no-max-parents".into());
+    }
+    let shallow = git::shallow(path)?;
+    if !shallow.is_empty() {
+        // the commits a shallow clone is cut off at seem to add every path of
+        // their tree, missing the parents they'd otherwise be diffed against.
+        // excluding them alone would leave nothing to include, unless a range
+        // to read was given
+        if args.iter().all(|arg| arg.starts_with('-')) {
+            args.push("HEAD".into());
+        }
+        args.extend(shallow.iter().map(|sha| format!("^{}", sha)));
+    }
+    // git's own format is known, so its parents needn't be told apart by their shas
+    let format = parse
+        .header_format
+        .clone()
+        .unwrap_or_else(|| HeaderFormat::git(parse.with_message));
+    let pretty = format.pretty(parse.with_message);
+    let parse = &Parse {
+        header_format: Some(format),
+        ..parse.clone()
+    };
+    git::log(path, &pretty, &args, |mut lines| {
+        run(name, parse, &mut lines, emitter, malformed)
+    })
+}
+
+/// tags the changes of a submodule with its path, leaving the emitter
+/// unfinished for changes read after them
+struct Submodule<'a, E> {
+    path: String,
+    emitter: &'a mut E,
+}
+
+impl<'a, E> Emitter for Submodule<'a, E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        line.submodule = Some(self.path.clone());
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+}
+
+/// reads the changes of a single source into an emitter, failing on malformed lines when `strict`.
+/// returns an account of the lines read, which is empty for history read through libgit2 or an api
+pub fn read<E>(
+    Source { name, input }: Source,
+    backend: &Backend,
+    parse: &Parse,
+    strict: bool,
+    args: &[String],
+    emitter: &mut E,
+) -> Result<Malformed, Box<dyn Error>>
+where
+    E: Emitter,
+{
+    let span = tracing::info_span!("read", repo = %name);
+    let _read = span.enter();
+    let emitter = &mut Traced::new(emitter);
+    match input {
+        Input::Repo(path) => {
+            let mut malformed = Malformed::new(format!("git log of {}", path.display()), strict);
+            match backend {
+                Backend::Git => {
+                    log(name.clone(), &path, parse, args, emitter, &mut malformed)?;
+                    if parse.submodules {
+                        for (submodule, checked_out) in git::submodules(&path)? {
+                            if !checked_out {
+                                tracing::warn!(%submodule, "skipping submodule which isn't checked out");
+                                continue;
+                            }
+                            let dir = path.join(&submodule);
+                            let emitter = &mut Submodule {
+                                path: submodule,
+                                emitter: &mut *emitter,
+                            };
+                            log(name.clone(), &dir, parse, args, emitter, &mut malformed)?;
+                        }
+                    }
+                }
+                #[cfg(feature = "libgit2")]
+                Backend::Libgit2 => libgit::walk(name, &path, parse, emitter)?,
+                #[cfg(not(feature = "libgit2"))]
+                Backend::Libgit2 => {
+                    return Err("git-linecat was built without the libgit2 feature".into())
+                }
+            }
+            Ok(malformed)
+        }
+        Input::Logs(logs) => {
+            let mut malformed = Malformed::new(if logs == "-" { "stdin" } else { &logs }, strict);
+            match &logs[..] {
+                "-" => run(
+                    name,
+                    parse,
+                    &mut lossy_lines(stdin().lock()),
+                    emitter,
+                    &mut malformed,
+                )?,
+                _ => {
+                    #[cfg(feature = "mmap")]
+                    if let Some(map) = map(&logs)? {
+                        // logs which aren't utf-8 are read as a stream, replacing what isn't
+                        if let Ok(text) = std::str::from_utf8(&map) {
+                            run(name, parse, &mut text.lines(), emitter, &mut malformed)?;
+                            return Ok(malformed);
+                        }
+                    }
+                    run(
+                        name,
+                        parse,
+                        &mut lossy_lines(open(&logs)?),
+                        emitter,
+                        &mut malformed,
+                    )?
+                }
+            }
+            Ok(malformed)
+        }
+        Input::Api(remote) => {
+            let read: Result<(), Box<dyn Error>> = match remote.forge {
+                #[cfg(feature = "github")]
+                Forge::Github => {
+                    // the since git log would be run with, which the api takes as well
+                    let since = args.iter().find_map(|arg| arg.strip_prefix("--since="));
+                    github::read(name, &remote, parse, since, emitter)
+                }
+                #[cfg(not(feature = "github"))]
+                Forge::Github => Err(format!(
+                    "git-linecat was built without the github feature required to read {}",
+                    remote.repo
+                )
+                .into()),
+                #[cfg(feature = "gitlab")]
+                Forge::Gitlab => {
+                    let since = args.iter().find_map(|arg| arg.strip_prefix("--since="));
+                    gitlab::read(name, &remote, parse, since, emitter)
+                }
+                #[cfg(not(feature = "gitlab"))]
+                Forge::Gitlab => Err(format!(
+                    "git-linecat was built without the gitlab feature required to read {}",
+                    remote.repo
+                )
+                .into()),
+                #[cfg(feature = "bitbucket")]
+                Forge::Bitbucket | Forge::BitbucketServer => {
+                    bitbucket::read(name, &remote, parse, emitter)
+                }
+                #[cfg(not(feature = "bitbucket"))]
+                Forge::Bitbucket | Forge::BitbucketServer => Err(format!(
+                    "git-linecat was built without the bitbucket feature required to read {}",
+                    remote.repo
+                )
+                .into()),
+            };
+            read?;
+            Ok(Malformed::new(remote.repo, strict))
+        }
+    }
+}
+
+/// parses `git log` output lines of a repository into changes, handing
+/// lines which aren't part of a commit to `malformed`. lines may be borrowed,
+/// as those of mapped logs are, and are only copied into message bodies
+pub fn run<L, E>(
+    repository: String,
+    parse: &Parse,
+    lines: &mut L,
+    emitter: &mut E,
+    malformed: &mut Malformed,
+) -> Result<(), Box<dyn Error>>
+where
+    L: Iterator,
+    L::Item: AsRef<str> + Into<String>,
+    E: Emitter,
+{
+    let mut merges = Merges::default();
+    // paths follow once any message has been read
+    let next = |header: Header, merges: &mut Merges, emitter: &mut E| {
+        if parse.merges {
+            merges.header(&repository, &header, emitter)?;
+        }
+        Ok::<_, Box<dyn Error>>(State::Next(header))
+    };
+    let start = |header: Header, lines: &mut Lines, merges: &mut Merges, emitter: &mut E| {
+        progress::commit();
+        lines.headers += 1;
+        if parse.with_message {
+            Ok(State::Body(header, Vec::new()))
+        } else {
+            next(header, merges, emitter)
+        }
+    };
+    let mut read: usize = 0;
+    let parsed = lines
+        .zip(1..)
+        .try_fold(State::Reset, |state, (item, number)| {
+            read = number;
+            let line = item.as_ref();
+            // counted in bulk as lines of many sources may be read at once
+            if number.is_multiple_of(1024) {
+                progress::lines(1024);
+            }
+            Ok::<_, Box<dyn Error>>(match state {
+                State::Reset if line.is_empty() => {
+                    malformed.lines.blank += 1;
+                    State::Reset
+                }
+                State::Reset => match parse.header(line) {
+                    Ok(header) => start(header, &mut malformed.lines, &mut merges, emitter)?,
+                    _ => {
+                        malformed.header(number, line)?;
+                        State::Reset
+                    }
+                },
+                State::Body(mut header, mut body) => match line.strip_suffix('\x1e') {
+                    Some(last) => {
+                        malformed.lines.bodies += 1;
+                        body.push(last.into());
+                        let body = body.join("\n").trim_end().to_string();
+                        header.body = Some(body).filter(|b| !b.is_empty());
+                        next(header, &mut merges, emitter)?
+                    }
+                    _ => {
+                        body.push(item.into());
+                        State::Body(header, body)
+                    }
+                },
+                State::Next(header) => {
+                    if line.is_empty() {
+                        malformed.lines.blank += 1;
+                        State::Reset
+                    } else if line.starts_with('-') && !parse.include_binary {
+                        malformed.lines.binary += 1;
+                        State::Next(header)
+                    } else {
+                        // we expect a path, but some commits may be empty (no path) so we must be flexible
+                        match line.parse::<Path>() {
+                            Ok(path) => {
+                                match path.additions {
+                                    Some(_) => malformed.lines.paths += 1,
+                                    _ => malformed.lines.binary += 1,
+                                }
+                                let change = (repository.clone(), header.clone(), path).into();
+                                if parse.merges {
+                                    merges.change(change);
+                                } else {
+                                    emitter.emit(change)?;
+                                }
+                                State::Next(header)
+                            }
+                            _ => match parse.header(line) {
+                                Ok(header) => {
+                                    start(header, &mut malformed.lines, &mut merges, emitter)?
+                                }
+                                _ => {
+                                    malformed.path(number, line)?;
+                                    State::Next(header)
+                                }
+                            },
+                        }
+                    }
+                }
+            })
+        });
+    progress::lines(read % 1024);
+    parsed?;
+    merges.settle(&repository, emitter)?;
+    malformed.finish();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn header_line_parses() -> Result<(), Box<dyn Error>> {
+        let _: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400""#.parse()?;
+        Ok(())
+    }
+
+    #[test]
+    fn header_line_with_committer_parses() -> Result<(), Box<dyn Error>> {
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","sol@sun.com","2019-08-10 09:00:00 -0400""#.parse()?;
+        assert_eq!(header.committer.as_deref(), Some("sol@sun.com"));
+        assert_eq!(
+            header.commit_timestamp.as_deref(),
+            Some("2019-08-10 09:00:00 -0400")
+        );
+        assert_eq!(header.subject, None);
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","sol@sun.com","2019-08-10 09:00:00 -0400","fix "quoted", things""#.parse()?;
+        assert_eq!(header.committer.as_deref(), Some("sol@sun.com"));
+        assert_eq!(header.subject, Some(r#"fix "quoted", things"#.into()));
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","sol@sun.com""#.parse()?;
+        assert_eq!(header.committer, None);
+        assert_eq!(header.subject.as_deref(), Some("sol@sun.com"));
+        Ok(())
+    }
+
+    #[test]
+    fn header_line_with_committer_and_subject_parses() -> Result<(), Box<dyn Error>> {
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","sol@sun.com","2019-08-10 09:00:00 -0400","fix the parser""#.parse()?;
+        assert_eq!(header.parents, None);
+        assert_eq!(header.subject.as_deref(), Some("fix the parser"));
+        Ok(())
+    }
+
+    #[test]
+    fn header_line_with_parents_parses() -> Result<(), Box<dyn Error>> {
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","sol@sun.com","2019-08-10 09:00:00 -0400","1862ee78aac96224fd7fbc8781ad12f9d06127a2 1b9e59c85e049896b88d5d8f19fbe2a0554ddbfd","merge""#.parse()?;
+        assert_eq!(
+            header.parents,
+            Some(vec![
+                "1862ee78aac96224fd7fbc8781ad12f9d06127a2".into(),
+                "1b9e59c85e049896b88d5d8f19fbe2a0554ddbfd".into()
+            ])
+        );
+        assert_eq!(header.subject.as_deref(), Some("merge"));
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","","root""#.parse()?;
+        assert_eq!(header.parents, Some(vec![]));
+        assert_eq!(header.subject.as_deref(), Some("root"));
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","1862ee78""#.parse()?;
+        assert_eq!(header.parents, None);
+        assert_eq!(header.subject.as_deref(), Some("1862ee78"));
+        Ok(())
+    }
+
+    #[test]
+    fn header_line_with_decorations_parses() -> Result<(), Box<dyn Error>> {
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","fix (parser)" (HEAD -> main, tag: v1.0, tag: v1.0.0, origin/main, origin/HEAD)"#.parse()?;
+        assert_eq!(header.subject.as_deref(), Some("fix (parser)"));
+        assert_eq!(header.tags, Some(vec!["v1.0".into(), "v1.0.0".into()]));
+        assert_eq!(
+            header.branches,
+            Some(vec!["main".into(), "origin/main".into()])
+        );
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","" (tag: refs/tags/v2, refs/heads/next)"#.parse()?;
+        assert_eq!(header.parents, Some(vec![]));
+        assert_eq!(header.tags, Some(vec!["v2".into()]));
+        assert_eq!(header.branches, Some(vec!["next".into()]));
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","fix (parser)""#.parse()?;
+        assert_eq!((header.tags, header.branches), (None, None));
+        Ok(())
+    }
+
+    #[test]
+    fn header_line_with_spaces_parses() -> Result<(), Box<dyn Error>> {
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","Jane Doe","2019-08-08 18:03:38 -0400","Sol, "Sun"","2019-08-10 09:00:00 -0400","say "hi","bye"" (tag: v1.0)"#.parse()?;
+        assert_eq!(header.author, "Jane Doe");
+        assert_eq!(header.committer.as_deref(), Some(r#"Sol, "Sun""#));
+        assert_eq!(header.subject.as_deref(), Some(r#"say "hi","bye""#));
+        assert_eq!(header.tags, Some(vec!["v1.0".into()]));
+        assert!(r#""61708727af02089cef4a72c6a532ddf332111b14","Jane Doe","jane@x.com","2019-08-08 18:03:38 -0400""#
+            .parse::<Header>()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn json_header_line_parses() -> Result<(), Box<dyn Error>> {
+        let header: Header = r#"{"sha":"61708727af02089cef4a72c6a532ddf332111b14","author":"luna@moon.com","author_name":"Luna, \"Moon\"","timestamp":"2019-08-08 18:03:38 -0400","parents":"1862ee78aac96224fd7fbc8781ad12f9d06127a2","subject":"fix \"quoted\", things","decorations":" (tag: v1.0)"}"#.parse()?;
+        assert_eq!(header.author, "luna@moon.com");
+        assert_eq!(
+            header.parents,
+            Some(vec!["1862ee78aac96224fd7fbc8781ad12f9d06127a2".into()])
+        );
+        assert_eq!(header.subject.as_deref(), Some(r#"fix "quoted", things"#));
+        assert_eq!(header.tags, Some(vec!["v1.0".into()]));
+        assert!(r#"{"sha":"61708727af02089cef4a72c6a532ddf332111b14"}"#
+            .parse::<Header>()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn header_line_with_subject_parses() -> Result<(), Box<dyn Error>> {
+        let header: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","fix "quoted", things""#.parse()?;
+        assert_eq!(header.timestamp, "2019-08-08 18:03:38 -0400");
+        assert_eq!(header.subject, Some(r#"fix "quoted", things"#.into()));
+        let header: Header = r##""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400","Revert "Revert "foo""""##.parse()?;
+        assert_eq!(
+            header.subject.as_deref(),
+            Some(r##"Revert "Revert "foo"""##)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn timestamps_are_normalized() {
+        let change: Change = (
+            "test".to_string(),
+            Header {
+                sha: "sha".into(),
+                author: "luna@moon.com".into(),
+                timestamp: "2019-08-09 15:36:50 -0400".into(),
+                committer: None,
+                commit_timestamp: None,
+                parents: None,
+                tags: None,
+                branches: None,
+                subject: None,
+                body: None,
+            },
+            Path {
+                additions: Some(1),
+                deletions: Some(0),
+                path: "foo.rs".into(),
+            },
+        )
+            .into();
+        assert_eq!(
+            change.timestamp_utc.as_deref(),
+            Some("2019-08-09T19:36:50Z")
+        );
+        assert_eq!(change.epoch_seconds, Some(1_565_379_410));
+        assert_eq!(change.local_hour, Some(15));
+        assert_eq!(change.local_weekday.as_deref(), Some("Friday"));
+    }
+
+    #[test]
+    fn rfc3339_timestamps_parse() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            parse_timestamp("2019-08-09T15:36:50-04:00")?,
+            parse_timestamp("2019-08-09 15:36:50 -0400")?
+        );
+        assert!(parse_timestamp("yesterday").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn path_line_parses() -> Result<(), Box<dyn Error>> {
+        let _: Path = r#"6       3       foo/bar/baz.rs"#.parse()?;
+        Ok(())
+    }
+
+    #[test]
+    fn renames_are_split() {
+        assert_eq!(
+            rename("dir_a => renamed_a"),
+            Some(("dir_a".into(), "renamed_a".into()))
+        );
+        assert_eq!(
+            rename("src/deep/{old.rs => new.rs}"),
+            Some(("src/deep/old.rs".into(), "src/deep/new.rs".into()))
+        );
+        assert_eq!(
+            rename("src/{ => sub}/lib.rs"),
+            Some(("src/lib.rs".into(), "src/sub/lib.rs".into()))
+        );
+        assert_eq!(
+            rename("{a => b}/lib.rs"),
+            Some(("a/lib.rs".into(), "b/lib.rs".into()))
+        );
+        assert_eq!(rename("src/main.rs"), None);
+    }
+
+    #[test]
+    fn renamed_path_lines_parse() -> Result<(), Box<dyn Error>> {
+        let path: Path = "0\t0\tsrc/{old.rs => new.rs}".parse()?;
+        let change: Change = (
+            "test".to_string(),
+            Header {
+                sha: "sha".into(),
+                author: "luna@moon.com".into(),
+                timestamp: "2019-08-09 15:36:50 -0400".into(),
+                committer: None,
+                commit_timestamp: None,
+                parents: None,
+                tags: None,
+                branches: None,
+                subject: None,
+                body: None,
+            },
+            path,
+        )
+            .into();
+        assert_eq!(change.path, "src/new.rs");
+        assert_eq!(change.old_path.as_deref(), Some("src/old.rs"));
+        assert!(change.renamed);
+        assert_eq!(change.language.as_deref(), Some("Rust"));
+        assert_eq!(change.dir.as_deref(), Some("src"));
+        assert_eq!(change.author_user.as_deref(), Some("luna"));
+        assert_eq!(change.author_domain.as_deref(), Some("moon.com"));
+        assert_eq!(change.top_dir.as_deref(), Some("src"));
+        Ok(())
+    }
+
+    #[test]
+    fn authors_are_split() {
+        let mut change = Change::default();
+        assert_eq!(change.set_author("luna@Moon.com".into()), "");
+        assert_eq!(change.author, "luna@Moon.com");
+        assert_eq!(change.author_user.as_deref(), Some("luna"));
+        assert_eq!(change.author_domain.as_deref(), Some("moon.com"));
+        change.set_author("dependabot[bot]".into());
+        assert_eq!((change.author_user, change.author_domain), (None, None));
+    }
+
+    #[test]
+    fn path_lines_with_spaces_parse() -> Result<(), Box<dyn Error>> {
+        let path: Path = "1\t0\twith space.txt".parse()?;
+        assert_eq!(path.path, "with space.txt");
+        assert_eq!(path.additions, Some(1));
+        let binary: Path = "-\t-\tlogo.png".parse()?;
+        assert_eq!((binary.additions, binary.deletions), (None, None));
+        assert!("1\t0\t".parse::<Path>().is_err());
+        assert!("one\t0\tfoo.rs".parse::<Path>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn quoted_paths_are_unquoted() {
+        assert_eq!(unquote(r#""\303\251.txt""#), "é.txt");
+        assert_eq!(unquote(r#""tab\t\"q\".txt""#), "tab\t\"q\".txt");
+        assert_eq!(unquote("plain.txt"), "plain.txt");
+    }
+
+    #[test]
+    fn quoted_renames_are_unquoted() -> Result<(), Box<dyn Error>> {
+        let change: Change = (
+            "test".to_string(),
+            Header {
+                sha: "sha".into(),
+                author: "luna@moon.com".into(),
+                timestamp: "2019-08-09 15:36:50 -0400".into(),
+                committer: None,
+                commit_timestamp: None,
+                parents: None,
+                tags: None,
+                branches: None,
+                subject: None,
+                body: None,
+            },
+            r#"0	0	"\303\251.txt" => "dir \303\251/\303\274.txt""#.parse()?,
+        )
+            .into();
+        assert_eq!(change.path, "dir é/ü.txt");
+        assert_eq!(change.old_path.as_deref(), Some("é.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn paths_with_test_are_categorized() {
+        assert_eq!(Change::categorize("foo/test/bar.txt"), Category::Test)
+    }
+
+    #[test]
+    fn paths_without_test_are_categorized() {
+        assert_eq!(Change::categorize("foo/bar/baz.txt"), Category::Default)
+    }
+
+    #[test]
+    fn tests_are_recognized_by_convention() {
+        for path in &[
+            "tests/cli.rs",
+            "src/test/java/com/example/App.java",
+            "web/__tests__/app.js",
+            "spec/models/user_spec.rb",
+            "pkg/parse/parse_test.go",
+            "app/test_views.py",
+            "app/conftest.py",
+            "web/src/app.spec.ts",
+            "web/src/app.test.jsx",
+            "src/main/java/com/example/ParserTest.java",
+            "src/main/scala/ParserSpec.scala",
+            "Sources/TestParser.swift",
+            "src/parser_unittest.cc",
+        ] {
+            assert_eq!(Change::categorize(path), Category::Test, "{}", path)
+        }
+        for path in &[
+            "src/contest.rs",
+            "config/latest_config.yml",
+            "src/Contest.java",
+            "src/attestation.go",
+            "src/testimonials.py",
+            "src/Test.java",
+        ] {
+            assert_ne!(Change::categorize(path), Category::Test, "{}", path)
+        }
+    }
+
+    #[test]
+    fn built_in_categories() {
+        for (path, category) in &[
+            ("vendor/github.com/foo/bar.go", Category::Vendored),
+            ("web/node_modules/left-pad/index.js", Category::Vendored),
+            ("Cargo.lock", Category::Generated),
+            ("web/package-lock.json", Category::Generated),
+            ("web/pnpm-lock.yaml", Category::Generated),
+            ("api/v1/service.pb.go", Category::Generated),
+            ("src/schema_generated.rs", Category::Generated),
+            ("static/app.min.js", Category::Generated),
+            ("pkg/apis/zz_generated.deepcopy.go", Category::Generated),
+            ("src/__generated__/query.graphql.ts", Category::Generated),
+            (".github/workflows/main.yml", Category::Ci),
+            (".travis.yml", Category::Ci),
+            ("Dockerfile", Category::Build),
+            ("build/rules.mk", Category::Build),
+            ("README.md", Category::Docs),
+            ("docs/intro.txt", Category::Docs),
+            ("config/app.yml", Category::Config),
+            ("static/logo.png", Category::Assets),
+        ] {
+            assert_eq!(&Change::categorize(path), category, "{}", path)
+        }
+    }
+
+    #[test]
+    fn paths_have_every_category_they_match() {
+        assert_eq!(
+            Change::categories_with("vendor/github.com/foo/bar/foo_test.go", None, None),
+            [Category::Vendored, Category::Test]
+        );
+        assert_eq!(
+            Change::categories_with("docs/config.yml", None, None),
+            [Category::Docs, Category::Config]
+        );
+        assert_eq!(
+            Change::categories_with("src/main.rs", None, None),
+            [Category::Default]
+        );
+        let mut change = Change {
+            category: Category::Docs,
+            categories: vec![Category::Docs, Category::Config],
+            ..Change::default()
+        };
+        change.categorize_as(Category::Config);
+        assert_eq!(change.category, Category::Config);
+        assert_eq!(change.categories, [Category::Config, Category::Docs]);
+    }
+
+    #[test]
+    fn categories_serialize_as_snake_case() -> Result<(), Box<dyn Error>> {
+        assert_eq!(serde_json::to_string(&Category::Ci)?, r#""ci""#);
+        assert_eq!(serde_json::to_string(&Category::Vendored)?, r#""vendored""#);
+        assert_eq!(
+            serde_json::to_string(&Category::Custom("docs/api".into()))?,
+            r#""docs/api""#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn category_names_match_serialization() -> Result<(), Box<dyn Error>> {
+        for category in &[
+            Category::Test,
+            Category::Docs,
+            Category::Build,
+            Category::Ci,
+            Category::Config,
+            Category::Vendored,
+            Category::Generated,
+            Category::Assets,
+            Category::Default,
+            Category::Custom("fixtures".into()),
+        ] {
+            assert_eq!(serde_json::to_value(category)?, category.name());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fields_match_serialized_change() -> Result<(), Box<dyn Error>> {
+        let change = Change {
+            timestamp_utc: Some("2019-08-09T19:36:50Z".into()),
+            epoch_seconds: Some(1_565_379_410),
+            old_path: Some("old.rs".into()),
+            renamed: true,
+            ext: Some("rs".into()),
+            binary: true,
+            dir: Some("src/bin".into()),
+            top_dir: Some("src".into()),
+            path_prefix: Some("src".into()),
+            subject: Some("subject".into()),
+            body: Some("body".into()),
+            author_raw: Some("luna@old.moon.com".into()),
+            author_canonical: Some("luna@moon.com".into()),
+            language: Some("Rust".into()),
+            commit_type: Some("feat".into()),
+            scope: Some("parser".into()),
+            breaking: true,
+            references: Some(vec!["#1".into()]),
+            committer: Some("sol@sun.com".into()),
+            commit_timestamp: Some("2019-08-10 09:00:00 -0400".into()),
+            co_authors: Some(vec!["terra@earth.com".into()]),
+            author_role: Some("author".into()),
+            owners: Some(vec!["@org/core".into()]),
+            author_user: Some("luna".into()),
+            author_domain: Some("moon.com".into()),
+            year: Some(2019),
+            year_month: Some("2019-08".into()),
+            iso_week: Some("2019-W32".into()),
+            weekday: Some("Friday".into()),
+            hour_utc: Some(19),
+            local_hour: Some(15),
+            local_weekday: Some("Friday".into()),
+            record_id: Some("4bd1a5a5".into()),
+            total_lines: Some(60),
+            pct_changed: Some(3.36),
+            canonical_path: Some("src/bin/new.rs".into()),
+            merge: true,
+            parents: Some(vec!["f00d".into()]),
+            tags: Some(vec!["v1.0".into()]),
+            branches: Some(vec!["main".into()]),
+            submodule: Some("vendor/lib".into()),
+            categories: vec![Category::Default],
+            ..Change::default()
+        };
+        let value = serde_json::to_value(&change)?;
+        let mut keys: Vec<_> = value
+            .as_object()
+            .ok_or("expected an object")?
+            .keys()
+            .collect();
+        let mut fields: Vec<_> = FIELDS.iter().map(|(name, _)| name).collect();
+        keys.sort();
+        fields.sort();
+        assert_eq!(keys, fields);
+        Ok(())
+    }
+
+    #[test]
+    fn stdout_emits() {
+        let mut stdout = Stdout::new();
+        assert!(stdout.emit(Change::default()).is_ok());
+        assert!(stdout.finish().is_ok())
+    }
+
+    #[test]
+    fn parses_lines() {
+        let mut collect = Collect::default();
+        drop(run(
+            "test".into(),
+            &Parse::default(),
+            &mut include_str!("../tests/data/git.log")
+                .lines()
+                .map(|l| l.to_string()),
+            &mut collect,
+            &mut Malformed::new("git.log", true),
+        ));
+        assert_eq!(2, collect.0.len());
+    }
+
+    #[test]
+    fn lossy_lines_replace_invalid_utf8() {
+        let lines = lossy_lines(&b"caf\xe9\r\nlatte\n"[..]).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["caf\u{fffd}", "latte"]);
+    }
+
+    #[test]
+    fn reads_submodules() -> Result<(), Box<dyn Error>> {
+        let dir = git::tests::superproject("read-submodules")?;
+        let read = |submodules: bool| -> Result<Vec<_>, Box<dyn Error>> {
+            let mut collect = Collect::default();
+            read(
+                Source {
+                    name: "test".into(),
+                    input: Input::Repo(dir.clone()),
+                },
+                &Backend::Git,
+                &Parse {
+                    submodules,
+                    ..Parse::default()
+                },
+                true,
+                &[],
+                &mut collect,
+            )?;
+            Ok(collect
+                .0
+                .into_iter()
+                .map(|change| (change.path, change.submodule))
+                .collect())
+        };
+        let tagged = |paths: &[(String, Option<String>)]| {
+            paths
+                .iter()
+                .filter(|(_, submodule)| submodule.is_some())
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let paths = read(true)?;
+        assert!(paths.contains(&(".gitmodules".into(), None)));
+        assert_eq!(
+            tagged(&paths),
+            vec![("src/lib.rs".into(), Some("vendor/lib".into()))]
+        );
+        assert!(tagged(&read(false)?).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn reads_compressed_logs() -> Result<(), Box<dyn Error>> {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("git-linecat-{}.log.gz", std::process::id()));
+        let mut gz = GzEncoder::new(File::create(&path)?, Compression::default());
+        gz.write_all(include_bytes!("../tests/data/git.log"))?;
+        gz.finish()?;
+        let mut collect = Collect::default();
+        read(
+            Source {
+                name: "test".into(),
+                input: Input::Logs(path.to_string_lossy().into()),
+            },
+            &Backend::Git,
+            &Parse::default(),
+            true,
+            &[],
+            &mut collect,
+        )?;
+        assert_eq!(2, collect.0.len());
+        Ok(())
+    }
+
+    /// a commit with a garbled path line, followed by a garbled header
+    const MALFORMED: &str = "\"sha1\",\"luna@moon.com\",\"2019-08-09 15:36:50 -0400\"\n\
+                             1\t0\ta.rs\n\
+                             garbled\t0\n\
+                             2\t0\tb.rs\n\
+                             \n\
+                             \"sha2\",\"luna@moon.com\"\n\
+                             3\t0\tc.rs\n";
+
+    #[test]
+    fn strict_parsing_fails_on_malformed_lines() {
+        let err = run(
+            "test".into(),
+            &Parse::default(),
+            &mut MALFORMED.lines().map(String::from),
+            &mut Stdout::new(),
+            &mut Malformed::new("git.log", true),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"git.log:3: expected a path or commit header but found "garbled\t0""#
+        );
+        assert_eq!(error::exit_code(&*err), error::EX_DATAERR);
+    }
+
+    #[test]
+    fn lenient_parsing_skips_malformed_lines() -> Result<(), Box<dyn Error>> {
+        let mut collect = Collect::default();
+        let mut malformed = Malformed::new("git.log", false);
+        run(
+            "test".into(),
+            &Parse::default(),
+            &mut MALFORMED.lines().map(String::from),
+            &mut collect,
+            &mut malformed,
+        )?;
+        let paths = collect
+            .0
+            .into_iter()
+            .map(|change| change.path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["a.rs", "b.rs"]);
+        assert_eq!(malformed.skipped, 3);
+        Ok(())
+    }
+
+    /// a header without paths, which may be a merge or an empty commit, a merge `-m`
+    /// reports against each of its parents, and an ordinary commit
+    const MERGES: &str = "\"sha1\",\"luna@moon.com\",\"2019-08-09 15:36:50 -0400\"\n\
+                          \"sha2\",\"luna@moon.com\",\"2019-08-09 15:36:50 -0400\"\n\
+                          1\t0\tb.rs\n\
+                          \n\
+                          \"sha2\",\"luna@moon.com\",\"2019-08-09 15:36:50 -0400\"\n\
+                          3\t1\tc.rs\n\
+                          \n\
+                          \"sha3\",\"luna@moon.com\",\"2019-08-09 15:36:50 -0400\"\n\
+                          2\t0\ta.rs\n";
+
+    #[test]
+    fn parses_merges() -> Result<(), Box<dyn Error>> {
+        let collected = |collect: Collect| {
+            (collect.0)
+                .into_iter()
+                .map(|line| (line.sha, line.path, line.merge, line.additions))
+                .collect::<Vec<_>>()
+        };
+        let parsed = |merges: bool| -> Result<Vec<_>, Box<dyn Error>> {
+            let mut collect = Collect::default();
+            run(
+                "test".into(),
+                &Parse {
+                    merges,
+                    ..Parse::default()
+                },
+                &mut MERGES.lines().map(String::from),
+                &mut collect,
+                &mut Malformed::new("git.log", true),
+            )?;
+            Ok(collected(collect))
+        };
+        let change = |sha: &str, path: &str, merge: bool, additions: Option<usize>| {
+            (sha.to_string(), path.to_string(), merge, additions)
+        };
+        assert_eq!(
+            parsed(true)?,
+            vec![
+                change("sha2", "", true, Some(1)),
+                change("sha3", "a.rs", false, Some(2)),
+            ]
+        );
+        let header = |sha: &str, parents: &[String]| {
+            format!(
+                "\"{}\",\"luna@moon.com\",\"2019-08-09 15:36:50 -0400\",\"{}\"\n",
+                sha,
+                parents.join(" ")
+            )
+        };
+        let mut collect = Collect::default();
+        run(
+            "test".into(),
+            &Parse {
+                merges: true,
+                ..Parse::default()
+            },
+            &mut [
+                header("sha4", &["a".repeat(40)]),
+                header("sha5", &["b".repeat(40), "c".repeat(40)]),
+            ]
+            .concat()
+            .lines()
+            .map(String::from),
+            &mut collect,
+            &mut Malformed::new("git.log", true),
+        )?;
+        // empty commits with known parents aren't merges
+        assert_eq!(collected(collect), vec![change("sha5", "", true, None)]);
+        assert_eq!(
+            parsed(false)?,
+            vec![
+                change("sha2", "b.rs", false, Some(1)),
+                change("sha2", "c.rs", false, Some(3)),
+                change("sha3", "a.rs", false, Some(2)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parses_lines_with_messages() -> Result<(), Box<dyn Error>> {
+        let mut collect = Collect::default();
+        run(
+            "test".into(),
+            &Parse {
+                with_message: true,
+                ..Parse::default()
+            },
+            &mut include_str!("../tests/data/git-messages.log")
+                .lines()
+                .map(|l| l.to_string()),
+            &mut collect,
+            &mut Malformed::new("git-messages.log", true),
+        )?;
+        let messages: Vec<_> = collect
+            .0
+            .iter()
+            .map(|c| (c.path.as_str(), c.subject.as_deref(), c.body.as_deref()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                ("b", Some("no body"), None),
+                (
+                    "a",
+                    Some(r#"subject "quoted", yes"#),
+                    Some("body line 1\n\nbody line 3")
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_uncompressed_logs() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("git-linecat-{}.log", std::process::id()));
+        std::fs::write(&path, include_str!("../tests/data/git-messages.log"))?;
+        let mut collect = Collect::default();
+        read(
+            Source {
+                name: "test".into(),
+                input: Input::Logs(path.to_string_lossy().into()),
+            },
+            &Backend::Git,
+            &Parse {
+                with_message: true,
+                ..Parse::default()
+            },
+            true,
+            &[],
+            &mut collect,
+        )?;
+        std::fs::remove_file(&path)?;
+        let bodies: Vec<_> = collect.0.iter().map(|c| c.body.as_deref()).collect();
+        assert_eq!(bodies, vec![None, Some("body line 1\n\nbody line 3")]);
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! reads history in-process via libgit2 rather than parsing
+//! the output of a `git log` subprocess
+use crate::{Change, Emitter, Header, Parse, Path, TIMESTAMP_FORMAT};
+use chrono::{DateTime, FixedOffset};
+use git2::{Commit, Oid, Patch, ReferenceType, Repository, Sort, Time};
+use std::{collections::HashMap, error::Error, path::Path as StdPath};
+
+/// the tags and branches pointing at commits
+type Decorations = HashMap<Oid, (Vec<String>, Vec<String>)>;
+
+/// formats a commit time the way `%ai` does
+fn timestamp(time: Time) -> Result<String, Box<dyn Error>> {
+    let offset = FixedOffset::east_opt(time.offset_minutes() * 60).ok_or("invalid offset")?;
+    let utc = DateTime::from_timestamp(time.seconds(), 0).ok_or("invalid timestamp")?;
+    Ok(utc
+        .with_timezone(&offset)
+        .format(TIMESTAMP_FORMAT)
+        .to_string())
+}
+
+/// the tags and local and remote branches pointing at each commit, as `%d`
+/// decorates them. symbolic refs, as `origin/HEAD`, are left out
+fn decorations(repo: &Repository) -> Result<Decorations, Box<dyn Error>> {
+    let mut decorations = Decorations::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        if reference.kind() != Some(ReferenceType::Direct) {
+            continue;
+        }
+        let (name, commit) = match (reference.name(), reference.peel_to_commit()) {
+            (Some(name), Ok(commit)) => (name.to_string(), commit.id()),
+            _ => continue,
+        };
+        let (tags, branches) = decorations.entry(commit).or_default();
+        if let Some(tag) = name.strip_prefix("refs/tags/") {
+            tags.push(tag.into());
+        } else if let Some(branch) = name
+            .strip_prefix("refs/heads/")
+            .or_else(|| name.strip_prefix("refs/remotes/"))
+        {
+            branches.push(branch.into());
+        }
+    }
+    Ok(decorations)
+}
+
+/// the numstat-equivalent path changes of a single commit, including
+/// binary ones only when `include_binary` is set
+fn paths(
+    repo: &Repository,
+    commit: &Commit,
+    include_binary: bool,
+) -> Result<Vec<Path>, Box<dyn Error>> {
+    let parent = match commit.parent_count() {
+        0 => None,
+        _ => Some(commit.parent(0)?.tree()?),
+    };
+    let diff = repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), None)?;
+    let mut paths = Vec::new();
+    for idx in 0..diff.deltas().len() {
+        let patch = match Patch::from_diff(&diff, idx)? {
+            Some(patch) => patch,
+            _ => continue,
+        };
+        let delta = patch.delta();
+        let path = match delta.new_file().path().or_else(|| delta.old_file().path()) {
+            Some(path) => path.to_string_lossy().into_owned(),
+            _ => continue,
+        };
+        // binary changes have no line counts, same as `-` in numstat
+        if delta.flags().is_binary() {
+            if include_binary {
+                paths.push(Path {
+                    additions: None,
+                    deletions: None,
+                    path,
+                });
+            }
+            continue;
+        }
+        let (_, additions, deletions) = patch.line_stats()?;
+        paths.push(Path {
+            additions: Some(additions),
+            deletions: Some(deletions),
+            path,
+        });
+    }
+    Ok(paths)
+}
+
+/// walks commits reachable from HEAD, newest first, emitting a change per text path,
+/// and per binary path given `include_binary`. merges are skipped unless `merges`
+pub fn walk<E>(
+    repository: String,
+    path: &StdPath,
+    &Parse {
+        with_message,
+        include_binary,
+        merges,
+        ..
+    }: &Parse,
+    emitter: &mut E,
+) -> Result<(), Box<dyn Error>>
+where
+    E: Emitter,
+{
+    let repo = Repository::open(path)?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TIME)?;
+    revwalk.push_head()?;
+    let decorations = decorations(&repo)?;
+    let some = |names: &Vec<String>| Some(names.clone()).filter(|names| !names.is_empty());
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        let merge = commit.parent_count() > 1;
+        if merge && !merges {
+            continue;
+        }
+        crate::progress::commit();
+        let (author, committer) = (commit.author(), commit.committer());
+        let header = Header {
+            sha: commit.id().to_string(),
+            author: String::from_utf8_lossy(author.email_bytes()).into_owned(),
+            timestamp: timestamp(author.when())?,
+            committer: Some(String::from_utf8_lossy(committer.email_bytes()).into_owned()),
+            commit_timestamp: Some(timestamp(committer.when())?),
+            parents: Some(commit.parent_ids().map(|id| id.to_string()).collect()),
+            tags: decorations
+                .get(&commit.id())
+                .and_then(|(tags, _)| some(tags)),
+            branches: decorations
+                .get(&commit.id())
+                .and_then(|(_, branches)| some(branches)),
+            subject: commit.summary().filter(|_| with_message).map(Into::into),
+            body: commit
+                .body()
+                .filter(|_| with_message)
+                .map(|b| b.trim_end().to_string())
+                .filter(|b| !b.is_empty()),
+        };
+        let paths = paths(&repo, &commit, include_binary)?;
+        if merge {
+            let changes = paths
+                .into_iter()
+                .map(|path| (repository.clone(), header.clone(), path).into())
+                .collect::<Vec<Change>>();
+            emitter.emit(Change::merge(repository.clone(), header, &changes))?;
+            continue;
+        }
+        for path in paths {
+            emitter.emit((repository.clone(), header.clone(), path).into())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{git, Collect};
+
+    #[test]
+    fn timestamp_matches_git_format() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            timestamp(Time::new(1_565_379_410, -240))?,
+            "2019-08-09 15:36:50 -0400"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn walk_emits_changes() -> Result<(), Box<dyn Error>> {
+        let dir = git::tests::repo("libgit")?;
+        let mut collect = Collect::default();
+        walk(
+            "test".into(),
+            &dir,
+            &Parse {
+                with_message: true,
+                ..Parse::default()
+            },
+            &mut collect,
+        )?;
+        assert_eq!(collect.0.len(), 1);
+        assert_eq!(collect.0[0].path, "src/lib.rs");
+        assert_eq!(collect.0[0].author, "luna@moon.com");
+        assert_eq!(collect.0[0].additions, Some(2));
+        assert_eq!(collect.0[0].subject.as_deref(), Some("init"));
+        Ok(())
+    }
+}
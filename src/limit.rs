@@ -0,0 +1,160 @@
+//! stops reading once enough commits or changes have been emitted, for sampling
+//! the head of a huge log
+use crate::{Change, Emitter};
+use std::{error::Error, fmt};
+
+/// the error emitting a change returns once a limit is reached, ending reading
+/// early. it's not a failure, and runs ending with it are finished as any other
+#[derive(Debug)]
+pub struct Limited;
+
+impl fmt::Display for Limited {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        write!(f, "limit reached")
+    }
+}
+
+impl Error for Limited {}
+
+/// whether reading ended early because a limit was reached rather than failing
+pub fn limited(result: Result<(), Box<dyn Error>>) -> Result<bool, Box<dyn Error>> {
+    match result {
+        Ok(()) => Ok(false),
+        Err(e) if e.is::<Limited>() => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+/// hands up to `commits` commits or `records` changes to another emitter, whichever
+/// comes first. commits are only cut short by `records`. changes emitted once a limit
+/// is reached, as those decorators held back are when finished, are dropped
+pub struct Limit<E> {
+    pub emitter: E,
+    commits: Option<usize>,
+    records: Option<usize>,
+    /// the sha of the last commit emitted and the commits and changes emitted so far
+    sha: Option<String>,
+    emitted_commits: usize,
+    emitted_records: usize,
+    reached: bool,
+}
+
+impl<E> Limit<E> {
+    pub fn new(
+        commits: Option<usize>,
+        records: Option<usize>,
+        emitter: E,
+    ) -> Self {
+        Limit {
+            emitter,
+            commits,
+            records,
+            sha: None,
+            emitted_commits: 0,
+            emitted_records: 0,
+            reached: false,
+        }
+    }
+
+    fn reach(&mut self) -> Result<(), Box<dyn Error>> {
+        self.reached = true;
+        Err(Limited.into())
+    }
+}
+
+impl<E> Emitter for Limit<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.reached {
+            return Ok(());
+        }
+        if self.sha.as_deref() != Some(&line.sha) {
+            // a commit is only known to be whole once the next one begins
+            if Some(self.emitted_commits) == self.commits {
+                return self.reach();
+            }
+            self.emitted_commits += 1;
+            self.sha = Some(line.sha.clone());
+        }
+        self.emitter.emit(line)?;
+        self.emitted_records += 1;
+        if Some(self.emitted_records) == self.records {
+            return self.reach();
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collect;
+
+    /// emits three commits of two changes each, returning whether a limit was reached
+    fn emit(limit: &mut Limit<Collect>) -> Result<bool, Box<dyn Error>> {
+        limited((0..3).try_for_each(|commit| {
+            (0..2).try_for_each(|path| {
+                limit.emit(Change {
+                    sha: commit.to_string(),
+                    path: path.to_string(),
+                    ..Change::default()
+                })
+            })
+        }))
+    }
+
+    fn emitted(limit: &Limit<Collect>) -> Vec<(&str, &str)> {
+        limit
+            .emitter
+            .0
+            .iter()
+            .map(|c| (c.sha.as_str(), c.path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn limits_commits() -> Result<(), Box<dyn Error>> {
+        let mut limit = Limit::new(Some(2), None, Collect::default());
+        assert!(emit(&mut limit)?);
+        assert_eq!(
+            emitted(&limit),
+            vec![("0", "0"), ("0", "1"), ("1", "0"), ("1", "1")]
+        );
+        // held back changes emitted once the limit is reached are dropped
+        limit.emit(Change::default())?;
+        assert_eq!(limit.emitter.0.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn limits_records() -> Result<(), Box<dyn Error>> {
+        let mut limit = Limit::new(Some(2), Some(3), Collect::default());
+        assert!(emit(&mut limit)?);
+        assert_eq!(emitted(&limit), vec![("0", "0"), ("0", "1"), ("1", "0")]);
+        Ok(())
+    }
+
+    #[test]
+    fn unreached_limits_emit_everything() -> Result<(), Box<dyn Error>> {
+        let mut limit = Limit::new(Some(3), Some(7), Collect::default());
+        assert!(!emit(&mut limit)?);
+        assert_eq!(limit.emitter.0.len(), 6);
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! warnings, errors and the timing of each source's parse and emit stages, logged
+//! through `tracing` to stderr as text or as json for ingestion jobs
+use crate::{Change, Emitter};
+use std::{
+    error::Error,
+    io::{self, IsTerminal},
+    str::FromStr,
+};
+use tracing::{Level, Span};
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    fmt::format::FmtSpan,
+    prelude::*,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unsupported log format {}", other)),
+        }
+    }
+}
+
+/// the target of the events `--explain-categorization` logs, which are logged
+/// at info whatever the level of the rest
+pub const EXPLAIN: &str = "explain";
+
+/// logs events of `level` and above to stderr. spans are logged as they close,
+/// with the time spent within them
+pub fn init(
+    level: Level,
+    format: LogFormat,
+) -> Result<(), Box<dyn Error>> {
+    let targets = Targets::new()
+        .with_default(level)
+        .with_target(EXPLAIN, Level::INFO);
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(LevelFilter::TRACE)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_span_events(FmtSpan::CLOSE);
+    match format {
+        LogFormat::Text => builder
+            .with_target(false)
+            .finish()
+            .with(targets)
+            .try_init()
+            .map_err(|e| e.to_string())?,
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .finish()
+            .with(targets)
+            .try_init()
+            .map_err(|e| e.to_string())?,
+    }
+    Ok(())
+}
+
+/// enters an `emit` span around each change handed to another emitter, timing the
+/// emit stage of a source apart from the parsing the span of its read includes
+pub struct Traced<'a, E> {
+    emitter: &'a mut E,
+    span: Span,
+}
+
+impl<'a, E> Traced<'a, E> {
+    /// a child of the current span
+    pub fn new(emitter: &'a mut E) -> Self {
+        Traced {
+            emitter,
+            span: tracing::info_span!("emit"),
+        }
+    }
+}
+
+impl<'a, E> Emitter for Traced<'a, E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let _emit = self.span.enter();
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        let _emit = self.span.enter();
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let _emit = self.span.enter();
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_log_formats() {
+        assert_eq!("text".parse(), Ok(LogFormat::Text));
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! canonicalizes author emails using a [mailmap](https://git-scm.com/docs/gitmailmap)
+//! so one human committing under many emails is counted as one author.
+//! as only author emails are known, entries mapping names alone are ignored
+//! and entries qualified by a commit name match on email alone
+use crate::{Change, Emitter};
+use std::{collections::HashMap, error::Error, fs, path::Path};
+
+#[derive(Debug, Default)]
+pub struct Mailmap {
+    /// proper emails keyed by lowercased commit email
+    emails: HashMap<String, String>,
+}
+
+impl Mailmap {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read mailmap {}: {}", path.display(), e))?;
+        Ok(Self::parse(&content))
+    }
+
+    fn parse(content: &str) -> Self {
+        let mut emails = HashMap::new();
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut found = line
+                .split('<')
+                .skip(1)
+                .filter_map(|rest| rest.split_once('>'))
+                .map(|(email, _)| email.trim());
+            if let (Some(proper), Some(commit)) = (found.next(), found.next()) {
+                emails.insert(commit.to_lowercase(), proper.to_string());
+            }
+        }
+        Mailmap { emails }
+    }
+
+    /// the proper email of an author, when mapped
+    pub fn canonical(
+        &self,
+        email: &str,
+    ) -> Option<&str> {
+        self.emails.get(&email.to_lowercase()).map(String::as_str)
+    }
+}
+
+/// replaces the author of changes with their canonical email before handing
+/// them to another emitter, retaining the original as `author_raw`.
+/// co-authors are likewise canonicalized
+pub struct Canonicalize<E> {
+    pub mailmap: Mailmap,
+    pub emitter: E,
+}
+
+impl<E> Emitter for Canonicalize<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let canonical = self
+            .mailmap
+            .canonical(&line.author)
+            .map(Into::into)
+            .unwrap_or_else(|| line.author.clone());
+        let raw = line.set_author(canonical.clone());
+        line.author_raw = Some(raw);
+        line.author_canonical = Some(canonical);
+        if let Some(co_authors) = line.co_authors.as_mut() {
+            for co_author in co_authors.iter_mut() {
+                if let Some(canonical) = self.mailmap.canonical(co_author) {
+                    *co_author = canonical.into();
+                }
+            }
+        }
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collect;
+
+    #[test]
+    fn parses_mailmap_entries() {
+        let mailmap = Mailmap::parse(
+            "# comments are ignored\n\
+             Luna Moon <luna@moon.com>\n\
+             <luna@moon.com> <luna@old.moon.com>\n\
+             Luna Moon <luna@moon.com> <LUNA@laptop.local> # trailing comment\n\
+             Luna Moon <luna@moon.com> luna <luna@ci.moon.com>\n",
+        );
+        assert_eq!(
+            mailmap.canonical("luna@old.moon.com"),
+            Some("luna@moon.com")
+        );
+        assert_eq!(
+            mailmap.canonical("luna@laptop.local"),
+            Some("luna@moon.com")
+        );
+        assert_eq!(mailmap.canonical("luna@ci.moon.com"), Some("luna@moon.com"));
+        assert_eq!(mailmap.canonical("luna@moon.com"), None);
+    }
+
+    #[test]
+    fn canonicalizes_authors() -> Result<(), Box<dyn Error>> {
+        let mut canonicalize = Canonicalize {
+            mailmap: Mailmap::parse("<luna@moon.com> <luna@old.moon.com>"),
+            emitter: Collect::default(),
+        };
+        for author in &["luna@old.moon.com", "sol@sun.com"] {
+            canonicalize.emit(Change {
+                author: author.to_string(),
+                co_authors: Some(vec!["luna@old.moon.com".into()]),
+                ..Change::default()
+            })?;
+        }
+        let changes = canonicalize.emitter.0;
+        assert!(changes
+            .iter()
+            .all(|change| change.co_authors == Some(vec!["luna@moon.com".into()])));
+        assert_eq!(
+            changes
+                .into_iter()
+                .map(|change| (change.author, change.author_raw))
+                .collect::<Vec<_>>(),
+            vec![
+                ("luna@moon.com".into(), Some("luna@old.moon.com".into())),
+                ("sol@sun.com".into(), Some("sol@sun.com".into())),
+            ]
+        );
+        Ok(())
+    }
+}
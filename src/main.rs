@@ -1,275 +1,1768 @@
 //! a tool for transforming and categorizing git log output
 //! expects input in the format of the output of
 //! `git log --pretty=format:'"%H","%ae","%ai"' --numstat --no-merges`
-use recap::Recap;
-use serde::{Deserialize, Serialize};
+//! or will invoke git itself given a `--repo-path`. with `--with-message`,
+//! commit messages are expected in the form of
+//! `git log --pretty=format:'"%H","%ae","%ai","%s"%n%b%x1e' --numstat --no-merges`
+#[cfg(feature = "duckdb")]
+use git_linecat::query::Query;
+#[cfg(feature = "wasm")]
+use git_linecat::wasm;
+use git_linecat::{
+    attributes::{Attributes, Linguist},
+    buckets::TimeBuckets,
+    buffer::Buffered,
+    cache::Shallow,
+    categorizer::Categorizer,
+    churn::Churn,
+    coauthors::Explode,
+    codeowners::{Codeowners, Own},
+    commits::{Commits, Granularity, Nest},
+    config::{self, Config},
+    coupling::Coupling,
+    dedupe::Dedupe,
+    error::{self, Blame},
+    expr::{Expr, Map, Select},
+    filter::{Between, Bound, Filter, HugeCommits, Rate, Sample, BOTS},
+    follow,
+    format::{Format, Projection, Rename},
+    header::HeaderFormat,
+    hotspots::{Hotspots, Window},
+    limit::{limited, Limit},
+    logging::{self, LogFormat},
+    mailmap::{Canonicalize, Mailmap},
+    manifest::{self, Forge, Input, Remote, Source},
+    metrics::{Metrics, Statsd},
+    output::{Compress, FlushEvery, Header, Post, Sink},
+    ownership::Ownership,
+    parallel,
+    partition::Template,
+    prefix::Prefix,
+    progress::Progress,
+    read,
+    references::{self, References},
+    renames::Renames,
+    rotate::{Limits, Rotate, Size},
+    rules::{Categorize, Explain, Rules},
+    run,
+    schema::Schema,
+    size::LineCounts,
+    state::{Incremental, State},
+    summarize::{Bucket, Dimension, Summarize},
+    summary::Summary,
+    trend::{Period, Trend},
+    validate::validate,
+    watch::watch,
+    Backend, Emitter, Fields, Malformed, Parse, FIELDS,
+};
+use regex::Regex;
 use std::{
+    collections::HashMap,
+    env,
     error::Error,
-    ffi::OsStr,
     fs::File,
-    io::{stdin, BufRead, BufReader},
-    path::Path as StdPath,
+    io::{self, Write},
+    path::PathBuf,
+    process, thread,
+    time::Duration,
 };
+use structopt::{
+    clap::{AppSettings, Shell},
+    StructOpt,
+};
+use tracing::Level;
 
-#[derive(Clone, Deserialize, Recap)]
-#[recap(regex = r#"(?x)
-    "(?P<sha>\S+)"
-    ,
-    "(?P<author>\S+)"
-    ,
-    "(?P<timestamp>.+)"
-  "#)]
-struct Header {
-    sha: String,
-    author: String,
-    timestamp: String,
-}
-
-/// text-only path changes
-/// binary file changes represent line
-/// changes with `-` which is of no use
-/// to us
-#[derive(Deserialize, Recap)]
-#[recap(regex = r#"(?x)
-    (?P<additions>\d+)
-    \s+
-    (?P<deletions>\d+)
-    \s+
-    (?P<path>\S+)
-  "#)]
-struct Path {
-    additions: usize,
-    deletions: usize,
-    path: String,
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Aggregates commits, files, additions and deletions instead of emitting each change
+    Summarize {
+        #[structopt(
+            long = "by",
+            help = "Comma separated fields to group by",
+            default_value = "author",
+            use_delimiter = true,
+            possible_values = &["repo", "author", "author_domain", "category", "path", "canonical_path", "ext", "dir", "top_dir", "path_prefix", "language", "commit_type"]
+        )]
+        by: Vec<Dimension>,
+        #[structopt(
+            long = "bucket",
+            help = "Additionally group by the utc day, iso week, month or year of each change",
+            possible_values = &["day", "week", "month", "year"]
+        )]
+        bucket: Option<Bucket>,
+    },
+    /// Ranks paths by churn, the number of commits touching a path times the lines they touched
+    Hotspots {
+        #[structopt(long = "top", help = "Number of paths to emit", default_value = "10")]
+        top: usize,
+        #[structopt(
+            long = "window",
+            help = "Only count changes within a span of days or weeks, as in 90d or 12w, before the latest change"
+        )]
+        window: Option<Window>,
+    },
+    /// Measures rework per path, the share of commits modifying a path within a span of days
+    /// or weeks of the commit modifying it before
+    Churn {
+        #[structopt(
+            long = "within",
+            help = "Span of days or weeks, as in 21d or 3w, within which a commit reworks the one before it",
+            default_value = "21d"
+        )]
+        within: Window,
+    },
+    /// Finds pairs of paths which change together in the same commits
+    Coupling {
+        #[structopt(
+            long = "min-support",
+            help = "Minimum number of commits changing both paths of a pair",
+            default_value = "2"
+        )]
+        min_support: usize,
+        #[structopt(
+            long = "min-confidence",
+            help = "Minimum share of commits to the less frequently changed path of a pair which also changed the other",
+            default_value = "0.5"
+        )]
+        min_confidence: f64,
+        #[structopt(
+            long = "max-files",
+            help = "Leave out commits changing more than this many paths, which couple everything they touch",
+            default_value = "30"
+        )]
+        max_files: usize,
+    },
+    /// Measures each author's share of the lines added per path or directory, flagging those
+    /// a single author owns most of
+    Ownership {
+        #[structopt(
+            long = "by",
+            help = "Whether to measure ownership per path or directory",
+            default_value = "path",
+            possible_values = &["path", "canonical_path", "dir", "top_dir", "path_prefix"]
+        )]
+        by: Dimension,
+        #[structopt(
+            long = "threshold",
+            help = "Share of additions above which a single author is considered to own a path",
+            default_value = "0.8"
+        )]
+        threshold: f64,
+    },
+    /// Emits rolling totals of commits, additions, deletions and active authors per day or week
+    Trend {
+        #[structopt(
+            long = "by",
+            help = "Comma separated fields to group by",
+            default_value = "repo",
+            use_delimiter = true,
+            possible_values = &["repo", "author", "author_domain", "category", "path", "canonical_path", "ext", "dir", "top_dir", "path_prefix", "language", "commit_type"]
+        )]
+        by: Vec<Dimension>,
+        #[structopt(
+            long = "period",
+            help = "Whether to total changes per utc day or iso week",
+            default_value = "week",
+            possible_values = &["day", "week"]
+        )]
+        period: Period,
+        #[structopt(
+            long = "window",
+            help = "Number of periods, ending with each period, to total",
+            default_value = "4"
+        )]
+        window: usize,
+    },
+    /// Loads changes into an in-memory duckdb `changes` table, one column per field, and
+    /// writes the rows a sql query of them selects
+    Query {
+        #[structopt(
+            help = "Query to run, as in `SELECT author, sum(additions) FROM changes GROUP BY 1`"
+        )]
+        sql: String,
+    },
+    /// Prints a completion script for a shell, to be installed where it loads completions from
+    Completions {
+        #[structopt(
+            help = "Shell to complete for",
+            possible_values = &Shell::variants(),
+            case_insensitive = true
+        )]
+        shell: Shell,
+    },
+    /// Prints a man page of every option and subcommand, in roff
+    Man,
+    /// Parses input without emitting changes, reporting counts of each kind of line read
+    /// from each source and failing if any were malformed
+    Validate,
+    /// Emits the changes of a --repo-path's history, then of each commit as it lands,
+    /// polling for new commits until interrupted
+    Watch {
+        #[structopt(
+            long = "interval",
+            help = "Seconds to wait between polls for new commits",
+            default_value = "10"
+        )]
+        interval: u64,
+    },
+    /// Emits the changes of repositories read through the github api rather than a clone,
+    /// the same as those of local repositories are emitted without a subcommand
+    Github {
+        #[structopt(
+            long = "repo",
+            help = "Repository to read, as org/name. may be repeated",
+            required = true,
+            number_of_values = 1
+        )]
+        repo: Vec<String>,
+        #[structopt(
+            long = "token",
+            help = "Token to authenticate with, raising the rate limit and granting access to private repositories. defaults to GITHUB_TOKEN"
+        )]
+        token: Option<String>,
+        #[structopt(
+            long = "base-url",
+            help = "Root of the api, as https://github.example.com/api/v3 for an enterprise server",
+            default_value = "https://api.github.com"
+        )]
+        base_url: String,
+    },
+    /// Emits the changes of projects read through the gitlab api rather than a clone,
+    /// the same as those of local repositories are emitted without a subcommand
+    Gitlab {
+        #[structopt(
+            long = "repo",
+            help = "Project to read, as group/name. may be repeated",
+            required = true,
+            number_of_values = 1
+        )]
+        repo: Vec<String>,
+        #[structopt(
+            long = "token",
+            help = "Token to authenticate with, granting access to private projects. defaults to GITLAB_TOKEN"
+        )]
+        token: Option<String>,
+        #[structopt(
+            long = "base-url",
+            help = "Root of a self-hosted instance, as https://gitlab.example.com",
+            default_value = "https://gitlab.com"
+        )]
+        base_url: String,
+    },
+    /// Clones or fetches every repository of a github organization into a cache directory, then
+    /// emits the changes of each, tagged with its name, the same as runs without a subcommand do
+    Org {
+        #[structopt(long = "github-org", help = "Organization whose repositories to read")]
+        github_org: String,
+        #[structopt(
+            long = "workdir",
+            help = "Directory to keep clones in, which later runs fetch into rather than cloning again",
+            parse(from_os_str)
+        )]
+        workdir: PathBuf,
+        #[structopt(
+            long = "token",
+            help = "Token to list and clone repositories with, granting access to private ones. defaults to GITHUB_TOKEN"
+        )]
+        token: Option<String>,
+        #[structopt(
+            long = "base-url",
+            help = "Root of the api, as https://github.example.com/api/v3 for an enterprise server",
+            default_value = "https://api.github.com"
+        )]
+        base_url: String,
+        #[structopt(long = "include-forks", help = "Read forks of other repositories too")]
+        include_forks: bool,
+        #[structopt(long = "include-archived", help = "Read archived repositories too")]
+        include_archived: bool,
+        #[structopt(
+            long = "depth",
+            help = "Clone and fetch only this many commits of each branch, from its tip"
+        )]
+        depth: Option<u32>,
+        #[structopt(
+            long = "shallow-since",
+            help = "Clone and fetch only commits committed on or after an iso date, timestamp, or span before now like 90d"
+        )]
+        shallow_since: Option<Bound>,
+        #[structopt(
+            long = "filter",
+            help = "Partial clone filter, as blob:none, leaving out file contents until they're read"
+        )]
+        filter: Option<String>,
+    },
+    /// Emits the changes of repositories read through the bitbucket cloud or bitbucket server
+    /// api rather than a clone, the same as those of local repositories are emitted without a subcommand
+    Bitbucket {
+        #[structopt(
+            long = "repo",
+            help = "Repository to read, as workspace/name, or PROJECT/name of a bitbucket server. may be repeated",
+            required = true,
+            number_of_values = 1
+        )]
+        repo: Vec<String>,
+        #[structopt(
+            long = "token",
+            help = "Access token to authenticate with, granting access to private repositories. defaults to BITBUCKET_TOKEN"
+        )]
+        token: Option<String>,
+        #[structopt(
+            long = "server",
+            help = "Read from a bitbucket server, or data center, at --base-url rather than bitbucket cloud",
+            requires = "base-url"
+        )]
+        server: bool,
+        #[structopt(
+            long = "base-url",
+            help = "Root of a bitbucket server, as https://bitbucket.example.com. defaults to bitbucket cloud's api"
+        )]
+        base_url: Option<String>,
+    },
 }
 
-#[derive(Debug, Serialize, PartialEq)]
-#[serde(rename_all = "snake_case")]
-enum Category {
-    Test,
-    Default,
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "git-linecat",
+    about = "A tool for transforming and categorizing git log data",
+    // so completions and man pages may be printed without naming a repository
+    setting = AppSettings::SubcommandsNegateReqs,
+    // so flags given on the command line override those of the config file
+    setting = AppSettings::AllArgsOverrideSelf
+)]
+struct Options {
+    #[structopt(
+        short = "r",
+        long = "repository",
+        help = "Repository name, or NAME=PATH to a log file or repository. may be repeated",
+        required_unless_one = &["manifest", "emit-schema"],
+        number_of_values = 1
+    )]
+    repository: Vec<String>,
+    #[structopt(
+        long = "manifest",
+        help = "Path to a yaml or toml file of repositories to read",
+        parse(from_os_str)
+    )]
+    manifest: Option<PathBuf>,
+    #[structopt(
+        short = "l",
+        long = "logs",
+        help = "Path to git log output. use `-` to read from stdin",
+        default_value = "-"
+    )]
+    logs: String,
+    #[structopt(
+        long = "repo-path",
+        alias = "git-dir",
+        help = "Path to a git repository to read history from directly. takes precedence over --logs",
+        parse(from_os_str)
+    )]
+    repo_path: Option<PathBuf>,
+    #[structopt(
+        long = "backend",
+        help = "How to read history given --repo-path",
+        default_value = "git",
+        possible_values = &["git", "libgit2"]
+    )]
+    backend: Backend,
+    #[structopt(
+        long = "recurse-submodules",
+        help = "Read the history of checked out submodules of a --repo-path too, tagging their changes with a submodule field"
+    )]
+    recurse_submodules: bool,
+    #[structopt(
+        long = "with-message",
+        help = "Include commit message subjects and bodies. See --help for the expected log format",
+        long_help = r#"Include commit message subjects and bodies. Expects logs in the format of
+git log --pretty=format:'"%H","%ae","%ai","%s"%n%b%x1e' --numstat --no-merges"#
+    )]
+    with_message: bool,
+    #[structopt(
+        long = "explode-coauthors",
+        help = "Emit each change once more for each co-author credited by a Co-authored-by trailer, with an author_role. requires --with-message"
+    )]
+    explode_coauthors: bool,
+    #[structopt(
+        long = "include-binary",
+        help = "Emit changes to binary files, which have no counts of lines added or deleted, with binary set"
+    )]
+    include_binary: bool,
+    #[structopt(
+        long = "include-merges",
+        alias = "merges",
+        help = "Emit a record of each merge commit, with merge set, rather than skipping them"
+    )]
+    merges: bool,
+    #[structopt(
+        long = "header-format",
+        help = "Placeholders of the header line, quoted in git log's --pretty format, as in %H,%ae,%ai,%s. --repo-path history is read in the same format"
+    )]
+    header_format: Option<HeaderFormat>,
+    #[structopt(
+        long = "strict",
+        help = "Fail on log lines which aren't part of a commit rather than skipping them with a warning"
+    )]
+    strict: bool,
+    #[structopt(
+        long = "reference-pattern",
+        help = "Regex matching issue or ticket references in commit messages, replacing the defaults. may be repeated",
+        number_of_values = 1
+    )]
+    reference_pattern: Vec<Regex>,
+    #[structopt(
+        long = "rules",
+        help = "Path to a yaml or toml file of categorization rules",
+        parse(from_os_str)
+    )]
+    rules: Option<PathBuf>,
+    #[structopt(
+        long = "explain-categorization",
+        help = "Log which rule categorized a sample of paths, and how many changes each rule categorized, to stderr"
+    )]
+    explain_categorization: bool,
+    #[structopt(
+        long = "single-category",
+        help = "Leave out the categories list of every category a path matches, emitting only its first as category, as before changes carried several"
+    )]
+    single_category: bool,
+    #[structopt(
+        long = "categorizer-cmd",
+        help = "Program reading paths from stdin, one per line, and writing a category label for each to stdout. blank labels keep the category otherwise assigned",
+        parse(from_os_str)
+    )]
+    categorizer_cmd: Option<PathBuf>,
+    #[structopt(
+        long = "categorizer-wasm",
+        help = "Path to a .wasm or .wat module exporting categorize, alloc and memory, labeling paths in a sandbox. See the readme for its abi. requires the wasm feature",
+        parse(from_os_str)
+    )]
+    categorizer_wasm: Option<PathBuf>,
+    #[structopt(
+        short = "f",
+        long = "output-format",
+        help = "Format to emit changes in",
+        default_value = "json",
+        possible_values = &["json", "csv", "parquet", "arrow", "sqlite", "es-bulk", "msgpack", "prometheus"]
+    )]
+    output_format: Format,
+    #[structopt(
+        long = "index",
+        help = "Elasticsearch index to write es-bulk output to. defaults to git-linecat"
+    )]
+    index: Option<String>,
+    #[structopt(
+        long = "granularity",
+        help = "Emit a record per changed file, or per commit with totals across its files",
+        default_value = "file",
+        possible_values = &["file", "commit"]
+    )]
+    granularity: Granularity,
+    #[structopt(
+        long = "nest",
+        help = "Emit a json document per commit with a files array of the changes to each of its paths"
+    )]
+    nest: bool,
+    #[structopt(
+        long = "fields",
+        help = "Comma separated fields to emit, in order, rather than every field",
+        use_delimiter = true
+    )]
+    fields: Vec<String>,
+    #[structopt(
+        long = "rename-field",
+        help = "Emit a field under another name, as in OLD=NEW. may be repeated. json, csv, es-bulk and msgpack output only",
+        number_of_values = 1
+    )]
+    rename_field: Vec<Rename>,
+    #[structopt(
+        short = "o",
+        long = "out",
+        help = "Path to write output to instead of stdout, compressed when ending in .gz or .zst",
+        parse(from_os_str)
+    )]
+    out: Option<PathBuf>,
+    #[structopt(
+        long = "output",
+        help = "Where to write output: stdout, file:PATH, gzip:PATH, zstd:PATH, es://HOST:PORT/INDEX, postgres://HOST/DB?table=NAME, kafka://BROKER/TOPIC, clickhouse://HOST:PORT/TABLE, duckdb:PATH, s3://BUCKET/KEY and gs://BUCKET/KEY templated with {repo} and {date}, or an http(s) webhook url. parquet and sqlite require a file. defaults to GIT_LINECAT_OUTPUT",
+        conflicts_with = "out"
+    )]
+    output: Option<Sink>,
+    #[structopt(
+        long = "out-template",
+        help = "Write each change to the file a path templated with its fields renders, as in out/{repo}/{year}/{month}/changes.jsonl. json and csv only",
+        conflicts_with_all = &["out", "output"]
+    )]
+    out_template: Option<Template>,
+    #[structopt(
+        long = "webhook-header",
+        help = "A header sent with each webhook request, as in 'Authorization: Bearer TOKEN'. may be repeated. defaults to the lines of GIT_LINECAT_WEBHOOK_HEADER",
+        number_of_values = 1
+    )]
+    webhook_header: Vec<Header>,
+    #[structopt(
+        long = "webhook-batch",
+        help = "Changes posted per webhook request",
+        default_value = "500"
+    )]
+    webhook_batch: usize,
+    #[structopt(long = "webhook-gzip", help = "Gzip webhook request bodies")]
+    webhook_gzip: bool,
+    #[structopt(
+        long = "dead-letter",
+        help = "Append changes a webhook never accepts to this file rather than failing",
+        parse(from_os_str)
+    )]
+    dead_letter: Option<PathBuf>,
+    #[structopt(
+        long = "compress",
+        help = "Compress file output, regardless of its extension",
+        possible_values = &["none", "gzip", "zstd"]
+    )]
+    compress: Option<Compress>,
+    #[structopt(
+        long = "rotate-size",
+        help = "Start a new numbered output file once one holds this many bytes, as in 256MB or 1GiB"
+    )]
+    rotate_size: Option<Size>,
+    #[structopt(
+        long = "rotate-records",
+        help = "Start a new numbered output file once one holds this many changes"
+    )]
+    rotate_records: Option<u64>,
+    #[structopt(
+        long = "progress",
+        help = "Report lines read, commits parsed, changes emitted and throughput on stderr"
+    )]
+    progress: bool,
+    #[structopt(
+        long = "flush-every",
+        help = "Flush output after every N changes rather than only once buffers fill"
+    )]
+    flush_every: Option<usize>,
+    #[structopt(
+        long = "limit-commits",
+        help = "Stop reading once this many commits have been emitted",
+        // history is read newest first, so the state of a limited run would skip what it never read
+        conflicts_with = "state"
+    )]
+    limit_commits: Option<usize>,
+    #[structopt(
+        long = "limit-records",
+        help = "Stop reading once this many changes have been emitted",
+        // history is read newest first, so the state of a limited run would skip what it never read
+        conflicts_with = "state"
+    )]
+    limit_records: Option<usize>,
+    #[structopt(
+        long = "sample",
+        help = "Emit the changes of a pseudo random fraction of commits, as in 0.05, keeping every change of those sampled"
+    )]
+    sample: Option<Rate>,
+    #[structopt(
+        long = "sample-seed",
+        default_value = "0",
+        help = "Seed deciding which commits --sample samples"
+    )]
+    sample_seed: u64,
+    #[structopt(
+        long = "buffer-records",
+        default_value = "10000",
+        help = "Changes waiting to be written to a network output, written on a thread of their own so reading doesn't wait on the network. 0 writes them as they're read"
+    )]
+    buffer_records: usize,
+    #[structopt(
+        long = "follow",
+        help = "Keep waiting for more lines at the end of --logs, like tail -f, flushing after every change"
+    )]
+    follow: bool,
+    #[structopt(
+        long = "summary",
+        help = "Write a json summary of the changes emitted to stderr once they've all been emitted"
+    )]
+    summary: bool,
+    #[structopt(
+        long = "summary-file",
+        help = "Write the json summary of --summary to a file rather than stderr",
+        parse(from_os_str)
+    )]
+    summary_file: Option<PathBuf>,
+    #[structopt(
+        long = "metrics",
+        help = "Send counters of records emitted, lines read, malformed lines, duration and throughput to statsd, as in statsd://localhost:8125. defaults to GIT_LINECAT_METRICS"
+    )]
+    metrics: Option<Statsd>,
+    #[structopt(
+        long = "metrics-file",
+        help = "Write the counters of --metrics to a json file once done",
+        parse(from_os_str)
+    )]
+    metrics_file: Option<PathBuf>,
+    #[structopt(
+        long = "since",
+        help = "Only emit changes authored on or after an iso date, timestamp, or span before now like 90d"
+    )]
+    since: Option<Bound>,
+    #[structopt(
+        long = "until",
+        help = "Only emit changes authored on or before an iso date, timestamp, or span before now like 90d"
+    )]
+    until: Option<Bound>,
+    #[structopt(
+        long = "after-sha",
+        help = "Only emit changes of commits read after this commit, as when resuming a run which died partway"
+    )]
+    after_sha: Option<String>,
+    #[structopt(
+        long = "before-sha",
+        help = "Only emit changes of commits read before this commit"
+    )]
+    before_sha: Option<String>,
+    #[structopt(
+        long = "state",
+        help = "Path to a json file of the newest changes emitted per repo. changes emitted by previous runs are skipped, and the file updated once output is written",
+        parse(from_os_str)
+    )]
+    state: Option<PathBuf>,
+    #[structopt(
+        long = "author",
+        help = "Only emit changes by authors whose email matches a regex. may be repeated",
+        number_of_values = 1
+    )]
+    author: Vec<Regex>,
+    #[structopt(
+        long = "exclude-author",
+        help = "Drop changes by authors whose email matches a regex. may be repeated",
+        number_of_values = 1
+    )]
+    exclude_author: Vec<Regex>,
+    #[structopt(
+        long = "exclude-bots",
+        help = "Drop changes by well known bots such as dependabot and renovate"
+    )]
+    exclude_bots: bool,
+    #[structopt(
+        long = "min-lines",
+        help = "Only emit changes adding and deleting at least this many lines in total"
+    )]
+    min_lines: Option<usize>,
+    #[structopt(
+        long = "max-lines",
+        help = "Only emit changes adding and deleting at most this many lines in total"
+    )]
+    max_lines: Option<usize>,
+    #[structopt(
+        long = "skip-huge-commits",
+        help = "Drop every change of commits changing more than this many paths, as vendoring and reformatting commits do"
+    )]
+    skip_huge_commits: Option<usize>,
+    #[structopt(
+        long = "exclude-generated",
+        help = "Drop changes categorized as generated, such as lockfiles, protobuf output and minified files"
+    )]
+    exclude_generated: bool,
+    #[structopt(
+        long = "select",
+        help = "Only emit changes for which a jq-like expression is neither false nor null, as in '.additions + .deletions > 100'"
+    )]
+    select: Option<Expr>,
+    #[structopt(
+        long = "map",
+        help = "Assign fields of each change with a jq-like expression, as in '.ext = (.ext // \"none\")'"
+    )]
+    map: Option<Expr>,
+    #[structopt(
+        long = "dedupe",
+        help = "Drop changes whose record_id was already emitted, as when overlapping logs are read"
+    )]
+    dedupe: bool,
+    #[structopt(
+        long = "mailmap",
+        help = "Path to a mailmap used to canonicalize author emails. defaults to the .mailmap of --repo-path, if any",
+        parse(from_os_str)
+    )]
+    mailmap: Option<PathBuf>,
+    #[structopt(
+        long = "codeowners",
+        help = "Path to a CODEOWNERS file used to attribute paths to their owners",
+        parse(from_os_str)
+    )]
+    codeowners: Option<PathBuf>,
+    #[structopt(
+        long = "path-depth",
+        help = "Emit a path_prefix of each path's directory truncated to this many components"
+    )]
+    path_depth: Option<usize>,
+    #[structopt(
+        long = "time-buckets",
+        help = "Emit the utc year, year_month, iso_week, weekday and hour_utc each change was authored in"
+    )]
+    time_buckets: bool,
+    #[structopt(
+        long = "line-counts",
+        help = "Emit the total_lines of each path at its commit and the pct_changed of them, looked up in --repo-path"
+    )]
+    line_counts: bool,
+    #[structopt(
+        long = "follow-renames",
+        help = "Emit the canonical_path of each path, its file's newest name, following renames back through history"
+    )]
+    follow_renames: bool,
+    #[structopt(
+        short = "j",
+        long = "jobs",
+        help = "Number of repositories to read at once. defaults to the number of cpus"
+    )]
+    jobs: Option<usize>,
+    #[structopt(
+        long = "emit-schema",
+        help = "Print a schema of the changes that would be emitted given the other options, then exit",
+        possible_values = &["bigquery"]
+    )]
+    emit_schema: Option<Schema>,
+    #[structopt(
+        long = "log-level",
+        help = "Least severe level of events to log to stderr. info logs the time spent reading and emitting each source",
+        default_value = "warn",
+        possible_values = &["error", "warn", "info", "debug", "trace"]
+    )]
+    log_level: Level,
+    #[structopt(
+        long = "log-format",
+        help = "Whether to log text or json lines",
+        default_value = "text",
+        possible_values = &["text", "json"]
+    )]
+    log_format: LogFormat,
+    #[structopt(
+        long = "profile",
+        help = "Name of a profile of the config file whose flags to use, after those it gives every invocation"
+    )]
+    // read before args are parsed, as it decides which args there are
+    #[allow(dead_code)]
+    profile: Option<String>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
-impl Default for Category {
-    fn default() -> Self {
-        Category::Default
+#[cfg_attr(tarpaulin, skip)]
+/// fields emitted given the options provided, excluding those
+/// which require flags that were not provided, or only those selected with --fields
+fn fields(options: &Options) -> Result<Fields, Box<dyn Error>> {
+    let available = FIELDS
+        .iter()
+        .copied()
+        .filter(|(name, _)| match *name {
+            "subject" | "body" | "commit_type" | "scope" | "breaking" | "references"
+            | "co_authors" => options.with_message,
+            "author_role" => options.explode_coauthors,
+            "categories" => !options.single_category,
+            "owners" => options.codeowners.is_some(),
+            "path_prefix" => options.path_depth.is_some(),
+            "year" | "year_month" | "iso_week" | "weekday" | "hour_utc" => options.time_buckets,
+            "binary" => options.include_binary,
+            "merge" => options.merges,
+            "total_lines" | "pct_changed" => options.line_counts,
+            "canonical_path" => options.follow_renames,
+            "submodule" => options.recurse_submodules,
+            "author_raw" | "author_canonical" => mailmap(options).is_some(),
+            _ => true,
+        })
+        .collect::<Fields>();
+    for rename in &options.rename_field {
+        if !FIELDS.iter().any(|(name, _)| *name == rename.from) {
+            return Err(format!("can't rename unknown field {}", rename.from).into());
+        }
+    }
+    if options.fields.is_empty() {
+        return Ok(available);
     }
+    options
+        .fields
+        .iter()
+        .map(
+            |selected| match available.iter().find(|(name, _)| name == selected) {
+                Some(field) => Ok(*field),
+                _ if FIELDS.iter().any(|(name, _)| name == selected) => Err(format!(
+                    "field {} isn't emitted without the flag enabling it",
+                    selected
+                )
+                .into()),
+                _ => Err(format!("unknown field {}", selected).into()),
+            },
+        )
+        .collect()
 }
 
-#[derive(Default, Debug, Serialize)]
-struct Change {
-    repo: String,
-    sha: String,
-    author: String,
-    timestamp: String,
-    path: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    ext: Option<String>,
-    category: Category,
-    additions: usize,
-    deletions: usize,
+#[cfg_attr(tarpaulin, skip)]
+/// the mailmap to canonicalize authors with, if any
+fn mailmap(options: &Options) -> Option<PathBuf> {
+    options.mailmap.clone().or_else(|| {
+        options
+            .repo_path
+            .as_ref()
+            .map(|path| path.join(".mailmap"))
+            .filter(|path| path.is_file())
+    })
 }
 
-impl Change {
-    fn categorize(path: &str) -> Category {
-        if path.contains("test") {
-            Category::Test
-        } else {
-            Category::Default
-        }
-    }
+/// sources reading repositories of a forge through its api, each named for its repository
+fn remotes(
+    forge: Forge,
+    repos: Vec<String>,
+    base_url: &str,
+    token: Option<String>,
+) -> Vec<Source> {
+    repos
+        .into_iter()
+        .map(|repo| Source {
+            name: repo.clone(),
+            input: Input::Api(Remote {
+                forge,
+                repo,
+                base_url: base_url.into(),
+                token: token.clone(),
+            }),
+        })
+        .collect()
 }
 
-impl Into<Change> for (String, Header, Path) {
-    fn into(self: (String, Header, Path)) -> Change {
-        let (
-            repo,
-            Header {
-                sha,
-                author,
-                timestamp,
-            },
-            Path {
-                additions,
-                deletions,
-                path,
-            },
-        ) = self;
-        let category = Change::categorize(&path);
-        let ext = StdPath::new(&path)
-            .extension()
-            .and_then(OsStr::to_str)
-            .map(|s| s.into());
-        Change {
-            repo,
-            sha,
-            author,
-            timestamp,
-            path,
-            category,
-            ext,
-            additions,
-            deletions,
+/// sources reading the clones of every repository of a github organization, once each
+/// is cloned or fetched into `workdir` by up to `jobs` threads, transferring no more
+/// than `shallow` allows
+#[allow(clippy::too_many_arguments)]
+fn org(
+    org: &str,
+    workdir: &std::path::Path,
+    token: Option<String>,
+    base_url: &str,
+    include_forks: bool,
+    include_archived: bool,
+    shallow: &Shallow,
+    jobs: usize,
+) -> Result<Vec<Source>, Box<dyn Error>> {
+    #[cfg(feature = "github")]
+    {
+        let remotes = git_linecat::github::repositories(base_url, org, token.as_deref())?
+            .into_iter()
+            .filter(|repo| (include_forks || !repo.fork) && (include_archived || !repo.archived))
+            .map(|repo| git_linecat::cache::Remote {
+                name: repo.full_name,
+                url: repo.clone_url,
+            })
+            .collect::<Vec<_>>();
+        if remotes.is_empty() {
+            return Err(format!("found no repositories of {} to read", org).into());
         }
+        Ok(git_linecat::cache::sync_all(
+            remotes,
+            workdir,
+            token.as_deref(),
+            shallow,
+            jobs,
+        ))
+    }
+    #[cfg(not(feature = "github"))]
+    {
+        drop((
+            workdir,
+            token,
+            base_url,
+            include_forks,
+            include_archived,
+            shallow,
+            jobs,
+        ));
+        Err(format!(
+            "git-linecat was built without the github feature required to list the repositories of {}",
+            org
+        )
+        .into())
     }
 }
 
-enum State {
-    Reset,
-    Next(Header),
-    Emit(Header, Path),
+/// escapes help text as literal roff, so lines starting with control characters
+/// and backslashes are printed as they're written
+fn roff(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e");
+            match line.starts_with('.') || line.starts_with('\'') {
+                true => format!("\\&{}\n", line),
+                _ => format!("{}\n", line),
+            }
+        })
+        .collect()
 }
 
-trait Emitter {
-    fn emit(
-        &mut self,
-        line: Change,
-    ) -> Result<(), Box<dyn Error>>;
+/// the long help of a subcommand, or of git-linecat itself, as clap renders it
+fn help(subcommand: Option<&str>) -> Result<String, Box<dyn Error>> {
+    let mut app = Options::clap();
+    match subcommand {
+        None => {
+            let mut help = Vec::new();
+            app.write_long_help(&mut help)?;
+            Ok(String::from_utf8(help)?)
+        }
+        Some(name) => match app.get_matches_from_safe(["git-linecat", name, "--help"]) {
+            Err(e) if e.kind == structopt::clap::ErrorKind::HelpDisplayed => Ok(e.message),
+            _ => Err(format!("failed to render help of {}", name).into()),
+        },
+    }
 }
 
-struct Stdout;
-
-impl Emitter for Stdout {
-    fn emit(
-        &mut self,
-        line: Change,
-    ) -> Result<(), Box<dyn Error>> {
-        println!("{}", serde_json::to_string(&line)?);
-        Ok(())
+/// a man page of git-linecat's options, followed by a section for each
+/// subcommand named by its help
+fn man() -> Result<String, Box<dyn Error>> {
+    let long_help = help(None)?;
+    let mut page = format!(
+        ".TH GIT-LINECAT 1 \"\" \"git-linecat {}\" \"User Commands\"\n\
+         .SH NAME\n\
+         git-linecat \\- {}\n\
+         .SH DESCRIPTION\n\
+         .nf\n{}.fi\n",
+        env!("CARGO_PKG_VERSION"),
+        // the line following the name and version
+        long_help.lines().nth(1).unwrap_or_default(),
+        roff(&long_help)
+    );
+    let subcommands = long_help
+        .lines()
+        .skip_while(|line| *line != "SUBCOMMANDS:")
+        .skip(1)
+        // names are indented once, the lines their descriptions wrap onto further
+        .filter_map(|line| line.strip_prefix("    "))
+        .filter(|rest| !rest.starts_with(' '))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .filter(|name| *name != "help");
+    for name in subcommands {
+        page.push_str(&format!(
+            ".SH \"{}\"\n.nf\n{}.fi\n",
+            name.to_uppercase(),
+            roff(&help(Some(name))?)
+        ));
     }
+    Ok(page)
 }
 
-use structopt::StructOpt;
-
-#[derive(Debug, StructOpt)]
-#[structopt(
-    name = "git-linecat",
-    about = "A tool for transforming and categorizing git log data"
-)]
-struct Options {
-    #[structopt(short = "r", long = "repository", help = "Repository name")]
-    repository: String,
-    #[structopt(
-        short = "l",
-        long = "logs",
-        help = "Path to git log output. use `-` to read from stdin",
-        default_value = "-"
-    )]
-    logs: String,
+/// command line args, following the flags of the config file, if there is one
+fn args() -> Result<Vec<String>, Box<dyn Error>> {
+    let mut args = env::args().collect::<Vec<_>>();
+    let profile = config::profile(&args);
+    let config = match config::path() {
+        Some(path) => Config::discover(&path)?,
+        _ => None,
+    };
+    let flags = match (config, profile) {
+        (Some(config), profile) => config.args(profile)?,
+        (_, Some(profile)) => {
+            return Err(format!("no config file to read profile {} from", profile).into())
+        }
+        _ => Vec::new(),
+    };
+    args.splice(1..1, flags);
+    Ok(args)
 }
 
-#[cfg_attr(tarpaulin, skip)]
-fn main() -> Result<(), Box<dyn Error>> {
-    let Options { repository, logs } = Options::from_args();
-    match &logs[..] {
-        "-" => run(
-            repository,
-            &mut stdin().lock().lines().filter_map(Result::ok),
-            &mut Stdout,
-        ),
-        _ => run(
-            repository,
-            &mut BufReader::new(&File::open(logs)?)
-                .lines()
-                .filter_map(Result::ok),
-            &mut Stdout,
-        ),
+fn main() {
+    // failures before logging is set up are printed as they are
+    let options = match args()
+        .map(Options::from_iter)
+        .and_then(|options| logging::init(options.log_level, options.log_format).map(|_| options))
+    {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(error::exit_code(&*e));
+        }
+    };
+    let printed = match options.command {
+        Some(Command::Completions { shell }) => {
+            Options::clap().gen_completions_to("git-linecat", shell, &mut io::stdout());
+            Ok(())
+        }
+        Some(Command::Man) => man().and_then(|page| Ok(io::stdout().write_all(page.as_bytes())?)),
+        _ => linecat(options),
+    };
+    if let Err(e) = printed {
+        tracing::error!("{}", e);
+        process::exit(error::exit_code(&*e));
     }
 }
 
-fn run<L, E>(
-    repository: String,
-    lines: &mut L,
-    emitter: &mut E,
-) -> Result<(), Box<dyn Error>>
-where
-    L: Iterator<Item = String>,
-    E: Emitter,
-{
-    lines
-        .try_fold(State::Reset, |state, line| {
-            Ok(match state {
-                State::Reset => State::Next(line.parse()?),
-                State::Next(header) => {
-                    if line.is_empty() {
-                        State::Reset
-                    } else if line.starts_with('-') {
-                        // binary file
-                        State::Next(header)
-                    } else {
-                        // we expect a path, but some commits may be empty (no path) so we must be flexible
-                        match line.parse::<Path>() {
-                            Ok(path) => State::Emit(header, path),
-                            _ => State::Next(line.parse()?),
-                        }
-                    }
-                }
-                State::Emit(header, diff) => {
-                    emitter.emit((repository.clone(), header.clone(), diff).into())?;
-                    if line.is_empty() {
-                        State::Reset
-                    } else {
-                        State::Next(header)
-                    }
-                }
+fn linecat(options: Options) -> Result<(), Box<dyn Error>> {
+    let fields = fields(&options)?;
+    // only projected when asked to, as serializing every field directly is cheaper
+    let projection =
+        (!options.fields.is_empty() || !options.rename_field.is_empty() || options.single_category)
+            .then(|| Projection::new(&fields, &options.rename_field));
+    let mailmap = mailmap(&options);
+    let Options {
+        repository,
+        manifest,
+        logs,
+        repo_path,
+        backend,
+        recurse_submodules,
+        with_message,
+        explode_coauthors,
+        include_binary,
+        merges,
+        header_format,
+        strict,
+        reference_pattern,
+        rules,
+        explain_categorization,
+        categorizer_cmd,
+        categorizer_wasm,
+        mut output_format,
+        index,
+        granularity,
+        nest,
+        out,
+        output,
+        out_template,
+        webhook_header,
+        webhook_batch,
+        webhook_gzip,
+        dead_letter,
+        compress,
+        rotate_size,
+        rotate_records,
+        progress,
+        flush_every,
+        limit_commits,
+        limit_records,
+        sample,
+        sample_seed,
+        buffer_records,
+        follow,
+        summary,
+        summary_file,
+        metrics,
+        metrics_file,
+        since,
+        until,
+        after_sha,
+        before_sha,
+        state,
+        author,
+        mut exclude_author,
+        exclude_bots,
+        min_lines,
+        max_lines,
+        skip_huge_commits,
+        exclude_generated,
+        select,
+        map,
+        dedupe,
+        jobs,
+        emit_schema,
+        rename_field,
+        codeowners,
+        path_depth,
+        time_buckets,
+        line_counts,
+        follow_renames,
+        command,
+        ..
+    } = options;
+    // repositories read through an api are emitted as those read without a subcommand are
+    let (command, remotes) = match command {
+        Some(Command::Github {
+            repo,
+            token,
+            base_url,
+        }) => {
+            let token = token.or_else(|| env::var("GITHUB_TOKEN").ok());
+            (None, remotes(Forge::Github, repo, &base_url, token))
+        }
+        Some(Command::Gitlab {
+            repo,
+            token,
+            base_url,
+        }) => {
+            let token = token.or_else(|| env::var("GITLAB_TOKEN").ok());
+            (None, remotes(Forge::Gitlab, repo, &base_url, token))
+        }
+        Some(Command::Bitbucket {
+            repo,
+            token,
+            server,
+            base_url,
+        }) => {
+            let token = token.or_else(|| env::var("BITBUCKET_TOKEN").ok());
+            let forge = match server {
+                true => Forge::BitbucketServer,
+                _ => Forge::Bitbucket,
+            };
+            let base_url = base_url.unwrap_or_else(|| "https://api.bitbucket.org".into());
+            (None, remotes(forge, repo, &base_url, token))
+        }
+        Some(Command::Org {
+            github_org,
+            workdir,
+            token,
+            base_url,
+            include_forks,
+            include_archived,
+            depth,
+            shallow_since,
+            filter,
+        }) => {
+            let token = token.or_else(|| env::var("GITHUB_TOKEN").ok());
+            let jobs =
+                jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, Into::into));
+            let sources = org(
+                &github_org,
+                &workdir,
+                token,
+                &base_url,
+                include_forks,
+                include_archived,
+                &Shallow {
+                    depth,
+                    since: shallow_since.map(Bound::to_git),
+                    filter,
+                },
+                jobs,
+            )?;
+            (None, sources)
+        }
+        command => (command, Vec::new()),
+    };
+    match (&mut output_format, index) {
+        (Format::EsBulk(name), Some(index)) => *name = index,
+        (_, Some(_)) => return Err("--index requires --output-format es-bulk".into()),
+        _ => (),
+    }
+    // gauges of each subcommand are kept apart by its name
+    if let Format::Prometheus(namespace) = &mut output_format {
+        let name = match &command {
+            Some(Command::Summarize { .. }) => "summarize",
+            Some(Command::Hotspots { .. }) => "hotspots",
+            Some(Command::Churn { .. }) => "churn",
+            Some(Command::Coupling { .. }) => "coupling",
+            Some(Command::Ownership { .. }) => "ownership",
+            Some(Command::Trend { .. }) => "trend",
+            Some(Command::Query { .. }) => "query",
+            Some(Command::Validate) => "validate",
+            Some(
+                Command::Completions { .. }
+                | Command::Man
+                | Command::Watch { .. }
+                | Command::Github { .. }
+                | Command::Gitlab { .. }
+                | Command::Bitbucket { .. }
+                | Command::Org { .. },
+            )
+            | None => {
+                return Err("prometheus output is only written by aggregating subcommands".into())
+            }
+        };
+        *namespace = format!("{}_{}", namespace, name);
+    }
+    // outputs given on the command line in any form take precedence over the variable's
+    let output = match output {
+        None if out.is_none() && out_template.is_none() => config::var("output")?,
+        output => output,
+    };
+    let output = output.or_else(|| out_template.map(Sink::Partitioned));
+    let sink = match (output, out, compress) {
+        (Some(output), _, None) => output,
+        (_, Some(out), None) => Sink::from(out),
+        (None, None, None) => Sink::Stdout,
+        (Some(Sink::File(path, _)), _, Some(compress)) => Sink::File(path, compress),
+        (_, Some(out), Some(compress)) => Sink::File(out, compress),
+        _ => return Err("--compress requires a file output".into()),
+    };
+    let webhook_header = match sink {
+        Sink::Webhook { .. } if webhook_header.is_empty() => config::vars("webhook-header")?,
+        _ => webhook_header,
+    };
+    let sink = match sink {
+        Sink::Webhook { url, .. } if webhook_batch > 0 => Sink::Webhook {
+            url,
+            post: Post {
+                headers: webhook_header,
+                batch: webhook_batch,
+                gzip: webhook_gzip,
+                dead_letter,
+            },
+        },
+        Sink::Webhook { .. } => return Err("--webhook-batch must be at least 1".into()),
+        _ if !webhook_header.is_empty() || webhook_gzip || dead_letter.is_some() => {
+            return Err(
+                "--webhook-header, --webhook-gzip and --dead-letter require a webhook output"
+                    .into(),
+            )
+        }
+        sink => sink,
+    };
+    if let Some(schema) = emit_schema {
+        if command.is_some() || granularity == Granularity::Commit || nest {
+            return Err("--emit-schema only describes changes emitted per file".into());
+        }
+        let mut write = sink.writer()?;
+        serde_json::to_writer_pretty(&mut write, &schema.describe(&fields, &rename_field))?;
+        writeln!(write)?;
+        return Ok(());
+    }
+    if recurse_submodules && matches!(backend, Backend::Libgit2) {
+        return Err("--recurse-submodules requires the git backend".into());
+    }
+    let parse = Parse {
+        with_message,
+        include_binary,
+        merges,
+        header_format,
+        submodules: recurse_submodules,
+    };
+    let state = state
+        .map(|path| State::load(&path).map(|state| (path, state)))
+        .transpose()?;
+    // git filters on commit dates so can only prune history older than `since`, or
+    // than the newest change of a previous run, as commits are never committed
+    // before they're authored
+    let args = |repo: &str| {
+        since
+            .into_iter()
+            .chain(state.as_ref().and_then(|(_, state)| state.since(repo)))
+            .max_by_key(|since| since.0)
+            .map(|since| format!("--since={}", since.to_git()))
+            .into_iter()
+            .collect::<Vec<_>>()
+    };
+    let mut sources = repository
+        .iter()
+        .map(|repository| {
+            Source::parse(repository, || match &repo_path {
+                Some(path) => Input::Repo(path.clone()),
+                _ => Input::Logs(logs.clone()),
             })
         })
-        .map(drop)
+        .collect::<Vec<_>>();
+    // required of subcommands too, which clap can't tell apart from those printing documentation
+    if repository.is_empty() && manifest.is_none() && remotes.is_empty() {
+        return Err("--repository or --manifest is required".into());
+    }
+    sources.extend(remotes);
+    if let Some(manifest) = manifest {
+        sources.extend(manifest::load(&manifest)?);
+    }
+    let interval = match &command {
+        Some(Command::Watch { interval }) => Some(Duration::from_secs(*interval)),
+        _ => None,
+    };
+    if nest && (command.is_some() || granularity == Granularity::Commit) {
+        return Err("--nest may not be combined with a subcommand or --granularity commit".into());
+    }
+    let limits = Some(Limits {
+        bytes: rotate_size.map(|Size(bytes)| bytes),
+        records: rotate_records,
+    })
+    .filter(|limits| *limits != Limits::default());
+    let rotates = match &command {
+        None | Some(Command::Watch { .. }) => granularity == Granularity::File && !nest,
+        _ => false,
+    };
+    if limits.is_some() && !rotates {
+        return Err(
+            "--rotate-size and --rotate-records only rotate changes emitted per file".into(),
+        );
+    }
+    if follow && command.is_some() {
+        return Err("--follow may not be combined with a subcommand".into());
+    }
+    let emitter: Box<dyn Emitter> = match (command, granularity) {
+        (Some(_), Granularity::Commit) => {
+            return Err("--granularity commit may not be combined with a subcommand".into())
+        }
+        (Some(Command::Summarize { by, bucket }), _) => {
+            Box::new(Summarize::new(by, bucket, output_format, sink.writer()?))
+        }
+        (Some(Command::Hotspots { top, window }), _) => {
+            Box::new(Hotspots::new(top, window, output_format, sink.writer()?))
+        }
+        (Some(Command::Churn { within }), _) => {
+            Box::new(Churn::new(within, output_format, sink.writer()?))
+        }
+        (
+            Some(Command::Coupling {
+                min_support,
+                min_confidence,
+                max_files,
+            }),
+            _,
+        ) => Box::new(Coupling::new(
+            min_support,
+            min_confidence,
+            max_files,
+            output_format,
+            sink.writer()?,
+        )),
+        (Some(Command::Ownership { by, threshold }), _) => {
+            Box::new(Ownership::new(by, threshold, output_format, sink.writer()?))
+        }
+        (Some(Command::Trend { by, period, window }), _) => Box::new(Trend::new(
+            by,
+            period,
+            window,
+            output_format,
+            sink.writer()?,
+        )),
+        #[cfg(feature = "duckdb")]
+        (Some(Command::Query { sql }), _) => {
+            Box::new(Query::new(sql, fields, output_format, sink.writer()?)?)
+        }
+        #[cfg(not(feature = "duckdb"))]
+        (Some(Command::Query { sql }), _) => {
+            return Err(format!(
+                "git-linecat was built without the duckdb feature required to query {}",
+                sql
+            )
+            .into())
+        }
+        (Some(Command::Completions { .. } | Command::Man), _) => {
+            unreachable!("completions and man pages are printed before reading")
+        }
+        (
+            Some(
+                Command::Github { .. }
+                | Command::Gitlab { .. }
+                | Command::Bitbucket { .. }
+                | Command::Org { .. },
+            ),
+            _,
+        ) => {
+            unreachable!("repositories read through an api are read without a subcommand")
+        }
+        (Some(Command::Validate), _) => {
+            return validate(
+                sources,
+                |source, emitter| {
+                    let args = args(&source.name);
+                    read(source, &backend, &parse, strict, &args, emitter)
+                },
+                &output_format,
+                sink.writer()?,
+            )
+        }
+        (None, Granularity::File) if nest => match output_format {
+            Format::Json => Box::new(Nest::new(
+                sink.writer()?,
+                projection.unwrap_or_else(|| Projection::new(&fields, &[])),
+            )),
+            _ => return Err("--nest may only be written as json".into()),
+        },
+        // watching emits changes as runs without a subcommand do, only continuously
+        (None | Some(Command::Watch { .. }), Granularity::File) => match (&sink, limits) {
+            (_, None) if sink.is_remote() && buffer_records > 0 => {
+                let (sink, format) = (sink.clone(), output_format.clone());
+                Box::new(Buffered::spawn(buffer_records, move || {
+                    sink.emitter(&format, fields, projection)
+                })?)
+            }
+            (_, None) => sink.emitter(&output_format, fields, projection)?,
+            (Sink::File(path, compress), Some(limits)) => Box::new(Rotate::new(
+                path.clone(),
+                *compress,
+                &output_format,
+                fields,
+                projection,
+                limits,
+            )?),
+            _ => return Err("--rotate-size and --rotate-records require a file output".into()),
+        },
+        (None, Granularity::Commit) if !rename_field.is_empty() => {
+            return Err("--rename-field may not be combined with --granularity commit".into())
+        }
+        (None, Granularity::Commit) => {
+            Box::new(Commits::new(fields, output_format, sink.writer()?))
+        }
+    };
+    let mut emitter: Box<dyn Emitter> = Box::new(Blame {
+        sink: sink.to_string(),
+        emitter,
+    });
+    let metrics = match metrics {
+        None => config::var("metrics")?,
+        metrics => metrics,
+    };
+    if metrics.is_some() || metrics_file.is_some() {
+        emitter = Box::new(Metrics::new(metrics, metrics_file, emitter)?);
+    }
+    // followed changes are flushed as they're read, as the end may never come
+    if let Some(every) = flush_every.or_else(|| Some(1).filter(|_| follow)) {
+        emitter = Box::new(FlushEvery::new(every, emitter));
+    }
+    if progress {
+        emitter = Box::new(Progress::new(emitter));
+    }
+    if let Some(path) = summary_file {
+        let file = File::create(&path)
+            .map_err(|e| format!("failed to create summary {}: {}", path.display(), e))?;
+        emitter = Box::new(Summary::new(emitter, file));
+    } else if summary {
+        emitter = Box::new(Summary::new(emitter, io::stderr()));
+    }
+    // limits count the changes written, once every other has been dropped
+    if limit_commits.is_some() || limit_records.is_some() {
+        emitter = Box::new(Limit::new(limit_commits, limit_records, emitter));
+    }
+    // changes are mapped before they're selected, after every field is filled in
+    if let Some(expr) = select {
+        emitter = Box::new(Select { expr, emitter });
+    }
+    // generated changes are dropped by their categories once rules and categorizers have had their say
+    if exclude_generated {
+        emitter = Box::new(Select {
+            expr: r#".category != "generated" and ((.categories // []) | contains(["generated"]) | not)"#
+                .parse()?,
+            emitter,
+        });
+    }
+    if let Some(expr) = map {
+        emitter = Box::new(Map { expr, emitter });
+    }
+    if !with_message && !reference_pattern.is_empty() {
+        return Err("--reference-pattern requires --with-message".into());
+    }
+    if !with_message && explode_coauthors {
+        return Err("--explode-coauthors requires --with-message".into());
+    }
+    if explode_coauthors && granularity == Granularity::Commit {
+        return Err("--explode-coauthors may not be combined with --granularity commit".into());
+    }
+    if explode_coauthors && nest {
+        return Err("--explode-coauthors may not be combined with --nest".into());
+    }
+    if with_message {
+        let patterns = match reference_pattern {
+            patterns if patterns.is_empty() => references::PATTERNS
+                .iter()
+                .map(|pattern| Regex::new(pattern))
+                .collect::<Result<_, _>>()?,
+            patterns => patterns,
+        };
+        emitter = Box::new(References::new(patterns, emitter));
+    }
+    if time_buckets {
+        emitter = Box::new(TimeBuckets { emitter });
+    }
+    if let Some(depth) = path_depth {
+        if depth == 0 {
+            return Err("--path-depth must be at least 1".into());
+        }
+        emitter = Box::new(Prefix { depth, emitter });
+    }
+    if let Some(codeowners) = codeowners {
+        emitter = Box::new(Own {
+            codeowners: Codeowners::load(&codeowners)?,
+            emitter,
+        });
+    }
+    if line_counts {
+        let repos = sources
+            .iter()
+            .filter_map(|source| match &source.input {
+                Input::Repo(path) => Some((source.name.clone(), path.clone())),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        if repos.is_empty() {
+            return Err("--line-counts requires a repository read from --repo-path".into());
+        }
+        emitter = Box::new(LineCounts::new(repos, emitter));
+    }
+    // labels from categorizers take precedence over rules
+    if let Some(exe) = categorizer_cmd {
+        emitter = Box::new(Categorizer::spawn(&exe, emitter)?);
+    }
+    if let Some(module) = categorizer_wasm {
+        #[cfg(feature = "wasm")]
+        {
+            emitter = Box::new(wasm::Categorize {
+                wasm: wasm::Wasm::load(&module)?,
+                emitter,
+            });
+        }
+        #[cfg(not(feature = "wasm"))]
+        return Err(format!(
+            "git-linecat was built without the wasm feature required by {}",
+            module.display()
+        )
+        .into());
+    }
+    if explain_categorization && rules.is_none() {
+        return Err("--explain-categorization requires rules loaded with --rules".into());
+    }
+    if let Some(rules) = rules {
+        emitter = Box::new(Categorize {
+            rules: Rules::load(&rules)?,
+            explain: explain_categorization.then(Explain::default),
+            emitter,
+        });
+    }
+    // the .gitattributes of repositories refine built-in categories, which rules then override
+    let mut attributes = HashMap::new();
+    for source in &sources {
+        if let Input::Repo(path) = &source.input {
+            if let Some(found) = Attributes::discover(path)? {
+                attributes.insert(source.name.clone(), found);
+            }
+        }
+    }
+    if !attributes.is_empty() {
+        emitter = Box::new(Linguist {
+            repos: attributes,
+            emitter,
+        });
+    }
+    if dedupe {
+        emitter = Box::new(Dedupe::new(emitter));
+    }
+    if exclude_bots {
+        exclude_author.push(Regex::new(BOTS)?);
+    }
+    if since.is_some()
+        || until.is_some()
+        || !author.is_empty()
+        || !exclude_author.is_empty()
+        || min_lines.is_some()
+        || max_lines.is_some()
+    {
+        emitter = Box::new(Filter {
+            since,
+            until,
+            authors: author,
+            excluded_authors: exclude_author,
+            min_lines,
+            max_lines,
+            strict,
+            emitter,
+        });
+    }
+    // renames are followed through commits which are filtered out too
+    if follow_renames {
+        emitter = Box::new(Renames::new(emitter));
+    }
+    if explode_coauthors {
+        emitter = Box::new(Explode { emitter });
+    }
+    // canonicalized authors are what author filters and aggregates see
+    if let Some(mailmap) = mailmap {
+        emitter = Box::new(Canonicalize {
+            mailmap: Mailmap::load(&mailmap)?,
+            emitter,
+        });
+    }
+    if let Some((path, state)) = &state {
+        emitter = Box::new(Incremental::new(path.clone(), state.clone(), emitter));
+    }
+    // commits are counted as read, before any of their changes are exploded or dropped
+    if let Some(max_files) = skip_huge_commits {
+        emitter = Box::new(HugeCommits::new(max_files, emitter));
+    }
+    // commits are sampled before anything but bounding them is done with their changes
+    if let Some(rate) = sample {
+        emitter = Box::new(Sample::new(rate, sample_seed, emitter));
+    }
+    // commits are bounded by sha first, as those bounding them may themselves be
+    // huge or left out of a sample
+    if after_sha.is_some() || before_sha.is_some() {
+        if sources.len() > 1 {
+            return Err("--after-sha and --before-sha require a single repository".into());
+        }
+        emitter = Box::new(Between::new(after_sha, before_sha, emitter));
+    }
+    if let Some(interval) = interval {
+        let (name, path) = match (&sources[..], &backend) {
+            (
+                [Source {
+                    name,
+                    input: Input::Repo(path),
+                }],
+                Backend::Git,
+            ) => (name, path),
+            _ => {
+                return Err("watch requires a single --repo-path read with the git backend".into())
+            }
+        };
+        // the revisions of a range are those of the repository, not its submodules
+        if recurse_submodules {
+            return Err("watch can't --recurse-submodules".into());
+        }
+        let watched = watch(
+            path,
+            interval,
+            &args(name),
+            &mut emitter,
+            |args, emitter| {
+                read(
+                    Source {
+                        name: name.clone(),
+                        input: Input::Repo(path.clone()),
+                    },
+                    &backend,
+                    &parse,
+                    strict,
+                    args,
+                    emitter,
+                )
+                .map(drop)
+            },
+        );
+        return match limited(watched)? {
+            true => emitter.finish(),
+            _ => Ok(()),
+        };
+    }
+    if follow {
+        let (name, logs) = match &sources[..] {
+            [Source {
+                name,
+                input: Input::Logs(logs),
+            }] => (name, logs),
+            _ => return Err("--follow requires a single source read from --logs".into()),
+        };
+        let followed = run(
+            name.clone(),
+            &parse,
+            &mut follow::open(logs, follow::INTERVAL)?,
+            &mut emitter,
+            &mut Malformed::new(if logs == "-" { "stdin" } else { logs }, strict),
+        );
+        return match limited(followed)? {
+            true => emitter.finish(),
+            _ => Ok(()),
+        };
+    }
+    let read = match jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, Into::into)) {
+        jobs if jobs > 1 && sources.len() > 1 => parallel::read(
+            sources,
+            jobs,
+            |source, emitter| {
+                let args = args(&source.name);
+                read(source, &backend, &parse, strict, &args, emitter).map(drop)
+            },
+            &mut emitter,
+        ),
+        _ => sources.into_iter().try_for_each(|source| {
+            let args = args(&source.name);
+            read(source, &backend, &parse, strict, &args, &mut emitter).map(drop)
+        }),
+    };
+    // runs cut short by a limit are finished as runs which read everything are
+    limited(read)?;
+    emitter.finish()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    #[test]
-    fn header_line_parses() -> Result<(), Box<dyn Error>> {
-        let _: Header = r#""61708727af02089cef4a72c6a532ddf332111b14","luna@moon.com","2019-08-08 18:03:38 -0400""#.parse()?;
-        Ok(())
-    }
 
     #[test]
-    fn path_line_parses() -> Result<(), Box<dyn Error>> {
-        let _: Path = r#"6       3       foo/bar/baz.rs"#.parse()?;
-        Ok(())
+    fn limits_conflict_with_state() {
+        for limit in &["--limit-commits", "--limit-records"] {
+            let parsed = Options::from_iter_safe(&[
+                "git-linecat",
+                "-r",
+                "test",
+                "--state",
+                "state.json",
+                limit,
+                "10",
+            ]);
+            assert!(parsed.is_err(), "{} was allowed with --state", limit);
+        }
+        assert!(
+            Options::from_iter_safe(&["git-linecat", "-r", "test", "--limit-commits", "10"])
+                .is_ok()
+        );
     }
 
     #[test]
-    fn paths_with_test_are_categorized() {
-        assert_eq!(Change::categorize("foo/test/bar.txt"), Category::Test)
+    fn single_category_leaves_out_categories() -> Result<(), Box<dyn Error>> {
+        let emitted = |args: &[&str]| -> Result<bool, Box<dyn Error>> {
+            let options = Options::from_iter_safe([&["git-linecat", "-r", "test"], args].concat())?;
+            Ok(fields(&options)?
+                .iter()
+                .any(|(name, _)| *name == "categories"))
+        };
+        assert!(emitted(&[])?);
+        assert!(!emitted(&["--single-category"])?);
+        Ok(())
     }
 
-    #[test]
-    fn paths_without_test_are_categorized() {
-        assert_eq!(Change::categorize("foo/bar/baz.txt"), Category::Default)
+    /// the paths a run resumed after the first of three commits emits, the first changing two paths
+    fn resumed(
+        name: &str,
+        args: &[&str],
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let dir = env::temp_dir().join(format!("git-linecat-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let (logs, output) = (dir.join("git.log"), dir.join("out.jsonl"));
+        let header = |sha: char| {
+            format!(
+                "\"{}\",\"luna@moon.com\",\"2019-08-09 15:36:50 -0400\"\n",
+                sha.to_string().repeat(40)
+            )
+        };
+        std::fs::write(
+            &logs,
+            [
+                header('a'),
+                "1\t0\ta.rs\n1\t0\tz.rs\n\n".into(),
+                header('b'),
+                "1\t0\tb.rs\n\n".into(),
+                header('c'),
+                "1\t0\tc.rs\n".into(),
+            ]
+            .concat(),
+        )?;
+        let (logs, output) = (
+            logs.to_string_lossy().to_string(),
+            format!("file:{}", output.display()),
+        );
+        let mut argv = vec![
+            "git-linecat",
+            "-r",
+            "test",
+            "--logs",
+            &logs,
+            "--output",
+            &output,
+            "--after-sha",
+            "aaaa",
+        ];
+        argv.extend(args);
+        let ran = linecat(Options::from_iter_safe(&argv)?);
+        let paths = std::fs::read_to_string(dir.join("out.jsonl"))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<serde_json::Value>(line).map(|v| v["path"].to_string())
+            })
+            .collect::<Result<Vec<_>, _>>();
+        std::fs::remove_dir_all(&dir)?;
+        ran?;
+        Ok(paths?)
     }
 
     #[test]
-    fn stdout_emits() {
-        assert!(Stdout.emit(Change::default()).is_ok())
+    fn resumes_after_huge_commits() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            resumed("huge", &["--skip-huge-commits", "1"])?,
+            vec![r#""b.rs""#, r#""c.rs""#]
+        );
+        Ok(())
     }
 
     #[test]
-    fn parses_lines() {
-        #[derive(Default)]
-        struct Counter {
-            n: usize,
-        }
-        impl Emitter for Counter {
-            fn emit(
-                &mut self,
-                _: Change,
-            ) -> Result<(), Box<dyn Error>> {
-                self.n += 1;
-                Ok(())
-            }
-        }
-        let mut counter = Counter::default();
-        drop(run(
-            "test".into(),
-            &mut include_str!("../tests/data/git.log")
-                .lines()
-                .map(|l| l.to_string()),
-            &mut counter,
-        ));
-        assert_eq!(1, counter.n);
+    fn resumes_after_commits_left_out_of_a_sample() -> Result<(), Box<dyn Error>> {
+        // the commit resumed after isn't sampled, but still bounds those which are
+        let paths = resumed("sample", &["--sample", "0.01"])?;
+        assert!(paths.iter().all(|path| path != r#""a.rs""#), "{:?}", paths);
+        Ok(())
     }
 }
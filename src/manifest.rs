@@ -0,0 +1,216 @@
+//! the repositories to read changes from in a single invocation, given as
+//! `--repository NAME=PATH` pairs or a yaml or toml manifest file
+//!
+//! ```yaml
+//! repositories:
+//!   - name: meetup/api
+//!     logs: logs/api.log
+//!   - name: meetup/web
+//!     repo_path: src/web
+//! ```
+use serde::Deserialize;
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// where a repository's history is read from
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    /// `git log` output at a path, or `-` for stdin
+    Logs(String),
+    /// a local repository to run `git log` in
+    Repo(PathBuf),
+    /// a repository hosted by a forge, read through its api rather than a clone
+    Api(Remote),
+}
+
+/// the forges whose apis repositories may be read through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Forge {
+    Github,
+    Gitlab,
+    Bitbucket,
+    BitbucketServer,
+}
+
+/// a repository of a forge, as `org/name`, and how to reach its api
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remote {
+    pub forge: Forge,
+    pub repo: String,
+    /// the root of the api, as `https://api.github.com`, or of a gitlab instance
+    pub base_url: String,
+    pub token: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Source {
+    pub name: String,
+    pub input: Input,
+}
+
+impl Source {
+    /// parses `NAME=PATH`, where path is either a log file or a repository directory.
+    /// a plain `NAME` reads from `default`
+    pub fn parse(
+        repository: &str,
+        default: impl FnOnce() -> Input,
+    ) -> Self {
+        match repository.split_once('=') {
+            Some((name, path)) => Source {
+                name: name.into(),
+                input: if Path::new(path).is_dir() {
+                    Input::Repo(path.into())
+                } else {
+                    Input::Logs(path.into())
+                },
+            },
+            _ => Source {
+                name: repository.into(),
+                input: default(),
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SourceDef {
+    name: String,
+    logs: Option<String>,
+    repo_path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct ManifestDef {
+    repositories: Vec<SourceDef>,
+}
+
+/// loads sources from a manifest file, choosing a format based on its extension.
+/// relative paths are resolved against the manifest's directory
+pub fn load(path: &Path) -> Result<Vec<Source>, Box<dyn Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read manifest {}: {}", path.display(), e))?;
+    let def = match path.extension().and_then(OsStr::to_str) {
+        Some("toml") => toml::from_str(&content)?,
+        Some("yml") | Some("yaml") => serde_yaml::from_str(&content)?,
+        _ => {
+            return Err(format!(
+                "unsupported manifest {}. expected a .toml, .yml, or .yaml extension",
+                path.display()
+            )
+            .into())
+        }
+    };
+    compile(def, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+fn compile(
+    def: ManifestDef,
+    base: &Path,
+) -> Result<Vec<Source>, Box<dyn Error>> {
+    def.repositories
+        .into_iter()
+        .map(
+            |SourceDef {
+                 name,
+                 logs,
+                 repo_path,
+             }| {
+                let input = match (logs, repo_path) {
+                    (Some(logs), None) if logs == "-" => Input::Logs(logs),
+                    (Some(logs), None) => Input::Logs(base.join(logs).to_string_lossy().into()),
+                    (None, Some(path)) => Input::Repo(base.join(path)),
+                    _ => {
+                        return Err(format!(
+                            "repository {} requires exactly one of logs or repo_path",
+                            name
+                        )
+                        .into())
+                    }
+                };
+                Ok(Source { name, input })
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_named_sources() {
+        let dir = std::env::temp_dir();
+        assert_eq!(
+            Source::parse("api=logs/api.log", || Input::Logs("-".into())),
+            Source {
+                name: "api".into(),
+                input: Input::Logs("logs/api.log".into())
+            }
+        );
+        assert_eq!(
+            Source::parse(&format!("web={}", dir.display()), || Input::Logs(
+                "-".into()
+            )),
+            Source {
+                name: "web".into(),
+                input: Input::Repo(dir)
+            }
+        );
+        assert_eq!(
+            Source::parse("api", || Input::Logs("-".into())),
+            Source {
+                name: "api".into(),
+                input: Input::Logs("-".into())
+            }
+        );
+    }
+
+    #[test]
+    fn yaml_manifests_compile() -> Result<(), Box<dyn Error>> {
+        let sources = compile(
+            serde_yaml::from_str(
+                r#"
+                repositories:
+                  - name: meetup/api
+                    logs: logs/api.log
+                  - name: meetup/web
+                    repo_path: src/web
+                "#,
+            )?,
+            Path::new("/etc/linecat"),
+        )?;
+        assert_eq!(
+            sources,
+            vec![
+                Source {
+                    name: "meetup/api".into(),
+                    input: Input::Logs("/etc/linecat/logs/api.log".into()),
+                },
+                Source {
+                    name: "meetup/web".into(),
+                    input: Input::Repo("/etc/linecat/src/web".into()),
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn manifests_require_one_input() -> Result<(), Box<dyn Error>> {
+        assert!(compile(
+            toml::from_str(
+                r#"
+                [[repositories]]
+                name = "meetup/api"
+                "#,
+            )?,
+            Path::new(""),
+        )
+        .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+//! run counters, sent to statsd as a run goes and written to a file once done,
+//! so ingestion jobs may be monitored without scraping stderr
+use crate::{progress, Change, Emitter};
+use serde::Serialize;
+use std::{
+    error::Error,
+    fs::File,
+    net::UdpSocket,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// how often counters are sent to statsd while changes are still being emitted
+const EVERY: Duration = Duration::from_secs(10);
+
+/// the namespace of every metric sent
+const PREFIX: &str = "git_linecat";
+
+/// a statsd daemon to send counters to, as in `statsd://localhost:8125`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statsd {
+    pub address: String,
+}
+
+impl FromStr for Statsd {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("statsd://") {
+            Some(address) if address.contains(':') => Ok(Statsd {
+                address: address.trim_end_matches('/').into(),
+            }),
+            Some(address) if !address.is_empty() => Ok(Statsd {
+                address: format!("{}:8125", address.trim_end_matches('/')),
+            }),
+            _ => Err(format!(
+                "expected a statsd uri like statsd://localhost:8125 but found {}",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+struct Counts {
+    /// changes emitted
+    records: u64,
+    /// log lines read
+    lines: u64,
+    commits: u64,
+    /// malformed lines, whether skipped or failed on
+    malformed: u64,
+}
+
+impl Counts {
+    fn since(
+        self,
+        sent: Counts,
+    ) -> Counts {
+        Counts {
+            records: self.records - sent.records,
+            lines: self.lines - sent.lines,
+            commits: self.commits - sent.commits,
+            malformed: self.malformed - sent.malformed,
+        }
+    }
+}
+
+/// the counters of a finished run, as written to a metrics file
+#[derive(Serialize)]
+struct Report {
+    #[serde(flatten)]
+    counts: Counts,
+    duration_seconds: f64,
+    records_per_second: f64,
+    failed: bool,
+}
+
+/// statsd metrics of counts since they were last sent. counts are sent as counters
+/// of their increase, throughput as a gauge and, once `done`, the run's duration
+/// as a timer
+fn packet(
+    delta: Counts,
+    records_per_second: f64,
+    done: Option<(Duration, bool)>,
+) -> String {
+    let mut metrics = vec![
+        format!("{}.records:{}|c", PREFIX, delta.records),
+        format!("{}.lines:{}|c", PREFIX, delta.lines),
+        format!("{}.commits:{}|c", PREFIX, delta.commits),
+        format!("{}.malformed:{}|c", PREFIX, delta.malformed),
+        format!("{}.records_per_second:{:.2}|g", PREFIX, records_per_second),
+    ];
+    if let Some((duration, failed)) = done {
+        metrics.push(format!(
+            "{}.duration_ms:{}|ms",
+            PREFIX,
+            duration.as_millis()
+        ));
+        if failed {
+            metrics.push(format!("{}.failed:1|c", PREFIX));
+        }
+    }
+    metrics.join("\n")
+}
+
+/// counts changes on their way to another emitter, reporting them along with what
+/// was read to statsd every so often and once finished, and to a metrics file once
+/// finished. runs which fail are reported as they're dropped, with `failed` set
+pub struct Metrics<E> {
+    pub emitter: E,
+    statsd: Option<UdpSocket>,
+    file: Option<PathBuf>,
+    started: Instant,
+    reported: Instant,
+    emitted: u64,
+    /// counts as of their last report to statsd, which are sent as increases
+    sent: Counts,
+    done: bool,
+}
+
+impl<E> Metrics<E> {
+    pub fn new(
+        statsd: Option<Statsd>,
+        file: Option<PathBuf>,
+        emitter: E,
+    ) -> Result<Self, Box<dyn Error>> {
+        let statsd = statsd
+            .map(|Statsd { address }| {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket
+                    .connect(&address)
+                    .map_err(|e| format!("failed to resolve statsd {}: {}", address, e))?;
+                Ok::<_, Box<dyn Error>>(socket)
+            })
+            .transpose()?;
+        let now = Instant::now();
+        Ok(Metrics {
+            emitter,
+            statsd,
+            file,
+            started: now,
+            reported: now,
+            emitted: 0,
+            sent: Counts::default(),
+            done: false,
+        })
+    }
+
+    fn counts(&self) -> Counts {
+        let (lines, commits, malformed) = progress::totals();
+        Counts {
+            records: self.emitted,
+            lines,
+            commits,
+            malformed,
+        }
+    }
+
+    fn records_per_second(&self) -> f64 {
+        self.emitted as f64 / self.started.elapsed().as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// sends counts to statsd. metrics are best effort, so failing to send
+    /// them never fails the run they describe
+    fn send(
+        &mut self,
+        done: Option<bool>,
+    ) {
+        let counts = self.counts();
+        if let Some(socket) = &self.statsd {
+            let packet = packet(
+                counts.since(self.sent),
+                self.records_per_second(),
+                done.map(|failed| (self.started.elapsed(), failed)),
+            );
+            drop(socket.send(packet.as_bytes()));
+        }
+        self.sent = counts;
+        self.reported = Instant::now();
+    }
+
+    /// reports a run once it's done
+    fn report(
+        &mut self,
+        failed: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        self.done = true;
+        self.send(Some(failed));
+        if let Some(path) = &self.file {
+            let file = File::create(path)
+                .map_err(|e| format!("failed to create metrics {}: {}", path.display(), e))?;
+            serde_json::to_writer(
+                file,
+                &Report {
+                    counts: self.counts(),
+                    duration_seconds: self.started.elapsed().as_secs_f64(),
+                    records_per_second: self.records_per_second(),
+                    failed,
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<E> Emitter for Metrics<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.emitter.emit(line)?;
+        self.emitted += 1;
+        if self.statsd.is_some() && self.reported.elapsed() >= EVERY {
+            self.send(None);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let finished = self.emitter.finish();
+        self.report(finished.is_err())?;
+        finished
+    }
+}
+
+impl<E> Drop for Metrics<E> {
+    fn drop(&mut self) {
+        if !self.done {
+            drop(self.report(true));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collect;
+    use std::{env, fs};
+
+    #[test]
+    fn statsd_parses() {
+        assert_eq!(
+            "statsd://localhost:8125".parse(),
+            Ok(Statsd {
+                address: "localhost:8125".into()
+            })
+        );
+        assert_eq!(
+            "statsd://metrics".parse(),
+            Ok(Statsd {
+                address: "metrics:8125".into()
+            })
+        );
+        assert!("udp://localhost:8125".parse::<Statsd>().is_err());
+    }
+
+    #[test]
+    fn packets_carry_increases() {
+        let delta = Counts {
+            records: 3,
+            lines: 10,
+            commits: 2,
+            malformed: 1,
+        }
+        .since(Counts {
+            records: 1,
+            lines: 4,
+            commits: 1,
+            malformed: 1,
+        });
+        assert_eq!(
+            packet(delta, 1.5, Some((Duration::from_millis(1200), false))),
+            "git_linecat.records:2|c\n\
+             git_linecat.lines:6|c\n\
+             git_linecat.commits:1|c\n\
+             git_linecat.malformed:0|c\n\
+             git_linecat.records_per_second:1.50|g\n\
+             git_linecat.duration_ms:1200|ms"
+        );
+    }
+
+    #[test]
+    fn sends_and_writes_metrics() -> Result<(), Box<dyn Error>> {
+        let daemon = UdpSocket::bind("127.0.0.1:0")?;
+        daemon.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let path = env::temp_dir().join("git-linecat-metrics-test.json");
+        let mut metrics = Metrics::new(
+            Some(Statsd {
+                address: daemon.local_addr()?.to_string(),
+            }),
+            Some(path.clone()),
+            Collect::default(),
+        )?;
+        metrics.emit(Change::default())?;
+        metrics.emit(Change::default())?;
+        metrics.finish()?;
+        let mut buf = [0; 1024];
+        let n = daemon.recv(&mut buf)?;
+        let packet = String::from_utf8_lossy(&buf[..n]);
+        assert!(packet.starts_with("git_linecat.records:2|c\n"));
+        assert!(packet.contains("git_linecat.duration_ms:"));
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        assert_eq!(report["records"], 2);
+        assert_eq!(report["failed"], false);
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! measures how concentrated the ownership of paths is, as each author's share
+//! of the lines added to them. paths one author owns most of are a risk should
+//! that author leave
+use crate::{
+    format::{write_records, Format},
+    summarize::Dimension,
+    Change, Emitter,
+};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    io::Write,
+};
+
+/// columns following the grouped path or directory
+const COLUMNS: &[&str] = &[
+    "additions",
+    "authors",
+    "owner",
+    "owner_share",
+    "concentrated",
+    "shares",
+];
+
+/// accumulates additions per author of each path or directory,
+/// writing their shares once all changes are seen
+pub struct Ownership<W>
+where
+    W: Write,
+{
+    by: Dimension,
+    threshold: f64,
+    format: Format,
+    write: Option<W>,
+    groups: HashMap<Option<String>, BTreeMap<String, usize>>,
+}
+
+impl<W> Ownership<W>
+where
+    W: Write,
+{
+    /// groups changes `by` a path or directory, flagging groups
+    /// a single author owns more than `threshold` of
+    pub fn new(
+        by: Dimension,
+        threshold: f64,
+        format: Format,
+        write: W,
+    ) -> Self {
+        Ownership {
+            by,
+            threshold,
+            format,
+            write: Some(write),
+            groups: HashMap::new(),
+        }
+    }
+}
+
+impl<W> Emitter for Ownership<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        // records of merges have no path to attribute
+        if line.merge {
+            return Ok(());
+        }
+        let additions = line.additions.unwrap_or_default();
+        if additions > 0 {
+            *self
+                .groups
+                .entry(self.by.value(&line))
+                .or_default()
+                .entry(line.author)
+                .or_default() += additions;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let write = match self.write.take() {
+            Some(write) => write,
+            _ => return Ok(()),
+        };
+        let mut groups = std::mem::take(&mut self.groups)
+            .into_iter()
+            .collect::<Vec<_>>();
+        groups.sort_by(|a, b| a.0.cmp(&b.0));
+        let name = self.by.name();
+        let records = groups
+            .into_iter()
+            .map(|(group, authors)| {
+                let additions = authors.values().sum::<usize>();
+                // the first of the authors with the most additions, by email
+                let (owner, owned) = authors
+                    .iter()
+                    .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+                    .map(|(owner, owned)| (owner.clone(), *owned))
+                    .unwrap_or_default();
+                let share = |n: usize| n as f64 / additions as f64;
+                let values = vec![
+                    additions.into(),
+                    authors.len().into(),
+                    owner.into(),
+                    share(owned).into(),
+                    (share(owned) > self.threshold).into(),
+                    authors
+                        .iter()
+                        .map(|(author, n)| (author.clone(), share(*n).into()))
+                        .collect::<Map<_, _>>()
+                        .into(),
+                ];
+                let mut record = Map::new();
+                record.insert(name.into(), group.map_or(Value::Null, Value::String));
+                record.extend(COLUMNS.iter().map(|column| column.to_string()).zip(values));
+                record
+            })
+            .collect();
+        let columns = std::iter::once(name)
+            .chain(COLUMNS.iter().copied())
+            .collect::<Vec<_>>();
+        write_records(&self.format, write, &columns, records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(
+        path: &str,
+        author: &str,
+        additions: usize,
+    ) -> Change {
+        let mut change = Change {
+            path: path.into(),
+            dir: path.rsplit_once('/').map(|(dir, _)| dir.into()),
+            additions: Some(additions),
+            ..Change::default()
+        };
+        change.set_author(author.into());
+        change
+    }
+
+    fn ownership(by: Dimension) -> Result<String, Box<dyn Error>> {
+        let mut buf = Vec::new();
+        {
+            let mut ownership = Ownership::new(by, 0.8, Format::Json, &mut buf);
+            for line in [
+                change("src/lib.rs", "luna@moon.com", 9),
+                change("src/lib.rs", "sol@sun.com", 1),
+                change("src/main.rs", "sol@sun.com", 5),
+                change("src/main.rs", "terra@earth.com", 5),
+                change("src/main.rs", "luna@moon.com", 0),
+            ] {
+                ownership.emit(line)?;
+            }
+            ownership.finish()?;
+        }
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn flags_concentrated_paths() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            ownership(Dimension::Path)?,
+            "{\"path\":\"src/lib.rs\",\"additions\":10,\"authors\":2,\"owner\":\"luna@moon.com\",\
+             \"owner_share\":0.9,\"concentrated\":true,\"shares\":{\"luna@moon.com\":0.9,\"sol@sun.com\":0.1}}\n\
+             {\"path\":\"src/main.rs\",\"additions\":10,\"authors\":2,\"owner\":\"sol@sun.com\",\
+             \"owner_share\":0.5,\"concentrated\":false,\"shares\":{\"sol@sun.com\":0.5,\"terra@earth.com\":0.5}}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn groups_by_directory() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            ownership(Dimension::Dir)?,
+            "{\"dir\":\"src\",\"additions\":20,\"authors\":3,\"owner\":\"luna@moon.com\",\
+             \"owner_share\":0.45,\"concentrated\":false,\
+             \"shares\":{\"luna@moon.com\":0.45,\"sol@sun.com\":0.3,\"terra@earth.com\":0.25}}\n"
+        );
+        Ok(())
+    }
+}
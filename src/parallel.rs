@@ -0,0 +1,168 @@
+//! reads many sources at once on a pool of threads, handing their
+//! changes to a single emitter on the calling thread
+use crate::{error::LinecatError, manifest::Source, Change, Emitter};
+use std::{
+    error::Error,
+    mem,
+    sync::{
+        mpsc::{sync_channel, SyncSender},
+        Mutex,
+    },
+    thread,
+};
+
+/// changes buffered before being handed to the emitter. batches only
+/// end between commits so a commit's changes are always emitted together
+const BATCH_SIZE: usize = 256;
+
+type Batch = Result<Vec<Change>, Box<dyn Error + Send + Sync>>;
+
+/// a worker's emitter, sending batches of changes to the calling thread
+pub struct Batches {
+    sender: SyncSender<Batch>,
+    buffer: Vec<Change>,
+}
+
+impl Batches {
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.buffer.is_empty() {
+            self.sender
+                .send(Ok(mem::take(&mut self.buffer)))
+                .map_err(|_| "stopped reading")?;
+        }
+        Ok(())
+    }
+}
+
+impl Emitter for Batches {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.buffer.len() >= BATCH_SIZE && self.buffer[self.buffer.len() - 1].sha != line.sha {
+            self.flush()?;
+        }
+        self.buffer.push(line);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()
+    }
+}
+
+/// reads sources with up to `jobs` threads, each calling `read` with an emitter
+/// whose changes are forwarded to `emitter`. reading stops at the first error
+pub fn read<F, E>(
+    sources: Vec<Source>,
+    jobs: usize,
+    read: F,
+    emitter: &mut E,
+) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(Source, &mut Batches) -> Result<(), Box<dyn Error>> + Sync,
+    E: Emitter + ?Sized,
+{
+    let workers = jobs.clamp(1, sources.len().max(1));
+    let queue = Mutex::new(sources.into_iter());
+    thread::scope(|scope| {
+        let (sender, receiver) = sync_channel::<Batch>(workers * 4);
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (queue, read) = (&queue, &read);
+            scope.spawn(move || loop {
+                let source = match queue.lock() {
+                    Ok(mut queue) => queue.next(),
+                    _ => None,
+                };
+                let source = match source {
+                    Some(source) => source,
+                    _ => break,
+                };
+                let name = source.name.clone();
+                let mut batches = Batches {
+                    sender: sender.clone(),
+                    buffer: Vec::new(),
+                };
+                if let Err(e) = read(source, &mut batches).and_then(|_| batches.finish()) {
+                    // errors of a kind keep it, so runs exit as they would reading sources in turn
+                    drop(sender.send(Err(match e.downcast::<LinecatError>() {
+                        Ok(e) => e,
+                        Err(e) => format!("{}: {}", name, e).into(),
+                    })));
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        // returning early drops the receiver, failing the sends of remaining workers
+        for batch in receiver {
+            for change in batch.map_err(|e| e as Box<dyn Error>)? {
+                emitter.emit(change)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manifest::Input, Collect};
+
+    fn sources(n: usize) -> Vec<Source> {
+        (0..n)
+            .map(|i| Source {
+                name: i.to_string(),
+                input: Input::Logs("-".into()),
+            })
+            .collect()
+    }
+
+    /// emits 500 commits of three changes each for a source
+    fn commits(
+        source: Source,
+        emitter: &mut Batches,
+    ) -> Result<(), Box<dyn Error>> {
+        for commit in 0..500 {
+            for path in 0..3 {
+                emitter.emit(Change {
+                    repo: source.name.clone(),
+                    sha: commit.to_string(),
+                    path: path.to_string(),
+                    ..Change::default()
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reads_sources_keeping_commits_together() -> Result<(), Box<dyn Error>> {
+        let mut collect = Collect::default();
+        read(sources(8), 4, commits, &mut collect)?;
+        assert_eq!(collect.0.len(), 8 * 500 * 3);
+        for commit in collect.0.chunks(3) {
+            assert!(commit
+                .iter()
+                .all(|c| c.repo == commit[0].repo && c.sha == commit[0].sha));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn surfaces_worker_errors() {
+        let mut collect = Collect::default();
+        let err = read(
+            sources(3),
+            2,
+            |source, emitter| match &source.name[..] {
+                "1" => Err("boom".into()),
+                _ => commits(source, emitter),
+            },
+            &mut collect,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "1: boom");
+    }
+}
@@ -0,0 +1,317 @@
+//! changes routed to files by their fields, as in
+//! `out/{repo}/{year}/{month}/changes.jsonl`, for hive-style partitions
+//! written without a separate step to split them
+use crate::{
+    format::{Csv, Format, Json, Projected, Projection},
+    output::Compress,
+    Change, Emitter, Fields, FIELDS,
+};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+    fmt,
+    fs::{self, OpenOptions},
+    path::PathBuf,
+    str::FromStr,
+};
+
+/// the most files kept open at once. the least recently written is closed to open another
+pub const MAX_OPEN: usize = 64;
+
+/// the partition of changes whose field has no value, as hive names it
+const DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field(&'static str),
+    /// parts of a change's `timestamp_utc`
+    Year,
+    Month,
+    Day,
+}
+
+/// a path with `{field}` placeholders of any emitted field, and of the `{year}`,
+/// `{month}` and `{day}` a change was authored in utc
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+impl FromStr for Template {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| format!("unclosed placeholder in {}", s))?;
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].into()));
+            }
+            segments.push(match &rest[start + 1..end] {
+                "year" => Segment::Year,
+                "month" => Segment::Month,
+                "day" => Segment::Day,
+                name => FIELDS
+                    .iter()
+                    .find(|(field, _)| *field == name)
+                    .map(|(field, _)| Segment::Field(field))
+                    .ok_or_else(|| format!("unsupported placeholder {{{}}} in {}", name, s))?,
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.into()));
+        }
+        if !segments.iter().any(|s| !matches!(s, Segment::Literal(_))) {
+            return Err(format!("expected a placeholder in {}", s));
+        }
+        Ok(Template {
+            template: s.into(),
+            segments,
+        })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        write!(f, "{}", self.template)
+    }
+}
+
+/// a value safe to name a directory or file after
+fn escape(value: &str) -> String {
+    match value {
+        "" => DEFAULT_PARTITION.into(),
+        "." | ".." => value.replace('.', "%2E"),
+        _ => value
+            .replace('%', "%25")
+            .replace('/', "%2F")
+            .replace('\\', "%5C"),
+    }
+}
+
+impl Template {
+    /// the path a change is written to
+    pub fn render(
+        &self,
+        line: &Change,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let value = match self.segments.iter().any(|s| matches!(s, Segment::Field(_))) {
+            true => serde_json::to_value(line)?,
+            _ => Value::Null,
+        };
+        let date = |range| {
+            line.timestamp_utc
+                .as_deref()
+                .and_then(|t| t.get(range))
+                .unwrap_or_default()
+        };
+        let mut path = String::new();
+        for segment in &self.segments {
+            path.push_str(&match segment {
+                Segment::Literal(literal) => literal.clone(),
+                Segment::Field(field) => escape(&match value.get(field) {
+                    None | Some(Value::Null) => String::new(),
+                    Some(Value::String(s)) => s.clone(),
+                    Some(other) => other.to_string(),
+                }),
+                Segment::Year => escape(date(0..4)),
+                Segment::Month => escape(date(5..7)),
+                Segment::Day => escape(date(8..10)),
+            });
+        }
+        Ok(path.into())
+    }
+}
+
+/// writes each change to the file its template renders, creating directories as
+/// needed. at most [`MAX_OPEN`] files are open at once, and files closed to write
+/// to others are appended to once reopened
+pub struct Partitioned {
+    template: Template,
+    /// the fields of csv rows, when written as csv rather than json
+    csv: Option<Projection>,
+    projection: Option<Projection>,
+    open: HashMap<PathBuf, Box<dyn Emitter>>,
+    /// open files, least recently written first
+    recent: VecDeque<PathBuf>,
+    /// files written to by this run, which are appended to rather than truncated
+    written: HashSet<PathBuf>,
+    max_open: usize,
+}
+
+impl Partitioned {
+    pub fn new(
+        template: Template,
+        format: &Format,
+        fields: Fields,
+        projection: Option<Projection>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let csv = match format {
+            Format::Json => None,
+            Format::Csv => Some(
+                projection
+                    .clone()
+                    .unwrap_or_else(|| Projection::new(&fields, &[])),
+            ),
+            _ => return Err("--out-template may only be written as json or csv".into()),
+        };
+        Ok(Partitioned {
+            template,
+            csv,
+            projection,
+            open: HashMap::new(),
+            recent: VecDeque::new(),
+            written: HashSet::new(),
+            max_open: MAX_OPEN,
+        })
+    }
+
+    fn open(
+        &mut self,
+        path: &PathBuf,
+    ) -> Result<Box<dyn Emitter>, Box<dyn Error>> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+        }
+        let append = !self.written.insert(path.clone());
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+        let write = Compress::infer(path).writer(file)?;
+        Ok(match (&self.csv, &self.projection) {
+            (Some(csv), _) => {
+                let csv = Csv::projected(write, csv.clone());
+                // files already headed by this run aren't headed again
+                Box::new(if append { csv.headed() } else { csv })
+            }
+            (_, Some(projection)) => Box::new(Projected::new(write, projection.clone())),
+            _ => Box::new(Json(write)),
+        })
+    }
+}
+
+impl Emitter for Partitioned {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = self.template.render(&line)?;
+        match self.recent.iter().position(|p| *p == path) {
+            Some(i) => {
+                let path = self.recent.remove(i).unwrap_or_default();
+                self.recent.push_back(path);
+            }
+            _ => {
+                if self.recent.len() >= self.max_open {
+                    if let Some(mut closed) = self
+                        .recent
+                        .pop_front()
+                        .and_then(|oldest| self.open.remove(&oldest))
+                    {
+                        closed.finish()?;
+                    }
+                }
+                let emitter = self.open(&path)?;
+                self.open.insert(path.clone(), emitter);
+                self.recent.push_back(path.clone());
+            }
+        }
+        match self.open.get_mut(&path) {
+            Some(emitter) => emitter.emit(line),
+            _ => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        for emitter in self.open.values_mut() {
+            emitter.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.recent.clear();
+        for (_, mut emitter) in self.open.drain() {
+            emitter.finish()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn change(
+        repo: &str,
+        timestamp_utc: Option<&str>,
+    ) -> Change {
+        Change {
+            repo: repo.into(),
+            timestamp_utc: timestamp_utc.map(Into::into),
+            ..Change::default()
+        }
+    }
+
+    #[test]
+    fn templates_render() -> Result<(), Box<dyn Error>> {
+        let template: Template = "out/{repo}/{year}/{month}/changes.jsonl".parse()?;
+        assert_eq!(
+            template.render(&change("org/app", Some("2019-08-09T19:36:50Z")))?,
+            PathBuf::from("out/org%2Fapp/2019/08/changes.jsonl")
+        );
+        assert_eq!(
+            template.render(&change("..", None))?,
+            PathBuf::from(
+                "out/%2E%2E/__HIVE_DEFAULT_PARTITION__/__HIVE_DEFAULT_PARTITION__/changes.jsonl"
+            )
+        );
+        assert!("out/{nope}.jsonl".parse::<Template>().is_err());
+        assert!("out/{repo.jsonl".parse::<Template>().is_err());
+        assert!("out/changes.jsonl".parse::<Template>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn routes_changes_to_partitions() -> Result<(), Box<dyn Error>> {
+        let dir = env::temp_dir().join("git-linecat-partition-test");
+        drop(fs::remove_dir_all(&dir));
+        let template = format!("{}/repo={{repo}}/changes.csv", dir.display()).parse()?;
+        let fields = vec![FIELDS[0]];
+        let mut partitioned = Partitioned::new(template, &Format::Csv, fields.clone(), None)?;
+        partitioned.max_open = 1;
+        for repo in &["a", "b", "a"] {
+            partitioned.emit(change(repo, None))?;
+        }
+        partitioned.finish()?;
+        assert_eq!(
+            fs::read_to_string(dir.join("repo=a/changes.csv"))?,
+            "repo\na\na\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("repo=b/changes.csv"))?,
+            "repo\nb\n"
+        );
+        assert!(
+            Partitioned::new("{repo}.parquet".parse()?, &Format::Parquet, fields, None).is_err()
+        );
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
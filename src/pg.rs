@@ -0,0 +1,234 @@
+//! writes changes into a postgres table, bulk loading them with `COPY`
+use crate::{Change, Emitter, Fields, Kind};
+use postgres::{Client, NoTls};
+use serde_json::Value;
+use std::{error::Error, io::Write};
+
+/// rows loaded per `COPY`
+const BATCH_SIZE: usize = 10_000;
+
+fn column_type(kind: Kind) -> &'static str {
+    match kind {
+        Kind::String => "TEXT",
+        Kind::Integer => "BIGINT",
+        Kind::Boolean => "BOOLEAN",
+        Kind::Float => "DOUBLE PRECISION",
+        Kind::Timestamp => "TIMESTAMPTZ",
+        Kind::List => "TEXT[]",
+    }
+}
+
+/// quotes each part of a possibly schema qualified name, as in `analytics.changes`
+fn identifier(name: &str) -> String {
+    name.split('.')
+        .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn create_table(
+    table: &str,
+    fields: &Fields,
+) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} ({})",
+        identifier(table),
+        fields
+            .iter()
+            .map(|(name, kind)| format!("{} {}", identifier(name), column_type(*kind)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+fn copy(
+    table: &str,
+    fields: &Fields,
+) -> String {
+    format!(
+        "COPY {} ({}) FROM STDIN (FORMAT csv)",
+        identifier(table),
+        fields
+            .iter()
+            .map(|(name, _)| identifier(name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// a field value as a csv cell `COPY` understands. missing values are
+/// unquoted and empty, which csv `COPY` loads as null
+fn cell(
+    kind: Kind,
+    value: Option<&Value>,
+) -> String {
+    match (kind, value) {
+        (_, None) | (_, Some(Value::Null)) => String::new(),
+        (Kind::List, Some(Value::Array(items))) => quote(&format!(
+            "{{{}}}",
+            items
+                .iter()
+                .map(|item| {
+                    let item = match item {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    format!("\"{}\"", item.replace('\\', "\\\\").replace('"', "\\\""))
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        )),
+        (_, Some(Value::String(s))) => quote(s),
+        (_, Some(other)) => quote(&other.to_string()),
+    }
+}
+
+pub struct Postgres {
+    client: Client,
+    fields: Fields,
+    copy: String,
+    /// csv rows yet to be loaded
+    rows: Vec<u8>,
+    pending: usize,
+}
+
+impl Postgres {
+    /// connects to a database by url, as in `postgres://user@localhost/analytics`
+    pub fn connect(
+        url: &str,
+        table: &str,
+        create: bool,
+        fields: Fields,
+    ) -> Result<Self, Box<dyn Error>> {
+        let client = Client::connect(url, NoTls)
+            .map_err(|e| format!("failed to connect to postgres: {}", e))?;
+        Self::new(client, table, create, fields)
+    }
+
+    /// writes into `table`, first creating it when `create` is set
+    pub fn new(
+        mut client: Client,
+        table: &str,
+        create: bool,
+        fields: Fields,
+    ) -> Result<Self, Box<dyn Error>> {
+        if create {
+            client.batch_execute(&create_table(table, &fields))?;
+        }
+        Ok(Postgres {
+            client,
+            copy: copy(table, &fields),
+            fields,
+            rows: Vec::new(),
+            pending: 0,
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.pending > 0 {
+            let mut writer = self.client.copy_in(&self.copy)?;
+            writer.write_all(&self.rows)?;
+            writer.finish()?;
+            self.rows.clear();
+            self.pending = 0;
+        }
+        Ok(())
+    }
+}
+
+impl Emitter for Postgres {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let value = serde_json::to_value(&line)?;
+        let row = self
+            .fields
+            .iter()
+            .map(|(name, kind)| cell(*kind, value.get(name)))
+            .collect::<Vec<_>>()
+            .join(",");
+        self.rows.extend_from_slice(row.as_bytes());
+        self.rows.push(b'\n');
+        self.pending += 1;
+        if self.pending >= BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FIELDS;
+
+    #[test]
+    fn statements_quote_identifiers() {
+        let fields = vec![("path", Kind::String), ("references", Kind::List)];
+        assert_eq!(
+            create_table("analytics.changes", &fields),
+            r#"CREATE TABLE IF NOT EXISTS "analytics"."changes" ("path" TEXT, "references" TEXT[])"#
+        );
+        assert_eq!(
+            copy("changes", &fields),
+            r#"COPY "changes" ("path", "references") FROM STDIN (FORMAT csv)"#
+        );
+    }
+
+    #[test]
+    fn cells_distinguish_nulls_from_empty_strings() {
+        assert_eq!(cell(Kind::String, None), "");
+        assert_eq!(cell(Kind::String, Some(&"".into())), r#""""#);
+        assert_eq!(cell(Kind::String, Some(&r#"a "b""#.into())), r#""a ""b""""#);
+        assert_eq!(cell(Kind::Integer, Some(&3.into())), r#""3""#);
+        assert_eq!(
+            cell(Kind::List, Some(&serde_json::json!(["#1", "a\"b"]))),
+            r##""{""#1"",""a\""b""}""##
+        );
+    }
+
+    /// loads changes into the database at `GIT_LINECAT_POSTGRES_URL`, when set
+    #[test]
+    fn loads_changes() -> Result<(), Box<dyn Error>> {
+        let url = match std::env::var("GIT_LINECAT_POSTGRES_URL") {
+            Ok(url) => url,
+            _ => return Ok(()),
+        };
+        let table = format!("git_linecat_test_{}", std::process::id());
+        let mut postgres = Postgres::connect(&url, &table, true, FIELDS.to_vec())?;
+        postgres.emit(Change {
+            repo: "test".into(),
+            sha: "sha".into(),
+            timestamp: "2019-08-09 15:36:50 -0400".into(),
+            path: "a, \"b\".rs".into(),
+            additions: Some(3),
+            references: Some(vec!["#1".into(), "GH-2".into()]),
+            ..Change::default()
+        })?;
+        postgres.finish()?;
+        let mut client = Client::connect(&url, NoTls)?;
+        let row = client.query_one(
+            &*format!(
+                "SELECT path, additions, extract(epoch from timestamp)::bigint, \"references\", old_path FROM {}",
+                table
+            ),
+            &[],
+        )?;
+        client.batch_execute(&format!("DROP TABLE {}", table))?;
+        assert_eq!(row.get::<_, String>(0), "a, \"b\".rs");
+        assert_eq!(row.get::<_, i64>(1), 3);
+        assert_eq!(row.get::<_, i64>(2), 1_565_379_410);
+        assert_eq!(row.get::<_, Vec<String>>(3), vec!["#1", "GH-2"]);
+        assert_eq!(row.get::<_, Option<String>>(4), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+//! truncates the directories of paths to a fixed depth,
+//! for aggregating changes by subtree
+use crate::{Change, Emitter};
+use std::error::Error;
+
+/// the first `depth` components of a directory
+pub fn truncate(
+    dir: &str,
+    depth: usize,
+) -> String {
+    dir.split('/').take(depth).collect::<Vec<_>>().join("/")
+}
+
+/// sets the `path_prefix` of changes before handing them to another emitter
+pub struct Prefix<E> {
+    pub depth: usize,
+    pub emitter: E,
+}
+
+impl<E> Emitter for Prefix<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        line.path_prefix = line.dir.as_deref().map(|dir| truncate(dir, self.depth));
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_dirs() {
+        assert_eq!(truncate("services/api/src/handlers", 2), "services/api");
+        assert_eq!(truncate("services", 2), "services");
+    }
+}
@@ -0,0 +1,153 @@
+//! reports lines read, commits parsed and changes emitted on stderr as sources are read
+use crate::{Change, Emitter};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{
+    error::Error,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// log lines read across all sources, which may be read on many threads
+static LINES: AtomicU64 = AtomicU64::new(0);
+/// commit headers parsed across all sources
+static COMMITS: AtomicU64 = AtomicU64::new(0);
+/// malformed lines across all sources
+static MALFORMED: AtomicU64 = AtomicU64::new(0);
+
+/// changes emitted between updates of the reported counts
+const EVERY: u64 = 1024;
+
+/// counts lines read by a parser
+pub(crate) fn lines(n: usize) {
+    LINES.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+/// counts a commit parsed
+pub(crate) fn commit() {
+    COMMITS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// counts a malformed line
+pub(crate) fn malformed() {
+    MALFORMED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// the lines read, commits parsed and malformed lines found so far
+pub(crate) fn totals() -> (u64, u64, u64) {
+    (
+        LINES.load(Ordering::Relaxed),
+        COMMITS.load(Ordering::Relaxed),
+        MALFORMED.load(Ordering::Relaxed),
+    )
+}
+
+/// the counts of lines read, commits parsed and changes emitted so far
+fn counts(emitted: u64) -> String {
+    format!(
+        "{} lines read, {} commits parsed, {} changes emitted",
+        LINES.load(Ordering::Relaxed),
+        COMMITS.load(Ordering::Relaxed),
+        emitted
+    )
+}
+
+/// draws a spinner of progress on stderr while changes are handed to another emitter,
+/// then a summary once they've all been emitted
+pub struct Progress<E> {
+    pub emitter: E,
+    bar: ProgressBar,
+    started: Instant,
+    emitted: u64,
+}
+
+impl<E> Progress<E> {
+    pub fn new(emitter: E) -> Self {
+        let bar = ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{spinner} {msg} [{elapsed}]")
+                .expect("progress template is valid"),
+        );
+        bar.enable_steady_tick(Duration::from_millis(100));
+        bar.set_message(counts(0));
+        Progress {
+            emitter,
+            bar,
+            started: Instant::now(),
+            emitted: 0,
+        }
+    }
+
+    /// the counts so far along with the rate lines have been read at
+    fn report(&self) -> String {
+        let lines = LINES.load(Ordering::Relaxed);
+        format!(
+            "{} ({:.0} lines/s)",
+            counts(self.emitted),
+            lines as f64 / self.started.elapsed().as_secs_f64().max(f64::EPSILON)
+        )
+    }
+}
+
+impl<E> Emitter for Progress<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.emitter.emit(line)?;
+        self.emitted += 1;
+        if self.emitted.is_multiple_of(EVERY) {
+            self.bar.set_message(self.report());
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let finished = self.emitter.finish();
+        self.bar.finish_and_clear();
+        eprintln!(
+            "{} in {:.1}s",
+            self.report(),
+            self.started.elapsed().as_secs_f64()
+        );
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run, Collect, Malformed, Parse};
+
+    #[test]
+    fn counts_lines_and_commits() -> Result<(), Box<dyn Error>> {
+        let (lines, commits) = (
+            LINES.load(Ordering::Relaxed),
+            COMMITS.load(Ordering::Relaxed),
+        );
+        let log = "\"sha1\",\"luna@moon.com\",\"2019-08-09 15:36:50 -0400\"\n\
+                   1\t0\ta.rs\n\
+                   \n\
+                   \"sha2\",\"luna@moon.com\",\"2019-08-09 15:36:50 -0400\"\n\
+                   2\t0\tb.rs\n";
+        let mut progress = Progress::new(Collect::default());
+        run(
+            "test".into(),
+            &Parse::default(),
+            &mut log.lines().map(String::from),
+            &mut progress,
+            &mut Malformed::new("git.log", true),
+        )?;
+        progress.finish()?;
+        // other tests may be parsing logs at the same time
+        assert!(LINES.load(Ordering::Relaxed) - lines >= 5);
+        assert!(COMMITS.load(Ordering::Relaxed) - commits >= 2);
+        assert_eq!(progress.emitted, 2);
+        Ok(())
+    }
+}
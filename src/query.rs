@@ -0,0 +1,180 @@
+//! loads changes into an in-memory duckdb `changes` table and runs a sql query of
+//! them once every change is read, writing its rows as aggregates are written
+use crate::{
+    duck::DuckDb,
+    format::{write_records, Format},
+    Change, Emitter, Fields,
+};
+use chrono::{DateTime, Duration, NaiveDate};
+use duckdb::{types::Value as Sql, Connection};
+use serde_json::{Map, Number, Value};
+use std::{convert::TryFrom, error::Error, io::Write};
+
+/// a sql value as json. integers too wide for json, as sums may be, are written as strings
+fn json(value: Sql) -> Value {
+    match value {
+        Sql::Null => Value::Null,
+        Sql::Boolean(b) => Value::Bool(b),
+        Sql::TinyInt(n) => n.into(),
+        Sql::SmallInt(n) => n.into(),
+        Sql::Int(n) => n.into(),
+        Sql::BigInt(n) => n.into(),
+        Sql::UTinyInt(n) => n.into(),
+        Sql::USmallInt(n) => n.into(),
+        Sql::UInt(n) => n.into(),
+        Sql::UBigInt(n) => n.into(),
+        Sql::HugeInt(n) => i64::try_from(n).map_or_else(|_| n.to_string().into(), Value::from),
+        Sql::UHugeInt(n) => u64::try_from(n).map_or_else(|_| n.to_string().into(), Value::from),
+        Sql::Float(n) => Number::from_f64(n.into()).map_or(Value::Null, Value::Number),
+        Sql::Double(n) => Number::from_f64(n).map_or(Value::Null, Value::Number),
+        Sql::Decimal(n) => n
+            .to_string()
+            .parse()
+            .ok()
+            .and_then(Number::from_f64)
+            .map_or(Value::Null, Value::Number),
+        Sql::Timestamp(unit, t) => DateTime::from_timestamp_micros(unit.to_micros(t))
+            .map_or(Value::Null, |t| {
+                t.format("%Y-%m-%dT%H:%M:%SZ").to_string().into()
+            }),
+        Sql::Date32(days) => NaiveDate::default()
+            .checked_add_signed(Duration::days(days.into()))
+            .map_or(Value::Null, |date| date.to_string().into()),
+        Sql::Text(s) | Sql::Enum(s) => Value::String(s),
+        Sql::List(values) | Sql::Array(values) => {
+            Value::Array(values.into_iter().map(json).collect())
+        }
+        Sql::Union(value) => json(*value),
+        other => Value::String(format!("{:?}", other)),
+    }
+}
+
+/// the columns and rows a query selects
+type Selected = (Vec<String>, Vec<Map<String, Value>>);
+
+fn select(
+    conn: &Connection,
+    sql: &str,
+) -> Result<Selected, Box<dyn Error>> {
+    let mut statement = conn.prepare(sql)?;
+    let mut rows = statement.query([])?;
+    let columns = rows
+        .as_ref()
+        .map(|statement| statement.column_names())
+        .unwrap_or_default();
+    let mut records = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut record = Map::new();
+        for (i, column) in columns.iter().enumerate() {
+            record.insert(column.clone(), json(row.get::<_, Sql>(i)?));
+        }
+        records.push(record);
+    }
+    Ok((columns, records))
+}
+
+pub struct Query<W> {
+    sql: String,
+    format: Format,
+    duckdb: DuckDb,
+    write: Option<W>,
+}
+
+impl<W> Query<W>
+where
+    W: Write,
+{
+    pub fn new(
+        sql: String,
+        fields: Fields,
+        format: Format,
+        write: W,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Query {
+            sql,
+            format,
+            duckdb: DuckDb::new(Connection::open_in_memory()?, fields)?,
+            write: Some(write),
+        })
+    }
+}
+
+impl<W> Emitter for Query<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.duckdb.emit(line)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let write = match self.write.take() {
+            Some(write) => write,
+            _ => return Ok(()),
+        };
+        self.duckdb.finish()?;
+        let (columns, records) = select(self.duckdb.connection(), &self.sql)
+            .map_err(|e| format!("failed to query changes: {}", e))?;
+        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+        write_records(&self.format, write, &columns, records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FIELDS;
+
+    fn change(
+        author: &str,
+        additions: usize,
+    ) -> Change {
+        Change {
+            author: author.into(),
+            timestamp: "2019-08-09 15:36:50 -0400".into(),
+            additions: Some(additions),
+            ..Change::default()
+        }
+    }
+
+    #[test]
+    fn queries_changes() -> Result<(), Box<dyn Error>> {
+        let mut buf = Vec::new();
+        let mut query = Query::new(
+            "SELECT author, sum(additions) AS additions, max(timestamp) AS latest FROM changes GROUP BY 1 ORDER BY 2 DESC".into(),
+            FIELDS.to_vec(),
+            Format::Csv,
+            &mut buf,
+        )?;
+        query.emit(change("luna@moon.com", 3))?;
+        query.emit(change("sol@sun.com", 1))?;
+        query.emit(change("luna@moon.com", 2))?;
+        query.finish()?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "author,additions,latest\n\
+             luna@moon.com,5,2019-08-09T19:36:50Z\n\
+             sol@sun.com,1,2019-08-09T19:36:50Z\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reports_bad_queries() -> Result<(), Box<dyn Error>> {
+        let mut query = Query::new(
+            "SELECT nope FROM changes".into(),
+            FIELDS.to_vec(),
+            Format::Json,
+            Vec::new(),
+        )?;
+        assert!(query
+            .finish()
+            .unwrap_err()
+            .to_string()
+            .starts_with("failed to query changes"));
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+//! extracts references to issues and tickets, as in `#1234`, `GH-123`
+//! or `JIRA-456`, from commit subjects and bodies
+use crate::{Change, Emitter};
+use regex::Regex;
+use std::error::Error;
+
+/// patterns matched by default: github style `#1234` issue numbers and
+/// `GH-123` or `JIRA-456` style keys of upper case projects
+pub const PATTERNS: &[&str] = &[r"\B#\d+\b", r"\b[A-Z][A-Z0-9_]+-\d+\b"];
+
+/// the distinct matches of any pattern within text, in order of appearance
+pub fn extract(
+    patterns: &[Regex],
+    text: &str,
+) -> Vec<String> {
+    let mut found = patterns
+        .iter()
+        .flat_map(|pattern| pattern.find_iter(text))
+        .map(|m| (m.start(), m.as_str()))
+        .collect::<Vec<_>>();
+    found.sort();
+    let mut references: Vec<String> = Vec::new();
+    for (_, reference) in found {
+        if !references.iter().any(|seen| seen == reference) {
+            references.push(reference.into());
+        }
+    }
+    references
+}
+
+/// sets the references of changes found in their commit's message
+/// before handing them to another emitter
+pub struct References<E> {
+    pub patterns: Vec<Regex>,
+    pub emitter: E,
+    /// the references of the last commit seen, as changes of a commit arrive together
+    last: Option<(String, Vec<String>)>,
+}
+
+impl<E> References<E> {
+    pub fn new(
+        patterns: Vec<Regex>,
+        emitter: E,
+    ) -> Self {
+        References {
+            patterns,
+            emitter,
+            last: None,
+        }
+    }
+}
+
+impl<E> Emitter for References<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let references = match &self.last {
+            Some((sha, references)) if *sha == line.sha => references.clone(),
+            _ => {
+                let message = [line.subject.as_deref(), line.body.as_deref()]
+                    .iter()
+                    .flatten()
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let references = extract(&self.patterns, &message);
+                self.last = Some((line.sha.clone(), references.clone()));
+                references
+            }
+        };
+        line.references = Some(references).filter(|r| !r.is_empty());
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collect;
+
+    fn defaults() -> Vec<Regex> {
+        PATTERNS.iter().map(|p| Regex::new(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn extracts_references_in_order() {
+        assert_eq!(
+            extract(
+                &defaults(),
+                "fix #12 and GH-3 (JIRA-456)\n\nsee #12, C#7 and JIRA-456 again"
+            ),
+            vec!["#12", "GH-3", "JIRA-456"]
+        );
+        assert!(extract(&defaults(), "no tickets here").is_empty());
+    }
+
+    #[test]
+    fn references_are_set_per_commit() -> Result<(), Box<dyn Error>> {
+        let mut references = References::new(vec![Regex::new(r"CHURN-\d+")?], Collect::default());
+        for (sha, subject) in &[("a", "CHURN-1 fix"), ("a", "CHURN-1 fix"), ("b", "#2")] {
+            references.emit(Change {
+                sha: sha.to_string(),
+                subject: Some(subject.to_string()),
+                body: Some("also CHURN-3".into()),
+                ..Change::default()
+            })?;
+        }
+        assert_eq!(
+            (references.emitter.0)
+                .into_iter()
+                .map(|change| change.references)
+                .collect::<Vec<_>>(),
+            vec![
+                Some(vec!["CHURN-1".into(), "CHURN-3".into()]),
+                Some(vec!["CHURN-1".into(), "CHURN-3".into()]),
+                Some(vec!["CHURN-3".into()]),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+//! follows files across renames, so changes to a file under any of its
+//! historical names share its current one
+use crate::{Change, Emitter};
+use std::{collections::HashMap, error::Error};
+
+/// sets the `canonical_path` of changes to the newest name of their file.
+/// expects the changes of each repo newest first, as `git log` emits
+/// them, so renames are seen before the changes made under older names
+pub struct Renames<E> {
+    pub emitter: E,
+    /// the newest names of paths which were renamed, by repo
+    renamed: HashMap<String, HashMap<String, String>>,
+}
+
+impl<E> Renames<E> {
+    pub fn new(emitter: E) -> Self {
+        Renames {
+            emitter,
+            renamed: HashMap::new(),
+        }
+    }
+}
+
+impl<E> Emitter for Renames<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let renamed = self.renamed.entry(line.repo.clone()).or_default();
+        let canonical = renamed
+            .get(&line.path)
+            .cloned()
+            .unwrap_or_else(|| line.path.clone());
+        if let Some(old_path) = &line.old_path {
+            // older changes under the old name are of the same file, even
+            // where a newer file was since given that name
+            renamed.insert(old_path.clone(), canonical.clone());
+        }
+        line.canonical_path = Some(canonical);
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collect;
+
+    fn change(
+        repo: &str,
+        path: &str,
+        old_path: Option<&str>,
+    ) -> Change {
+        Change {
+            repo: repo.into(),
+            path: path.into(),
+            old_path: old_path.map(Into::into),
+            renamed: old_path.is_some(),
+            ..Change::default()
+        }
+    }
+
+    #[test]
+    fn follows_rename_chains() -> Result<(), Box<dyn Error>> {
+        let mut renames = Renames::new(Collect::default());
+        for line in [
+            change("a", "src/c.rs", None),
+            change("a", "src/c.rs", Some("src/b.rs")),
+            change("a", "src/b.rs", Some("lib/a.rs")),
+            change("b", "lib/a.rs", None),
+            change("a", "lib/a.rs", None),
+            change("a", "README.md", None),
+        ] {
+            renames.emit(line)?;
+        }
+        assert_eq!(
+            renames
+                .emitter
+                .0
+                .iter()
+                .map(|line| line.canonical_path.as_deref())
+                .collect::<Vec<_>>(),
+            vec![
+                Some("src/c.rs"),
+                Some("src/c.rs"),
+                Some("src/c.rs"),
+                Some("lib/a.rs"),
+                Some("src/c.rs"),
+                Some("README.md")
+            ]
+        );
+        Ok(())
+    }
+}
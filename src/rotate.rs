@@ -0,0 +1,270 @@
+//! file output rotated once so many bytes or changes are written, so huge runs
+//! produce a sequence of bounded files, as in `changes-00000.jsonl`, `changes-00001.jsonl`
+use crate::{
+    error::LinecatError,
+    format::{Format, Projection},
+    output::{Compress, Sink},
+    Change, Emitter, Fields,
+};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// a number of bytes, as in `256MB` or `1GiB`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Size(pub u64);
+
+impl FromStr for Size {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expected = || format!("expected a size like 256MB but found {}", s);
+        let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let n: u64 = s[..digits].parse().map_err(|_| expected())?;
+        let unit = match s[digits..].trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "K" | "KB" => 1_000,
+            "M" | "MB" => 1_000_000,
+            "G" | "GB" => 1_000_000_000,
+            "KIB" => 1 << 10,
+            "MIB" => 1 << 20,
+            "GIB" => 1 << 30,
+            _ => return Err(expected()),
+        };
+        match n.checked_mul(unit) {
+            Some(0) | None => Err(expected()),
+            Some(bytes) => Ok(Size(bytes)),
+        }
+    }
+}
+
+/// when a file is rotated, once either limit is reached
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    pub bytes: Option<u64>,
+    pub records: Option<u64>,
+}
+
+/// the path of a file in a sequence, numbered before its extensions
+pub fn numbered(
+    path: &Path,
+    index: usize,
+) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match name.find('.').filter(|i| *i > 0) {
+        Some(i) => format!("{}-{:05}{}", &name[..i], index, &name[i..]),
+        _ => format!("{}-{:05}", name, index),
+    };
+    path.with_file_name(name)
+}
+
+/// counts the bytes written to a file, as written after compression
+struct Counted {
+    file: File,
+    written: Arc<AtomicU64>,
+}
+
+impl Write for Counted {
+    fn write(
+        &mut self,
+        buf: &[u8],
+    ) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.written.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// writes changes to a sequence of files, finishing each and starting the next
+/// once it holds as many bytes or changes as it may. bytes are counted as they
+/// leave buffers, so files may run over their limit by a buffer's worth
+pub struct Rotate {
+    path: PathBuf,
+    compress: Compress,
+    format: Format,
+    fields: Fields,
+    projection: Option<Projection>,
+    limits: Limits,
+    /// the file being written to, if any, numbered by `index`
+    emitter: Option<Box<dyn Emitter>>,
+    index: usize,
+    written: Arc<AtomicU64>,
+    records: u64,
+}
+
+impl Rotate {
+    pub fn new(
+        path: PathBuf,
+        compress: Compress,
+        format: &Format,
+        fields: Fields,
+        projection: Option<Projection>,
+        limits: Limits,
+    ) -> Result<Self, Box<dyn Error>> {
+        if let Format::Sqlite = format {
+            return Err("sqlite output may not be rotated".into());
+        }
+        let mut rotate = Rotate {
+            path,
+            compress,
+            format: format.clone(),
+            fields,
+            projection,
+            limits,
+            emitter: None,
+            index: 0,
+            written: Arc::default(),
+            records: 0,
+        };
+        // the first file is created even when nothing is written to it
+        rotate.emitter = Some(rotate.open()?);
+        Ok(rotate)
+    }
+
+    fn open(&mut self) -> Result<Box<dyn Emitter>, Box<dyn Error>> {
+        let path = numbered(&self.path, self.index);
+        let sink = Sink::File(path.clone(), self.compress);
+        self.written = Arc::default();
+        self.records = 0;
+        let written = self.written.clone();
+        sink.emitter_to(
+            &self.format,
+            self.fields.clone(),
+            self.projection.clone(),
+            || {
+                let file = File::create(&path).map_err(|e| LinecatError::emit(&sink, e))?;
+                self.compress.writer(Counted { file, written })
+            },
+        )
+    }
+
+    fn full(&self) -> bool {
+        self.limits
+            .bytes
+            .is_some_and(|bytes| self.written.load(Ordering::Relaxed) >= bytes)
+            || self
+                .limits
+                .records
+                .is_some_and(|records| self.records >= records)
+    }
+}
+
+impl Emitter for Rotate {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let emitter = match &mut self.emitter {
+            Some(emitter) => emitter,
+            _ => {
+                self.index += 1;
+                let emitter = self.open()?;
+                self.emitter.insert(emitter)
+            }
+        };
+        emitter.emit(line)?;
+        self.records += 1;
+        // files are finished once full, and the next only started by a change to write to it
+        if self.full() {
+            if let Some(mut emitter) = self.emitter.take() {
+                emitter.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        match &mut self.emitter {
+            Some(emitter) => emitter.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.emitter.take() {
+            Some(mut emitter) => emitter.finish(),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FIELDS;
+    use std::{env, fs};
+
+    #[test]
+    fn sizes_parse() {
+        assert_eq!("256MB".parse(), Ok(Size(256_000_000)));
+        assert_eq!("1GiB".parse(), Ok(Size(1 << 30)));
+        assert_eq!("512".parse(), Ok(Size(512)));
+        assert_eq!("64 kb".parse(), Ok(Size(64_000)));
+        assert!("0MB".parse::<Size>().is_err());
+        assert!("MB".parse::<Size>().is_err());
+        assert!("12 parsecs".parse::<Size>().is_err());
+    }
+
+    #[test]
+    fn files_are_numbered_before_extensions() {
+        assert_eq!(
+            numbered(Path::new("out/changes.jsonl.gz"), 3),
+            PathBuf::from("out/changes-00003.jsonl.gz")
+        );
+        assert_eq!(
+            numbered(Path::new("changes"), 0),
+            PathBuf::from("changes-00000")
+        );
+    }
+
+    #[test]
+    fn rotates_after_records() -> Result<(), Box<dyn Error>> {
+        let dir = env::temp_dir().join("git-linecat-rotate-test");
+        drop(fs::remove_dir_all(&dir));
+        fs::create_dir_all(&dir)?;
+        let mut rotate = Rotate::new(
+            dir.join("changes.csv"),
+            Compress::None,
+            &Format::Csv,
+            vec![FIELDS[0]],
+            None,
+            Limits {
+                bytes: None,
+                records: Some(2),
+            },
+        )?;
+        for repo in &["a", "b", "c", "d"] {
+            rotate.emit(Change {
+                repo: (*repo).into(),
+                ..Change::default()
+            })?;
+        }
+        rotate.finish()?;
+        assert_eq!(
+            fs::read_to_string(dir.join("changes-00000.csv"))?,
+            "repo\na\nb\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("changes-00001.csv"))?,
+            "repo\nc\nd\n"
+        );
+        // a full file isn't followed by an empty one
+        assert!(!dir.join("changes-00002.csv").exists());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
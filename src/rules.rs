@@ -0,0 +1,458 @@
+//! user defined categorization rules, loaded from a yaml or toml file
+//! of ordered glob or regex patterns. the first matching rule wins
+//!
+//! ```yaml
+//! rules:
+//!   - glob: "docs/**"
+//!     category: docs
+//!   - regex: "^(ci|\\.github)/"
+//!     category: ci
+//! ```
+use crate::{logging::EXPLAIN, Category, Change, Emitter};
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use serde::Deserialize;
+use std::{collections::HashSet, error::Error, ffi::OsStr, fs, path::Path};
+
+/// paths logged for each rule, and for paths no rule matches, when explaining
+const SAMPLES: usize = 5;
+
+#[derive(Deserialize)]
+struct RuleDef {
+    glob: Option<String>,
+    regex: Option<String>,
+    category: Category,
+}
+
+#[derive(Deserialize)]
+struct RulesDef {
+    rules: Vec<RuleDef>,
+}
+
+enum Pattern {
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn is_match(
+        &self,
+        path: &str,
+    ) -> bool {
+        match self {
+            Pattern::Glob(glob) => glob.is_match(path),
+            Pattern::Regex(regex) => regex.is_match(path),
+        }
+    }
+}
+
+struct Rule {
+    /// the pattern as written, as in `glob "docs/**"`
+    source: String,
+    pattern: Pattern,
+    category: Category,
+}
+
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    /// loads rules from a file, choosing a format based on its extension
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read rules {}: {}", path.display(), e))?;
+        let def = match path.extension().and_then(OsStr::to_str) {
+            Some("toml") => toml::from_str(&content)?,
+            Some("yml") | Some("yaml") => serde_yaml::from_str(&content)?,
+            _ => {
+                return Err(format!(
+                    "unsupported rules file {}. expected a .toml, .yml, or .yaml extension",
+                    path.display()
+                )
+                .into())
+            }
+        };
+        Self::compile(def)
+    }
+
+    fn compile(def: RulesDef) -> Result<Self, Box<dyn Error>> {
+        let rules = def
+            .rules
+            .into_iter()
+            .map(
+                |RuleDef {
+                     glob,
+                     regex,
+                     category,
+                 }| {
+                    let (source, pattern) = match (glob, regex) {
+                        (Some(glob), None) => (
+                            format!("glob {:?}", glob),
+                            Pattern::Glob(Glob::new(&glob)?.compile_matcher()),
+                        ),
+                        (None, Some(regex)) => (
+                            format!("regex {:?}", regex),
+                            Pattern::Regex(Regex::new(&regex)?),
+                        ),
+                        _ => return Err("each rule requires exactly one of glob or regex".into()),
+                    };
+                    Ok(Rule {
+                        source,
+                        pattern,
+                        category,
+                    })
+                },
+            )
+            .collect::<Result<_, Box<dyn Error>>>()?;
+        Ok(Rules { rules })
+    }
+
+    /// the indexes of every rule matching path, in order
+    fn matching(
+        &self,
+        path: &str,
+    ) -> Vec<usize> {
+        (0..self.rules.len())
+            .filter(|i| self.rules[*i].pattern.is_match(path))
+            .collect()
+    }
+
+    /// the categories of every rule matching path, in order
+    pub fn categories(
+        &self,
+        path: &str,
+    ) -> Vec<Category> {
+        self.matching(path)
+            .into_iter()
+            .map(|i| self.rules[i].category.clone())
+            .collect()
+    }
+
+    /// a rule as written in debug output, as in `rule 2 (glob "docs/**")`
+    fn describe(
+        &self,
+        i: usize,
+    ) -> String {
+        format!("rule {} ({})", i + 1, self.rules[i].source)
+    }
+
+    /// the category of the first rule matching path, if any
+    pub fn categorize(
+        &self,
+        path: &str,
+    ) -> Option<Category> {
+        self.rules
+            .iter()
+            .find(|rule| rule.pattern.is_match(path))
+            .map(|rule| rule.category.clone())
+    }
+}
+
+/// tallies which rules categorize which paths, so rule authors can see why a
+/// path lands where it does
+#[derive(Default)]
+pub struct Explain {
+    /// changes each rule categorized, by rule
+    hits: Vec<usize>,
+    /// changes each rule matched after an earlier rule had categorized them, by rule
+    shadowed: Vec<usize>,
+    /// changes no rule matched
+    unmatched: usize,
+    /// paths already logged
+    logged: HashSet<String>,
+    /// paths logged for each rule, with paths no rule matched last
+    samples: Vec<usize>,
+}
+
+/// why a path landed where it did: the rule categorizing it, if any, and later rules it also matched
+#[derive(Debug, PartialEq)]
+struct Explanation {
+    path: String,
+    rule: Option<String>,
+    category: Option<String>,
+    also: Vec<String>,
+}
+
+/// how many changes a rule, or no rule, categorized, and how many it matched after an
+/// earlier rule had categorized them
+#[derive(Debug, PartialEq)]
+struct Tally {
+    rule: Option<String>,
+    category: Option<String>,
+    hits: usize,
+    shadowed: usize,
+}
+
+impl Explain {
+    /// tallies the rules matching a path, returning an explanation of its
+    /// category for the first few paths each rule categorizes
+    fn explain(
+        &mut self,
+        rules: &Rules,
+        path: &str,
+        matching: &[usize],
+    ) -> Option<Explanation> {
+        let len = rules.rules.len();
+        if self.hits.len() < len {
+            self.hits.resize(len, 0);
+            self.shadowed.resize(len, 0);
+            self.samples.resize(len + 1, 0);
+        }
+        let sample = match matching.split_first() {
+            Some((first, rest)) => {
+                self.hits[*first] += 1;
+                for i in rest {
+                    self.shadowed[*i] += 1;
+                }
+                *first
+            }
+            _ => {
+                self.unmatched += 1;
+                len
+            }
+        };
+        if self.samples[sample] >= SAMPLES || !self.logged.insert(path.into()) {
+            return None;
+        }
+        self.samples[sample] += 1;
+        let first = matching.first();
+        Some(Explanation {
+            path: path.into(),
+            rule: first.map(|i| rules.describe(*i)),
+            category: first.map(|i| rules.rules[*i].category.name().to_string()),
+            also: matching
+                .iter()
+                .skip(1)
+                .map(|i| rules.describe(*i))
+                .collect(),
+        })
+    }
+
+    /// a tally for each rule of the changes it categorized, then of those no rule did
+    fn report(
+        &self,
+        rules: &Rules,
+    ) -> Vec<Tally> {
+        let mut tallies = (0..rules.rules.len())
+            .map(|i| Tally {
+                rule: Some(rules.describe(i)),
+                category: Some(rules.rules[i].category.name().to_string()),
+                hits: self.hits.get(i).copied().unwrap_or_default(),
+                shadowed: self.shadowed.get(i).copied().unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        tallies.push(Tally {
+            rule: None,
+            category: None,
+            hits: self.unmatched,
+            shadowed: 0,
+        });
+        tallies
+    }
+}
+
+/// recategorizes changes using rules before handing them to another emitter,
+/// leaving the built-in category in place for paths no rule matches. the first
+/// matching rule's category becomes the change's `category`, and every matching
+/// rule's category is added to its `categories`
+pub struct Categorize<E> {
+    pub rules: Rules,
+    /// explains categorization in events logged to stderr, when set
+    pub explain: Option<Explain>,
+    pub emitter: E,
+}
+
+impl<E> Emitter for Categorize<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let matching = self.rules.matching(&line.path);
+        if let Some(explain) = &mut self.explain {
+            if let Some(Explanation {
+                path,
+                rule,
+                category,
+                also,
+            }) = explain.explain(&self.rules, &line.path, &matching)
+            {
+                tracing::info!(
+                    target: EXPLAIN,
+                    path = %path,
+                    rule = rule.as_deref(),
+                    category = category.as_deref(),
+                    also = Some(&*also.join(", ")).filter(|also| !also.is_empty()),
+                    "explain"
+                );
+            }
+        }
+        for i in matching.into_iter().rev() {
+            line.categorize_as(self.rules.rules[i].category.clone());
+        }
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(explain) = self.explain.take() {
+            for Tally {
+                rule,
+                category,
+                hits,
+                shadowed,
+            } in explain.report(&self.rules)
+            {
+                tracing::info!(
+                    target: EXPLAIN,
+                    rule = rule.as_deref(),
+                    category = category.as_deref(),
+                    hits,
+                    shadowed,
+                    "explain tally"
+                );
+            }
+        }
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_rules_compile() -> Result<(), Box<dyn Error>> {
+        let rules = Rules::compile(toml::from_str(
+            r#"
+            [[rules]]
+            glob = "api/**/*.proto"
+            category = "protos"
+
+            [[rules]]
+            glob = "api/**"
+            category = "docs"
+
+            [[rules]]
+            regex = "^migrations/"
+            category = "test"
+            "#,
+        )?)?;
+        assert_eq!(
+            rules.categorize("api/v1/users.proto"),
+            Some(Category::Custom("protos".into()))
+        );
+        assert_eq!(rules.categorize("api/intro.txt"), Some(Category::Docs));
+        assert_eq!(rules.categorize("migrations/001.sql"), Some(Category::Test));
+        assert_eq!(rules.categorize("src/main.rs"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn yaml_rules_match_in_order() -> Result<(), Box<dyn Error>> {
+        let rules = Rules::compile(serde_yaml::from_str(
+            r#"
+            rules:
+              - glob: "vendor/**"
+                category: vendor
+              - glob: "*.go"
+                category: go
+            "#,
+        )?)?;
+        assert_eq!(
+            rules.categorize("vendor/lib/foo.go"),
+            Some(Category::Custom("vendor".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn explains_which_rules_categorize_paths() -> Result<(), Box<dyn Error>> {
+        let rules = Rules::compile(serde_yaml::from_str(
+            r#"
+            rules:
+              - glob: "api/**/*.proto"
+                category: protos
+              - regex: "^api/"
+                category: docs
+            "#,
+        )?)?;
+        let mut explain = Explain::default();
+        let mut explanations = Vec::new();
+        for path in &[
+            "api/v1/users.proto",
+            "api/v1/users.proto",
+            "api/intro.md",
+            "src/main.rs",
+        ] {
+            explanations.push(explain.explain(&rules, path, &rules.matching(path)));
+        }
+        let explanation =
+            |path: &str, rule: Option<&str>, category: Option<&str>, also: &[&str]| {
+                Some(Explanation {
+                    path: path.into(),
+                    rule: rule.map(Into::into),
+                    category: category.map(Into::into),
+                    also: also.iter().map(|rule| rule.to_string()).collect(),
+                })
+            };
+        assert_eq!(
+            explanations,
+            vec![
+                explanation(
+                    "api/v1/users.proto",
+                    Some(r#"rule 1 (glob "api/**/*.proto")"#),
+                    Some("protos"),
+                    &[r#"rule 2 (regex "^api/")"#]
+                ),
+                None,
+                explanation(
+                    "api/intro.md",
+                    Some(r#"rule 2 (regex "^api/")"#),
+                    Some("docs"),
+                    &[]
+                ),
+                explanation("src/main.rs", None, None, &[]),
+            ]
+        );
+        let tally =
+            |rule: Option<&str>, category: Option<&str>, hits: usize, shadowed: usize| Tally {
+                rule: rule.map(Into::into),
+                category: category.map(Into::into),
+                hits,
+                shadowed,
+            };
+        assert_eq!(
+            explain.report(&rules),
+            vec![
+                tally(
+                    Some(r#"rule 1 (glob "api/**/*.proto")"#),
+                    Some("protos"),
+                    2,
+                    0
+                ),
+                tally(Some(r#"rule 2 (regex "^api/")"#), Some("docs"), 1, 2),
+                tally(None, None, 1, 0),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rules_require_one_pattern() {
+        assert!(Rules::compile(RulesDef {
+            rules: vec![RuleDef {
+                glob: Some("*".into()),
+                regex: Some(".*".into()),
+                category: Category::Test,
+            }],
+        })
+        .is_err())
+    }
+}
@@ -0,0 +1,110 @@
+//! schemas describing emitted changes, for creating the tables they're loaded into
+use crate::{
+    format::{renamed, Rename},
+    Fields, Kind,
+};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum Schema {
+    /// a [bigquery](https://cloud.google.com/bigquery/docs/schemas#specifying_a_json_schema_file)
+    /// json schema, as accepted by `bq load --schema`
+    Bigquery,
+}
+
+impl FromStr for Schema {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bigquery" => Ok(Schema::Bigquery),
+            other => Err(format!("unsupported schema {}", other)),
+        }
+    }
+}
+
+impl Schema {
+    /// describes records of the given fields, under the names they're renamed to
+    pub fn describe(
+        &self,
+        fields: &Fields,
+        renames: &[Rename],
+    ) -> Value {
+        match self {
+            Schema::Bigquery => bigquery(fields, renames),
+        }
+    }
+}
+
+fn bigquery(
+    fields: &Fields,
+    renames: &[Rename],
+) -> Value {
+    Value::Array(
+        fields
+            .iter()
+            .map(|(name, kind)| {
+                let (field_type, mode) = match (*name, kind) {
+                    // git's `-0400` offsets aren't a timestamp bigquery accepts,
+                    // so timestamps as git reported them are kept as is
+                    ("timestamp", _) | ("commit_timestamp", _) => ("STRING", "NULLABLE"),
+                    (_, Kind::String) => ("STRING", "NULLABLE"),
+                    (_, Kind::Integer) => ("INT64", "NULLABLE"),
+                    (_, Kind::Boolean) => ("BOOL", "NULLABLE"),
+                    (_, Kind::Float) => ("FLOAT64", "NULLABLE"),
+                    (_, Kind::Timestamp) => ("TIMESTAMP", "NULLABLE"),
+                    (_, Kind::List) => ("STRING", "REPEATED"),
+                };
+                json!({ "name": renamed(renames, name), "type": field_type, "mode": mode })
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FIELDS;
+
+    #[test]
+    fn bigquery_schemas_describe_fields() {
+        let schema = Schema::Bigquery.describe(&FIELDS.to_vec(), &[]);
+        let columns = schema.as_array().unwrap();
+        assert_eq!(columns.len(), FIELDS.len());
+        assert_eq!(
+            columns[3],
+            json!({ "name": "timestamp", "type": "STRING", "mode": "NULLABLE" })
+        );
+        assert_eq!(
+            columns[4],
+            json!({ "name": "timestamp_utc", "type": "TIMESTAMP", "mode": "NULLABLE" })
+        );
+        assert_eq!(
+            columns[8],
+            json!({ "name": "renamed", "type": "BOOL", "mode": "NULLABLE" })
+        );
+        assert!(columns
+            .contains(&json!({ "name": "references", "type": "STRING", "mode": "REPEATED" })));
+        assert!(columns.contains(
+            &json!({ "name": "commit_timestamp", "type": "STRING", "mode": "NULLABLE" })
+        ));
+    }
+
+    #[test]
+    fn bigquery_schemas_describe_renamed_fields() -> Result<(), String> {
+        let schema = Schema::Bigquery.describe(
+            &FIELDS[..4].to_vec(),
+            &["timestamp=authored_at".parse()?, "sha=commit".parse()?],
+        );
+        assert_eq!(
+            schema,
+            json!([
+                { "name": "repo", "type": "STRING", "mode": "NULLABLE" },
+                { "name": "commit", "type": "STRING", "mode": "NULLABLE" },
+                { "name": "author", "type": "STRING", "mode": "NULLABLE" },
+                { "name": "authored_at", "type": "STRING", "mode": "NULLABLE" },
+            ])
+        );
+        Ok(())
+    }
+}
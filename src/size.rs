@@ -0,0 +1,129 @@
+//! sizes changes against the files they change, as a 50 line change means
+//! something else of a 60 line file than of a 6000 line one
+use crate::{git::Blobs, Change, Emitter};
+use std::{collections::HashMap, error::Error, path::PathBuf};
+
+/// the percent of a path's lines before and after a change which it changed,
+/// from 0 for none to 100 for files created or deleted outright
+pub fn pct_changed(
+    total_lines: usize,
+    additions: usize,
+    deletions: usize,
+) -> Option<f64> {
+    let before = (total_lines + deletions).saturating_sub(additions);
+    match before + total_lines {
+        0 => None,
+        lines => {
+            let pct = 100.0 * (additions + deletions) as f64 / lines as f64;
+            Some((pct.min(100.0) * 100.0).round() / 100.0)
+        }
+    }
+}
+
+/// sets the `total_lines` and `pct_changed` of changes of repos read from a
+/// local repository, looking up the lines of each path at its commit
+pub struct LineCounts<E> {
+    pub emitter: E,
+    repos: HashMap<String, PathBuf>,
+    blobs: HashMap<String, Blobs>,
+}
+
+impl<E> LineCounts<E> {
+    /// `repos` are the paths of the repositories changes of each repo were read from
+    pub fn new(
+        repos: HashMap<String, PathBuf>,
+        emitter: E,
+    ) -> Self {
+        LineCounts {
+            emitter,
+            repos,
+            blobs: HashMap::new(),
+        }
+    }
+
+    fn total_lines(
+        &mut self,
+        line: &Change,
+    ) -> Result<Option<usize>, Box<dyn Error>> {
+        let path = match self.repos.get(&line.repo) {
+            Some(path) => path,
+            _ => return Ok(None),
+        };
+        if !self.blobs.contains_key(&line.repo) {
+            self.blobs.insert(line.repo.clone(), Blobs::spawn(path)?);
+        }
+        let blobs = self.blobs.get_mut(&line.repo).expect("spawned");
+        Ok(match blobs.lines(&line.sha, &line.path)? {
+            // deleted files are no longer a file of their commit
+            None if line.additions == Some(0) => Some(0),
+            total_lines => total_lines,
+        })
+    }
+}
+
+impl<E> Emitter for LineCounts<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        // binary files have no lines to count
+        if let (Some(additions), Some(deletions)) = (line.additions, line.deletions) {
+            line.total_lines = self.total_lines(&line)?;
+            line.pct_changed = line
+                .total_lines
+                .and_then(|total_lines| pct_changed(total_lines, additions, deletions));
+        }
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{git, Collect};
+
+    #[test]
+    fn pct_changed_is_relative_to_file_size() {
+        assert_eq!(pct_changed(60, 50, 0), Some(71.43));
+        assert_eq!(pct_changed(6000, 50, 0), Some(0.42));
+        assert_eq!(pct_changed(60, 60, 0), Some(100.0));
+        assert_eq!(pct_changed(0, 0, 60), Some(100.0));
+        assert_eq!(pct_changed(0, 0, 0), None);
+    }
+
+    #[test]
+    fn counts_lines_of_paths_at_commits() -> Result<(), Box<dyn Error>> {
+        let dir = git::tests::repo("line-counts")?;
+        let sha = git::head(&dir)?.ok_or("expected a commit")?;
+        let mut counts = LineCounts::new(
+            vec![("test".to_string(), dir)].into_iter().collect(),
+            Collect::default(),
+        );
+        for (repo, path) in &[("test", "src/lib.rs"), ("other", "src/lib.rs")] {
+            counts.emit(Change {
+                repo: repo.to_string(),
+                sha: sha.clone(),
+                path: path.to_string(),
+                additions: Some(2),
+                deletions: Some(0),
+                ..Change::default()
+            })?;
+        }
+        let lines = &counts.emitter.0;
+        assert_eq!(lines[0].total_lines, Some(2));
+        assert_eq!(lines[0].pct_changed, Some(100.0));
+        assert_eq!(lines[1].total_lines, None);
+        Ok(())
+    }
+}
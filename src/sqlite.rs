@@ -0,0 +1,177 @@
+//! writes changes into a `changes` table of a sqlite database
+use crate::{parse_timestamp, Change, Emitter, Fields, Kind};
+use chrono::Utc;
+use rusqlite::{params_from_iter, types::Value as Sql, Connection};
+use serde_json::Value;
+use std::{error::Error, path::Path};
+
+/// rows inserted per transaction
+const BATCH_SIZE: usize = 10_000;
+
+/// columns worth indexing for ad-hoc queries. column names are quoted
+/// as some, like `references`, are sql keywords
+const INDEXED: &[&str] = &["repo", "author", "path", "timestamp"];
+
+fn column_type(kind: Kind) -> &'static str {
+    match kind {
+        // lists are stored as json arrays
+        Kind::String | Kind::Timestamp | Kind::List => "TEXT",
+        Kind::Integer | Kind::Boolean => "INTEGER",
+        Kind::Float => "REAL",
+    }
+}
+
+/// converts a field value to its sql representation. timestamps are stored
+/// as utc `YYYY-MM-DD HH:MM:SS` text, which sqlite's date functions understand
+fn sql(
+    kind: Kind,
+    value: Option<&Value>,
+) -> Result<Sql, Box<dyn Error>> {
+    Ok(match (kind, value) {
+        (_, None) | (_, Some(Value::Null)) => Sql::Null,
+        (Kind::Timestamp, Some(Value::String(s))) => Sql::Text(
+            parse_timestamp(s)?
+                .with_timezone(&Utc)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+        ),
+        (_, Some(Value::String(s))) => Sql::Text(s.clone()),
+        (_, Some(Value::Bool(b))) => Sql::Integer(*b as i64),
+        (_, Some(Value::Number(n))) => n
+            .as_i64()
+            .map(Sql::Integer)
+            .or_else(|| n.as_f64().map(Sql::Real))
+            .unwrap_or(Sql::Null),
+        (_, Some(other)) => Sql::Text(other.to_string()),
+    })
+}
+
+pub struct Sqlite {
+    conn: Connection,
+    fields: Fields,
+    insert: String,
+    pending: usize,
+}
+
+impl Sqlite {
+    pub fn open(
+        path: &Path,
+        fields: Fields,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new(Connection::open(path)?, fields)
+    }
+
+    /// creates the `changes` table and its indexes, if they don't already exist
+    pub fn new(
+        conn: Connection,
+        fields: Fields,
+    ) -> Result<Self, Box<dyn Error>> {
+        let columns = fields
+            .iter()
+            .map(|(name, kind)| format!("\"{}\" {}", name, column_type(*kind)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS changes ({});",
+            columns
+        ))?;
+        for column in INDEXED {
+            conn.execute_batch(&format!(
+                "CREATE INDEX IF NOT EXISTS changes_{0} ON changes (\"{0}\");",
+                column
+            ))?;
+        }
+        let insert = format!(
+            "INSERT INTO changes ({}) VALUES ({})",
+            fields
+                .iter()
+                .map(|(name, _)| format!("\"{}\"", name))
+                .collect::<Vec<_>>()
+                .join(", "),
+            vec!["?"; fields.len()].join(", ")
+        );
+        Ok(Sqlite {
+            conn,
+            fields,
+            insert,
+            pending: 0,
+        })
+    }
+
+    fn commit(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.pending > 0 {
+            self.conn.execute_batch("COMMIT")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+}
+
+impl Emitter for Sqlite {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.pending == 0 {
+            self.conn.execute_batch("BEGIN")?;
+        }
+        let value = serde_json::to_value(&line)?;
+        let params = self
+            .fields
+            .iter()
+            .map(|(name, kind)| sql(*kind, value.get(name)))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.conn
+            .prepare_cached(&self.insert)?
+            .execute(params_from_iter(params))?;
+        self.pending += 1;
+        if self.pending >= BATCH_SIZE {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FIELDS;
+
+    #[test]
+    fn sqlite_inserts_changes() -> Result<(), Box<dyn Error>> {
+        let mut sqlite = Sqlite::new(Connection::open_in_memory()?, FIELDS.to_vec())?;
+        sqlite.emit(Change {
+            repo: "test".into(),
+            timestamp: "2019-08-09 15:36:50 -0400".into(),
+            path: "Makefile".into(),
+            additions: Some(3),
+            ..Change::default()
+        })?;
+        sqlite.finish()?;
+        let (timestamp, ext, additions): (String, Option<String>, i64) = sqlite.conn.query_row(
+            "SELECT timestamp, ext, additions FROM changes WHERE repo = 'test'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        assert_eq!(timestamp, "2019-08-09 19:36:50");
+        assert_eq!(ext, None);
+        assert_eq!(additions, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn sqlite_creates_indexes() -> Result<(), Box<dyn Error>> {
+        let sqlite = Sqlite::new(Connection::open_in_memory()?, FIELDS.to_vec())?;
+        let indexes: i64 = sqlite.conn.query_row(
+            "SELECT count(*) FROM sqlite_master WHERE type = 'index' AND tbl_name = 'changes'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(indexes, INDEXED.len() as i64);
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+//! checkpoints of the newest changes emitted from each repo, so later runs
+//! emit only changes committed since, for incremental ingestion
+use crate::{filter::Bound, parse_timestamp, Change, Emitter};
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// the newest second changes of a repo were committed in
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Checkpoint {
+    pub epoch_seconds: i64,
+    /// the commits committed in that second, which are the only ones
+    /// of that second already emitted
+    pub shas: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct State {
+    pub repos: BTreeMap<String, Checkpoint>,
+}
+
+impl State {
+    /// loads state from a json file, which is empty when the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("failed to parse state {}: {}", path.display(), e).into()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(format!("failed to read state {}: {}", path.display(), e).into()),
+        }
+    }
+
+    /// writes state to a json file, replacing it only once completely written
+    pub fn save(
+        &self,
+        path: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        fs::write(&partial, json)
+            .map_err(|e| format!("failed to write state {}: {}", partial.display(), e))?;
+        fs::rename(&partial, path)
+            .map_err(|e| format!("failed to write state {}: {}", path.display(), e))?;
+        Ok(())
+    }
+
+    /// the time of the newest change emitted from a repo
+    pub fn since(
+        &self,
+        repo: &str,
+    ) -> Option<Bound> {
+        self.repos
+            .get(repo)
+            .and_then(|checkpoint| Utc.timestamp_opt(checkpoint.epoch_seconds, 0).single())
+            .map(Bound)
+    }
+
+    /// whether a change was emitted given this state
+    fn emitted(
+        &self,
+        change: &Change,
+    ) -> bool {
+        match (self.repos.get(&change.repo), committed(change)) {
+            (Some(checkpoint), Some(epoch_seconds)) => {
+                epoch_seconds < checkpoint.epoch_seconds
+                    || (epoch_seconds == checkpoint.epoch_seconds
+                        && checkpoint.shas.contains(&change.sha))
+            }
+            _ => false,
+        }
+    }
+
+    /// advances the checkpoint of a change's repo to include it
+    fn advance(
+        &mut self,
+        change: &Change,
+    ) {
+        let epoch_seconds = match committed(change) {
+            Some(epoch_seconds) => epoch_seconds,
+            _ => return,
+        };
+        let checkpoint = self
+            .repos
+            .entry(change.repo.clone())
+            .or_insert_with(|| Checkpoint {
+                epoch_seconds,
+                shas: Vec::new(),
+            });
+        if epoch_seconds > checkpoint.epoch_seconds {
+            *checkpoint = Checkpoint {
+                epoch_seconds,
+                shas: Vec::new(),
+            };
+        }
+        if epoch_seconds == checkpoint.epoch_seconds && !checkpoint.shas.contains(&change.sha) {
+            checkpoint.shas.push(change.sha.clone());
+        }
+    }
+}
+
+/// when a change was committed, as `git log --since` filters on, so commits authored
+/// long before they landed, as when rebased or merged, aren't taken for those emitted.
+/// when it was authored for logs without committers
+fn committed(change: &Change) -> Option<i64> {
+    change
+        .commit_timestamp
+        .as_deref()
+        .and_then(|timestamp| parse_timestamp(timestamp).ok())
+        .map(|timestamp| timestamp.timestamp())
+        .or(change.epoch_seconds)
+}
+
+/// drops changes emitted by previous runs, saving the checkpoints of those
+/// emitted by this one once flushed or finished. it's only flushed between
+/// reads, once the changes of every commit read have been emitted
+pub struct Incremental<E> {
+    pub emitter: E,
+    path: PathBuf,
+    previous: State,
+    next: State,
+}
+
+impl<E> Incremental<E> {
+    pub fn new(
+        path: PathBuf,
+        previous: State,
+        emitter: E,
+    ) -> Self {
+        Incremental {
+            emitter,
+            path,
+            next: previous.clone(),
+            previous,
+        }
+    }
+}
+
+impl<E> Emitter for Incremental<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.previous.emitted(&line) {
+            return Ok(());
+        }
+        self.next.advance(&line);
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()?;
+        self.next.save(&self.path)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        // state only advances once changes are safely written
+        self.emitter.finish()?;
+        self.next.save(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collect;
+    use std::env;
+
+    fn change(
+        repo: &str,
+        sha: &str,
+        epoch_seconds: i64,
+    ) -> Change {
+        Change {
+            repo: repo.into(),
+            sha: sha.into(),
+            epoch_seconds: Some(epoch_seconds),
+            ..Change::default()
+        }
+    }
+
+    fn run(
+        path: &Path,
+        changes: Vec<Change>,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut incremental = Incremental::new(path.into(), State::load(path)?, Collect::default());
+        for line in changes {
+            incremental.emit(line)?;
+        }
+        incremental.finish()?;
+        Ok(incremental
+            .emitter
+            .0
+            .into_iter()
+            .map(|change| change.sha)
+            .collect())
+    }
+
+    #[test]
+    fn skips_changes_emitted_by_previous_runs() -> Result<(), Box<dyn Error>> {
+        let path = env::temp_dir().join("git-linecat-state-test.json");
+        let _ = fs::remove_file(&path);
+        assert_eq!(
+            run(
+                &path,
+                vec![
+                    change("a", "sha2", 20),
+                    change("a", "sha1", 10),
+                    change("b", "sha3", 5)
+                ]
+            )?,
+            vec!["sha2", "sha1", "sha3"]
+        );
+        assert_eq!(
+            run(
+                &path,
+                vec![
+                    change("a", "sha4", 20),
+                    change("a", "sha2", 20),
+                    change("a", "sha1", 10),
+                    change("b", "sha5", 6),
+                ]
+            )?,
+            vec!["sha4", "sha5"]
+        );
+        let state = State::load(&path)?;
+        assert_eq!(
+            state.repos["a"],
+            Checkpoint {
+                epoch_seconds: 20,
+                shas: vec!["sha2".into(), "sha4".into()]
+            }
+        );
+        assert_eq!(state.since("b").map(|since| since.0.timestamp()), Some(6));
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn checkpoints_commits_by_when_they_landed() -> Result<(), Box<dyn Error>> {
+        let path = env::temp_dir().join(format!(
+            "git-linecat-state-landed-{}.json",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let landed = |sha: &str, authored: i64, committed: &str| Change {
+            commit_timestamp: Some(committed.into()),
+            ..change("a", sha, authored)
+        };
+        assert_eq!(
+            run(&path, vec![landed("sha1", 20, "1970-01-01 00:00:30 +0000")])?,
+            vec!["sha1"]
+        );
+        // authored before the last run's newest change, but merged after it
+        assert_eq!(
+            run(
+                &path,
+                vec![
+                    landed("sha2", 10, "1970-01-01 00:00:40 +0000"),
+                    landed("sha1", 20, "1970-01-01 00:00:30 +0000"),
+                ]
+            )?,
+            vec!["sha2"]
+        );
+        assert_eq!(State::load(&path)?.repos["a"].epoch_seconds, 40);
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
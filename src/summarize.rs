@@ -0,0 +1,354 @@
+//! aggregates changes into totals per author and, optionally,
+//! per category and time bucket, instead of emitting them one by one
+use crate::{
+    format::{write_records, Format},
+    parse_timestamp, Change, Emitter,
+};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, error::Error, io::Write, str::FromStr};
+
+/// a field changes are grouped by
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dimension {
+    Repo,
+    Author,
+    /// the domain of author emails, as in the company they work for
+    AuthorDomain,
+    Category,
+    Path,
+    /// the newest name of paths, given `--follow-renames`
+    CanonicalPath,
+    Ext,
+    Dir,
+    TopDir,
+    /// directories truncated by `--path-depth`
+    PathPrefix,
+    Language,
+    /// the type of conventional commits, given commit messages
+    CommitType,
+}
+
+impl Dimension {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Dimension::Repo => "repo",
+            Dimension::Author => "author",
+            Dimension::AuthorDomain => "author_domain",
+            Dimension::Category => "category",
+            Dimension::Path => "path",
+            Dimension::CanonicalPath => "canonical_path",
+            Dimension::Ext => "ext",
+            Dimension::Dir => "dir",
+            Dimension::TopDir => "top_dir",
+            Dimension::PathPrefix => "path_prefix",
+            Dimension::Language => "language",
+            Dimension::CommitType => "commit_type",
+        }
+    }
+
+    pub(crate) fn value(
+        self,
+        change: &Change,
+    ) -> Option<String> {
+        match self {
+            Dimension::Repo => Some(change.repo.clone()),
+            Dimension::Author => Some(change.author.clone()),
+            Dimension::AuthorDomain => change.author_domain.clone(),
+            Dimension::Category => Some(change.category.to_string()),
+            Dimension::Path => Some(change.path.clone()),
+            Dimension::CanonicalPath => change.canonical_path.clone(),
+            Dimension::Ext => change.ext.clone(),
+            Dimension::Dir => change.dir.clone(),
+            Dimension::TopDir => change.top_dir.clone(),
+            Dimension::PathPrefix => change.path_prefix.clone(),
+            Dimension::Language => change.language.clone(),
+            Dimension::CommitType => change.commit_type.clone(),
+        }
+    }
+}
+
+impl FromStr for Dimension {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "repo" => Ok(Dimension::Repo),
+            "author" => Ok(Dimension::Author),
+            "author_domain" => Ok(Dimension::AuthorDomain),
+            "category" => Ok(Dimension::Category),
+            "path" => Ok(Dimension::Path),
+            "canonical_path" => Ok(Dimension::CanonicalPath),
+            "ext" => Ok(Dimension::Ext),
+            "dir" => Ok(Dimension::Dir),
+            "top_dir" => Ok(Dimension::TopDir),
+            "path_prefix" => Ok(Dimension::PathPrefix),
+            "language" => Ok(Dimension::Language),
+            "commit_type" => Ok(Dimension::CommitType),
+            other => Err(format!("unsupported dimension {}", other)),
+        }
+    }
+}
+
+/// a span of time changes are grouped by, in utc
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bucket {
+    Day,
+    /// iso 8601 weeks, as in `2019-W32`
+    Week,
+    Month,
+    Year,
+}
+
+impl Bucket {
+    /// the `chrono` format of the bucket's name
+    pub(crate) fn format(self) -> &'static str {
+        match self {
+            Bucket::Day => "%Y-%m-%d",
+            Bucket::Week => "%G-W%V",
+            Bucket::Month => "%Y-%m",
+            Bucket::Year => "%Y",
+        }
+    }
+
+    fn of(
+        self,
+        change: &Change,
+    ) -> Result<String, Box<dyn Error>> {
+        Ok(parse_timestamp(&change.timestamp)?
+            .with_timezone(&Utc)
+            .format(self.format())
+            .to_string())
+    }
+}
+
+impl FromStr for Bucket {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Bucket::Day),
+            "week" => Ok(Bucket::Week),
+            "month" => Ok(Bucket::Month),
+            "year" => Ok(Bucket::Year),
+            other => Err(format!("unsupported bucket {}", other)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Totals {
+    commits: usize,
+    files: usize,
+    additions: usize,
+    deletions: usize,
+    /// the last commit counted. changes of a commit arrive together
+    last_sha: String,
+}
+
+/// columns following the grouped dimensions
+const TOTALS: &[&str] = &["commits", "files", "additions", "deletions"];
+
+/// accumulates totals per group, writing them out once all changes are seen
+pub struct Summarize<W>
+where
+    W: Write,
+{
+    by: Vec<Dimension>,
+    bucket: Option<Bucket>,
+    format: Format,
+    write: Option<W>,
+    groups: BTreeMap<Vec<Option<String>>, Totals>,
+}
+
+impl<W> Summarize<W>
+where
+    W: Write,
+{
+    pub fn new(
+        by: Vec<Dimension>,
+        bucket: Option<Bucket>,
+        format: Format,
+        write: W,
+    ) -> Self {
+        Summarize {
+            by,
+            bucket,
+            format,
+            write: Some(write),
+            groups: BTreeMap::new(),
+        }
+    }
+
+    fn columns(&self) -> Vec<&'static str> {
+        self.by
+            .iter()
+            .map(|dimension| dimension.name())
+            .chain(self.bucket.map(|_| "bucket"))
+            .chain(TOTALS.iter().copied())
+            .collect()
+    }
+}
+
+impl<W> Emitter for Summarize<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut key = self
+            .by
+            .iter()
+            .map(|dimension| dimension.value(&line))
+            .collect::<Vec<_>>();
+        if let Some(bucket) = self.bucket {
+            key.push(Some(bucket.of(&line)?));
+        }
+        let totals = self.groups.entry(key).or_default();
+        if totals.last_sha != line.sha {
+            totals.commits += 1;
+            totals.last_sha = line.sha;
+        }
+        totals.files += 1;
+        totals.additions += line.additions.unwrap_or_default();
+        totals.deletions += line.deletions.unwrap_or_default();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let write = match self.write.take() {
+            Some(write) => write,
+            _ => return Ok(()),
+        };
+        let columns = self.columns();
+        let records = std::mem::take(&mut self.groups)
+            .into_iter()
+            .map(|(key, totals)| {
+                let values = key
+                    .into_iter()
+                    .map(|value| value.map(Value::String).unwrap_or(Value::Null))
+                    .chain(
+                        vec![
+                            totals.commits,
+                            totals.files,
+                            totals.additions,
+                            totals.deletions,
+                        ]
+                        .into_iter()
+                        .map(Value::from),
+                    );
+                columns
+                    .iter()
+                    .map(|column| column.to_string())
+                    .zip(values)
+                    .collect::<Map<_, _>>()
+            })
+            .collect();
+        write_records(&self.format, write, &columns, records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Category;
+
+    fn change(
+        sha: &str,
+        author: &str,
+        category: Category,
+        timestamp: &str,
+    ) -> Change {
+        Change {
+            sha: sha.into(),
+            author: author.into(),
+            category,
+            timestamp: timestamp.into(),
+            additions: Some(2),
+            deletions: Some(1),
+            ..Change::default()
+        }
+    }
+
+    fn summarize(
+        by: Vec<Dimension>,
+        bucket: Option<Bucket>,
+        format: Format,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut buf = Vec::new();
+        {
+            let mut summarize = Summarize::new(by, bucket, format, &mut buf);
+            for line in [
+                change(
+                    "a",
+                    "luna@moon.com",
+                    Category::Test,
+                    "2019-08-09 15:36:50 -0400",
+                ),
+                change(
+                    "a",
+                    "luna@moon.com",
+                    Category::Docs,
+                    "2019-08-09 15:36:50 -0400",
+                ),
+                change(
+                    "b",
+                    "luna@moon.com",
+                    Category::Test,
+                    "2019-09-01 10:00:00 +0000",
+                ),
+                change(
+                    "c",
+                    "sol@sun.com",
+                    Category::Test,
+                    "2019-09-02 10:00:00 +0000",
+                ),
+            ] {
+                summarize.emit(line)?;
+            }
+            summarize.finish()?;
+        }
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn summarizes_by_author() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            summarize(vec![Dimension::Author], None, Format::Json)?,
+            "{\"author\":\"luna@moon.com\",\"commits\":2,\"files\":3,\"additions\":6,\"deletions\":3}\n\
+             {\"author\":\"sol@sun.com\",\"commits\":1,\"files\":1,\"additions\":2,\"deletions\":1}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn summarizes_by_category_and_bucket() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            summarize(
+                vec![Dimension::Author, Dimension::Category],
+                Some(Bucket::Month),
+                Format::Csv
+            )?,
+            "author,category,bucket,commits,files,additions,deletions\n\
+             luna@moon.com,docs,2019-08,1,1,2,1\n\
+             luna@moon.com,test,2019-08,1,1,2,1\n\
+             luna@moon.com,test,2019-09,1,1,2,1\n\
+             sol@sun.com,test,2019-09,1,1,2,1\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn buckets_weeks_in_utc() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            Bucket::Week.of(&change(
+                "a",
+                "",
+                Category::Test,
+                "2019-12-30 01:00:00 +0200"
+            ))?,
+            "2019-W52"
+        );
+        Ok(())
+    }
+}
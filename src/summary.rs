@@ -0,0 +1,185 @@
+//! a summary of every change emitted, written once they've all been emitted,
+//! as a sanity check of what a run produced
+use crate::{Change, Emitter};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    io::Write,
+};
+
+#[derive(Default, Serialize)]
+struct Lines {
+    additions: usize,
+    deletions: usize,
+}
+
+#[derive(Default, Serialize)]
+struct Totals {
+    commits: usize,
+    files: usize,
+    additions: usize,
+    deletions: usize,
+    /// lines changed per category
+    categories: BTreeMap<String, Lines>,
+    /// the number of distinct authors
+    authors: usize,
+    /// the utc time of the earliest change
+    since: Option<String>,
+    /// the utc time of the latest change
+    until: Option<String>,
+}
+
+/// totals changes on their way to another emitter, writing a json summary
+/// of them once finished
+pub struct Summary<E, W>
+where
+    W: Write,
+{
+    pub emitter: E,
+    write: W,
+    totals: Totals,
+    authors: HashSet<String>,
+    /// the last commit counted. changes of a commit arrive together
+    last_commit: (String, String),
+}
+
+impl<E, W> Summary<E, W>
+where
+    W: Write,
+{
+    pub fn new(
+        emitter: E,
+        write: W,
+    ) -> Self {
+        Summary {
+            emitter,
+            write,
+            totals: Totals::default(),
+            authors: HashSet::new(),
+            last_commit: Default::default(),
+        }
+    }
+
+    fn count(
+        &mut self,
+        line: &Change,
+    ) {
+        let totals = &mut self.totals;
+        if self.last_commit.0 != line.repo || self.last_commit.1 != line.sha {
+            totals.commits += 1;
+            self.last_commit = (line.repo.clone(), line.sha.clone());
+        }
+        let (additions, deletions) = (
+            line.additions.unwrap_or_default(),
+            line.deletions.unwrap_or_default(),
+        );
+        totals.files += 1;
+        totals.additions += additions;
+        totals.deletions += deletions;
+        let lines = totals
+            .categories
+            .entry(line.category.name().into())
+            .or_default();
+        lines.additions += additions;
+        lines.deletions += deletions;
+        if !self.authors.contains(&line.author) {
+            self.authors.insert(line.author.clone());
+        }
+        // rfc3339 utc timestamps sort as strings
+        if let Some(timestamp) = &line.timestamp_utc {
+            if totals.since.as_ref().is_none_or(|since| timestamp < since) {
+                totals.since = Some(timestamp.clone());
+            }
+            if totals.until.as_ref().is_none_or(|until| timestamp > until) {
+                totals.until = Some(timestamp.clone());
+            }
+        }
+    }
+}
+
+impl<E, W> Emitter for Summary<E, W>
+where
+    E: Emitter,
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.count(&line);
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()?;
+        self.totals.authors = self.authors.len();
+        serde_json::to_writer(&mut self.write, &self.totals)?;
+        writeln!(self.write)?;
+        self.write.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Category, Collect};
+
+    fn change(
+        sha: &str,
+        author: &str,
+        timestamp_utc: &str,
+        category: Category,
+    ) -> Change {
+        Change {
+            repo: "test".into(),
+            sha: sha.into(),
+            author: author.into(),
+            timestamp_utc: Some(timestamp_utc.into()),
+            category,
+            additions: Some(2),
+            deletions: Some(1),
+            ..Change::default()
+        }
+    }
+
+    #[test]
+    fn summarizes_changes() -> Result<(), Box<dyn Error>> {
+        let mut buf = Vec::new();
+        {
+            let mut summary = Summary::new(Collect::default(), &mut buf);
+            summary.emit(change(
+                "sha2",
+                "luna@moon.com",
+                "2019-08-10T09:00:00Z",
+                Category::Test,
+            ))?;
+            summary.emit(change(
+                "sha2",
+                "luna@moon.com",
+                "2019-08-10T09:00:00Z",
+                Category::Default,
+            ))?;
+            summary.emit(change(
+                "sha1",
+                "sol@sun.com",
+                "2019-08-09T19:36:50Z",
+                Category::Test,
+            ))?;
+            summary.finish()?;
+        }
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "{\"commits\":2,\"files\":3,\"additions\":6,\"deletions\":3,\
+             \"categories\":{\"default\":{\"additions\":2,\"deletions\":1},\
+             \"test\":{\"additions\":4,\"deletions\":2}},\
+             \"authors\":2,\"since\":\"2019-08-09T19:36:50Z\",\"until\":\"2019-08-10T09:00:00Z\"}\n"
+        );
+        Ok(())
+    }
+}
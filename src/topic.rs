@@ -0,0 +1,100 @@
+//! publishes changes as json messages to a kafka topic
+use crate::{Change, Emitter};
+use kafka::producer::{Producer, Record, RequiredAcks};
+use std::{error::Error, ops::Range, time::Duration};
+
+/// messages sent per produce request
+const BATCH_SIZE: usize = 500;
+
+/// the key of a change's message, so compacted topics keep only the latest
+/// message of each change when re-published
+pub fn key(change: &Change) -> String {
+    format!("{}:{}:{}", change.repo, change.sha, change.path)
+}
+
+/// sends changes to a topic, each keyed by its [`key`]
+pub struct Kafka {
+    producer: Producer,
+    topic: String,
+    /// messages yet to be sent, serialized in place into a buffer reused between batches
+    buf: Vec<u8>,
+    /// the key of each pending message and the range of `buf` it spans
+    pending: Vec<(String, Range<usize>)>,
+}
+
+impl Kafka {
+    /// connects to a cluster through any of its `brokers`, as in `localhost:9092`
+    pub fn connect(
+        brokers: Vec<String>,
+        topic: String,
+    ) -> Result<Self, Box<dyn Error>> {
+        let producer = Producer::from_hosts(brokers)
+            .with_ack_timeout(Duration::from_secs(30))
+            .with_required_acks(RequiredAcks::One)
+            .create()
+            .map_err(|e| format!("failed to connect to kafka: {}", e))?;
+        Ok(Kafka {
+            producer,
+            topic,
+            buf: Vec::new(),
+            pending: Vec::new(),
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.pending.is_empty() {
+            let (topic, buf) = (&self.topic, &self.buf);
+            let records = self
+                .pending
+                .iter()
+                .map(|(key, message)| {
+                    Record::from_key_value(topic, key.as_bytes(), &buf[message.clone()])
+                })
+                .collect::<Vec<_>>();
+            self.producer
+                .send_all(&records)
+                .map_err(|e| format!("failed to publish to {}: {}", topic, e))?;
+            self.pending.clear();
+            self.buf.clear();
+        }
+        Ok(())
+    }
+}
+
+impl Emitter for Kafka {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let start = self.buf.len();
+        serde_json::to_writer(&mut self.buf, &line)?;
+        self.pending.push((key(&line), start..self.buf.len()));
+        if self.pending.len() >= BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_identify_changes() {
+        assert_eq!(
+            key(&Change {
+                repo: "softprops/git-linecat".into(),
+                sha: "61708727af02089cef4a72c6a532ddf332111b14".into(),
+                path: "src/main.rs".into(),
+                additions: Some(2),
+                ..Change::default()
+            }),
+            "softprops/git-linecat:61708727af02089cef4a72c6a532ddf332111b14:src/main.rs"
+        );
+    }
+}
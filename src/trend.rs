@@ -0,0 +1,302 @@
+//! rolling totals of changes per day or week, so trends are windowed
+//! the same way wherever they're charted
+use crate::{
+    format::{write_records, Format},
+    parse_timestamp,
+    summarize::{Bucket, Dimension},
+    Change, Emitter,
+};
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    io::Write,
+    str::FromStr,
+};
+
+/// the span of time each rolling total is reported for, in utc
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Period {
+    Day,
+    /// iso 8601 weeks, starting on mondays
+    Week,
+}
+
+impl Period {
+    fn days(self) -> i64 {
+        match self {
+            Period::Day => 1,
+            Period::Week => 7,
+        }
+    }
+
+    /// the first day of the period a date falls in
+    fn start(
+        self,
+        date: NaiveDate,
+    ) -> NaiveDate {
+        match self {
+            Period::Day => date,
+            Period::Week => date - Duration::days(date.weekday().num_days_from_monday().into()),
+        }
+    }
+
+    /// the period's name, as `summarize` buckets name it
+    fn name(
+        self,
+        start: NaiveDate,
+    ) -> String {
+        let bucket = match self {
+            Period::Day => Bucket::Day,
+            Period::Week => Bucket::Week,
+        };
+        start.format(bucket.format()).to_string()
+    }
+}
+
+impl FromStr for Period {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Period::Day),
+            "week" => Ok(Period::Week),
+            other => Err(format!("unsupported period {}", other)),
+        }
+    }
+}
+
+/// columns following the grouped dimensions
+const TOTALS: &[&str] = &["period", "commits", "additions", "deletions", "authors"];
+
+#[derive(Default)]
+struct Totals {
+    commits: HashSet<String>,
+    additions: usize,
+    deletions: usize,
+    authors: HashSet<String>,
+}
+
+/// accumulates totals per group and period, writing rolling totals for every period
+/// from a group's first change to its last once all changes are seen. periods without
+/// changes are written too, so windows are never skipped over
+pub struct Trend<W>
+where
+    W: Write,
+{
+    by: Vec<Dimension>,
+    period: Period,
+    window: usize,
+    format: Format,
+    write: Option<W>,
+    groups: BTreeMap<Vec<Option<String>>, BTreeMap<NaiveDate, Totals>>,
+}
+
+impl<W> Trend<W>
+where
+    W: Write,
+{
+    /// totals of each period roll up the `window` periods ending with it
+    pub fn new(
+        by: Vec<Dimension>,
+        period: Period,
+        window: usize,
+        format: Format,
+        write: W,
+    ) -> Self {
+        Trend {
+            by,
+            period,
+            window: window.max(1),
+            format,
+            write: Some(write),
+            groups: BTreeMap::new(),
+        }
+    }
+
+    fn columns(&self) -> Vec<&'static str> {
+        self.by
+            .iter()
+            .map(|dimension| dimension.name())
+            .chain(TOTALS.iter().copied())
+            .collect()
+    }
+
+    /// the rolling totals of each period of a group
+    fn roll(
+        &self,
+        periods: &BTreeMap<NaiveDate, Totals>,
+    ) -> Vec<(NaiveDate, Vec<usize>)> {
+        let (first, last) = match (periods.keys().next(), periods.keys().next_back()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Vec::new(),
+        };
+        let step = Duration::days(self.period.days());
+        let span = step * (self.window as i32 - 1);
+        let mut rolled = Vec::new();
+        let mut start = first;
+        while start <= last {
+            let (mut commits, mut additions, mut deletions) = (0, 0, 0);
+            let mut authors = HashSet::new();
+            for totals in periods
+                .range(start - span..=start)
+                .map(|(_, totals)| totals)
+            {
+                commits += totals.commits.len();
+                additions += totals.additions;
+                deletions += totals.deletions;
+                authors.extend(totals.authors.iter());
+            }
+            rolled.push((start, vec![commits, additions, deletions, authors.len()]));
+            start += step;
+        }
+        rolled
+    }
+}
+
+impl<W> Emitter for Trend<W>
+where
+    W: Write,
+{
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        let key = self
+            .by
+            .iter()
+            .map(|dimension| dimension.value(&line))
+            .collect::<Vec<_>>();
+        let date = parse_timestamp(&line.timestamp)?
+            .with_timezone(&Utc)
+            .date_naive();
+        let totals = self
+            .groups
+            .entry(key)
+            .or_default()
+            .entry(self.period.start(date))
+            .or_default();
+        totals.commits.insert(line.sha);
+        totals.additions += line.additions.unwrap_or_default();
+        totals.deletions += line.deletions.unwrap_or_default();
+        totals.authors.insert(line.author);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let write = match self.write.take() {
+            Some(write) => write,
+            _ => return Ok(()),
+        };
+        let columns = self.columns();
+        let groups = std::mem::take(&mut self.groups);
+        let mut records = Vec::new();
+        for (key, periods) in &groups {
+            for (start, totals) in self.roll(periods) {
+                let values = key
+                    .iter()
+                    .map(|value| value.clone().map_or(Value::Null, Value::String))
+                    .chain(std::iter::once(Value::String(self.period.name(start))))
+                    .chain(totals.into_iter().map(Value::from));
+                records.push(
+                    columns
+                        .iter()
+                        .map(|column| column.to_string())
+                        .zip(values)
+                        .collect::<Map<_, _>>(),
+                );
+            }
+        }
+        write_records(&self.format, write, &columns, records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Category;
+
+    fn change(
+        sha: &str,
+        author: &str,
+        category: Category,
+        timestamp: &str,
+    ) -> Change {
+        Change {
+            repo: "test".into(),
+            sha: sha.into(),
+            author: author.into(),
+            category,
+            timestamp: timestamp.into(),
+            additions: Some(2),
+            deletions: Some(1),
+            ..Change::default()
+        }
+    }
+
+    fn trend(
+        by: Vec<Dimension>,
+        period: Period,
+        window: usize,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut buf = Vec::new();
+        {
+            let mut trend = Trend::new(by, period, window, Format::Csv, &mut buf);
+            for line in [
+                change(
+                    "a",
+                    "luna@moon.com",
+                    Category::Test,
+                    "2019-08-05 09:00:00 +0000",
+                ),
+                change(
+                    "a",
+                    "luna@moon.com",
+                    Category::Docs,
+                    "2019-08-05 09:00:00 +0000",
+                ),
+                change(
+                    "b",
+                    "sol@sun.com",
+                    Category::Test,
+                    "2019-08-11 23:00:00 +0000",
+                ),
+                change(
+                    "c",
+                    "luna@moon.com",
+                    Category::Test,
+                    "2019-08-26 10:00:00 +0000",
+                ),
+            ] {
+                trend.emit(line)?;
+            }
+            trend.finish()?;
+        }
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn rolls_weekly_totals() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            trend(vec![Dimension::Repo], Period::Week, 2)?,
+            "repo,period,commits,additions,deletions,authors\n\
+             test,2019-W32,2,6,3,2\n\
+             test,2019-W33,2,6,3,2\n\
+             test,2019-W34,0,0,0,0\n\
+             test,2019-W35,1,2,1,1\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn totals_days_per_category() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            trend(vec![Dimension::Category], Period::Day, 1)?
+                .lines()
+                .filter(|line| line.starts_with("docs"))
+                .collect::<Vec<_>>(),
+            vec!["docs,2019-08-05,1,2,1,1"]
+        );
+        Ok(())
+    }
+}
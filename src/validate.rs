@@ -0,0 +1,141 @@
+//! checks that logs are in a format git-linecat understands, counting the lines
+//! of each kind read from each source rather than emitting changes
+use crate::{
+    format::{write_records, Format},
+    manifest::Source,
+    Change, Emitter, Malformed,
+};
+use serde_json::{Map, Value};
+use std::{error::Error, io::Write};
+
+/// columns of the record written for each source
+pub const COLUMNS: &[&str] = &[
+    "repo",
+    "origin",
+    "headers",
+    "bodies",
+    "paths",
+    "binary",
+    "blank",
+    "malformed",
+];
+
+/// an emitter dropping every change
+pub struct Discard;
+
+impl Emitter for Discard {
+    fn emit(
+        &mut self,
+        _: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+fn record(
+    repo: String,
+    malformed: &Malformed,
+) -> Map<String, Value> {
+    let lines = &malformed.lines;
+    let mut record = Map::new();
+    record.insert("repo".into(), repo.into());
+    record.insert("origin".into(), malformed.origin.clone().into());
+    record.insert("headers".into(), lines.headers.into());
+    record.insert("bodies".into(), lines.bodies.into());
+    record.insert("paths".into(), lines.paths.into());
+    record.insert("binary".into(), lines.binary.into());
+    record.insert("blank".into(), lines.blank.into());
+    record.insert("malformed".into(), malformed.skipped.into());
+    record
+}
+
+/// reads every source with `read`, writing a record of the lines read from each,
+/// then fails if any lines were malformed
+pub fn validate<F, W>(
+    sources: Vec<Source>,
+    read: F,
+    format: &Format,
+    write: W,
+) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(Source, &mut Discard) -> Result<Malformed, Box<dyn Error>>,
+    W: Write,
+{
+    let mut records = Vec::new();
+    let mut skipped = 0;
+    for source in sources {
+        let repo = source.name.clone();
+        let malformed = read(source, &mut Discard)?;
+        skipped += malformed.skipped;
+        records.push(record(repo, &malformed));
+    }
+    write_records(format, write, COLUMNS, records)?;
+    match skipped {
+        0 => Ok(()),
+        _ => Err(format!("found {} malformed lines", skipped).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manifest::Input, read, Backend, Parse};
+
+    fn source(logs: &str) -> Source {
+        Source {
+            name: "test".into(),
+            input: Input::Logs(logs.into()),
+        }
+    }
+
+    fn lenient(
+        source: Source,
+        emitter: &mut Discard,
+    ) -> Result<Malformed, Box<dyn Error>> {
+        read(
+            source,
+            &Backend::Git,
+            &Parse::default(),
+            false,
+            &[],
+            emitter,
+        )
+    }
+
+    #[test]
+    fn counts_lines_of_each_kind() -> Result<(), Box<dyn Error>> {
+        let mut buf = Vec::new();
+        validate(
+            vec![source("tests/data/git.log")],
+            lenient,
+            &Format::Csv,
+            &mut buf,
+        )?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "repo,origin,headers,bodies,paths,binary,blank,malformed\n\
+             test,tests/data/git.log,3,0,2,1,2,0\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_malformed_lines() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("git-linecat-{}.log", std::process::id()));
+        std::fs::write(
+            &path,
+            "\"sha1\",\"luna@moon.com\",\"2019-08-09 15:36:50 -0400\"\ngarbled\n",
+        )?;
+        let mut buf = Vec::new();
+        let err = validate(
+            vec![source(&path.to_string_lossy())],
+            lenient,
+            &Format::Json,
+            &mut buf,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "found 1 malformed lines");
+        assert!(String::from_utf8(buf)?.contains("\"malformed\":1"));
+        Ok(())
+    }
+}
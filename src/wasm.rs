@@ -0,0 +1,157 @@
+//! categorizes paths with a webassembly module, sandboxed within an interpreter
+//! and called in-process rather than through a subprocess.
+//!
+//! modules implement `fn categorize(path: &str) -> &str` across the wasm boundary by
+//! exporting their `memory` along with
+//!
+//! * `alloc(len: i32) -> i32`, a pointer to `len` bytes the path is written to.
+//!   modules may hand out the same buffer each call
+//! * `categorize(ptr: i32, len: i32) -> i64`, the utf-8 label of the path at `ptr`,
+//!   packed as its pointer in the upper 32 bits and its length in the lower 32 bits.
+//!   empty labels leave the category already assigned in place
+//!
+//! modules are given no imports, so have no access to the host, and each call is
+//! limited by fuel so runaway modules fail rather than hang
+use crate::{Category, Change, Emitter};
+use std::{collections::HashMap, convert::TryFrom, error::Error, fs, path::Path};
+use wasmi::{Config, Engine, Linker, Memory, Module, Store, TypedFunc};
+
+/// instructions, roughly, a module may execute per path
+const FUEL: u64 = 10_000_000;
+
+/// a loaded categorizer module
+pub struct Wasm {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    categorize: TypedFunc<(i32, i32), i64>,
+    categories: HashMap<String, Option<Category>>,
+}
+
+impl Wasm {
+    /// loads a module from a `.wasm` binary or `.wat` text file
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let wasm = fs::read(path)
+            .map_err(|e| format!("failed to read categorizer {}: {}", path.display(), e))?;
+        Self::compile(&wasm)
+    }
+
+    fn compile(wasm: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm)?;
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::new(&engine).instantiate_and_start(&mut store, &module)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or("categorizer modules must export memory")?;
+        let alloc = instance.get_typed_func(&store, "alloc")?;
+        let categorize = instance.get_typed_func(&store, "categorize")?;
+        Ok(Wasm {
+            store,
+            memory,
+            alloc,
+            categorize,
+            categories: HashMap::new(),
+        })
+    }
+
+    fn call(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<Category>, Box<dyn Error>> {
+        self.store.set_fuel(FUEL)?;
+        let len = i32::try_from(path.len())?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, path.as_bytes())?;
+        let label = self.categorize.call(&mut self.store, (ptr, len))? as u64;
+        let mut buf = vec![0; (label & 0xffff_ffff) as usize];
+        self.memory
+            .read(&self.store, (label >> 32) as usize, &mut buf)?;
+        Ok(match String::from_utf8(buf)?.trim() {
+            "" => None,
+            label => Some(serde_json::from_value(label.into())?),
+        })
+    }
+
+    /// the category the module assigns path, if any. paths are only asked about once
+    pub fn categorize(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<Category>, Box<dyn Error>> {
+        if let Some(category) = self.categories.get(path) {
+            return Ok(category.clone());
+        }
+        let category = self
+            .call(path)
+            .map_err(|e| format!("categorizer failed on {}: {}", path, e))?;
+        self.categories.insert(path.into(), category.clone());
+        Ok(category)
+    }
+}
+
+/// recategorizes changes with a module before handing them to another emitter
+pub struct Categorize<E> {
+    pub wasm: Wasm,
+    pub emitter: E,
+}
+
+impl<E> Emitter for Categorize<E>
+where
+    E: Emitter,
+{
+    fn emit(
+        &mut self,
+        mut line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(category) = self.wasm.categorize(&line.path)? {
+            line.categorize_as(category);
+        }
+        self.emitter.emit(line)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.emitter.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categorizes_with_modules() -> Result<(), Box<dyn Error>> {
+        let mut wasm = Wasm::load(Path::new("tests/data/categorizer.wat"))?;
+        assert_eq!(
+            wasm.categorize("api/users.proto")?,
+            Some(Category::Custom("protos".into()))
+        );
+        assert_eq!(wasm.categorize("docs/intro.md")?, Some(Category::Docs));
+        assert_eq!(wasm.categorize("src/main.rs")?, None);
+        assert_eq!(
+            wasm.categorize("api/users.proto")?,
+            Some(Category::Custom("protos".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn runaway_modules_fail() -> Result<(), Box<dyn Error>> {
+        let mut wasm = Wasm::compile(
+            br#"(module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) i32.const 0)
+                (func (export "categorize") (param i32 i32) (result i64)
+                    (loop (br 0))
+                    i64.const 0))"#,
+        )?;
+        assert!(wasm.categorize("src/main.rs").is_err());
+        Ok(())
+    }
+}
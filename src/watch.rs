@@ -0,0 +1,34 @@
+//! follows a repository, emitting the changes of commits as they land
+use crate::{git, Emitter};
+use std::{error::Error, path::Path, thread, time::Duration};
+
+/// reads history up to the current `HEAD` of the repository at `path`, then polls
+/// `HEAD` every `interval`, reading the commits it moved past. `read` reads the
+/// changes of `git log` given `args` followed by a revision range into the emitter,
+/// which is flushed after each read. runs until interrupted or reading fails
+pub fn watch<E, F>(
+    path: &Path,
+    interval: Duration,
+    args: &[String],
+    emitter: &mut E,
+    mut read: F,
+) -> Result<(), Box<dyn Error>>
+where
+    E: Emitter,
+    F: FnMut(&[String], &mut E) -> Result<(), Box<dyn Error>>,
+{
+    let mut head = None;
+    loop {
+        let next = git::head(path)?;
+        if let Some(next) = next.as_ref().filter(|next| head.as_ref() != Some(*next)) {
+            let range = match &head {
+                Some(head) => format!("{}..{}", head, next),
+                _ => next.clone(),
+            };
+            read(&[args, &[range]].concat(), emitter)?;
+            emitter.flush()?;
+        }
+        head = next;
+        thread::sleep(interval);
+    }
+}
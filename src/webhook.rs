@@ -0,0 +1,279 @@
+//! posts batches of changes as json arrays to an http endpoint, retrying with
+//! backoff when it fails, and appending batches it never accepts to a dead letter file
+use crate::{format::Projection, output::Post, Change, Emitter};
+use flate2::{write::GzEncoder, Compression};
+use std::{error::Error, fs::OpenOptions, io::Write, ops::Range, thread, time::Duration};
+use ureq::{Agent, AgentBuilder};
+
+/// requests made for a batch before giving up on it
+const ATTEMPTS: u32 = 6;
+
+/// the delay before the first retry, doubling with each attempt after
+const BACKOFF: Duration = Duration::from_millis(500);
+
+/// whether a failed request is worth retrying, as when the endpoint is overloaded
+fn transient(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Status(status, _) => *status == 408 || *status == 429 || *status >= 500,
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+pub struct Webhook {
+    agent: Agent,
+    url: String,
+    post: Post,
+    projection: Option<Projection>,
+    /// the json array of changes yet to be sent, serialized in place into a
+    /// buffer reused between batches
+    buf: Vec<u8>,
+    /// the range of `buf` each pending change spans
+    pending: Vec<Range<usize>>,
+    backoff: Duration,
+}
+
+impl Webhook {
+    pub fn new(
+        url: &str,
+        post: Post,
+        projection: Option<Projection>,
+    ) -> Self {
+        Webhook {
+            agent: AgentBuilder::new().timeout(Duration::from_secs(60)).build(),
+            url: url.into(),
+            post,
+            projection,
+            buf: Vec::new(),
+            pending: Vec::new(),
+            backoff: BACKOFF,
+        }
+    }
+
+    /// posts a body, retrying transient failures, describing the last once out of attempts
+    fn send(
+        &self,
+        body: &[u8],
+    ) -> Result<(), String> {
+        let mut delay = self.backoff;
+        let mut attempt = 1;
+        loop {
+            let mut request = self
+                .agent
+                .post(&self.url)
+                .set("Content-Type", "application/json");
+            if self.post.gzip {
+                request = request.set("Content-Encoding", "gzip");
+            }
+            for header in &self.post.headers {
+                request = request.set(&header.name, &header.value);
+            }
+            match request.send_bytes(body) {
+                Ok(_) => return Ok(()),
+                Err(e) if transient(&e) && attempt < ATTEMPTS => {
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    /// sends pending changes, keeping their buffer for the next batch
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.buf.push(b']');
+        let sent = self.post_batch();
+        self.buf.clear();
+        self.pending.clear();
+        sent
+    }
+
+    /// posts the pending batch, writing its changes to the dead letter file if they're never accepted
+    fn post_batch(&self) -> Result<(), Box<dyn Error>> {
+        let gzipped;
+        let body = match self.post.gzip {
+            true => {
+                let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+                gzip.write_all(&self.buf)?;
+                gzipped = gzip.finish()?;
+                &gzipped
+            }
+            _ => &self.buf,
+        };
+        let (batch, buf) = (&self.pending, &self.buf);
+        let error = match self.send(body) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        let path = match &self.post.dead_letter {
+            Some(path) => path,
+            _ => return Err(format!("failed to post {} changes: {}", batch.len(), error).into()),
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("failed to open dead letters {}: {}", path.display(), e))?;
+        for line in batch {
+            file.write_all(&buf[line.clone()])?;
+            writeln!(file)?;
+        }
+        tracing::warn!(
+            changes = batch.len(),
+            dead_letter = %path.display(),
+            "wrote changes to dead letters after failing to post them: {}",
+            error
+        );
+        Ok(())
+    }
+}
+
+impl Emitter for Webhook {
+    fn emit(
+        &mut self,
+        line: Change,
+    ) -> Result<(), Box<dyn Error>> {
+        self.buf
+            .push(if self.pending.is_empty() { b'[' } else { b',' });
+        let start = self.buf.len();
+        match &self.projection {
+            Some(projection) => serde_json::to_writer(&mut self.buf, &projection.project(&line)?)?,
+            _ => serde_json::to_writer(&mut self.buf, &line)?,
+        }
+        self.pending.push(start..self.buf.len());
+        if self.pending.len() >= self.post.batch {
+            Webhook::flush(self)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Webhook::flush(self)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Webhook::flush(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Header;
+    use flate2::read::GzDecoder;
+    use std::{
+        env, fs,
+        io::{BufRead, BufReader, Read},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    /// a request's headers, lowercased, and body
+    type Served = mpsc::Receiver<(Vec<String>, Vec<u8>)>;
+
+    /// answers requests with statuses in order, sending back each request
+    fn serve(statuses: Vec<u16>) -> Result<(String, Served), Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/churn", listener.local_addr()?);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for (status, stream) in statuses.into_iter().zip(listener.incoming()) {
+                let mut stream = BufReader::new(stream.expect("connection"));
+                let mut headers = Vec::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).expect("request line");
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().expect("content length");
+                        }
+                    }
+                    headers.push(line.trim().to_lowercase());
+                }
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).expect("request body");
+                drop(sender.send((headers, body)));
+                write!(
+                    stream.get_mut(),
+                    "HTTP/1.1 {} OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .expect("response");
+            }
+        });
+        Ok((url, receiver))
+    }
+
+    fn change(path: &str) -> Change {
+        Change {
+            repo: "test".into(),
+            path: path.into(),
+            ..Change::default()
+        }
+    }
+
+    #[test]
+    fn posts_gzipped_batches() -> Result<(), Box<dyn Error>> {
+        let (url, requests) = serve(vec![503, 200, 200])?;
+        let mut webhook = Webhook::new(
+            &url,
+            Post {
+                headers: vec!["Authorization: Bearer secret".parse::<Header>()?],
+                batch: 2,
+                gzip: true,
+                dead_letter: None,
+            },
+            None,
+        );
+        webhook.backoff = Duration::from_millis(1);
+        for path in &["a.rs", "b.rs", "c.rs"] {
+            webhook.emit(change(path))?;
+        }
+        webhook.finish()?;
+        let requests = requests.try_iter().collect::<Vec<_>>();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0], requests[1]);
+        let (headers, body) = &requests[1];
+        assert!(headers.contains(&"authorization: bearer secret".to_string()));
+        assert!(headers.contains(&"content-encoding: gzip".to_string()));
+        let mut json = String::new();
+        GzDecoder::new(&body[..]).read_to_string(&mut json)?;
+        let batch: Vec<serde_json::Value> = serde_json::from_str(&json)?;
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[1]["path"], "b.rs");
+        Ok(())
+    }
+
+    #[test]
+    fn writes_rejected_batches_to_dead_letters() -> Result<(), Box<dyn Error>> {
+        let (url, _) = serve(vec![400, 400])?;
+        let dead_letter = env::temp_dir().join("git-linecat-webhook-test.jsonl");
+        drop(fs::remove_file(&dead_letter));
+        let mut webhook = Webhook::new(
+            &url,
+            Post {
+                dead_letter: Some(dead_letter.clone()),
+                ..Post::default()
+            },
+            None,
+        );
+        webhook.emit(change("a.rs"))?;
+        webhook.emit(change("b.rs"))?;
+        webhook.finish()?;
+        let letters = fs::read_to_string(&dead_letter)?;
+        assert_eq!(letters.lines().count(), 2);
+        assert!(letters.contains("b.rs"));
+        fs::remove_file(&dead_letter)?;
+        let mut webhook = Webhook::new(&url, Post::default(), None);
+        webhook.emit(change("a.rs"))?;
+        assert!(webhook.finish().is_err());
+        Ok(())
+    }
+}
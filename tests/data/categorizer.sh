@@ -0,0 +1,8 @@
+#!/bin/sh
+# labels protobuf definitions, leaving other paths' categories in place
+while read -r path; do
+  case "$path" in
+    *.proto) echo protos ;;
+    *) echo ;;
+  esac
+done
@@ -0,0 +1,41 @@
+;; labels protobuf definitions and docs, leaving other paths' categories in place
+(module
+  (memory (export "memory") 1)
+  (data (i32.const 0) "protos")
+  (data (i32.const 8) "docs")
+  (data (i32.const 16) ".proto")
+  (data (i32.const 24) "docs/")
+
+  ;; every path is written to the same buffer
+  (func (export "alloc") (param $len i32) (result i32)
+    i32.const 1024)
+
+  ;; whether the n bytes at $at match those at $lit
+  (func $matches (param $at i32) (param $lit i32) (param $n i32) (result i32)
+    (local $i i32)
+    (block $mismatch
+      (loop $next
+        (if (i32.eq (local.get $i) (local.get $n))
+          (then (return (i32.const 1))))
+        (br_if $mismatch
+          (i32.ne
+            (i32.load8_u (i32.add (local.get $at) (local.get $i)))
+            (i32.load8_u (i32.add (local.get $lit) (local.get $i)))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    i32.const 0)
+
+  ;; labels are returned as their pointer << 32 | their length
+  (func (export "categorize") (param $ptr i32) (param $len i32) (result i64)
+    (if (i32.ge_u (local.get $len) (i32.const 6))
+      (then
+        (if (call $matches
+              (i32.sub (i32.add (local.get $ptr) (local.get $len)) (i32.const 6))
+              (i32.const 16)
+              (i32.const 6))
+          (then (return (i64.const 6))))))
+    (if (i32.ge_u (local.get $len) (i32.const 5))
+      (then
+        (if (call $matches (local.get $ptr) (i32.const 24) (i32.const 5))
+          (then (return (i64.const 0x800000004))))))
+    i64.const 0))